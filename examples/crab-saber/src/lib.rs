@@ -10,8 +10,8 @@ use hotham::{
         physics_step,
     },
     systems::{
-        audio_system, collision_system, draw_gui_system, rendering_system,
-        update_parent_transform_matrix_system, update_rigid_body_transforms_system,
+        audio_system, collision_system, draw_gui_system, lighting_system, rendering_system,
+        shadows_system, update_parent_transform_matrix_system, update_rigid_body_transforms_system,
         update_transform_matrix_system,
     },
     systems::{pointers_system, Queries},
@@ -155,6 +155,19 @@ fn tick(
 
     // Rendering tasks - only necessary if we are in at least the visible state
     if current_state == xr::SessionState::VISIBLE || current_state == xr::SessionState::FOCUSED {
+        lighting_system(
+            &mut hotham_queries.lighting_query,
+            world,
+            vulkan_context,
+            render_context,
+        );
+        shadows_system(
+            &mut hotham_queries.shadows_query,
+            world,
+            vulkan_context,
+            xr_context.frame_index,
+            render_context,
+        );
         begin_pbr_renderpass(xr_context, vulkan_context, render_context);
         rendering_system(
             &mut hotham_queries.rendering_query,
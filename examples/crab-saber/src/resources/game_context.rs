@@ -6,11 +6,12 @@ use std::{
 
 use hotham::{
     components::{
-        hand::Handedness, ui_panel::add_ui_panel_to_world, Collider, Pointer, RigidBody,
-        SoundEmitter, Visible,
+        hand::Handedness, ui_panel::add_ui_panel_to_world, CastsShadow, Collider, Light, Pointer,
+        RigidBody, SoundEmitter, Transform, TransformMatrix, Visible,
     },
     gltf_loader::{self, add_model_to_world},
     hecs::{Entity, World},
+    nalgebra::{UnitQuaternion, Vector3},
     rapier3d::prelude::{
         ActiveCollisionTypes, ActiveEvents, ColliderBuilder, InteractionGroups, RigidBodyBuilder,
     },
@@ -18,6 +19,7 @@ use hotham::{
         audio_context::MusicTrack, physics_context::DEFAULT_COLLISION_GROUP,
         vulkan_context::VulkanContext, AudioContext, PhysicsContext, RenderContext,
     },
+    scene_data::SceneParams,
     vk, Engine,
 };
 use rand::prelude::*;
@@ -212,7 +214,8 @@ fn add_environment(
         None,
         vulkan_context,
         &render_context.descriptor_set_layouts,
-    );
+    )
+    .expect("Unable to add Environment");
 
     add_model_to_world(
         "Ramp",
@@ -221,7 +224,20 @@ fn add_environment(
         None,
         vulkan_context,
         &render_context.descriptor_set_layouts,
-    );
+    )
+    .expect("Unable to add Ramp");
+
+    // Add the sun, shining from the same direction as the default light, so the cubes and sabers cast shadows.
+    let light_direction = -SceneParams::default().light_direction.xyz();
+    let rotation = UnitQuaternion::rotation_between(&-Vector3::z(), &light_direction).unwrap();
+    world.spawn((
+        Light::directional(1.),
+        Transform {
+            rotation,
+            ..Default::default()
+        },
+        TransformMatrix::default(),
+    ));
 }
 
 pub fn add_songs(audio_context: &mut AudioContext, game_context: &mut GameContext) {
@@ -307,7 +323,7 @@ pub fn pre_spawn_cube(
 
     world.remove_one::<Visible>(cube).unwrap();
     world
-        .insert(cube, (Cube {}, color, RigidBody { handle }))
+        .insert(cube, (Cube {}, color, RigidBody { handle }, CastsShadow {}))
         .unwrap();
 }
 
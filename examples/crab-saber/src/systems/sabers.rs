@@ -1,6 +1,6 @@
 use hotham::nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion, Vector3};
 use hotham::{
-    components::RigidBody,
+    components::{hand::Handedness, CastsShadow, RigidBody},
     gltf_loader::{add_model_to_world, Models},
     hecs::{Entity, PreparedQuery, With, World},
     rapier3d::prelude::{ActiveCollisionTypes, ActiveEvents, ColliderBuilder, RigidBodyBuilder},
@@ -32,21 +32,14 @@ pub fn sabers_system(
     physics_context: &mut PhysicsContext,
 ) {
     for (_, (color, rigid_body)) in query.query_mut(world) {
-        // Get our the space and path of the hand.
-        let time = xr_context.frame_state.predicted_display_time;
-        let (space, _) = match color {
-            Color::Red => (
-                &xr_context.left_hand_space,
-                xr_context.left_hand_subaction_path,
-            ),
-            Color::Blue => (
-                &xr_context.right_hand_space,
-                xr_context.right_hand_subaction_path,
-            ),
+        // Get the hand holding this saber.
+        let handedness = match color {
+            Color::Red => Handedness::Left,
+            Color::Blue => Handedness::Right,
         };
 
         // Locate the hand in the space.
-        let space = space.locate(&xr_context.reference_space, time).unwrap();
+        let space = xr_context.locate_hand(handedness).unwrap();
         if !is_space_valid(&space) {
             return;
         }
@@ -88,7 +81,9 @@ pub fn add_saber(
     )
     .unwrap();
     add_saber_physics(world, physics_context, saber);
-    world.insert(saber, (Saber {}, color)).unwrap();
+    world
+        .insert(saber, (Saber {}, color, CastsShadow {}))
+        .unwrap();
     saber
 }
 
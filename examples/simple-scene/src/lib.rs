@@ -8,10 +8,10 @@ use hotham::{
         begin_frame, begin_pbr_renderpass, end_frame, end_pbr_renderpass, physics_step,
     },
     systems::{
-        animation_system, collision_system, grabbing_system, hands::add_hand, hands_system,
-        rendering::rendering_system, skinning::skinning_system,
-        update_parent_transform_matrix_system, update_rigid_body_transforms_system,
-        update_transform_matrix_system, Queries,
+        animation_system, collision_system, grabbing_system, hand_tracking_system, hands::add_hand,
+        hands_system, lighting_system, morph_targets_system, rendering::rendering_system,
+        skinning::skinning_system, update_parent_transform_matrix_system,
+        update_rigid_body_transforms_system, update_transform_matrix_system, Queries,
     },
     Engine, HothamResult,
 };
@@ -122,14 +122,32 @@ fn tick(engine: &mut Engine, world: &mut World, queries: &mut Queries) {
         world,
         physics_context,
     );
-    animation_system(&mut queries.animation_query, world);
+    animation_system(&mut queries.animation_query, world, xr_context);
+    morph_targets_system(
+        &mut queries.morph_targets_query,
+        world,
+        vulkan_context,
+        render_context,
+    );
+    hand_tracking_system(&mut queries.hand_tracking_query, world, xr_context);
     update_transform_matrix_system(&mut queries.update_transform_matrix_query, world);
     update_parent_transform_matrix_system(
         &mut queries.parent_query,
         &mut queries.roots_query,
         world,
     );
-    skinning_system(&mut queries.joints_query, &mut queries.meshes_query, world);
+    skinning_system(
+        &mut queries.meshes_query,
+        world,
+        vulkan_context,
+        render_context,
+    );
+    lighting_system(
+        &mut queries.lighting_query,
+        world,
+        vulkan_context,
+        render_context,
+    );
     begin_pbr_renderpass(xr_context, vulkan_context, render_context);
     rendering_system(
         &mut queries.rendering_query,
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use openxr_sys::{ActionType, Path, Result, Time};
+
+/// The state of an action for a single subaction path, as of the last call to `xrSyncActions`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActionValue {
+    pub current_state: f32,
+    pub changed_since_last_sync: bool,
+    pub last_change_time: Time,
+    pub is_active: bool,
+}
+
+impl Default for ActionValue {
+    fn default() -> Self {
+        Self {
+            current_state: 0.,
+            changed_since_last_sync: false,
+            last_change_time: Time::from_nanos(0),
+            is_active: false,
+        }
+    }
+}
+
+/// Everything the simulator knows about an action created with `xrCreateAction`.
+#[derive(Debug, Clone)]
+pub struct SimulatedAction {
+    pub name: String,
+    pub action_type: ActionType,
+    pub subaction_paths: Vec<Path>,
+    /// Input paths suggested for this action, across all interaction profiles
+    pub bindings: Vec<Path>,
+    /// State for each subaction path. `Path::NULL` holds the state of all the bindings combined.
+    states: HashMap<Path, ActionValue>,
+}
+
+impl SimulatedAction {
+    pub fn new(name: String, action_type: ActionType, subaction_paths: Vec<Path>) -> Self {
+        Self {
+            name,
+            action_type,
+            subaction_paths,
+            bindings: Vec::new(),
+            states: Default::default(),
+        }
+    }
+
+    /// Update the state of this action for every subaction path. `input_value` gets the current value of a
+    /// binding, or `None` if the simulator doesn't know how to simulate it.
+    pub fn sync(
+        &mut self,
+        time: Time,
+        paths: &HashMap<Path, String>,
+        input_value: impl Fn(&str) -> Option<f32>,
+    ) {
+        let bindings = self
+            .bindings
+            .iter()
+            .filter_map(|b| paths.get(b))
+            .collect::<Vec<_>>();
+
+        let subaction_paths = self
+            .subaction_paths
+            .iter()
+            .copied()
+            .chain(std::iter::once(Path::NULL));
+
+        for subaction_path in subaction_paths {
+            let prefix = paths.get(&subaction_path).map(|p| format!("{}/", p));
+            let values = bindings
+                .iter()
+                .filter(|b| prefix.as_ref().map(|p| b.starts_with(p)).unwrap_or(true))
+                .filter_map(|b| input_value(b))
+                .collect::<Vec<_>>();
+
+            let is_active = !values.is_empty();
+
+            // When more than one input is bound to an action, the one with the largest magnitude wins.
+            let mut current_state =
+                values
+                    .into_iter()
+                    .fold(0., |a: f32, b: f32| if b.abs() > a.abs() { b } else { a });
+            if self.action_type == ActionType::BOOLEAN_INPUT {
+                current_state = if current_state > 0.5 { 1. } else { 0. };
+            }
+
+            let previous = self
+                .states
+                .get(&subaction_path)
+                .copied()
+                .unwrap_or_default();
+            let changed_since_last_sync =
+                is_active && previous.is_active && previous.current_state != current_state;
+            let last_change_time = if changed_since_last_sync || is_active != previous.is_active {
+                time
+            } else {
+                previous.last_change_time
+            };
+
+            self.states.insert(
+                subaction_path,
+                ActionValue {
+                    current_state,
+                    changed_since_last_sync,
+                    last_change_time,
+                    is_active,
+                },
+            );
+        }
+    }
+
+    /// Get the state of this action for a subaction path, checking that the application asked for the right type.
+    pub fn get(
+        &self,
+        action_type: ActionType,
+        subaction_path: Path,
+    ) -> std::result::Result<ActionValue, Result> {
+        if self.action_type != action_type {
+            return Err(Result::ERROR_ACTION_TYPE_MISMATCH);
+        }
+
+        if subaction_path != Path::NULL && !self.subaction_paths.contains(&subaction_path) {
+            return Err(Result::ERROR_PATH_UNSUPPORTED);
+        }
+
+        Ok(self
+            .states
+            .get(&subaction_path)
+            .copied()
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths() -> HashMap<Path, String> {
+        [
+            (1, "/user/hand/left"),
+            (2, "/user/hand/right"),
+            (3, "/user/hand/left/input/squeeze/value"),
+            (4, "/user/hand/right/input/squeeze/value"),
+            (5, "/user/hand/right/input/thumbstick"),
+        ]
+        .iter()
+        .map(|(raw, path)| (Path::from_raw(*raw), path.to_string()))
+        .collect()
+    }
+
+    fn grab_action(action_type: ActionType) -> SimulatedAction {
+        let mut action = SimulatedAction::new(
+            "grab".to_string(),
+            action_type,
+            vec![Path::from_raw(1), Path::from_raw(2)],
+        );
+        action.bindings = vec![Path::from_raw(3), Path::from_raw(4)];
+        action
+    }
+
+    fn grip(left: f32, right: f32) -> impl Fn(&str) -> Option<f32> {
+        move |binding| match binding {
+            "/user/hand/left/input/squeeze/value" => Some(left),
+            "/user/hand/right/input/squeeze/value" => Some(right),
+            _ => None,
+        }
+    }
+
+    #[test]
+    pub fn test_per_hand_state() {
+        let paths = paths();
+        let mut action = grab_action(ActionType::FLOAT_INPUT);
+        let (left, right) = (Path::from_raw(1), Path::from_raw(2));
+
+        action.sync(Time::from_nanos(1), &paths, grip(0.25, 0.));
+        let left_state = action.get(ActionType::FLOAT_INPUT, left).unwrap();
+        assert!(left_state.is_active);
+        assert_eq!(left_state.current_state, 0.25);
+        assert!(!left_state.changed_since_last_sync);
+        assert_eq!(left_state.last_change_time, Time::from_nanos(1));
+        let right_state = action.get(ActionType::FLOAT_INPUT, right).unwrap();
+        assert_eq!(right_state.current_state, 0.);
+
+        action.sync(Time::from_nanos(2), &paths, grip(0.25, 1.));
+        let left_state = action.get(ActionType::FLOAT_INPUT, left).unwrap();
+        assert!(!left_state.changed_since_last_sync);
+        assert_eq!(left_state.last_change_time, Time::from_nanos(1));
+        let right_state = action.get(ActionType::FLOAT_INPUT, right).unwrap();
+        assert!(right_state.changed_since_last_sync);
+        assert_eq!(right_state.current_state, 1.);
+        assert_eq!(right_state.last_change_time, Time::from_nanos(2));
+
+        let combined = action.get(ActionType::FLOAT_INPUT, Path::NULL).unwrap();
+        assert_eq!(combined.current_state, 1.);
+    }
+
+    #[test]
+    pub fn test_boolean_action() {
+        let paths = paths();
+        let mut action = grab_action(ActionType::BOOLEAN_INPUT);
+        let right = Path::from_raw(2);
+
+        action.sync(Time::from_nanos(1), &paths, grip(0., 0.4));
+        action.sync(Time::from_nanos(2), &paths, grip(0., 0.45));
+        let state = action.get(ActionType::BOOLEAN_INPUT, right).unwrap();
+        assert_eq!(state.current_state, 0.);
+        assert!(!state.changed_since_last_sync);
+
+        action.sync(Time::from_nanos(3), &paths, grip(0., 0.6));
+        let state = action.get(ActionType::BOOLEAN_INPUT, right).unwrap();
+        assert_eq!(state.current_state, 1.);
+        assert!(state.changed_since_last_sync);
+        assert_eq!(state.last_change_time, Time::from_nanos(3));
+    }
+
+    #[test]
+    pub fn test_errors_and_unbound_actions() {
+        let paths = paths();
+        let mut action = grab_action(ActionType::FLOAT_INPUT);
+        action.bindings = vec![Path::from_raw(5)];
+        action.sync(Time::from_nanos(1), &paths, grip(1., 1.));
+
+        let state = action
+            .get(ActionType::FLOAT_INPUT, Path::from_raw(2))
+            .unwrap();
+        assert!(!state.is_active);
+        assert_eq!(
+            action.get(ActionType::BOOLEAN_INPUT, Path::NULL),
+            Err(Result::ERROR_ACTION_TYPE_MISMATCH)
+        );
+        assert_eq!(
+            action.get(ActionType::FLOAT_INPUT, Path::from_raw(3)),
+            Err(Result::ERROR_PATH_UNSUPPORTED)
+        );
+    }
+}
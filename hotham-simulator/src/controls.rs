@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+
+use cgmath::{Quaternion, Rad, Rotation3};
+use openxr_sys::{Quaternionf, Vector3f};
+use winit::event::VirtualKeyCode;
+
+/// Hold to move the left hand instead of the head
+pub const LEFT_HAND_MODIFIER: VirtualKeyCode = VirtualKeyCode::LShift;
+/// Hold to move the right hand instead of the head
+pub const RIGHT_HAND_MODIFIER: VirtualKeyCode = VirtualKeyCode::LControl;
+
+/// Keys that squeeze the grip on the left and right hands
+pub const GRIP_KEYS: [VirtualKeyCode; 2] = [VirtualKeyCode::Q, VirtualKeyCode::E];
+/// Keys that pull the trigger on the left and right hands
+pub const TRIGGER_KEYS: [VirtualKeyCode; 2] = [VirtualKeyCode::Z, VirtualKeyCode::C];
+
+/// How far the grip or trigger moves each frame while its key is held or released. Buttons take a few frames
+/// to travel so that applications see the analog values in between.
+const BUTTON_SPEED: f32 = 0.2;
+
+/// How far the head or a hand moves for each key press, in meters
+const MOVE_STEP: f32 = 0.05;
+/// How far the hands rotate for each unit of mouse movement, in radians
+const HAND_ROTATION_SPEED: f32 = 0.005;
+
+/// What the mouse and WASD keys are currently controlling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlTarget {
+    Head,
+    LeftHand,
+    RightHand,
+}
+
+impl ControlTarget {
+    pub fn from_held_keys(held_keys: &HashSet<VirtualKeyCode>) -> Self {
+        if held_keys.contains(&LEFT_HAND_MODIFIER) {
+            ControlTarget::LeftHand
+        } else if held_keys.contains(&RIGHT_HAND_MODIFIER) {
+            ControlTarget::RightHand
+        } else {
+            ControlTarget::Head
+        }
+    }
+
+    /// The name of the spaces moved by this target, if it is a hand
+    pub fn space_name(&self) -> Option<&'static str> {
+        match self {
+            ControlTarget::Head => None,
+            ControlTarget::LeftHand => Some("Left Hand"),
+            ControlTarget::RightHand => Some("Right Hand"),
+        }
+    }
+}
+
+/// Get the movement for a single key press, or `None` if the key doesn't move anything.
+/// W/S move forward and back, A/D move left and right and R/F move up and down.
+pub fn movement(key: VirtualKeyCode) -> Option<Vector3f> {
+    let (x, y, z) = match key {
+        VirtualKeyCode::W => (0., 0., -MOVE_STEP),
+        VirtualKeyCode::S => (0., 0., MOVE_STEP),
+        VirtualKeyCode::A => (-MOVE_STEP, 0., 0.),
+        VirtualKeyCode::D => (MOVE_STEP, 0., 0.),
+        VirtualKeyCode::R => (0., MOVE_STEP, 0.),
+        VirtualKeyCode::F => (0., -MOVE_STEP, 0.),
+        _ => return None,
+    };
+    Some(Vector3f { x, y, z })
+}
+
+/// Rotate a hand by some mouse movement: horizontal movement turns the hand around the world's up axis, and
+/// vertical movement tilts it around its own X axis.
+pub fn rotate_hand(orientation: Quaternionf, x: f64, y: f64) -> Quaternionf {
+    let orientation = Quaternion::new(orientation.w, orientation.x, orientation.y, orientation.z);
+    let yaw = Quaternion::from_angle_y(Rad(-y as f32 * HAND_ROTATION_SPEED));
+    let pitch = Quaternion::from_angle_x(Rad(-x as f32 * HAND_ROTATION_SPEED));
+    let rotated = yaw * orientation * pitch;
+
+    Quaternionf {
+        x: rotated.v.x,
+        y: rotated.v.y,
+        z: rotated.v.z,
+        w: rotated.s,
+    }
+}
+
+/// Move a button's value one frame closer to fully pressed or fully released.
+pub fn update_button(value: f32, pressed: bool) -> f32 {
+    if pressed {
+        (value + BUTTON_SPEED).min(1.)
+    } else {
+        (value - BUTTON_SPEED).max(0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_control_target() {
+        let mut held_keys = HashSet::new();
+        assert_eq!(
+            ControlTarget::from_held_keys(&held_keys),
+            ControlTarget::Head
+        );
+
+        held_keys.insert(RIGHT_HAND_MODIFIER);
+        assert_eq!(
+            ControlTarget::from_held_keys(&held_keys),
+            ControlTarget::RightHand
+        );
+
+        held_keys.insert(LEFT_HAND_MODIFIER);
+        assert_eq!(
+            ControlTarget::from_held_keys(&held_keys),
+            ControlTarget::LeftHand
+        );
+    }
+
+    #[test]
+    pub fn test_update_button() {
+        let mut value = 0.;
+        for _ in 0..3 {
+            value = update_button(value, true);
+        }
+        assert!(value > 0. && value < 1.);
+
+        for _ in 0..10 {
+            value = update_button(value, true);
+        }
+        assert_eq!(value, 1.);
+
+        for _ in 0..10 {
+            value = update_button(value, false);
+        }
+        assert_eq!(value, 0.);
+    }
+}
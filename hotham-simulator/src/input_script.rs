@@ -0,0 +1,226 @@
+use openxr_sys::{Posef, Quaternionf, Vector3f};
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Set this to the path of an input script to replay it instead of using the keyboard and mouse.
+pub const PLAYBACK_ENV_VAR: &str = "HOTHAM_SIMULATOR_PLAYBACK";
+
+/// Set this to a path to record the input of a live session to it.
+pub const RECORD_ENV_VAR: &str = "HOTHAM_SIMULATOR_RECORD";
+
+/// Number of values on each line of an input script.
+const VALUES_PER_FRAME: usize = 1 + 7 * 3 + 4;
+
+const HEADER: &str = "# Hotham Simulator input script\n\
+# time head(px py pz qx qy qz qw) left_hand(px py pz qx qy qz qw) right_hand(px py pz qx qy qz qw) left_grip left_trigger right_grip right_trigger\n";
+
+/// A snapshot of all the simulated input at a point in time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputFrame {
+    /// Seconds since the session began
+    pub time: f64,
+    pub head: Posef,
+    pub left_hand: Posef,
+    pub right_hand: Posef,
+    pub left_grip: f32,
+    pub left_trigger: f32,
+    pub right_grip: f32,
+    pub right_trigger: f32,
+}
+
+impl InputFrame {
+    fn parse(line: &str) -> Result<Self, String> {
+        let values = line
+            .split_whitespace()
+            .map(|v| v.parse::<f64>().map_err(|e| format!("{:?}: {}", v, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if values.len() != VALUES_PER_FRAME {
+            return Err(format!(
+                "expected {} values, found {}",
+                VALUES_PER_FRAME,
+                values.len()
+            ));
+        }
+
+        let pose = |offset: usize| {
+            let v = &values[offset..offset + 7];
+            Posef {
+                position: Vector3f {
+                    x: v[0] as _,
+                    y: v[1] as _,
+                    z: v[2] as _,
+                },
+                orientation: Quaternionf {
+                    x: v[3] as _,
+                    y: v[4] as _,
+                    z: v[5] as _,
+                    w: v[6] as _,
+                },
+            }
+        };
+
+        Ok(Self {
+            time: values[0],
+            head: pose(1),
+            left_hand: pose(8),
+            right_hand: pose(15),
+            left_grip: values[22] as _,
+            left_trigger: values[23] as _,
+            right_grip: values[24] as _,
+            right_trigger: values[25] as _,
+        })
+    }
+
+    fn write_line(&self, out: &mut String) {
+        write!(out, "{}", self.time).unwrap();
+        for pose in [&self.head, &self.left_hand, &self.right_hand] {
+            let (p, o) = (pose.position, pose.orientation);
+            write!(
+                out,
+                " {} {} {} {} {} {} {}",
+                p.x, p.y, p.z, o.x, o.y, o.z, o.w
+            )
+            .unwrap();
+        }
+        writeln!(
+            out,
+            " {} {} {} {}",
+            self.left_grip, self.left_trigger, self.right_grip, self.right_trigger
+        )
+        .unwrap();
+    }
+}
+
+/// A recorded sequence of input, ordered by time.
+///
+/// Scripts are plain text, one frame per line. Blank lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputScript {
+    pub frames: Vec<InputFrame>,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read input script {:?}: {}", path, e))?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut frames = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let frame =
+                InputFrame::parse(line).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            if frames
+                .last()
+                .map(|f: &InputFrame| f.time > frame.time)
+                .unwrap_or(false)
+            {
+                return Err(format!("line {}: frames must be in time order", number + 1));
+            }
+            frames.push(frame);
+        }
+
+        Ok(Self { frames })
+    }
+
+    /// Get the input at `time`: the last frame recorded at or before it. Before the first frame, the first frame
+    /// is used, and after the last frame the last frame is held.
+    pub fn sample(&self, time: f64) -> Option<&InputFrame> {
+        let next = self.frames.partition_point(|f| f.time <= time);
+        self.frames.get(next.saturating_sub(1))
+    }
+}
+
+impl std::fmt::Display for InputScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut out = HEADER.to_string();
+        for frame in &self.frames {
+            frame.write_line(&mut out);
+        }
+        f.write_str(&out)
+    }
+}
+
+/// Writes input frames to a file as they happen, in the same format that `InputScript` reads.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    pub fn create(path: &Path) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(HEADER.as_bytes())?;
+        Ok(Self { writer })
+    }
+
+    pub fn record(&mut self, frame: &InputFrame) -> std::io::Result<()> {
+        let mut line = String::new();
+        frame.write_line(&mut line);
+        self.writer.write_all(line.as_bytes())?;
+
+        // The simulator is usually torn down by the process exiting, so don't leave anything in the buffer.
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(time: f64, grip: f32) -> InputFrame {
+        let mut head = Posef::IDENTITY;
+        head.position.y = 1.4;
+        InputFrame {
+            time,
+            head,
+            left_hand: Posef::IDENTITY,
+            right_hand: Posef::IDENTITY,
+            left_grip: grip,
+            left_trigger: 0.,
+            right_grip: 0.,
+            right_trigger: 0.25,
+        }
+    }
+
+    #[test]
+    pub fn test_round_trip() {
+        let script = InputScript {
+            frames: vec![frame(0., 0.), frame(0.5, 1.0)],
+        };
+        let parsed = InputScript::parse(&script.to_string()).unwrap();
+        assert_eq!(parsed, script);
+    }
+
+    #[test]
+    pub fn test_sample() {
+        let script = InputScript {
+            frames: vec![frame(0.1, 0.), frame(0.5, 1.0)],
+        };
+        assert_eq!(script.sample(0.).unwrap().left_grip, 0.);
+        assert_eq!(script.sample(0.4).unwrap().left_grip, 0.);
+        assert_eq!(script.sample(0.5).unwrap().left_grip, 1.);
+        assert_eq!(script.sample(10.).unwrap().left_grip, 1.);
+        assert!(InputScript::default().sample(0.).is_none());
+    }
+
+    #[test]
+    pub fn test_parse_errors() {
+        assert!(InputScript::parse("0 1 2").is_err());
+        let out_of_order = InputScript {
+            frames: vec![frame(1., 0.), frame(0., 0.)],
+        };
+        assert!(InputScript::parse(&out_of_order.to_string()).is_err());
+        assert_eq!(InputScript::parse("# nothing\n\n").unwrap().frames.len(), 0);
+    }
+}
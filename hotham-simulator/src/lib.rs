@@ -1,6 +1,12 @@
 // TODO Safety doc would be nice
 #![allow(clippy::missing_safety_doc)]
 
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub mod action_state;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub mod controls;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+pub mod input_script;
 #[cfg(any(target_os = "windows", target_os = "linux"))]
 pub mod openxr_loader;
 #[cfg(any(target_os = "windows", target_os = "linux"))]
@@ -147,6 +153,8 @@ pub unsafe extern "C" fn get_instance_proc_addr(
         *function = transmute::<pfn::GetActionStateFloat, _>(get_action_state_float);
     } else if name == b"xrGetActionStateBoolean" {
         *function = transmute::<pfn::GetActionStateBoolean, _>(get_action_state_boolean);
+    } else if name == b"xrGetActionStateVector2f" {
+        *function = transmute::<pfn::GetActionStateVector2f, _>(get_action_state_vector2f);
     } else if name == b"xrEndSession" {
         *function = transmute::<pfn::EndSession, _>(end_session);
     } else {
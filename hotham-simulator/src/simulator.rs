@@ -4,6 +4,7 @@
     non_upper_case_globals,
     non_camel_case_types
 )]
+use crate::action_state::SimulatedAction;
 use crate::openxr_loader::{self, XrExtensionProperties, XrResult};
 use crate::space_state::SpaceState;
 use crate::state::State;
@@ -19,18 +20,18 @@ use openxr_sys::GraphicsBindingVulkanKHR;
 use openxr_sys::{
     platform::{VkDevice, VkInstance, VkPhysicalDevice, VkResult},
     Action, ActionCreateInfo, ActionSet, ActionSetCreateInfo, ActionSpaceCreateInfo,
-    ActionStateBoolean, ActionStateFloat, ActionStateGetInfo, ActionStatePose, ActionsSyncInfo,
-    Duration, EnvironmentBlendMode, EventDataBuffer, EventDataSessionStateChanged, Fovf,
-    FrameBeginInfo, FrameEndInfo, FrameState, FrameWaitInfo, GraphicsRequirementsVulkanKHR,
-    Instance, InstanceCreateInfo, InstanceProperties, InteractionProfileSuggestedBinding, Path,
-    Posef, Quaternionf, ReferenceSpaceCreateInfo, ReferenceSpaceType, Result, Session,
-    SessionActionSetsAttachInfo, SessionBeginInfo, SessionCreateInfo, SessionState, Space,
-    SpaceLocation, SpaceLocationFlags, StructureType, Swapchain, SwapchainCreateInfo,
-    SwapchainImageAcquireInfo, SwapchainImageBaseHeader, SwapchainImageReleaseInfo,
-    SwapchainImageVulkanKHR, SwapchainImageWaitInfo, SystemGetInfo, SystemId, SystemProperties,
-    Time, Vector3f, Version, View, ViewConfigurationType, ViewConfigurationView, ViewLocateInfo,
-    ViewState, ViewStateFlags, VulkanDeviceCreateInfoKHR, VulkanGraphicsDeviceGetInfoKHR,
-    VulkanInstanceCreateInfoKHR, FALSE, TRUE,
+    ActionStateBoolean, ActionStateFloat, ActionStateGetInfo, ActionStatePose, ActionStateVector2f,
+    ActionType, ActionsSyncInfo, EnvironmentBlendMode, EventDataBuffer,
+    EventDataSessionStateChanged, Fovf, FrameBeginInfo, FrameEndInfo, FrameState, FrameWaitInfo,
+    GraphicsRequirementsVulkanKHR, Instance, InstanceCreateInfo, InstanceProperties,
+    InteractionProfileSuggestedBinding, Path, Quaternionf, ReferenceSpaceCreateInfo,
+    ReferenceSpaceType, Result, Session, SessionActionSetsAttachInfo, SessionBeginInfo,
+    SessionCreateInfo, SessionState, Space, SpaceLocation, SpaceLocationFlags, StructureType,
+    Swapchain, SwapchainCreateInfo, SwapchainImageAcquireInfo, SwapchainImageBaseHeader,
+    SwapchainImageReleaseInfo, SwapchainImageVulkanKHR, SwapchainImageWaitInfo, SystemGetInfo,
+    SystemId, SystemProperties, Time, Vector2f, Vector3f, Version, View, ViewConfigurationType,
+    ViewConfigurationView, ViewLocateInfo, ViewState, ViewStateFlags, VulkanDeviceCreateInfoKHR,
+    VulkanGraphicsDeviceGetInfoKHR, VulkanInstanceCreateInfoKHR, TRUE,
 };
 use rand::random;
 use std::{
@@ -45,7 +46,7 @@ use std::{
     sync::{atomic::Ordering::Relaxed, mpsc::channel, Mutex, MutexGuard},
     thread,
 };
-use winit::event::{DeviceEvent, VirtualKeyCode};
+use winit::event::{DeviceEvent, ElementState, VirtualKeyCode};
 
 #[cfg(any(target_os = "windows", target_os = "linux"))]
 use winit::{
@@ -66,6 +67,8 @@ static SWAPCHAIN_COLOR_FORMAT: vk::Format = vk::Format::B8G8R8A8_SRGB;
 pub const NUM_VIEWS: usize = 2; // TODO: Make dynamic
 pub const VIEWPORT_HEIGHT: u32 = 1000;
 pub const VIEWPORT_WIDTH: u32 = 1000;
+/// The simulator pretends to run at 90Hz
+pub const FRAME_PERIOD_NANOS: i64 = 11_111_111;
 
 lazy_static! {
     static ref STATE: Mutex<State> = Default::default();
@@ -78,8 +81,14 @@ struct HothamSession {
 
 #[derive(Debug, Clone)]
 pub enum HothamInputEvent {
-    KeyboardInput { key: Option<VirtualKeyCode> },
-    MouseInput { x: f64, y: f64 },
+    KeyboardInput {
+        key: Option<VirtualKeyCode>,
+        state: ElementState,
+    },
+    MouseInput {
+        x: f64,
+        y: f64,
+    },
 }
 
 #[no_mangle]
@@ -120,6 +129,7 @@ pub unsafe extern "system" fn create_instance(
     instance: *mut Instance,
 ) -> Result {
     *instance = Instance::from_raw(42);
+    STATE.lock().unwrap().configure_input();
 
     Result::SUCCESS
 }
@@ -703,24 +713,53 @@ pub unsafe extern "system" fn create_action_set(
 
 pub unsafe extern "system" fn create_action(
     _action_set: ActionSet,
-    _create_info: *const ActionCreateInfo,
+    create_info: *const ActionCreateInfo,
     action_out: *mut Action,
 ) -> Result {
-    *action_out = Action::from_raw(random());
+    let create_info = *create_info;
+    let name = CStr::from_ptr(create_info.action_name.as_ptr())
+        .to_string_lossy()
+        .to_string();
+    let subaction_paths = slice::from_raw_parts(
+        create_info.subaction_paths,
+        create_info.count_subaction_paths as _,
+    )
+    .to_vec();
+
+    let raw = random();
+    STATE.lock().unwrap().actions.insert(
+        raw,
+        SimulatedAction::new(name, create_info.action_type, subaction_paths),
+    );
+    *action_out = Action::from_raw(raw);
     Result::SUCCESS
 }
 
 pub unsafe extern "system" fn suggest_interaction_profile_bindings(
     _instance: Instance,
-    _suggested_bindings: *const InteractionProfileSuggestedBinding,
+    suggested_bindings: *const InteractionProfileSuggestedBinding,
 ) -> Result {
-    // let suggested_bindings = *suggested_bindings;
-    // let bindings = slice::from_raw_parts(
-    //     (suggested_bindings).suggested_bindings,
-    //     (suggested_bindings).count_suggested_bindings as _,
-    // );
+    let suggested_bindings = *suggested_bindings;
+    let bindings = slice::from_raw_parts(
+        suggested_bindings.suggested_bindings,
+        suggested_bindings.count_suggested_bindings as _,
+    );
+
+    let mut state = STATE.lock().unwrap();
+
+    // Don't apply any of the bindings if one of them is invalid
+    if bindings
+        .iter()
+        .any(|b| !state.actions.contains_key(&b.action.into_raw()))
+    {
+        return Result::ERROR_HANDLE_INVALID;
+    }
 
-    // for binding in bindings {}
+    for binding in bindings {
+        if let Some(action) = state.actions.get_mut(&binding.action.into_raw()) {
+            action.bindings.push(binding.binding);
+        }
+    }
 
     Result::SUCCESS
 }
@@ -920,7 +959,7 @@ pub unsafe extern "system" fn wait_frame(
     _frame_wait_info: *const FrameWaitInfo,
     frame_state: *mut FrameState,
 ) -> Result {
-    let state = STATE.lock().unwrap();
+    let mut state = STATE.lock().unwrap();
     let _device = state.device.as_ref().unwrap();
     let _fence = state.swapchain_fence;
 
@@ -928,8 +967,8 @@ pub unsafe extern "system" fn wait_frame(
     *frame_state = FrameState {
         ty: StructureType::FRAME_STATE,
         next: ptr::null_mut(),
-        predicted_display_time: Time::from_nanos(1),
-        predicted_display_period: Duration::from_nanos(1),
+        predicted_display_time: state.advance_clock(),
+        predicted_display_period: State::frame_period(),
         should_render: TRUE,
     };
     Result::SUCCESS
@@ -1130,6 +1169,7 @@ unsafe fn build_swapchain(state: &mut MutexGuard<State>) -> vk::SwapchainKHR {
                     DeviceEvent::Key(k) => event_tx
                         .send(HothamInputEvent::KeyboardInput {
                             key: k.virtual_keycode,
+                            state: k.state,
                         })
                         .unwrap(),
                     DeviceEvent::MouseMotion { delta: (y, x) } => event_tx
@@ -1479,16 +1519,14 @@ pub unsafe extern "system" fn dummy() -> Result {
 
 pub unsafe extern "system" fn locate_space(
     space: Space,
-    _base_space: Space,
+    base_space: Space,
     _time: Time,
     location_out: *mut SpaceLocation,
 ) -> Result {
-    match STATE.lock().unwrap().spaces.get(&space.into_raw()) {
+    let state = STATE.lock().unwrap();
+    match state.spaces.get(&space.into_raw()) {
         Some(space_state) => {
-            let pose = Posef {
-                position: space_state.position,
-                orientation: space_state.orientation,
-            };
+            let pose = state.locate(space_state.pose(), base_space);
             *location_out = SpaceLocation {
                 ty: StructureType::SPACE_LOCATION,
                 next: null_mut(),
@@ -1519,14 +1557,16 @@ pub unsafe extern "system" fn sync_actions(
     _session: Session,
     _sync_info: *const ActionsSyncInfo,
 ) -> Result {
-    STATE.lock().unwrap().update_actions();
+    let mut state = STATE.lock().unwrap();
+    state.sync_input();
+    state.sync_actions();
 
     Result::SUCCESS
 }
 
 pub unsafe extern "system" fn locate_views(
     _session: Session,
-    _view_locate_info: *const ViewLocateInfo,
+    view_locate_info: *const ViewLocateInfo,
     view_state: *mut ViewState,
     view_capacity_input: u32,
     view_count_output: *mut u32,
@@ -1547,7 +1587,7 @@ pub unsafe extern "system" fn locate_views(
     let state = STATE.lock().unwrap();
     #[allow(clippy::approx_constant)]
     for (i, view) in views.iter_mut().enumerate() {
-        let pose = state.view_poses[i];
+        let pose = state.locate(state.view_poses[i], (*view_locate_info).space);
         *view = View {
             ty: StructureType::VIEW,
             next: null_mut(),
@@ -1641,11 +1681,17 @@ pub unsafe extern "system" fn request_exit_session(_session: Session) -> Result
     Result::SUCCESS
 }
 
-pub unsafe extern "system" fn destroy_space(_space: Space) -> Result {
+pub unsafe extern "system" fn destroy_space(space: Space) -> Result {
+    let mut state = STATE.lock().unwrap();
+    // The stage reference space is shared by every stage space with a rotation, so keep it around.
+    if space != state.reference_space {
+        state.spaces.remove(&space.into_raw());
+    }
     Result::SUCCESS
 }
 
-pub unsafe extern "system" fn destroy_action(_action: Action) -> Result {
+pub unsafe extern "system" fn destroy_action(action: Action) -> Result {
+    STATE.lock().unwrap().actions.remove(&action.into_raw());
     Result::SUCCESS
 }
 
@@ -1720,16 +1766,26 @@ pub unsafe extern "system" fn enumerate_swapchain_formats(
 
 pub unsafe extern "system" fn get_action_state_float(
     _session: Session,
-    _get_info: *const ActionStateGetInfo,
+    get_info: *const ActionStateGetInfo,
     state: *mut ActionStateFloat,
 ) -> Result {
+    let get_info = *get_info;
+    let value = match STATE.lock().unwrap().get_action_state(
+        get_info.action,
+        ActionType::FLOAT_INPUT,
+        get_info.subaction_path,
+    ) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+
     *state = ActionStateFloat {
         ty: StructureType::ACTION_STATE_FLOAT,
         next: ptr::null_mut(),
-        current_state: 0.0,
-        changed_since_last_sync: FALSE,
-        last_change_time: openxr_sys::Time::from_nanos(0),
-        is_active: TRUE,
+        current_state: value.current_state,
+        changed_since_last_sync: value.changed_since_last_sync.into(),
+        last_change_time: value.last_change_time,
+        is_active: value.is_active.into(),
     };
     Result::SUCCESS
 }
@@ -1744,16 +1800,53 @@ pub unsafe extern "system" fn end_session(_session: Session) -> Result {
 
 pub unsafe extern "system" fn get_action_state_boolean(
     _session: Session,
-    _get_info: *const ActionStateGetInfo,
+    get_info: *const ActionStateGetInfo,
     state: *mut ActionStateBoolean,
 ) -> Result {
+    let get_info = *get_info;
+    let value = match STATE.lock().unwrap().get_action_state(
+        get_info.action,
+        ActionType::BOOLEAN_INPUT,
+        get_info.subaction_path,
+    ) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+
     *state = ActionStateBoolean {
         ty: StructureType::ACTION_STATE_BOOLEAN,
         next: ptr::null_mut(),
-        current_state: TRUE,
-        changed_since_last_sync: FALSE,
-        last_change_time: openxr_sys::Time::from_nanos(0),
-        is_active: TRUE,
+        current_state: (value.current_state != 0.).into(),
+        changed_since_last_sync: value.changed_since_last_sync.into(),
+        last_change_time: value.last_change_time,
+        is_active: value.is_active.into(),
+    };
+    Result::SUCCESS
+}
+
+pub unsafe extern "system" fn get_action_state_vector2f(
+    _session: Session,
+    get_info: *const ActionStateGetInfo,
+    state: *mut ActionStateVector2f,
+) -> Result {
+    let get_info = *get_info;
+    let value = match STATE.lock().unwrap().get_action_state(
+        get_info.action,
+        ActionType::VECTOR2F_INPUT,
+        get_info.subaction_path,
+    ) {
+        Ok(value) => value,
+        Err(e) => return e,
+    };
+
+    // There are no simulated thumbsticks yet, so these actions are always centred.
+    *state = ActionStateVector2f {
+        ty: StructureType::ACTION_STATE_VECTOR2F,
+        next: ptr::null_mut(),
+        current_state: Vector2f { x: 0., y: 0. },
+        changed_since_last_sync: value.changed_since_last_sync.into(),
+        last_change_time: value.last_change_time,
+        is_active: value.is_active.into(),
     };
     Result::SUCCESS
 }
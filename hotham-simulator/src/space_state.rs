@@ -1,5 +1,6 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
 use core::fmt::Debug;
-use openxr_sys::{Quaternionf, Vector3f};
+use openxr_sys::{Posef, Quaternionf, Vector3f};
 
 #[derive(Clone)]
 pub struct SpaceState {
@@ -16,6 +17,37 @@ impl SpaceState {
             orientation: Quaternionf::IDENTITY,
         }
     }
+
+    pub fn pose(&self) -> Posef {
+        Posef {
+            position: self.position,
+            orientation: self.orientation,
+        }
+    }
+}
+
+/// Express `pose`, which is relative to the stage, relative to `base` instead.
+pub fn relative_pose(pose: Posef, base: Posef) -> Posef {
+    let to_quaternion = |q: Quaternionf| Quaternion::new(q.w, q.x, q.y, q.z).normalize();
+    let to_vector = |v: Vector3f| Vector3::new(v.x, v.y, v.z);
+
+    let base_rotation = to_quaternion(base.orientation).invert();
+    let position = base_rotation.rotate_vector(to_vector(pose.position) - to_vector(base.position));
+    let orientation = base_rotation * to_quaternion(pose.orientation);
+
+    Posef {
+        position: Vector3f {
+            x: position.x,
+            y: position.y,
+            z: position.z,
+        },
+        orientation: Quaternionf {
+            x: orientation.v.x,
+            y: orientation.v.y,
+            z: orientation.v.z,
+            w: orientation.s,
+        },
+    }
 }
 
 impl Debug for SpaceState {
@@ -39,3 +71,46 @@ impl Debug for SpaceState {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Rad, Rotation3};
+
+    #[test]
+    pub fn test_relative_pose() {
+        // A base space one meter forward and turned 90 degrees to the left.
+        let turn = Quaternion::from_angle_y(Rad(std::f32::consts::FRAC_PI_2));
+        let base = Posef {
+            position: Vector3f {
+                x: 0.,
+                y: 0.,
+                z: -1.,
+            },
+            orientation: Quaternionf {
+                x: turn.v.x,
+                y: turn.v.y,
+                z: turn.v.z,
+                w: turn.s,
+            },
+        };
+
+        // A point two meters forward is one meter to the base's right.
+        let pose = Posef {
+            position: Vector3f {
+                x: 0.,
+                y: 1.,
+                z: -2.,
+            },
+            orientation: base.orientation,
+        };
+        let relative = relative_pose(pose, base);
+        assert!((relative.position.x - 1.).abs() < 0.0001);
+        assert!((relative.position.y - 1.).abs() < 0.0001);
+        assert!(relative.position.z.abs() < 0.0001);
+        assert!((relative.orientation.w.abs() - 1.).abs() < 0.0001);
+
+        let unchanged = relative_pose(pose, Posef::IDENTITY);
+        assert_eq!(unchanged.position.z, -2.);
+    }
+}
@@ -4,11 +4,14 @@ use ash::{
     Device, Entry as AshEntry, Instance as AshInstance,
 };
 
-use openxr_sys::{Path, Posef, SessionState, Space, Vector3f};
+use openxr_sys::{
+    Action, ActionType, Duration, Path, Posef, Result, SessionState, Space, Time, Vector3f,
+};
 
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fmt::Debug,
+    path::Path as FilePath,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
         mpsc::Receiver,
@@ -17,9 +20,14 @@ use std::{
     thread::JoinHandle,
 };
 
+use winit::event::{ElementState, VirtualKeyCode};
+
 use crate::{
-    simulator::{HothamInputEvent, NUM_VIEWS},
-    space_state::SpaceState,
+    action_state::{ActionValue, SimulatedAction},
+    controls::{self, ControlTarget, GRIP_KEYS, TRIGGER_KEYS},
+    input_script::{InputFrame, InputRecorder, InputScript, PLAYBACK_ENV_VAR, RECORD_ENV_VAR},
+    simulator::{HothamInputEvent, FRAME_PERIOD_NANOS, NUM_VIEWS},
+    space_state::{relative_pose, SpaceState},
 };
 // use crate::simulator::spa
 pub struct State {
@@ -61,6 +69,15 @@ pub struct State {
     pub right_hand_space: u64,
     pub view_poses: Vec<Posef>,
     pub event_rx: Option<Receiver<HothamInputEvent>>,
+    pub predicted_display_time: Time,
+    pub actions: HashMap<u64, SimulatedAction>,
+    /// Squeeze values for the left and right hands
+    pub grip_values: [f32; 2],
+    /// Trigger values for the left and right hands
+    pub trigger_values: [f32; 2],
+    pub held_keys: HashSet<VirtualKeyCode>,
+    pub input_script: Option<InputScript>,
+    pub input_recorder: Option<InputRecorder>,
 }
 
 impl Default for State {
@@ -103,6 +120,13 @@ impl Default for State {
             left_hand_space: 0,
             right_hand_space: 0,
             event_rx: None,
+            predicted_display_time: Time::from_nanos(0),
+            actions: Default::default(),
+            grip_values: [0.; 2],
+            trigger_values: [0.; 2],
+            held_keys: Default::default(),
+            input_script: None,
+            input_recorder: None,
             view_poses: (0..NUM_VIEWS)
                 .map(|_| {
                     let mut pose = Posef::IDENTITY;
@@ -190,50 +214,238 @@ impl State {
         println!("[HOTHAM_SIMULATOR] All things are now destroyed");
     }
 
-    pub fn update_actions(&mut self) -> Option<()> {
-        let mut z_delta = 0.00;
-        let mut x_delta = 0.00;
-        let mut x_rot = 0.0;
-        let mut y_rot = 0.0;
-
-        match self.event_rx.as_ref()?.try_recv().ok()? {
-            HothamInputEvent::KeyboardInput { key } => match key? {
-                winit::event::VirtualKeyCode::W => {
-                    z_delta = -0.05;
-                }
-                winit::event::VirtualKeyCode::S => {
-                    z_delta = 0.05;
+    /// Check the environment to see if input should be played back from or recorded to a script.
+    pub fn configure_input(&mut self) {
+        if let Ok(path) = std::env::var(PLAYBACK_ENV_VAR) {
+            match InputScript::load(FilePath::new(&path)) {
+                Ok(script) => {
+                    println!(
+                        "[HOTHAM_SIMULATOR] Playing back {} input frames from {}",
+                        script.frames.len(),
+                        path
+                    );
+                    self.input_script = Some(script);
                 }
-                winit::event::VirtualKeyCode::A => {
-                    x_delta = -0.05;
+                Err(e) => eprintln!("[HOTHAM_SIMULATOR] Unable to load input script: {}", e),
+            }
+        }
+
+        if let Ok(path) = std::env::var(RECORD_ENV_VAR) {
+            match InputRecorder::create(FilePath::new(&path)) {
+                Ok(recorder) => {
+                    println!("[HOTHAM_SIMULATOR] Recording input to {}", path);
+                    self.input_recorder = Some(recorder);
                 }
-                winit::event::VirtualKeyCode::D => {
-                    x_delta = 0.05;
+                Err(e) => eprintln!(
+                    "[HOTHAM_SIMULATOR] Unable to record input to {}: {}",
+                    path, e
+                ),
+            }
+        }
+    }
+
+    /// Advance the simulated clock by a single frame. The clock doesn't depend on how long frames actually take,
+    /// so that scripts play back the same way every time.
+    pub fn advance_clock(&mut self) -> Time {
+        self.predicted_display_time =
+            Time::from_nanos(self.predicted_display_time.as_nanos() + FRAME_PERIOD_NANOS);
+        self.predicted_display_time
+    }
+
+    pub fn frame_period() -> Duration {
+        Duration::from_nanos(FRAME_PERIOD_NANOS)
+    }
+
+    /// Update the simulated input, either from the input script or the keyboard and mouse, then record it if
+    /// we've been asked to.
+    pub fn sync_input(&mut self) {
+        let time = self.predicted_display_time.as_nanos() as f64 / 1_000_000_000.;
+        let scripted = self
+            .input_script
+            .as_ref()
+            .and_then(|s| s.sample(time))
+            .copied();
+
+        match scripted {
+            Some(frame) => self.apply_input(&frame),
+            None => self.update_actions(),
+        }
+
+        if self.input_recorder.is_none() {
+            return;
+        }
+
+        let frame = self.current_input(time);
+        if let Err(e) = self.input_recorder.as_mut().unwrap().record(&frame) {
+            eprintln!("[HOTHAM_SIMULATOR] Unable to record input: {}", e);
+            self.input_recorder = None;
+        }
+    }
+
+    pub fn current_input(&self, time: f64) -> InputFrame {
+        InputFrame {
+            time,
+            head: self.view_poses[0],
+            left_hand: self.hand_pose(self.left_hand_space),
+            right_hand: self.hand_pose(self.right_hand_space),
+            left_grip: self.grip_values[0],
+            left_trigger: self.trigger_values[0],
+            right_grip: self.grip_values[1],
+            right_trigger: self.trigger_values[1],
+        }
+    }
+
+    pub fn apply_input(&mut self, frame: &InputFrame) {
+        for pose in self.view_poses.iter_mut() {
+            *pose = frame.head;
+        }
+
+        for (name, pose) in [
+            ("Left Hand", frame.left_hand),
+            ("Right Hand", frame.right_hand),
+        ] {
+            for space in self.hand_spaces_mut(name) {
+                space.position = pose.position;
+                space.orientation = pose.orientation;
+            }
+        }
+
+        self.grip_values = [frame.left_grip, frame.right_grip];
+        self.trigger_values = [frame.left_trigger, frame.right_trigger];
+    }
+
+    /// Get the current value of an input path, or `None` if it isn't an input the simulator knows how to simulate.
+    pub fn input_value(&self, binding: &str) -> Option<f32> {
+        let (index, input) = if let Some(input) = binding.strip_prefix("/user/hand/left/input/") {
+            (0, input)
+        } else {
+            (1, binding.strip_prefix("/user/hand/right/input/")?)
+        };
+
+        match input {
+            "squeeze/value" | "squeeze/click" => Some(self.grip_values[index]),
+            "trigger/value" | "trigger/click" => Some(self.trigger_values[index]),
+            _ => None,
+        }
+    }
+
+    /// Update the state of every action from the current input
+    pub fn sync_actions(&mut self) {
+        let time = self.predicted_display_time;
+        let mut actions = std::mem::take(&mut self.actions);
+        for action in actions.values_mut() {
+            action.sync(time, &self.paths, |binding| self.input_value(binding));
+        }
+        self.actions = actions;
+    }
+
+    pub fn get_action_state(
+        &self,
+        action: Action,
+        action_type: ActionType,
+        subaction_path: Path,
+    ) -> std::result::Result<ActionValue, Result> {
+        self.actions
+            .get(&action.into_raw())
+            .ok_or(Result::ERROR_HANDLE_INVALID)?
+            .get(action_type, subaction_path)
+    }
+
+    /// Express a pose relative to `base_space`. Reference spaces created with a pose, eg. for locomotion, move
+    /// everything located in them.
+    pub fn locate(&self, pose: Posef, base_space: Space) -> Posef {
+        match self.spaces.get(&base_space.into_raw()) {
+            Some(base) => relative_pose(pose, base.pose()),
+            None => pose,
+        }
+    }
+
+    fn hand_pose(&self, space: u64) -> Posef {
+        self.spaces
+            .get(&space)
+            .map(|s| Posef {
+                position: s.position,
+                orientation: s.orientation,
+            })
+            .unwrap_or(Posef::IDENTITY)
+    }
+
+    /// Handle any keyboard and mouse input since the last sync. Holding a modifier key moves one of the hands
+    /// instead of the head, and the grip and trigger keys work on either hand at any time.
+    pub fn update_actions(&mut self) {
+        let events = match self.event_rx.as_ref() {
+            Some(event_rx) => event_rx.try_iter().collect::<Vec<_>>(),
+            None => return,
+        };
+
+        for event in events {
+            match event {
+                HothamInputEvent::KeyboardInput {
+                    key: Some(key),
+                    state,
+                } => {
+                    if state == ElementState::Released {
+                        self.held_keys.remove(&key);
+                        continue;
+                    }
+                    self.held_keys.insert(key);
+                    if let Some(delta) = controls::movement(key) {
+                        self.move_target(delta);
+                    }
                 }
+                HothamInputEvent::MouseInput { x, y } => self.rotate_target(x, y),
                 _ => {}
-            },
-            HothamInputEvent::MouseInput { x, y } => {
-                x_rot = -(x * 0.001) as _;
-                y_rot = -(y * 0.001) as _;
             }
         }
 
-        let pose = &mut self.view_poses[0];
-        let orientation = &mut pose.orientation;
-        orientation.x = (orientation.x + x_rot).clamp(-1.0, 1.0);
-        orientation.y = (orientation.y + y_rot).clamp(-1.0, 1.0);
+        for hand in 0..2 {
+            self.grip_values[hand] = controls::update_button(
+                self.grip_values[hand],
+                self.held_keys.contains(&GRIP_KEYS[hand]),
+            );
+            self.trigger_values[hand] = controls::update_button(
+                self.trigger_values[hand],
+                self.held_keys.contains(&TRIGGER_KEYS[hand]),
+            );
+        }
+    }
+
+    fn move_target(&mut self, delta: Vector3f) {
+        let target = ControlTarget::from_held_keys(&self.held_keys);
+        let positions: Vec<&mut Vector3f> = match target.space_name() {
+            Some(name) => self
+                .hand_spaces_mut(name)
+                .map(|s| &mut s.position)
+                .collect(),
+            None => vec![&mut self.view_poses[0].position],
+        };
 
-        let position = &mut pose.position;
-        position.z += z_delta;
-        position.x += x_delta;
+        for position in positions {
+            position.x += delta.x;
+            position.y += delta.y;
+            position.z += delta.z;
+        }
+    }
 
-        // let left_hand = self.left_hand_space;
-        // let right_hand = self.right_hand_space;
-        // self.spaces.get_mut(&left_hand).unwrap().position.z += z_delta;
-        // self.spaces.get_mut(&left_hand).unwrap().position.x += x_delta;
-        // self.spaces.get_mut(&right_hand).unwrap().position.z += z_delta;
-        // self.spaces.get_mut(&right_hand).unwrap().position.x += x_delta;
+    fn rotate_target(&mut self, x: f64, y: f64) {
+        let target = ControlTarget::from_held_keys(&self.held_keys);
+        if let Some(name) = target.space_name() {
+            for space in self.hand_spaces_mut(name) {
+                space.orientation = controls::rotate_hand(space.orientation, x, y);
+            }
+            return;
+        }
+
+        let orientation = &mut self.view_poses[0].orientation;
+        orientation.x = (orientation.x - (x * 0.001) as f32).clamp(-1.0, 1.0);
+        orientation.y = (orientation.y - (y * 0.001) as f32).clamp(-1.0, 1.0);
+    }
 
-        Some(())
+    /// Every action space for a hand (eg. grip and aim) shares the same pose.
+    fn hand_spaces_mut<'a>(
+        &'a mut self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a mut SpaceState> + 'a {
+        self.spaces.values_mut().filter(move |s| s.name == name)
     }
 }
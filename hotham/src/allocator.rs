@@ -0,0 +1,287 @@
+use std::{collections::HashMap, ffi::c_void, ops::Range};
+
+use anyhow::Result;
+use ash::{vk, Device};
+
+/// Size of the blocks of device memory that resources are suballocated from
+pub const BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+/// A piece of device memory, suballocated from a block by the `Allocator`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Allocation {
+    /// The block of memory this allocation is in
+    pub memory: vk::DeviceMemory,
+    /// Where the allocation starts in `memory`
+    pub offset: vk::DeviceSize,
+    /// The size of the allocation
+    pub size: vk::DeviceSize,
+    pool: PoolKey,
+    block: usize,
+}
+
+/// Blocks are kept apart by memory type, and images are kept apart from buffers so neighbours never have to
+/// respect `bufferImageGranularity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+struct PoolKey {
+    memory_type_index: u32,
+    is_image: bool,
+}
+
+/// Hands out pieces of a few large blocks of device memory, instead of calling `vkAllocateMemory` for every buffer
+/// and image. Host visible blocks stay mapped for as long as they're alive.
+#[derive(Debug, Default)]
+pub(crate) struct Allocator {
+    pools: HashMap<PoolKey, Vec<Option<Block>>>,
+}
+
+#[derive(Debug)]
+struct Block {
+    memory: vk::DeviceMemory,
+    free: FreeList,
+    mapped: Option<MappedMemory>,
+    /// Dedicated blocks hold a single allocation that was too big to share a block
+    dedicated: bool,
+}
+
+#[derive(Debug)]
+struct MappedMemory(*mut c_void);
+
+// SAFETY: The pointer is only ever used while the `Allocator` is locked.
+unsafe impl Send for MappedMemory {}
+
+impl Allocator {
+    pub fn allocate(
+        &mut self,
+        device: &Device,
+        requirements: vk::MemoryRequirements,
+        memory_type_index: u32,
+        is_image: bool,
+        host_visible: bool,
+    ) -> Result<Allocation> {
+        let pool_key = PoolKey {
+            memory_type_index,
+            is_image,
+        };
+        let pool = self.pools.entry(pool_key).or_default();
+        let size = requirements.size;
+
+        // Look for space in the blocks we already have
+        if size <= BLOCK_SIZE / 2 {
+            for (i, block) in pool.iter_mut().enumerate() {
+                let block = match block {
+                    Some(b) if !b.dedicated => b,
+                    _ => continue,
+                };
+                if let Some(range) = block.free.allocate(size, requirements.alignment) {
+                    return Ok(Allocation {
+                        memory: block.memory,
+                        offset: range.start,
+                        size,
+                        pool: pool_key,
+                        block: i,
+                    });
+                }
+            }
+        }
+
+        // Otherwise make a new block, with this allocation at the start
+        let dedicated = size > BLOCK_SIZE / 2;
+        let block_size = if dedicated { size } else { BLOCK_SIZE };
+        println!(
+            "[HOTHAM_ALLOCATOR] Allocating a block of {} bytes from memory type {}",
+            block_size, memory_type_index
+        );
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .memory_type_index(memory_type_index)
+            .allocation_size(block_size);
+        let memory = unsafe { device.allocate_memory(&allocate_info, None) }?;
+        let mapped = if host_visible {
+            let ptr = unsafe {
+                device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty())
+            }?;
+            Some(MappedMemory(ptr))
+        } else {
+            None
+        };
+
+        let mut free = FreeList::new(block_size);
+        free.allocate(size, 1);
+        let block = Block {
+            memory,
+            free,
+            mapped,
+            dedicated,
+        };
+
+        // Reuse an empty slot, so existing allocations keep their block index
+        let i = match pool.iter().position(Option::is_none) {
+            Some(i) => {
+                pool[i] = Some(block);
+                i
+            }
+            None => {
+                pool.push(Some(block));
+                pool.len() - 1
+            }
+        };
+
+        Ok(Allocation {
+            memory,
+            offset: 0,
+            size,
+            pool: pool_key,
+            block: i,
+        })
+    }
+
+    /// Give the allocation's memory back. Empty blocks are freed, unless they're the only block of their kind.
+    pub fn free(&mut self, device: &Device, allocation: &Allocation) {
+        let pool = match self.pools.get_mut(&allocation.pool) {
+            Some(p) => p,
+            None => return,
+        };
+        let blocks_in_pool = pool.iter().filter(|b| b.is_some()).count();
+        let slot = &mut pool[allocation.block];
+        let block = match slot {
+            Some(b) if b.memory == allocation.memory => b,
+            _ => return,
+        };
+
+        block
+            .free
+            .release(allocation.offset..allocation.offset + allocation.size);
+        if block.free.is_empty() && (block.dedicated || blocks_in_pool > 1) {
+            // Freeing the memory also unmaps it
+            unsafe { device.free_memory(block.memory, None) };
+            *slot = None;
+        }
+    }
+
+    /// Where the allocation can be written to, if it is in host visible memory
+    pub fn mapped_ptr(&self, allocation: &Allocation) -> Option<*mut u8> {
+        let block = self
+            .pools
+            .get(&allocation.pool)?
+            .get(allocation.block)?
+            .as_ref()?;
+        let mapped = block.mapped.as_ref()?;
+        Some(unsafe { (mapped.0 as *mut u8).add(allocation.offset as _) })
+    }
+}
+
+/// The free ranges of a block of memory, in order. Neighbouring ranges are always merged.
+#[derive(Debug, Clone)]
+pub(crate) struct FreeList {
+    capacity: u64,
+    free: Vec<Range<u64>>,
+}
+
+impl FreeList {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            free: vec![Range {
+                start: 0,
+                end: capacity,
+            }],
+        }
+    }
+
+    /// Take space from the first free range that's big enough, starting on a multiple of `alignment`
+    pub fn allocate(&mut self, len: u64, alignment: u64) -> Option<Range<u64>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+
+        let alignment = alignment.max(1);
+        for i in 0..self.free.len() {
+            let range = self.free[i].clone();
+            let start = range.start + (alignment - range.start % alignment) % alignment;
+            if start + len > range.end {
+                continue;
+            }
+
+            // Keep whatever is left on either side
+            let leftovers = [range.start..start, start + len..range.end];
+            self.free.splice(
+                i..i + 1,
+                leftovers.iter().filter(|r| !r.is_empty()).cloned(),
+            );
+            return Some(start..start + len);
+        }
+
+        None
+    }
+
+    pub fn release(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let i = self.free.partition_point(|r| r.start < range.start);
+        let joins_previous = i > 0 && self.free[i - 1].end == range.start;
+        let joins_next = i < self.free.len() && self.free[i].start == range.end;
+        match (joins_previous, joins_next) {
+            (true, true) => {
+                self.free[i - 1].end = self.free[i].end;
+                self.free.remove(i);
+            }
+            (true, false) => self.free[i - 1].end = range.end,
+            (false, true) => self.free[i].start = range.start,
+            (false, false) => self.free.insert(i, range),
+        }
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn available(&self) -> u64 {
+        self.free.iter().map(|r| r.end - r.start).sum()
+    }
+
+    /// Whether nothing has been allocated
+    pub fn is_empty(&self) -> bool {
+        self.available() == self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_free_list() {
+        let mut free_list = FreeList::new(10);
+        let a = free_list.allocate(4, 1).unwrap();
+        let b = free_list.allocate(4, 1).unwrap();
+        assert_eq!(a, 0..4);
+        assert_eq!(b, 4..8);
+        assert_eq!(free_list.allocate(3, 1), None);
+
+        free_list.release(a);
+        assert_eq!(free_list.allocate(3, 1).unwrap(), 0..3);
+        free_list.release(b);
+        assert_eq!(free_list.available(), 7);
+        assert_eq!(free_list.free[0], 3..10);
+        free_list.release(0..3);
+        assert!(free_list.is_empty());
+    }
+
+    #[test]
+    pub fn test_free_list_alignment() {
+        let mut free_list = FreeList::new(256);
+        assert_eq!(free_list.allocate(10, 1).unwrap(), 0..10);
+
+        // The gap left by aligning is still free
+        assert_eq!(free_list.allocate(64, 64).unwrap(), 64..128);
+        assert_eq!(free_list.free, [10..64, 128..256]);
+        assert_eq!(free_list.allocate(50, 4).unwrap(), 12..62);
+        assert_eq!(free_list.allocate(100, 256), None);
+
+        free_list.release(12..62);
+        free_list.release(64..128);
+        free_list.release(0..10);
+        assert!(free_list.is_empty());
+    }
+}
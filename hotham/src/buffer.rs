@@ -1,18 +1,19 @@
 use std::marker::PhantomData;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ash::vk;
 
-use crate::resources::VulkanContext;
+use crate::{allocator::Allocation, deletion_queue::Garbage, resources::VulkanContext};
 
 // TODO: Let Buffer<T> own the data
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+/// A Vulkan buffer and its memory. There is only ever one `Buffer` for each Vulkan buffer, which is destroyed by
+/// `destroy`, or by the `GpuResources` or `Shared` that owns it.
+#[derive(Debug, Eq, PartialEq, Hash)]
 pub struct Buffer<T> {
     pub handle: vk::Buffer,
-    pub device_memory: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub _phantom: PhantomData<T>,
     pub size: vk::DeviceSize,
-    pub device_memory_size: vk::DeviceSize,
     pub usage: vk::BufferUsageFlags,
 }
 
@@ -26,14 +27,30 @@ where
         usage: vk::BufferUsageFlags,
     ) -> Result<Self> {
         let size = std::mem::size_of_val(data) as vk::DeviceSize;
-        let (handle, device_memory, device_memory_size) =
-            vulkan_context.create_buffer_with_data(data, usage, size)?;
+        let (handle, allocation) = vulkan_context.create_buffer_with_data(data, usage, size)?;
 
         Ok(Self {
             handle,
-            device_memory,
+            allocation,
+            size,
+            usage,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Create a buffer with room for `capacity` elements, without filling it
+    pub fn with_capacity(
+        vulkan_context: &VulkanContext,
+        capacity: usize,
+        usage: vk::BufferUsageFlags,
+    ) -> Result<Self> {
+        let size = (capacity * std::mem::size_of::<T>()) as vk::DeviceSize;
+        let (handle, allocation) = vulkan_context.create_buffer_with_data::<T>(&[], usage, size)?;
+
+        Ok(Self {
+            handle,
+            allocation,
             size,
-            device_memory_size,
             usage,
             _phantom: PhantomData,
         })
@@ -42,22 +59,39 @@ where
     /// **NOTE**: If passing in a Vec, you MUST use vec.as_ptr(), passing in
     /// a reference will result in A Very Bad Time.
     pub fn update(&self, vulkan_context: &VulkanContext, data: &[T]) -> Result<()> {
-        vulkan_context.update_buffer(
-            data,
-            self.device_memory,
-            self.device_memory_size,
-            self.usage,
-        )
+        vulkan_context.update_buffer(data, &self.allocation, self.usage)
+    }
+
+    /// Write `data` into part of the buffer, starting `offset` elements in
+    pub fn update_range(
+        &self,
+        vulkan_context: &VulkanContext,
+        offset: usize,
+        data: &[T],
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let allocator = vulkan_context.allocator.lock().unwrap();
+        let dst = allocator
+            .mapped_ptr(&self.allocation)
+            .ok_or_else(|| anyhow!("Buffer memory is not host visible"))?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), (dst as *mut T).add(offset), data.len());
+        }
+
+        Ok(())
     }
 }
 
-// TODO: Need to be able to drop Buffers
-// impl<T> Buffer<T> {
-//     pub(crate) fn destroy(&self, vulkan_context: &VulkanContext) -> () {
-//         let device = &vulkan_context.device;
-//         unsafe {
-//             device.destroy_buffer(self.handle, None);
-//             device.free_memory(self.device_memory, None);
-//         };
-//     }
-// }
+impl<T> Buffer<T> {
+    /// Destroy the buffer once the GPU has finished with it
+    pub fn destroy(self, vulkan_context: &VulkanContext) {
+        vulkan_context.destroy_later(self.garbage());
+    }
+
+    pub(crate) fn garbage(self) -> Garbage {
+        Garbage::Buffer(self.handle, self.allocation)
+    }
+}
@@ -1,12 +1,53 @@
-#[derive(Debug, Clone, PartialEq, Default)]
-
 /// Component that controls how an `AnimationTarget` should be animated.
-/// Added by `gltf_loader` to the root node if its children contain animation data.
+/// Added by `gltf_loader` to the root node if its children contain animation data. Clips are played back by the
+/// `AnimationPlayer` on the same entity.
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct AnimationController {
-    /// The amount to blend from
+    /// The clip to blend from
     pub blend_from: usize,
-    /// The amount to blend to
+    /// The clip to blend to
     pub blend_to: usize,
-    /// The total blend amount
+    /// How far to blend from `blend_from` to `blend_to`, from 0.0 to 1.0
     pub blend_amount: f32,
+    /// The clips this model's `AnimationTarget`s have keyframes for
+    pub clips: Vec<AnimationClip>,
+}
+
+/// A named animation, like "Walk" or "Grip". Maps closely to a [glTF animation](https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#animations)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationClip {
+    /// The name of the clip
+    pub name: String,
+    /// The time of the clip's last keyframe, in seconds
+    pub duration: f32,
+}
+
+impl AnimationController {
+    /// Find a clip by name
+    pub fn clip_index(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|c| c.name == name)
+    }
+
+    /// Play a single clip, without blending
+    pub fn set_clip(&mut self, clip: usize) {
+        self.blend(clip, clip, 0.);
+    }
+
+    /// Blend between two clips. An `amount` of 0.0 is all `from`, and 1.0 is all `to`.
+    pub fn blend(&mut self, from: usize, to: usize, amount: f32) {
+        self.blend_from = from;
+        self.blend_to = to;
+        self.blend_amount = amount;
+    }
+
+    /// The duration of a clip, or 0.0 if it doesn't exist
+    pub fn clip_duration(&self, clip: usize) -> f32 {
+        self.clips.get(clip).map(|c| c.duration).unwrap_or(0.)
+    }
+
+    /// The duration of the longest clip being played
+    pub fn duration(&self) -> f32 {
+        self.clip_duration(self.blend_from)
+            .max(self.clip_duration(self.blend_to))
+    }
 }
@@ -0,0 +1,128 @@
+/// Component that plays the clips chosen by the `AnimationController` on the same entity.
+/// Added by `gltf_loader` alongside the `AnimationController`, paused at the start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnimationPlayer {
+    /// How far through the clips playback is, in seconds
+    pub time: f32,
+    /// How fast to play. 1.0 is normal speed, and negative speeds play backwards.
+    pub speed: f32,
+    /// Start again when the end of a clip is reached, instead of stopping there
+    pub looping: bool,
+    /// Whether `animation_system` moves `time` forward
+    pub playing: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            time: 0.,
+            speed: 1.,
+            looping: true,
+            playing: false,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    /// Start playing from the current time
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    /// Stop at the current time
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    /// Jump to `time` seconds
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    /// Move time on by `delta_time` seconds, in a clip `duration` seconds long. Playback stops at either end of the
+    /// clip, unless it is looping.
+    pub fn advance(&mut self, delta_time: f32, duration: f32) {
+        if !self.playing {
+            return;
+        }
+
+        self.time += delta_time * self.speed;
+        if self.looping {
+            self.time = self.clip_time(duration);
+        } else if self.time > duration || self.time < 0. {
+            self.time = self.time.max(0.).min(duration);
+            self.playing = false;
+        }
+    }
+
+    /// How far through a clip `duration` seconds long playback is
+    pub fn clip_time(&self, duration: f32) -> f32 {
+        if duration <= 0. {
+            0.
+        } else if self.looping {
+            self.time.rem_euclid(duration)
+        } else {
+            self.time.max(0.).min(duration)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_advance() {
+        let mut player = AnimationPlayer::default();
+
+        // Nothing happens until it's played
+        player.advance(0.5, 2.);
+        assert_eq!(player.time, 0.);
+
+        player.play();
+        player.advance(0.5, 2.);
+        assert_eq!(player.time, 0.5);
+
+        // Loops back to the start
+        player.advance(2., 2.);
+        assert_eq!(player.time, 0.5);
+
+        // Backwards
+        player.speed = -1.;
+        player.advance(1., 2.);
+        assert_eq!(player.time, 1.5);
+
+        // Stops at the end
+        player.speed = 2.;
+        player.looping = false;
+        player.advance(1., 2.);
+        assert_eq!(player.time, 2.);
+        assert!(!player.playing);
+
+        player.seek(0.25);
+        player.play();
+        player.advance(0.25, 2.);
+        assert_eq!(player.time, 0.75);
+
+        player.pause();
+        player.advance(0.25, 2.);
+        assert_eq!(player.time, 0.75);
+    }
+
+    #[test]
+    pub fn test_clip_time() {
+        let mut player = AnimationPlayer {
+            time: 3.,
+            ..Default::default()
+        };
+
+        // A shorter clip loops on its own
+        assert_eq!(player.clip_time(2.), 1.);
+        assert_eq!(player.clip_time(4.), 3.);
+        assert_eq!(player.clip_time(0.), 0.);
+
+        // Or stays at its end
+        player.looping = false;
+        assert_eq!(player.clip_time(2.), 2.);
+    }
+}
@@ -1,5 +1,7 @@
-use super::Transform;
+use super::{MorphTargetWeights, Transform};
 use hecs::Entity;
+use nalgebra::{DVector, Quaternion, SVector, UnitQuaternion, Vector3};
+use std::ops::{Add, Mul};
 
 /// A component that allows an entity to be animated.
 /// Usually added by `gltf_loader` if the node contains animation data.
@@ -7,6 +9,341 @@ use hecs::Entity;
 pub struct AnimationTarget {
     /// The entity that is controlling this animation
     pub controller: Entity,
-    /// The transforms that will be applied to this entity
-    pub animations: Vec<Vec<Transform>>,
+    /// The keyframes for this entity in each of the controller's clips, in the same order as
+    /// `AnimationController::clips`
+    pub animations: Vec<TransformKeyframes>,
+    /// The transform the entity has when a clip doesn't animate some part of it
+    pub rest_transform: Transform,
+}
+
+impl AnimationTarget {
+    /// Sample clip `clip` at `time` seconds. Clips that don't exist leave the entity at rest.
+    pub fn sample(&self, clip: usize, time: f32) -> Transform {
+        self.animations
+            .get(clip)
+            .map(|keyframes| keyframes.sample(time, &self.rest_transform))
+            .unwrap_or(self.rest_transform)
+    }
+
+    /// Sample the morph target weights of clip `clip` at `time` seconds. Clips that don't animate the weights
+    /// leave them at `rest_weights`.
+    pub fn sample_weights(
+        &self,
+        clip: usize,
+        time: f32,
+        rest_weights: &MorphTargetWeights,
+    ) -> MorphTargetWeights {
+        self.animations
+            .get(clip)
+            .and_then(|keyframes| keyframes.weights.as_ref())
+            .and_then(|weights| weights.sample(time))
+            .unwrap_or_else(|| rest_weights.clone())
+    }
+
+    /// Whether any of the clips animate the entity's morph target weights
+    pub fn animates_weights(&self) -> bool {
+        self.animations.iter().any(|k| k.weights.is_some())
+    }
+}
+
+/// The keyframes that move one entity in one clip. Parts of the transform without keyframes stay at rest.
+/// Entities with a `MorphWeights` can have their weights animated too.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TransformKeyframes {
+    /// Keyframes for the translation
+    pub translations: Option<Keyframes<Vector3<f32>>>,
+    /// Keyframes for the rotation. These aren't unit quaternions, as cubic spline tangents can be any length.
+    pub rotations: Option<Keyframes<Quaternion<f32>>>,
+    /// Keyframes for the scale
+    pub scales: Option<Keyframes<Vector3<f32>>>,
+    /// Keyframes for the morph target weights
+    pub weights: Option<Keyframes<MorphTargetWeights>>,
+}
+
+impl TransformKeyframes {
+    /// Sample each part of the transform at `time` seconds
+    pub fn sample(&self, time: f32, rest_transform: &Transform) -> Transform {
+        let translation = self.translations.as_ref().and_then(|k| k.sample(time));
+        let rotation = self.rotations.as_ref().and_then(|k| k.sample(time));
+        let scale = self.scales.as_ref().and_then(|k| k.sample(time));
+
+        Transform {
+            translation: translation.unwrap_or(rest_transform.translation),
+            rotation: rotation
+                .map(UnitQuaternion::new_normalize)
+                .unwrap_or(rest_transform.rotation),
+            scale: scale.unwrap_or(rest_transform.scale),
+        }
+    }
+
+    /// The time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        let translations = self.translations.as_ref().map(Keyframes::duration);
+        let rotations = self.rotations.as_ref().map(Keyframes::duration);
+        let scales = self.scales.as_ref().map(Keyframes::duration);
+        let weights = self.weights.as_ref().map(Keyframes::duration);
+        [translations, rotations, scales, weights]
+            .iter()
+            .flatten()
+            .fold(0., |a, b| a.max(*b))
+    }
+}
+
+/// How values between keyframes are found. Maps closely to the [glTF spec](https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#animations)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Interpolate linearly, or with spherical linear interpolation for rotations
+    Linear,
+    /// Keep each keyframe's value until the next keyframe
+    Step,
+    /// Follow a cubic spline. Each keyframe has an in-tangent, a value and an out-tangent, in that order.
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(interpolation: gltf::animation::Interpolation) -> Self {
+        match interpolation {
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        }
+    }
+}
+
+/// A value that can be keyframed
+pub trait Keyframe: Clone + Add<Output = Self> + Mul<f32, Output = Self> {
+    /// Move `amount` of the way from `self` to `other`
+    fn interpolate(&self, other: &Self, amount: f32) -> Self;
+}
+
+impl Keyframe for f32 {
+    fn interpolate(&self, other: &Self, amount: f32) -> Self {
+        self + (other - self) * amount
+    }
+}
+
+impl<const D: usize> Keyframe for SVector<f32, D> {
+    fn interpolate(&self, other: &Self, amount: f32) -> Self {
+        self.lerp(other, amount)
+    }
+}
+
+impl Keyframe for DVector<f32> {
+    fn interpolate(&self, other: &Self, amount: f32) -> Self {
+        self.lerp(other, amount)
+    }
+}
+
+impl Keyframe for Quaternion<f32> {
+    fn interpolate(&self, other: &Self, amount: f32) -> Self {
+        UnitQuaternion::new_normalize(*self)
+            .slerp(&UnitQuaternion::new_normalize(*other), amount)
+            .into_inner()
+    }
+}
+
+/// Values at points in time, and how to find the values in between
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframes<T> {
+    /// The time of each keyframe in seconds, in increasing order
+    pub times: Vec<f32>,
+    /// The value at each keyframe. Cubic splines have three values per keyframe.
+    pub values: Vec<T>,
+    /// How to find the values between keyframes
+    pub interpolation: Interpolation,
+}
+
+impl<T: Keyframe> Keyframes<T> {
+    /// Find the value at `time` seconds. Times before the first keyframe or after the last keyframe are clamped.
+    /// Returns `None` if there are no keyframes.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let last = self.times.len().checked_sub(1)?;
+        let next = self.times.partition_point(|t| *t <= time);
+        if next == 0 {
+            return self.value(0);
+        }
+        if next > last {
+            return self.value(last);
+        }
+
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let amount = if delta > 0. {
+            (time - self.times[previous]) / delta
+        } else {
+            0.
+        };
+
+        match self.interpolation {
+            Interpolation::Step => self.value(previous),
+            Interpolation::Linear => Some(
+                self.value(previous)?
+                    .interpolate(&self.value(next)?, amount),
+            ),
+            Interpolation::CubicSpline => {
+                let start = self.value(previous)?;
+                let start_out_tangent = self.values.get(previous * 3 + 2)?.clone();
+                let end_in_tangent = self.values.get(next * 3)?.clone();
+                let end = self.value(next)?;
+
+                // Hermite basis functions, from the glTF spec
+                let t = amount;
+                let t2 = t * t;
+                let t3 = t2 * t;
+                Some(
+                    start * (2. * t3 - 3. * t2 + 1.)
+                        + start_out_tangent * ((t3 - 2. * t2 + t) * delta)
+                        + end * (-2. * t3 + 3. * t2)
+                        + end_in_tangent * ((t3 - t2) * delta),
+                )
+            }
+        }
+    }
+
+    /// The time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.)
+    }
+
+    fn value(&self, keyframe: usize) -> Option<T> {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values.get(keyframe * 3 + 1).cloned(),
+            _ => self.values.get(keyframe).cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::vector;
+
+    fn keyframes(interpolation: Interpolation, values: Vec<f32>) -> Keyframes<f32> {
+        Keyframes {
+            times: vec![1., 2., 4.],
+            values,
+            interpolation,
+        }
+    }
+
+    #[test]
+    pub fn test_sample_linear() {
+        let keyframes = keyframes(Interpolation::Linear, vec![0., 10., 30.]);
+
+        // Clamped to the first and last keyframes
+        assert_eq!(keyframes.sample(0.), Some(0.));
+        assert_eq!(keyframes.sample(5.), Some(30.));
+
+        assert_eq!(keyframes.sample(1.), Some(0.));
+        assert_eq!(keyframes.sample(1.5), Some(5.));
+        assert_eq!(keyframes.sample(2.), Some(10.));
+        assert_eq!(keyframes.sample(3.), Some(20.));
+        assert_eq!(keyframes.duration(), 4.);
+    }
+
+    #[test]
+    pub fn test_sample_step() {
+        let keyframes = keyframes(Interpolation::Step, vec![0., 10., 30.]);
+        assert_eq!(keyframes.sample(1.5), Some(0.));
+        assert_eq!(keyframes.sample(2.), Some(10.));
+        assert_eq!(keyframes.sample(3.9), Some(10.));
+        assert_eq!(keyframes.sample(4.), Some(30.));
+    }
+
+    #[test]
+    pub fn test_sample_cubic_spline() {
+        // in-tangent, value, out-tangent for each keyframe
+        let keyframes = keyframes(
+            Interpolation::CubicSpline,
+            vec![0., 0., 10., 10., 10., 10., 0., 30., 0.],
+        );
+
+        // Keyframes are hit exactly
+        assert_eq!(keyframes.sample(1.), Some(0.));
+        assert_eq!(keyframes.sample(2.), Some(10.));
+        assert_eq!(keyframes.sample(4.), Some(30.));
+
+        // Tangents of 10/s between values 10s apart is a straight line
+        assert_relative_eq!(keyframes.sample(1.5).unwrap(), 5.);
+
+        // A flat in-tangent at the last keyframe eases into it, so it gets ahead of a straight line at first
+        assert_relative_eq!(keyframes.sample(3.5).unwrap(), 27.8125);
+    }
+
+    #[test]
+    pub fn test_sample_empty() {
+        let keyframes = keyframes(Interpolation::Linear, vec![]);
+        assert_eq!(keyframes.sample(1.), None);
+
+        let keyframes = Keyframes::<f32> {
+            times: vec![],
+            values: vec![],
+            interpolation: Interpolation::Linear,
+        };
+        assert_eq!(keyframes.sample(1.), None);
+        assert_eq!(keyframes.duration(), 0.);
+    }
+
+    #[test]
+    pub fn test_sample_transform() {
+        let rest_transform = Transform {
+            translation: vector![1., 2., 3.],
+            ..Default::default()
+        };
+        let quarter_turn = UnitQuaternion::from_euler_angles(0., std::f32::consts::FRAC_PI_2, 0.);
+        let keyframes = TransformKeyframes {
+            rotations: Some(Keyframes {
+                times: vec![0., 1.],
+                values: vec![
+                    UnitQuaternion::identity().into_inner(),
+                    quarter_turn.into_inner(),
+                ],
+                interpolation: Interpolation::Linear,
+            }),
+            ..Default::default()
+        };
+
+        let transform = keyframes.sample(0.5, &rest_transform);
+        assert_relative_eq!(
+            transform.rotation,
+            UnitQuaternion::from_euler_angles(0., std::f32::consts::FRAC_PI_4, 0.),
+            epsilon = 1e-6
+        );
+
+        // The parts that aren't animated stay at rest
+        assert_eq!(transform.translation, rest_transform.translation);
+        assert_eq!(transform.scale, rest_transform.scale);
+        assert_eq!(keyframes.duration(), 1.);
+    }
+
+    #[test]
+    pub fn test_sample_weights() {
+        let mut target_weights = MorphTargetWeights::zeros(2);
+        target_weights[1] = 1.;
+        let target = AnimationTarget {
+            controller: hecs::World::new().spawn(()),
+            animations: vec![
+                TransformKeyframes {
+                    weights: Some(Keyframes {
+                        times: vec![0., 2.],
+                        values: vec![MorphTargetWeights::zeros(2), target_weights],
+                        interpolation: Interpolation::Linear,
+                    }),
+                    ..Default::default()
+                },
+                Default::default(),
+            ],
+            rest_transform: Default::default(),
+        };
+        let rest_weights = MorphTargetWeights::repeat(2, 0.25);
+
+        assert!(target.animates_weights());
+        assert_eq!(target.animations[0].duration(), 2.);
+        assert_relative_eq!(target.sample_weights(0, 1., &rest_weights)[1], 0.5);
+        assert_eq!(target.sample_weights(0, 1., &rest_weights)[0], 0.);
+
+        // The second clip doesn't animate the weights
+        assert_eq!(target.sample_weights(1, 1., &rest_weights), rest_weights);
+    }
 }
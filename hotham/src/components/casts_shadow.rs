@@ -0,0 +1,10 @@
+/// A component that makes a `Mesh` cast shadows from the scene's main directional `Light`.
+/// Requires `shadows_system`.
+///
+/// Basic usage:
+/// ```ignore
+/// use hotham::components::CastsShadow;
+/// world.insert_one(entity, CastsShadow {});
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CastsShadow {}
@@ -0,0 +1,86 @@
+use hecs::{Entity, World};
+use nalgebra::Isometry3;
+
+use crate::components::{Info, Parent, Transform};
+
+/// A component that controls where an object sits in the hand when it is grabbed, eg. by the handle of a sword.
+/// Objects without grab points are held wherever they were grabbed. Used by `grabbing_system`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GrabPoints {
+    /// Where the hand should be, relative to the object, for each way the object can be held. The hand's -Z axis
+    /// points forward out of the fist. The closest point to the hand is used when it grabs the object.
+    pub poses: Vec<Isometry3<f32>>,
+}
+
+impl GrabPoints {
+    /// Use the nodes below `root` whose names start with `prefix` as grab points, eg. `GrabPoint` nodes added to a
+    /// model in Blender.
+    pub fn from_nodes(world: &World, root: Entity, prefix: &str) -> GrabPoints {
+        let mut query = world.query::<(&Info, &Transform)>();
+        let poses = query
+            .iter()
+            .filter(|(_, (info, _))| info.name.starts_with(prefix))
+            .filter_map(|(entity, _)| pose_relative_to(world, entity, root))
+            .collect();
+
+        GrabPoints { poses }
+    }
+
+    /// The grab point closest to `hand`, given the hand's pose relative to the object
+    pub fn closest(&self, hand: &Isometry3<f32>) -> Option<&Isometry3<f32>> {
+        let distance = |pose: &Isometry3<f32>| {
+            (pose.translation.vector - hand.translation.vector).norm_squared()
+        };
+        self.poses
+            .iter()
+            .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+    }
+}
+
+/// The pose of `entity` relative to `root`, or `None` if it isn't below `root`.
+fn pose_relative_to(world: &World, mut entity: Entity, root: Entity) -> Option<Isometry3<f32>> {
+    let mut pose = Isometry3::identity();
+    while entity != root {
+        pose = world.get::<Transform>(entity).ok()?.position() * pose;
+        entity = world.get::<Parent>(entity).ok()?.0;
+    }
+    Some(pose)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::vector;
+
+    #[test]
+    pub fn test_grab_points() {
+        let mut world = World::new();
+        let info = |name: &str| Info {
+            name: name.to_string(),
+            node_id: 0,
+        };
+        let transform = |y: f32| Transform {
+            translation: vector![0., y, 0.],
+            ..Default::default()
+        };
+
+        let sword = world.spawn((info("Sword"), transform(5.)));
+        let blade = world.spawn((info("Blade"), transform(0.5), Parent(sword)));
+        let _handle = world.spawn((info("GrabPoint.Handle"), transform(-0.5), Parent(sword)));
+        let _tip = world.spawn((info("GrabPoint.Tip"), transform(0.5), Parent(blade)));
+        let _elsewhere = world.spawn((info("GrabPoint"), transform(0.)));
+
+        let grab_points = GrabPoints::from_nodes(&world, sword, "GrabPoint");
+        assert_eq!(grab_points.poses.len(), 2);
+
+        let closest = grab_points
+            .closest(&Isometry3::translation(0., 0.9, 0.))
+            .unwrap();
+        assert_relative_eq!(closest.translation.vector, vector![0., 1., 0.]);
+        let closest = grab_points
+            .closest(&Isometry3::translation(0., -1., 0.))
+            .unwrap();
+        assert_relative_eq!(closest.translation.vector, vector![0., -0.5, 0.]);
+    }
+}
@@ -1,4 +1,13 @@
+use std::collections::VecDeque;
+
 use hecs::Entity;
+use nalgebra::{Isometry3, Vector3};
+
+/// How many recent poses `PoseHistory` keeps. At 90Hz this is about 60ms of movement.
+const POSE_HISTORY_LENGTH: usize = 6;
+
+/// When a pose was recorded, in seconds, and the pose itself
+type PoseSample = (f64, Isometry3<f32>);
 
 /// A component that represents the "side" or "handedness" that an entity is on
 /// Used by components such as `Hand` and `Pointer` to identify which controller they should map to
@@ -21,24 +30,117 @@ pub struct Hand {
     pub handedness: Handedness,
     /// Have we grabbed something?
     pub grabbed_entity: Option<Entity>,
+    /// Where the grabbed entity is relative to the hand. Set by `grabbing_system` when something is grabbed.
+    pub grab_offset: Isometry3<f32>,
+    /// Where the hand has been recently, used to throw things. Updated by `hands_system`.
+    pub pose_history: PoseHistory,
 }
 
 impl Hand {
     /// Shortcut helper to create a Left hand
     pub fn left() -> Hand {
-        Hand {
-            grip_value: 0.0,
-            handedness: Handedness::Left,
-            grabbed_entity: None,
-        }
+        Hand::new(Handedness::Left)
     }
 
     /// Shortcut helper to create a right hand
     pub fn right() -> Hand {
+        Hand::new(Handedness::Right)
+    }
+
+    /// Create a hand on the given side that isn't holding anything
+    pub fn new(handedness: Handedness) -> Hand {
         Hand {
             grip_value: 0.0,
-            handedness: Handedness::Right,
+            handedness,
             grabbed_entity: None,
+            grab_offset: Isometry3::identity(),
+            pose_history: Default::default(),
         }
     }
 }
+
+/// The last few poses of something that moves, along with when it was there
+#[derive(Debug, Clone, Default)]
+pub struct PoseHistory {
+    samples: VecDeque<PoseSample>,
+}
+
+impl PoseHistory {
+    /// Record where we were at `time`, in seconds
+    pub fn record(&mut self, time: f64, pose: Isometry3<f32>) {
+        if self.samples.len() == POSE_HISTORY_LENGTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((time, pose));
+    }
+
+    /// The most recently recorded pose
+    pub fn latest(&self) -> Option<Isometry3<f32>> {
+        self.samples.back().map(|(_, pose)| *pose)
+    }
+
+    /// Average velocity over the recorded poses, in meters per second
+    pub fn linear_velocity(&self) -> Vector3<f32> {
+        match self.first_and_last() {
+            Some(((t1, p1), (t2, p2))) => {
+                (p2.translation.vector - p1.translation.vector) / (t2 - t1) as f32
+            }
+            None => Vector3::zeros(),
+        }
+    }
+
+    /// Average angular velocity over the recorded poses, as an axis scaled by radians per second
+    pub fn angular_velocity(&self) -> Vector3<f32> {
+        match self.first_and_last() {
+            Some(((t1, p1), (t2, p2))) => {
+                (p2.rotation * p1.rotation.inverse()).scaled_axis() / (t2 - t1) as f32
+            }
+            None => Vector3::zeros(),
+        }
+    }
+
+    fn first_and_last(&self) -> Option<(PoseSample, PoseSample)> {
+        let first = *self.samples.front()?;
+        let last = *self.samples.back()?;
+        if last.0 > first.0 {
+            Some((first, last))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::{vector, UnitQuaternion};
+
+    #[test]
+    pub fn test_pose_history() {
+        let mut history = PoseHistory::default();
+        assert_eq!(history.linear_velocity(), Vector3::zeros());
+
+        // Move 1m/s along X while turning 1 radian per second around Y
+        for frame in 0..10 {
+            let time = frame as f64 * 0.1;
+            let pose = Isometry3::from_parts(
+                vector![time as f32, 0., 0.].into(),
+                UnitQuaternion::from_axis_angle(&Vector3::y_axis(), time as f32),
+            );
+            history.record(time, pose);
+        }
+
+        assert_relative_eq!(
+            history.linear_velocity(),
+            vector![1., 0., 0.],
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(
+            history.angular_velocity(),
+            vector![0., 1., 0.],
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(history.latest().unwrap().translation.vector.x, 0.9);
+    }
+}
@@ -0,0 +1,103 @@
+use hecs::{Entity, World};
+use nalgebra::UnitQuaternion;
+
+use crate::{
+    components::{Info, Joint, Parent},
+    resources::xr_context::HAND_JOINT_COUNT,
+};
+
+/// A component that maps the joints of a hand model to the joints tracked by `XR_EXT_hand_tracking`.
+/// Added to hands by `add_hand`. Requires `hand_tracking_system`
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandSkeleton {
+    /// The entity for each tracked joint, indexed by `HandJoint`. Joints the model doesn't have are `None`, and are
+    /// left to the hand's animation.
+    pub joints: [Option<Entity>; HAND_JOINT_COUNT],
+    /// Rotation from OpenXR's joint axes (-Z towards the fingertips, +Y out of the back of the hand) to the axes of
+    /// the model's bones
+    pub joint_rotation: UnitQuaternion<f32>,
+}
+
+impl HandSkeleton {
+    /// Find the joints below `root` whose names end with each of `names`, ignoring case, spaces and punctuation.
+    /// This matches `Wrist`, `L_Wrist` and `left_hand.wrist` to a name of `Wrist`.
+    pub fn from_joint_names(
+        world: &World,
+        root: Entity,
+        names: &[&str; HAND_JOINT_COUNT],
+    ) -> HandSkeleton {
+        let mut joints = [None; HAND_JOINT_COUNT];
+        let mut query = world.query::<(&Info, &Joint)>();
+        for (entity, (info, _)) in query.iter() {
+            if !is_descendant(world, entity, root) {
+                continue;
+            }
+
+            let node_name = normalize(&info.name);
+            if let Some(index) = names
+                .iter()
+                .position(|name| node_name.ends_with(&normalize(name)))
+            {
+                joints[index] = Some(entity);
+            }
+        }
+
+        HandSkeleton {
+            joints,
+            joint_rotation: UnitQuaternion::identity(),
+        }
+    }
+}
+
+fn is_descendant(world: &World, mut entity: Entity, root: Entity) -> bool {
+    while let Ok(parent) = world.get::<Parent>(entity) {
+        if parent.0 == root {
+            return true;
+        }
+        entity = parent.0;
+    }
+    false
+}
+
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::xr_context::{HandJoint, HAND_JOINT_NAMES};
+
+    #[test]
+    pub fn test_from_joint_names() {
+        let mut world = World::new();
+        let hand = world.spawn((Info::default(),));
+        let other_hand = world.spawn((Info::default(),));
+        let joint = Joint {
+            skeleton_root: hand,
+        };
+        let info = |name: &str| Info {
+            name: name.to_string(),
+            node_id: 0,
+        };
+
+        let wrist = world.spawn((info("L_Wrist"), joint, Parent(hand)));
+        let index_tip = world.spawn((info("left_hand.index_tip"), joint, Parent(wrist)));
+        let _other_wrist = world.spawn((info("L_Wrist"), joint, Parent(other_hand)));
+        let _not_a_joint = world.spawn((info("ThumbTip"), Parent(wrist)));
+
+        let skeleton = HandSkeleton::from_joint_names(&world, hand, &HAND_JOINT_NAMES);
+        assert_eq!(
+            skeleton.joints[HandJoint::WRIST.into_raw() as usize],
+            Some(wrist)
+        );
+        assert_eq!(
+            skeleton.joints[HandJoint::INDEX_TIP.into_raw() as usize],
+            Some(index_tip)
+        );
+        assert_eq!(skeleton.joints.iter().flatten().count(), 2);
+    }
+}
@@ -1,13 +1,11 @@
 use hecs::Entity;
-use nalgebra::Matrix4;
 
 /// A component that adds a "skinned joint" to an entity.
 /// For more detail, check out the [glTF spec](https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#skins-overview)
-/// Automatically added by `gltf_loader` for nodes that contain skin data
+/// Automatically added by `gltf_loader` for nodes that contain skin data. A joint can be used by several `Skin`s,
+/// which each hold its inverse bind matrix.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Joint {
-    /// Pointer to the root of the skeleton
+    /// Pointer to the root of the skeleton: the first skinned mesh that uses the joint
     pub skeleton_root: Entity,
-    /// Inverse bind matrix used to apply the skin in the vertex shader
-    pub inverse_bind_matrix: Matrix4<f32>,
 }
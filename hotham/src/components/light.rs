@@ -0,0 +1,196 @@
+use gltf::khr_lights_punctual::{Kind, Light as LightData};
+use nalgebra::{vector, Matrix4, Vector3, Vector4};
+
+use crate::scene_data::ShaderLight;
+
+/// The kind of light, following `KHR_lights_punctual`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightType {
+    /// Infinitely far away and shining along the entity's -Z axis, like the sun. Intensity is in lux
+    Directional,
+    /// Shines in all directions from the entity's position, like a light bulb. Intensity is in candela
+    Point,
+    /// Shines in a cone along the entity's -Z axis, like a torch. Intensity is in candela
+    Spot {
+        /// Angle in radians from the centre of the cone at which the light starts to fade
+        inner_cone_angle: f32,
+        /// Angle in radians from the centre of the cone at which the light has faded out completely
+        outer_cone_angle: f32,
+    },
+}
+
+/// A component that lights the scene. Its position and direction come from the entity's `TransformMatrix`.
+/// Added automatically by `gltf_loader` for nodes with `KHR_lights_punctual` lights. Requires `lighting_system`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    /// What kind of light this is
+    pub light_type: LightType,
+    /// The color of the light, in linear space
+    pub color: Vector3<f32>,
+    /// How bright the light is. See `LightType` for the units
+    pub intensity: f32,
+    /// How far away point and spot lights stop having an effect. `None` means they go on forever.
+    pub range: Option<f32>,
+}
+
+impl Light {
+    /// A white directional light
+    pub fn directional(intensity: f32) -> Self {
+        Self {
+            light_type: LightType::Directional,
+            color: vector![1., 1., 1.],
+            intensity,
+            range: None,
+        }
+    }
+
+    /// A white point light
+    pub fn point(intensity: f32, range: Option<f32>) -> Self {
+        Self {
+            light_type: LightType::Point,
+            color: vector![1., 1., 1.],
+            intensity,
+            range,
+        }
+    }
+
+    /// A white spot light
+    pub fn spot(
+        intensity: f32,
+        range: Option<f32>,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Self {
+            light_type: LightType::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            color: vector![1., 1., 1.],
+            intensity,
+            range,
+        }
+    }
+
+    pub(crate) fn load(light_data: &LightData) -> Self {
+        let light_type = match light_data.kind() {
+            Kind::Directional => LightType::Directional,
+            Kind::Point => LightType::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightType::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        };
+
+        Self {
+            light_type,
+            color: light_data.color().into(),
+            intensity: light_data.intensity(),
+            range: light_data.range(),
+        }
+    }
+
+    /// Pack this light into the form `pbr.frag` expects, placed in the world by `transform`
+    pub(crate) fn shader_light(&self, transform: &Matrix4<f32>) -> ShaderLight {
+        let position = transform.column(3).xyz();
+        let direction = (transform.fixed_slice::<3, 3>(0, 0) * -Vector3::z())
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| -Vector3::z());
+
+        let (light_type, cone) = match self.light_type {
+            LightType::Directional => (0., Vector4::zeros()),
+            LightType::Point => (1., Vector4::zeros()),
+            LightType::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                // See the KHR_lights_punctual spec for where these come from
+                let scale = 1. / (inner_cone_angle.cos() - outer_cone_angle.cos()).max(0.001);
+                let offset = -outer_cone_angle.cos() * scale;
+                (2., vector![scale, offset, 0., 0.])
+            }
+        };
+
+        ShaderLight {
+            position: position.push(light_type),
+            direction: direction.push(self.range.unwrap_or(0.)),
+            color: self.color.push(self.intensity),
+            cone,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::{Isometry3, UnitQuaternion};
+
+    #[test]
+    pub fn test_load_lights() {
+        let gltf = gltf::Gltf::from_slice(
+            br#"{
+                "asset": {"version": "2.0"},
+                "extensionsUsed": ["KHR_lights_punctual"],
+                "extensions": {"KHR_lights_punctual": {"lights": [
+                    {"type": "point", "color": [1.0, 0.5, 0.0], "intensity": 20.0, "range": 5.0},
+                    {"type": "spot", "spot": {"innerConeAngle": 0.2, "outerConeAngle": 0.5}},
+                    {"type": "directional", "intensity": 3.0}
+                ]}},
+                "nodes": [
+                    {"extensions": {"KHR_lights_punctual": {"light": 0}}},
+                    {"extensions": {"KHR_lights_punctual": {"light": 1}}},
+                    {"extensions": {"KHR_lights_punctual": {"light": 2}}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let lights = gltf
+            .nodes()
+            .map(|n| Light::load(&n.light().unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(lights[0].light_type, LightType::Point);
+        assert_eq!(lights[0].color, vector![1., 0.5, 0.]);
+        assert_eq!(lights[0].intensity, 20.);
+        assert_eq!(lights[0].range, Some(5.));
+        assert_eq!(
+            lights[1].light_type,
+            LightType::Spot {
+                inner_cone_angle: 0.2,
+                outer_cone_angle: 0.5
+            }
+        );
+        assert_eq!(lights[1].intensity, 1.);
+        assert_eq!(lights[1].range, None);
+        assert_eq!(lights[2].light_type, LightType::Directional);
+    }
+
+    #[test]
+    pub fn test_shader_light() {
+        // A spot light above the origin, pointing straight down
+        let light = Light::spot(10., Some(4.), 0.2, 0.5);
+        let transform = Isometry3::from_parts(
+            vector![0., 2., 0.].into(),
+            UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -std::f32::consts::FRAC_PI_2),
+        )
+        .to_homogeneous();
+        let shader_light = light.shader_light(&transform);
+
+        assert_relative_eq!(shader_light.position, vector![0., 2., 0., 2.]);
+        assert_relative_eq!(
+            shader_light.direction,
+            vector![0., -1., 0., 4.],
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(shader_light.color, vector![1., 1., 1., 10.]);
+
+        // The cone should be fully lit at the inner angle and dark at the outer angle
+        let falloff = |angle: f32| angle.cos() * shader_light.cone.x + shader_light.cone.y;
+        assert_relative_eq!(falloff(0.2), 1., epsilon = 0.0001);
+        assert_relative_eq!(falloff(0.5), 0., epsilon = 0.0001);
+    }
+}
@@ -3,7 +3,17 @@ use ash::vk;
 use gltf::{texture::Info, Material as MaterialData};
 use nalgebra::{vector, Vector4};
 
-use crate::{resources::VulkanContext, texture::Texture};
+use std::collections::HashMap;
+
+use crate::{
+    deletion_queue::{Garbage, GpuResources},
+    resources::VulkanContext,
+    texture::Texture,
+};
+
+/// Materials that have already been loaded from a glTF document, by material index
+pub(crate) type LoadedMaterials =
+    HashMap<Option<usize>, (Material, vk::DescriptorSet, GpuResources)>;
 
 /// A component that instructs the renderer how an entity should look when rendered
 /// Mostly maps to the [glTF material spec](https://www.khronos.org/registry/glTF/specs/2.0/glTF-2.0.html#materials) and
@@ -35,22 +45,21 @@ pub struct Material {
     pub metallic_factor: f32,
     /// The factor for the roughness of the material.
     pub roughness_factor: f32,
-    /// Alpha mask - see fragment shader
+    /// Alpha mode - 0.0 for opaque / 1.0 for masked by `alpha_mask_cutoff` / 2.0 for blended. See fragment shader
     pub alpha_mask: f32,
     /// Alpha mask cutoff - see fragment shader
     pub alpha_mask_cutoff: f32,
 }
 
 impl Material {
-    /// Load a material from a glTF document
+    /// Load a material from a glTF document. The returned `GpuResources` own the material's textures and descriptor set.
     pub fn load(
         mesh_name: &str,
         set_layout: vk::DescriptorSetLayout,
         material: MaterialData,
         vulkan_context: &VulkanContext,
-        _buffer: &[u8],
         images: &[gltf::image::Data],
-    ) -> Result<(Self, vk::DescriptorSet)> {
+    ) -> Result<(Self, vk::DescriptorSet, GpuResources)> {
         let material_name = format!(
             "Material {} for mesh {}",
             material.name().unwrap_or("<unnamed>"),
@@ -65,32 +74,28 @@ impl Material {
         // Base Color
         let base_color_texture_info = pbr_metallic_roughness.base_color_texture();
         let base_color_texture_set = get_texture_set(base_color_texture_info.as_ref());
-        let base_color_texture = base_color_texture_info
-            .and_then(|i| {
-                Texture::load(
-                    &format!("Base Color texture for {}", mesh_name),
-                    i.texture(),
-                    vulkan_context,
-                    images,
-                )
-            })
-            .unwrap_or_else(|| empty_texture.clone());
+        let base_color_texture = base_color_texture_info.and_then(|i| {
+            Texture::load(
+                &format!("Base Color texture for {}", mesh_name),
+                i.texture(),
+                vulkan_context,
+                images,
+            )
+        });
         let base_color_factor = Vector4::from(pbr_metallic_roughness.base_color_factor());
 
         // Metallic Roughness
         let metallic_roughness_texture_info = pbr_metallic_roughness.metallic_roughness_texture();
         let metallic_roughness_texture_set =
             get_texture_set(metallic_roughness_texture_info.as_ref());
-        let metallic_roughness_texture = metallic_roughness_texture_info
-            .and_then(|i| {
-                Texture::load(
-                    &format!("Metallic Roughness texture for {}", mesh_name),
-                    i.texture(),
-                    vulkan_context,
-                    images,
-                )
-            })
-            .unwrap_or_else(|| empty_texture.clone());
+        let metallic_roughness_texture = metallic_roughness_texture_info.and_then(|i| {
+            Texture::load(
+                &format!("Metallic Roughness texture for {}", mesh_name),
+                i.texture(),
+                vulkan_context,
+                images,
+            )
+        });
 
         // Normal map
         let normal_texture_info = material.normal_texture();
@@ -98,16 +103,14 @@ impl Material {
             .as_ref()
             .map(|t| t.tex_coord() as i32)
             .unwrap_or(-1);
-        let normal_texture = normal_texture_info
-            .and_then(|i| {
-                Texture::load(
-                    &format!("Normal texture for {}", mesh_name),
-                    i.texture(),
-                    vulkan_context,
-                    images,
-                )
-            })
-            .unwrap_or_else(|| empty_texture.clone());
+        let normal_texture = normal_texture_info.and_then(|i| {
+            Texture::load(
+                &format!("Normal texture for {}", mesh_name),
+                i.texture(),
+                vulkan_context,
+                images,
+            )
+        });
 
         // Occlusion
         let occlusion_texture_info = material.occlusion_texture();
@@ -115,30 +118,25 @@ impl Material {
             .as_ref()
             .map(|t| t.tex_coord() as i32)
             .unwrap_or(-1);
-        let occlusion_texture = occlusion_texture_info
-            .and_then(|i| {
-                Texture::load(
-                    &format!("Occlusion texture for {}", mesh_name),
-                    i.texture(),
-                    vulkan_context,
-                    images,
-                )
-            })
-            .unwrap_or_else(|| empty_texture.clone());
+        let occlusion_texture = occlusion_texture_info.and_then(|i| {
+            Texture::load(
+                &format!("Occlusion texture for {}", mesh_name),
+                i.texture(),
+                vulkan_context,
+                images,
+            )
+        });
 
         // Emission
         let emissive_texture_info = material.emissive_texture();
-        let emissive_texture = emissive_texture_info
-            .as_ref()
-            .and_then(|i| {
-                Texture::load(
-                    &format!("Occlusion texture for {}", mesh_name),
-                    i.texture(),
-                    vulkan_context,
-                    images,
-                )
-            })
-            .unwrap_or_else(|| empty_texture.clone());
+        let emissive_texture = emissive_texture_info.as_ref().and_then(|i| {
+            Texture::load(
+                &format!("Occlusion texture for {}", mesh_name),
+                i.texture(),
+                vulkan_context,
+                images,
+            )
+        });
         let emissive_texture_set = emissive_texture_info
             .map(|t| t.tex_coord() as i32)
             .unwrap_or(-1);
@@ -159,6 +157,7 @@ impl Material {
         // Alpha
         let (alpha_mask, alpha_mask_cutoff) = match (material.alpha_mode(), material.alpha_cutoff())
         {
+            (gltf::material::AlphaMode::Blend, _) => (2., 1.),
             (gltf::material::AlphaMode::Mask, _) => (1., 0.5),
             (_, Some(alpha_cutoff)) => (1., alpha_cutoff),
             _ => (0., 1.),
@@ -171,18 +170,32 @@ impl Material {
             0.
         };
 
-        // Descriptor set
+        // Descriptor set. Textures the material doesn't have are filled in with the empty texture.
+        let textures = [
+            base_color_texture,
+            metallic_roughness_texture,
+            normal_texture,
+            occlusion_texture,
+            emissive_texture,
+        ];
         let descriptor_set = vulkan_context.create_textures_descriptor_sets(
             set_layout,
             &material_name,
             &[
-                &base_color_texture,
-                &metallic_roughness_texture,
-                &normal_texture,
-                &occlusion_texture,
-                &emissive_texture,
+                textures[0].as_ref().unwrap_or(&empty_texture),
+                textures[1].as_ref().unwrap_or(&empty_texture),
+                textures[2].as_ref().unwrap_or(&empty_texture),
+                textures[3].as_ref().unwrap_or(&empty_texture),
+                textures[4].as_ref().unwrap_or(&empty_texture),
             ],
         )?[0];
+        let garbage = IntoIterator::into_iter(textures)
+            .flatten()
+            .chain(std::iter::once(empty_texture))
+            .flat_map(|t| t.garbage())
+            .chain(std::iter::once(Garbage::DescriptorSet(descriptor_set)))
+            .collect();
+        let resources = GpuResources::new(vulkan_context, garbage);
 
         Ok((
             Material {
@@ -202,8 +215,14 @@ impl Material {
                 alpha_mask_cutoff,
             },
             descriptor_set,
+            resources,
         ))
     }
+
+    /// Whether this material is blended with whatever is behind it, and so has to be drawn after opaque materials
+    pub fn is_transparent(&self) -> bool {
+        self.alpha_mask == 2.
+    }
 }
 
 fn arr_to_vec4(vec3: [f32; 3]) -> Vector4<f32> {
@@ -1,43 +1,47 @@
 use anyhow::Result;
 use ash::vk;
-use nalgebra::Matrix4;
+use nalgebra::{Matrix4, Vector3};
 
-use super::primitive::Primitive;
+use super::{
+    material::LoadedMaterials,
+    primitive::{MorphDelta, Primitive},
+};
 use crate::{
     buffer::Buffer,
+    deletion_queue::{Garbage, GpuResources, Shared},
     resources::{render_context::DescriptorSetLayouts, VulkanContext},
 };
-use std::mem::{transmute, MaybeUninit};
 
-/// Uniform buffer used by the vertex shader for each entity
+/// Uniform buffer used by the vertex shader for each entity. Transforms aren't in here: the shaders take them from
+/// `RenderContext::instance_buffer` instead.
 #[repr(C)]
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub struct MeshUBO {
-    /// The transform of the entity, in world space
-    pub transform: Matrix4<f32>,
-    /// Joint matrices for skinning
-    pub joint_matrices: [Matrix4<f32>; 128],
     /// The number of joints
     pub joint_count: f32,
+    /// The number of morph targets
+    pub morph_target_count: f32,
+    /// Where the mesh's joint matrices start in `RenderContext::joint_buffer`, set by `skinning_system`. `NO_JOINTS`
+    /// if it has no joint matrices this frame, in which case it's drawn in its bind pose.
+    pub first_joint: u32,
+    /// Where the mesh's morph target weights start in `RenderContext::morph_weight_buffer`, set by
+    /// `morph_targets_system`. `NO_MORPH_WEIGHTS` if it has no weights this frame, in which case it isn't morphed.
+    pub first_morph_weight: u32,
 }
 
+/// `MeshUBO::first_joint` for meshes without joint matrices
+pub const NO_JOINTS: u32 = u32::MAX;
+
+/// `MeshUBO::first_morph_weight` for meshes without morph target weights
+pub const NO_MORPH_WEIGHTS: u32 = u32::MAX;
+
 impl Default for MeshUBO {
     fn default() -> Self {
-        let joint_matrices = {
-            // Most meshes don't have joints, so no point allocating.
-            let mut joint_matrices: [MaybeUninit<Matrix4<f32>>; 128] =
-                unsafe { MaybeUninit::uninit().assume_init() };
-            for elem in &mut joint_matrices[0..0] {
-                elem.write(Matrix4::identity());
-            }
-            unsafe { transmute::<_, [Matrix4<f32>; 128]>(joint_matrices) }
-            //joint_matrices[0] = Matrix4::identity();
-        };
-
         Self {
-            transform: Default::default(),
             joint_count: Default::default(),
-            joint_matrices,
+            morph_target_count: Default::default(),
+            first_joint: NO_JOINTS,
+            first_morph_weight: NO_MORPH_WEIGHTS,
         }
     }
 }
@@ -48,66 +52,261 @@ impl Default for MeshUBO {
 pub struct Mesh {
     /// The descriptor sets for the UBO
     pub descriptor_sets: [vk::DescriptorSet; 1],
-    /// UBO sent to the shader
-    pub ubo_buffer: Buffer<MeshUBO>,
+    /// UBO sent to the shader, shared with clones of this mesh
+    pub ubo_buffer: Shared<Buffer<MeshUBO>>,
     /// The actual contents of the UBO
     pub ubo_data: MeshUBO,
     /// The primitives in this mesh (eg. actual geometry)
     pub primitives: Vec<Primitive>,
+    /// A sphere around all of the primitives, used by `rendering_system` to skip meshes the user can't see.
+    /// `None` means the mesh is always drawn.
+    pub bounding_sphere: Option<BoundingSphere>,
+    /// Owns `descriptor_sets`, which are freed once the last clone of this mesh is dropped
+    pub resources: GpuResources,
+    /// The deltas of every primitive's morph targets, if any of them have morph targets. Shared with copies of
+    /// the mesh that have their own `ubo_buffer`.
+    pub morph_target_buffer: Option<Shared<Buffer<MorphDelta>>>,
+}
+
+/// A sphere that contains all of a mesh's vertices, in the mesh's local space
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    /// The center of the sphere
+    pub center: Vector3<f32>,
+    /// The radius of the sphere
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// The smallest sphere around an axis aligned bounding box
+    pub fn from_bounds(min: &Vector3<f32>, max: &Vector3<f32>) -> Self {
+        Self {
+            center: (min + max) / 2.,
+            radius: (max - min).norm() / 2.,
+        }
+    }
+
+    /// Move the sphere into world space. The radius grows with the largest scale along any axis.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Self {
+        let scale = (0..3)
+            .map(|i| transform.fixed_slice::<3, 1>(0, i).norm())
+            .fold(0., f32::max);
+
+        Self {
+            center: transform.transform_point(&self.center.into()).coords,
+            radius: self.radius * scale,
+        }
+    }
 }
 
 impl Mesh {
     pub(crate) fn load(
         mesh_data: &gltf::Mesh,
-        buffer: &[u8],
+        buffers: &[gltf::buffer::Data],
         vulkan_context: &VulkanContext,
         descriptor_set_layouts: &DescriptorSetLayouts,
         images: &[gltf::image::Data],
+        materials: &mut LoadedMaterials,
     ) -> Result<Mesh> {
         let name = mesh_data.name().unwrap_or("");
-        let primitives = mesh_data
-            .primitives()
-            .map(|p| {
-                Primitive::load(
-                    descriptor_set_layouts.textures_layout,
-                    name,
-                    p,
-                    buffer,
-                    vulkan_context,
-                    images,
+        let mut primitives = Vec::new();
+        let mut bounding_boxes = Vec::new();
+        let mut morph_deltas = Vec::new();
+        for primitive_data in mesh_data.primitives() {
+            let bounding_box = primitive_data.bounding_box();
+
+            // Primitives we can't draw are skipped
+            if let Some(primitive) = Primitive::load(
+                descriptor_set_layouts.textures_layout,
+                name,
+                primitive_data,
+                buffers,
+                vulkan_context,
+                images,
+                materials,
+                &mut morph_deltas,
+            )? {
+                primitives.push(primitive);
+                bounding_boxes.push(bounding_box);
+            }
+        }
+
+        // glTF requires the bounds of every primitive's positions
+        let (min, max) = bounding_boxes.into_iter().fold(
+            (Vector3::repeat(f32::MAX), Vector3::repeat(f32::MIN)),
+            |(min, max), b| {
+                (
+                    min.inf(&Vector3::from(b.min)),
+                    max.sup(&Vector3::from(b.max)),
                 )
-            })
-            .collect::<Result<Vec<_>>>()?;
+            },
+        );
+        let bounding_sphere = if primitives.is_empty() {
+            None
+        } else {
+            Some(BoundingSphere::from_bounds(&min, &max))
+        };
+
+        // glTF requires every primitive of a mesh to have the same number of morph targets
+        let mut ubo_data = MeshUBO::default();
+        let morph_target_buffer = if morph_deltas.is_empty() {
+            None
+        } else {
+            let buffer = Buffer::new(
+                vulkan_context,
+                &morph_deltas,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+            )?;
+            let target_count = primitives
+                .iter()
+                .filter_map(|p| p.morph_targets.map(|m| m.target_count))
+                .max()
+                .unwrap_or_default();
+            ubo_data.morph_target_count = target_count as _;
+            Some(Shared::new(vulkan_context, buffer))
+        };
 
-        // Create descriptor sets
-        println!("[HOTHAM_MODEL] Creating descriptor sets for {}", name);
-        let descriptor_sets = vulkan_context.create_mesh_descriptor_sets(
-            descriptor_set_layouts.mesh_layout,
+        let (descriptor_sets, ubo_buffer, resources) = create_ubo(
+            vulkan_context,
+            descriptor_set_layouts,
             mesh_data
                 .name()
                 .unwrap_or(&format!("Mesh {}", mesh_data.index())),
+            &ubo_data,
+            morph_target_buffer.as_deref(),
         )?;
-        let descriptor_sets = [descriptor_sets[0]];
-        println!("[HOTHAM_MODEL] ..done!");
 
-        let mesh_ubo = MeshUBO::default();
-        let ubo_buffer = Buffer::new(
+        Ok(Mesh {
+            descriptor_sets,
+            ubo_buffer,
+            ubo_data,
+            primitives,
+            bounding_sphere,
+            resources,
+            morph_target_buffer,
+        })
+    }
+
+    /// Create a mesh without morph targets from primitives that have already been loaded
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        descriptor_set_layouts: &DescriptorSetLayouts,
+        name: &str,
+        primitives: Vec<Primitive>,
+        bounding_sphere: Option<BoundingSphere>,
+    ) -> Result<Mesh> {
+        let ubo_data = MeshUBO::default();
+        let (descriptor_sets, ubo_buffer, resources) = create_ubo(
             vulkan_context,
-            &[mesh_ubo],
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
+            descriptor_set_layouts,
+            name,
+            &ubo_data,
+            None,
         )?;
-        vulkan_context.update_buffer_descriptor_set(
-            &ubo_buffer,
-            descriptor_sets[0],
-            0,
-            vk::DescriptorType::UNIFORM_BUFFER,
-        );
 
         Ok(Mesh {
-            ubo_buffer,
-            ubo_data: mesh_ubo,
             descriptor_sets,
+            ubo_buffer,
+            ubo_data,
             primitives,
+            bounding_sphere,
+            resources,
+            morph_target_buffer: None,
+        })
+    }
+
+    /// Copy the mesh, giving the copy its own `MeshUBO` so that it can be deformed separately
+    pub(crate) fn clone_with_own_ubo(
+        &self,
+        vulkan_context: &VulkanContext,
+        descriptor_set_layouts: &DescriptorSetLayouts,
+        name: &str,
+    ) -> Result<Mesh> {
+        let (descriptor_sets, ubo_buffer, resources) = create_ubo(
+            vulkan_context,
+            descriptor_set_layouts,
+            name,
+            &self.ubo_data,
+            self.morph_target_buffer.as_deref(),
+        )?;
+
+        Ok(Mesh {
+            descriptor_sets,
+            ubo_buffer,
+            resources,
+            ..self.clone()
         })
     }
+
+    /// Whether the mesh is deformed by a `Skin`
+    pub fn is_skinned(&self) -> bool {
+        self.ubo_data.joint_count > 0.
+    }
+
+    /// Whether the mesh has morph targets
+    pub fn has_morph_targets(&self) -> bool {
+        self.ubo_data.morph_target_count > 0.
+    }
+
+    /// Whether the mesh is deformed by a `Skin` or morph targets. Deformed meshes need their own `MeshUBO`, so they
+    /// can't be instanced.
+    pub fn is_deformed(&self) -> bool {
+        self.is_skinned() || self.has_morph_targets()
+    }
+}
+
+/// Create a buffer for `ubo_data`, and a descriptor set for it and `morph_target_buffer`
+fn create_ubo(
+    vulkan_context: &VulkanContext,
+    descriptor_set_layouts: &DescriptorSetLayouts,
+    name: &str,
+    ubo_data: &MeshUBO,
+    morph_target_buffer: Option<&Buffer<MorphDelta>>,
+) -> Result<(
+    [vk::DescriptorSet; 1],
+    Shared<Buffer<MeshUBO>>,
+    GpuResources,
+)> {
+    println!("[HOTHAM_MODEL] Creating descriptor sets for {}", name);
+    let descriptor_sets =
+        vulkan_context.create_mesh_descriptor_sets(descriptor_set_layouts.mesh_layout, name)?;
+    println!("[HOTHAM_MODEL] ..done!");
+
+    // Meshes without morph targets never read them, but the descriptor still has to point at a storage buffer, so it
+    // points at the UBO instead
+    let ubo_buffer = Buffer::new(
+        vulkan_context,
+        &[*ubo_data],
+        vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER,
+    )?;
+    vulkan_context.update_buffer_descriptor_set(
+        &ubo_buffer,
+        descriptor_sets[0],
+        0,
+        vk::DescriptorType::UNIFORM_BUFFER,
+    );
+    match morph_target_buffer {
+        Some(buffer) => vulkan_context.update_buffer_descriptor_set(
+            buffer,
+            descriptor_sets[0],
+            1,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ),
+        None => vulkan_context.update_buffer_descriptor_set(
+            &ubo_buffer,
+            descriptor_sets[0],
+            1,
+            vk::DescriptorType::STORAGE_BUFFER,
+        ),
+    }
+
+    let resources = GpuResources::new(
+        vulkan_context,
+        vec![Garbage::DescriptorSet(descriptor_sets[0])],
+    );
+    Ok((
+        [descriptor_sets[0]],
+        Shared::new(vulkan_context, ubo_buffer),
+        resources,
+    ))
 }
@@ -1,14 +1,21 @@
 #![allow(missing_docs)]
 pub mod animation_controller;
+pub mod animation_player;
 pub mod animation_target;
+pub mod casts_shadow;
 pub mod collider;
+pub mod grab_points;
 pub mod hand;
+pub mod hand_skeleton;
 pub mod info;
 pub mod joint;
+pub mod light;
 pub mod material;
 pub mod mesh;
+pub mod morph_weights;
 pub mod panel;
 pub mod parent;
+pub mod physical_hand;
 pub mod pointer;
 pub mod primitive;
 pub mod rigid_body;
@@ -17,26 +24,37 @@ pub mod skin;
 pub mod sound_emitter;
 pub mod transform;
 pub mod transform_matrix;
+pub mod two_handed_grab;
+pub mod two_handed_scaling;
 pub mod ui_panel;
 pub mod visible;
 
-pub use animation_controller::AnimationController;
-pub use animation_target::AnimationTarget;
+pub use animation_controller::{AnimationClip, AnimationController};
+pub use animation_player::AnimationPlayer;
+pub use animation_target::{AnimationTarget, Interpolation, Keyframes, TransformKeyframes};
+pub use casts_shadow::CastsShadow;
 pub use collider::Collider;
+pub use grab_points::GrabPoints;
 pub use hand::Hand;
+pub use hand_skeleton::HandSkeleton;
 pub use info::Info;
 pub use joint::Joint;
+pub use light::Light;
 pub use material::Material;
-pub use mesh::Mesh;
+pub use mesh::{BoundingSphere, Mesh};
+pub use morph_weights::{MorphTargetWeights, MorphWeights};
 pub use panel::Panel;
 pub use parent::Parent;
+pub use physical_hand::PhysicalHand;
 pub use pointer::Pointer;
-pub use primitive::Primitive;
+pub use primitive::{MorphDelta, MorphTargets, Primitive};
 pub use rigid_body::RigidBody;
 pub use root::Root;
 pub use skin::Skin;
 pub use sound_emitter::SoundEmitter;
 pub use transform::Transform;
 pub use transform_matrix::TransformMatrix;
+pub use two_handed_grab::TwoHandedGrab;
+pub use two_handed_scaling::TwoHandedScaling;
 pub use ui_panel::UIPanel;
 pub use visible::Visible;
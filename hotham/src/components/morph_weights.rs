@@ -0,0 +1,48 @@
+use nalgebra::DVector;
+
+/// The weight of each of a mesh's morph targets
+pub type MorphTargetWeights = DVector<f32>;
+
+/// Component that controls how much each of the morph targets (or blend shapes) of the `Mesh` on the same entity
+/// deforms it. Added by `gltf_loader` to meshes with morph targets, and animated by `animation_system`.
+/// `morph_targets_system` sends the weights to the GPU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MorphWeights {
+    /// How much of each morph target to apply, usually from 0.0 to 1.0. There is one weight for each of the mesh's
+    /// morph targets: missing weights count as zero, and extra weights are ignored.
+    pub weights: MorphTargetWeights,
+    /// The weights from the glTF file, used when a clip doesn't animate them
+    pub rest_weights: MorphTargetWeights,
+}
+
+impl MorphWeights {
+    /// Create the component for a mesh with `target_count` morph targets from its glTF default weights. Meshes
+    /// without default weights start at zero.
+    pub fn new(target_count: usize, default_weights: &[f32]) -> Self {
+        let mut weights = MorphTargetWeights::zeros(target_count);
+        for (weight, default) in weights.iter_mut().zip(default_weights) {
+            *weight = *default;
+        }
+
+        Self {
+            rest_weights: weights.clone(),
+            weights,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_new() {
+        let morph_weights = MorphWeights::new(3, &[0.5, 1.]);
+        assert_eq!(morph_weights.weights.as_slice(), &[0.5, 1., 0.]);
+        assert_eq!(morph_weights.rest_weights, morph_weights.weights);
+
+        // There's no limit on the number of targets, but weights past them are dropped
+        let morph_weights = MorphWeights::new(64, &[1.; 65]);
+        assert_eq!(morph_weights.weights, MorphTargetWeights::repeat(64, 1.));
+    }
+}
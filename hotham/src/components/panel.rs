@@ -3,9 +3,8 @@ use egui::Pos2;
 use itertools::izip;
 use nalgebra::{vector, Vector2, Vector4};
 
-use crate::buffer::Buffer;
-use crate::components::mesh::MeshUBO;
-use crate::components::{Material, Mesh, Primitive};
+use crate::components::{BoundingSphere, Material, Mesh, Primitive};
+use crate::deletion_queue::{Garbage, GpuResources, Shared};
 use crate::hotham_error::HothamError;
 use crate::{
     resources::{RenderContext, VulkanContext},
@@ -18,8 +17,8 @@ pub struct Panel {
     pub resolution: vk::Extent2D,
     /// The world-size of the Panel
     pub world_size: Vector2<f32>,
-    /// Texture backing the Panel
-    pub texture: Texture,
+    /// Texture backing the Panel, which is destroyed once the panel is dropped
+    pub texture: Shared<Texture>,
     /// Input received this frame
     pub input: Option<PanelInput>,
 }
@@ -48,11 +47,14 @@ impl Panel {
             .image_view(output_image.view)
             .sampler(sampler)
             .build();
-        let texture = Texture {
-            image: output_image,
-            sampler,
-            descriptor,
-        };
+        let texture = Shared::new(
+            vulkan_context,
+            Texture {
+                image: output_image,
+                sampler,
+                descriptor,
+            },
+        );
         let mesh = create_mesh(&texture, vulkan_context, render_context, world_size);
 
         Ok((
@@ -73,7 +75,8 @@ fn create_mesh(
     render_context: &RenderContext,
     world_size: Vector2<f32>,
 ) -> Mesh {
-    let (material, descriptor_set) = get_material(output_texture, vulkan_context, render_context);
+    let (material, descriptor_set, resources) =
+        get_material(output_texture, vulkan_context, render_context);
     let (half_width, half_height) = (world_size.x / 2., world_size.y / 2.);
 
     let positions = [
@@ -97,61 +100,34 @@ fn create_mesh(
         })
         .collect();
 
-    let vertex_buffer = Buffer::new(
-        vulkan_context,
-        &vertices,
-        vk::BufferUsageFlags::VERTEX_BUFFER,
-    )
-    .unwrap();
-
-    let index_buffer = Buffer::new(
-        vulkan_context,
-        &[0, 1, 2, 0, 3, 1],
-        vk::BufferUsageFlags::INDEX_BUFFER,
-    )
-    .unwrap();
+    let geometry = vulkan_context
+        .mesh_registry
+        .add(vulkan_context, &vertices, &[0, 1, 2, 0, 3, 1])
+        .unwrap();
 
     let primitive = Primitive {
-        index_buffer,
-        vertex_buffer,
-        indices_count: 6,
+        geometry,
         material,
         texture_descriptor_set: descriptor_set,
+        resources,
+        morph_targets: None,
     };
 
-    // Create descriptor sets
-    let descriptor_sets = vulkan_context
-        .create_mesh_descriptor_sets(render_context.descriptor_set_layouts.mesh_layout, "GUI")
-        .unwrap();
-    let descriptor_sets = [descriptor_sets[0]];
-
-    let mesh_ubo = MeshUBO::default();
-    let ubo_buffer = Buffer::new(
+    Mesh::new(
         vulkan_context,
-        &[mesh_ubo],
-        vk::BufferUsageFlags::UNIFORM_BUFFER,
+        &render_context.descriptor_set_layouts,
+        "GUI",
+        vec![primitive],
+        Some(BoundingSphere::from_bounds(&positions[3], &positions[2])),
     )
-    .unwrap();
-    vulkan_context.update_buffer_descriptor_set(
-        &ubo_buffer,
-        descriptor_sets[0],
-        0,
-        vk::DescriptorType::UNIFORM_BUFFER,
-    );
-
-    Mesh {
-        descriptor_sets,
-        ubo_buffer,
-        ubo_data: mesh_ubo,
-        primitives: vec![primitive],
-    }
+    .unwrap()
 }
 
 fn get_material(
     output_texture: &Texture,
     vulkan_context: &VulkanContext,
     render_context: &RenderContext,
-) -> (Material, vk::DescriptorSet) {
+) -> (Material, vk::DescriptorSet, GpuResources) {
     let empty_texture = Texture::empty(vulkan_context).unwrap();
     // Descriptor set
     let descriptor_set = vulkan_context
@@ -185,7 +161,13 @@ fn get_material(
         alpha_mask_cutoff: 1.,
     };
 
-    (material, descriptor_set)
+    // The panel's texture goes when the panel does
+    let garbage = IntoIterator::into_iter(empty_texture.garbage())
+        .chain(std::iter::once(Garbage::DescriptorSet(descriptor_set)))
+        .collect();
+    let resources = GpuResources::new(vulkan_context, garbage);
+
+    (material, descriptor_set, resources)
 }
 
 /// Input to a panel
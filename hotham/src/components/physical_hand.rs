@@ -0,0 +1,116 @@
+use nalgebra::{Isometry3, Vector3};
+use rapier3d::prelude::ColliderHandle;
+
+/// A component that makes a hand solid, so that it can push, punch and press objects and is stopped by walls
+/// instead of passing through them. Added by `add_physical_hand`.
+///
+/// The hand's rigid body is dynamic, and `hands_system` sets its velocity each frame to move it towards the user's
+/// real hand. The hand's `Collider` is still a sensor, slightly larger than the solid collider, and is used for
+/// grabbing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalHand {
+    /// The hand's solid collider
+    pub solid_collider: ColliderHandle,
+    /// The fastest the hand can move to catch up with the user's hand, in meters per second
+    pub max_linear_speed: f32,
+    /// The fastest the hand can turn to catch up with the user's hand, in radians per second
+    pub max_angular_speed: f32,
+}
+
+impl PhysicalHand {
+    /// Create a physical hand that uses `solid_collider` to push things
+    pub fn new(solid_collider: ColliderHandle) -> Self {
+        Self {
+            solid_collider,
+            max_linear_speed: 20.,
+            max_angular_speed: 50.,
+        }
+    }
+
+    /// The linear and angular velocity that will move the hand from `current` to `target` in `delta_time` seconds,
+    /// limited to `max_linear_speed` and `max_angular_speed`.
+    pub fn velocity_towards(
+        &self,
+        current: &Isometry3<f32>,
+        target: &Isometry3<f32>,
+        delta_time: f32,
+    ) -> (Vector3<f32>, Vector3<f32>) {
+        if delta_time <= 0. {
+            return (Vector3::zeros(), Vector3::zeros());
+        }
+
+        let linear = (target.translation.vector - current.translation.vector) / delta_time;
+        let angular = (target.rotation * current.rotation.inverse()).scaled_axis() / delta_time;
+
+        (
+            linear.cap_magnitude(self.max_linear_speed),
+            angular.cap_magnitude(self.max_angular_speed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::RigidBody, resources::PhysicsContext, systems::hands::make_hand_physical,
+    };
+    use approx::assert_relative_eq;
+    use hecs::World;
+    use nalgebra::vector;
+    use rapier3d::prelude::{ColliderBuilder, RigidBodyBuilder};
+
+    #[test]
+    pub fn test_velocity_towards() {
+        let hand = PhysicalHand::new(ColliderHandle::invalid());
+        let current = Isometry3::identity();
+        let target = Isometry3::new(vector![0.1, 0., 0.], vector![0., 0.1, 0.]);
+
+        let (linear, angular) = hand.velocity_towards(&current, &target, 0.1);
+        assert_relative_eq!(linear, vector![1., 0., 0.], epsilon = 0.0001);
+        assert_relative_eq!(angular, vector![0., 1., 0.], epsilon = 0.0001);
+
+        // Teleporting hands should be limited to the maximum speed
+        let target = Isometry3::translation(100., 0., 0.);
+        let (linear, _) = hand.velocity_towards(&current, &target, 0.1);
+        assert_relative_eq!(linear, vector![20., 0., 0.], epsilon = 0.0001);
+    }
+
+    #[test]
+    pub fn test_hand_stops_at_wall() {
+        let mut world = World::new();
+        let mut physics_context = PhysicsContext::default();
+        let wall = ColliderBuilder::cuboid(0.1, 2., 2.)
+            .translation(vector![0.5, 0., 0.])
+            .build();
+        physics_context.colliders.insert(wall);
+
+        let hand = world.spawn(());
+        let rigid_body = RigidBodyBuilder::new_kinematic_position_based().build();
+        let collider = ColliderBuilder::capsule_y(0.05, 0.02).sensor(true).build();
+        let components = physics_context.get_rigid_body_and_collider(hand, rigid_body, collider);
+        world.insert(hand, components).unwrap();
+        make_hand_physical(&mut world, hand, &mut physics_context);
+
+        // Reach through the wall as fast as possible
+        let physical_hand = *world.get::<PhysicalHand>(hand).unwrap();
+        let handle = world.get::<RigidBody>(hand).unwrap().handle;
+        let target = Isometry3::translation(2., 0., 0.);
+        let delta_time = physics_context.integration_parameters.dt;
+        for _ in 0..60 {
+            let rigid_body = &mut physics_context.rigid_bodies[handle];
+            let (linear, angular) =
+                physical_hand.velocity_towards(rigid_body.position(), &target, delta_time);
+            rigid_body.set_linvel(linear, true);
+            rigid_body.set_angvel(angular, true);
+            physics_context.update();
+        }
+
+        let x = physics_context.rigid_bodies[handle].translation().x;
+        assert!(
+            x > 0.3 && x < 0.4,
+            "hand should be resting against the wall, but is at {}",
+            x
+        );
+    }
+}
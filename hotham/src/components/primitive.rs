@@ -1,36 +1,75 @@
-use crate::{buffer::Buffer, resources::VulkanContext, vertex::Vertex};
+use super::{material::LoadedMaterials, Material};
+use crate::{
+    deletion_queue::GpuResources,
+    resources::{MeshGeometry, VulkanContext},
+    vertex::Vertex,
+};
 use anyhow::{anyhow, Result};
 use ash::vk;
 use itertools::izip;
-use nalgebra::vector;
-
-use super::Material;
+use nalgebra::{vector, Vector4};
 
 /// Geometry for a mesh
 /// Automatically generated by `gltf_loader`
 #[derive(Debug, Clone, PartialEq)]
 pub struct Primitive {
-    /// Buffer for the indices
-    pub index_buffer: Buffer<u32>,
-    /// Buffer for the vertices
-    pub vertex_buffer: Buffer<Vertex>,
-    /// Number of vertices
-    pub indices_count: u32,
+    /// Vertices and indices, stored in `VulkanContext::mesh_registry`
+    pub geometry: MeshGeometry,
     /// Material used
     pub material: Material,
     /// Texture descriptor set
     pub texture_descriptor_set: vk::DescriptorSet,
+    /// Owns the material's textures and `texture_descriptor_set`, which are shared with other primitives using the
+    /// same material
+    pub resources: GpuResources,
+    /// Where this primitive's morph targets are in its mesh's `morph_target_buffer`, if it has any
+    pub morph_targets: Option<MorphTargets>,
+}
+
+/// How far one morph target moves one vertex
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MorphDelta {
+    /// Added to the vertex's position, scaled by the target's weight. `w` is unused.
+    pub position: Vector4<f32>,
+    /// Added to the vertex's normal, scaled by the target's weight. `w` is unused.
+    pub normal: Vector4<f32>,
+}
+
+/// The morph targets of a primitive. Each vertex has one `MorphDelta` per target, next to each other, starting at
+/// `first_delta` for the primitive's first vertex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MorphTargets {
+    /// The index of the primitive's first delta in the mesh's `morph_target_buffer`
+    pub first_delta: u32,
+    /// How many morph targets the primitive has
+    pub target_count: u32,
 }
 
 impl Primitive {
+    /// Load a primitive from a glTF mesh. Returns `None`, after printing a warning, if the primitive can't be drawn
+    /// by Hotham. Its morph targets are added to `morph_deltas`.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     pub(crate) fn load(
         textures_layout: vk::DescriptorSetLayout,
         mesh_name: &str,
         primitive_data: gltf::Primitive,
-        buffer: &[u8],
+        buffers: &[gltf::buffer::Data],
         vulkan_context: &VulkanContext,
         images: &[gltf::image::Data],
-    ) -> Result<Self> {
+        materials: &mut LoadedMaterials,
+        morph_deltas: &mut Vec<MorphDelta>,
+    ) -> Result<Option<Self>> {
+        if let Err(reason) = check_supported(&primitive_data) {
+            println!(
+                "[HOTHAM_GLTF] WARNING: Skipping primitive {} of mesh {}: {}",
+                primitive_data.index(),
+                mesh_name,
+                reason
+            );
+            return Ok(None);
+        }
+
         let mut indices = Vec::new();
         let mut positions = Vec::new();
         let mut tex_coords_0 = Vec::new();
@@ -39,7 +78,7 @@ impl Primitive {
         let mut joint_indices = Vec::new();
         let mut joint_weights = Vec::new();
 
-        let reader = primitive_data.reader(|_| Some(buffer));
+        let reader = primitive_data.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
 
         // Positions
         for v in reader
@@ -56,6 +95,17 @@ impl Primitive {
             }
         }
 
+        if let Some(i) = indices.iter().find(|i| **i as usize >= positions.len()) {
+            println!(
+                "[HOTHAM_GLTF] WARNING: Skipping primitive {} of mesh {}: index {} is out of range for {} vertices",
+                primitive_data.index(),
+                mesh_name,
+                i,
+                positions.len()
+            );
+            return Ok(None);
+        }
+
         // Normals
         if let Some(iter) = reader.read_normals() {
             for v in iter {
@@ -107,14 +157,44 @@ impl Primitive {
             }
         }
 
-        let (material, texture_descriptor_set) = Material::load(
-            mesh_name,
-            textures_layout,
-            primitive_data.material(),
-            vulkan_context,
-            buffer,
-            images,
-        )?;
+        let morph_targets = match read_morph_targets(&reader, positions.len()) {
+            Ok(deltas) if deltas.is_empty() => None,
+            Ok(deltas) => {
+                let first_delta = morph_deltas.len() as u32;
+                morph_deltas.extend(deltas);
+                Some(MorphTargets {
+                    first_delta,
+                    target_count: primitive_data.morph_targets().len() as _,
+                })
+            }
+            Err(reason) => {
+                println!(
+                    "[HOTHAM_GLTF] WARNING: Ignoring the morph targets of primitive {} of mesh {}: {}",
+                    primitive_data.index(),
+                    mesh_name,
+                    reason
+                );
+                None
+            }
+        };
+
+        // Primitives with the same material share its textures
+        let material_data = primitive_data.material();
+        let key = material_data.index();
+        let (material, texture_descriptor_set, resources) = match materials.get(&key) {
+            Some(loaded) => loaded.clone(),
+            None => {
+                let loaded = Material::load(
+                    mesh_name,
+                    textures_layout,
+                    material_data,
+                    vulkan_context,
+                    images,
+                )?;
+                materials.insert(key, loaded.clone());
+                loaded
+            }
+        };
 
         let vertices: Vec<Vertex> = izip!(
             positions,
@@ -128,21 +208,155 @@ impl Primitive {
         .map(Vertex::from_zip)
         .collect();
 
-        // Create buffers
-        let vertex_buffer = Buffer::new(
-            vulkan_context,
-            &vertices,
-            vk::BufferUsageFlags::VERTEX_BUFFER,
-        )?;
-        let index_buffer =
-            Buffer::new(vulkan_context, &indices, vk::BufferUsageFlags::INDEX_BUFFER)?;
+        let geometry = vulkan_context
+            .mesh_registry
+            .add(vulkan_context, &vertices, &indices)?;
 
-        Ok(Primitive {
+        Ok(Some(Primitive {
             material,
-            index_buffer,
-            vertex_buffer,
-            indices_count: indices.len() as _,
+            geometry,
             texture_descriptor_set,
-        })
+            resources,
+            morph_targets,
+        }))
+    }
+}
+
+/// Read the deltas of each morph target, interleaved so that each vertex's deltas are next to each other
+fn read_morph_targets<'a, 's, F>(
+    reader: &gltf::mesh::Reader<'a, 's, F>,
+    vertex_count: usize,
+) -> std::result::Result<Vec<MorphDelta>, String>
+where
+    F: Clone + Fn(gltf::Buffer<'a>) -> Option<&'s [u8]>,
+{
+    let targets = reader.read_morph_targets();
+    let target_count = targets.len();
+
+    let mut deltas = vec![MorphDelta::default(); vertex_count * target_count];
+    for (target, (positions, normals, _)) in targets.enumerate() {
+        let positions = positions.map(|p| p.collect::<Vec<_>>()).unwrap_or_default();
+        let normals = normals.map(|n| n.collect::<Vec<_>>()).unwrap_or_default();
+        for (values, name) in [(&positions, "positions"), (&normals, "normals")] {
+            if !values.is_empty() && values.len() != vertex_count {
+                return Err(format!(
+                    "target {} has {} {} but there are {} vertices",
+                    target,
+                    values.len(),
+                    name,
+                    vertex_count
+                ));
+            }
+        }
+
+        for (i, p) in positions.iter().enumerate() {
+            deltas[i * target_count + target].position = vector![p[0], p[1], p[2], 0.];
+        }
+        for (i, n) in normals.iter().enumerate() {
+            deltas[i * target_count + target].normal = vector![n[0], n[1], n[2], 0.];
+        }
+    }
+
+    Ok(deltas)
+}
+
+/// Check that the primitive is something `rendering_system` knows how to draw
+fn check_supported(primitive_data: &gltf::Primitive) -> std::result::Result<(), String> {
+    if primitive_data.mode() != gltf::mesh::Mode::Triangles {
+        return Err(format!(
+            "{:?} primitives are not supported, only triangles",
+            primitive_data.mode()
+        ));
+    }
+
+    if primitive_data.get(&gltf::Semantic::Positions).is_none() {
+        return Err("it has no positions".to_string());
+    }
+
+    // Every vertex attribute must have a value for each vertex
+    let vertex_count = primitive_data
+        .get(&gltf::Semantic::Positions)
+        .map(|a| a.count())
+        .unwrap_or_default();
+    if let Some((semantic, accessor)) = primitive_data
+        .attributes()
+        .find(|(_, accessor)| accessor.count() != vertex_count)
+    {
+        return Err(format!(
+            "{:?} has {} values but there are {} vertices",
+            semantic,
+            accessor.count(),
+            vertex_count
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floats(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|f| f.to_le_bytes()).collect()
+    }
+
+    #[test]
+    pub fn test_read_morph_targets() {
+        // A triangle with two morph targets. Only the second one moves the normals.
+        let buffer = [
+            floats(&[0., 0., 0., 1., 0., 0., 0., 1., 0.]),
+            floats(&[1., 0., 0., 2., 0., 0., 3., 0., 0.]),
+            floats(&[0., 1., 0., 0., 2., 0., 0., 3., 0.]),
+            floats(&[0., 0., 1., 0., 0., 2., 0., 0., 3.]),
+        ]
+        .concat();
+        let accessors = (0..4)
+            .map(|i| {
+                format!(
+                    r#"{{ "bufferView": {}, "componentType": 5126, "count": 3, "type": "VEC3",
+                        "min": [0, 0, 0], "max": [3, 3, 3] }}"#,
+                    i
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let buffer_views = (0..4)
+            .map(|i| {
+                format!(
+                    r#"{{ "buffer": 0, "byteOffset": {}, "byteLength": 36 }}"#,
+                    i * 36
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let gltf = format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{{ "byteLength": 144 }}],
+                "bufferViews": [{}],
+                "accessors": [{}],
+                "meshes": [{{ "primitives": [{{
+                    "attributes": {{ "POSITION": 0 }},
+                    "targets": [{{ "POSITION": 1 }}, {{ "POSITION": 2, "NORMAL": 3 }}]
+                }}] }}]
+            }}"#,
+            buffer_views, accessors
+        );
+
+        let gltf = gltf::Gltf::from_slice(gltf.as_bytes()).unwrap();
+        let primitive = gltf.meshes().next().unwrap().primitives().next().unwrap();
+        let reader = primitive.reader(|_| Some(&buffer));
+        let deltas = read_morph_targets(&reader, 3).unwrap();
+
+        // Each vertex's deltas are next to each other
+        assert_eq!(deltas.len(), 6);
+        assert_eq!(deltas[2].position, vector![2., 0., 0., 0.]);
+        assert_eq!(deltas[3].position, vector![0., 2., 0., 0.]);
+        assert_eq!(deltas[2].normal, Vector4::zeros());
+        assert_eq!(deltas[3].normal, vector![0., 0., 2., 0.]);
+
+        // The targets have to cover every vertex
+        assert!(read_morph_targets(&reader, 4).is_err());
     }
 }
@@ -1,7 +1,13 @@
-/// Component added to an entity to point to the joints in the node
-/// Automatically added by `gltf_loader`
+use hecs::Entity;
+use nalgebra::Matrix4;
+
+/// Component added to an entity to point to the joints that deform its `Mesh`
+/// Automatically added by `gltf_loader`. Meshes with equal skins, like two meshes using the same glTF skin, share
+/// one set of joint matrices on the GPU.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Skin {
-    /// List of joints, represented by their node ID
-    pub joint_ids: Vec<usize>,
+    /// The entity of each joint, in the order the mesh's vertices refer to them
+    pub joints: Vec<Entity>,
+    /// Inverse bind matrix of each joint, used to apply the skin in the vertex shader
+    pub inverse_bind_matrices: Vec<Matrix4<f32>>,
 }
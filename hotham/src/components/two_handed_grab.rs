@@ -0,0 +1,138 @@
+use nalgebra::{Isometry3, Matrix3, Rotation3, Translation3, UnitQuaternion, Vector3};
+
+/// A component added to an entity while it is held in both hands. Added and removed by `grabbing_system`, and used
+/// by `hands_system` to move the entity with both hands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoHandedGrab {
+    /// Where the entity is relative to the hands, in the space returned by `TwoHandedGrab::hands_pose`
+    pub offset: Isometry3<f32>,
+    /// How far apart the hands were when the second hand grabbed the entity
+    pub initial_distance: f32,
+    /// The entity's scale when the second hand grabbed it
+    pub initial_scale: Vector3<f32>,
+}
+
+impl TwoHandedGrab {
+    /// Start holding an entity at `pose` with both hands
+    pub fn new(
+        left_hand: &Isometry3<f32>,
+        right_hand: &Isometry3<f32>,
+        pose: &Isometry3<f32>,
+        scale: Vector3<f32>,
+    ) -> Self {
+        Self {
+            offset: Self::hands_pose(left_hand, right_hand).inverse() * pose,
+            initial_distance: distance(left_hand, right_hand),
+            initial_scale: scale,
+        }
+    }
+
+    /// A pose halfway between the hands, with +X pointing from the left hand to the right hand and +Y pointing up
+    /// from the backs of both hands. Turning, tilting or moving the hands together moves this pose the same way.
+    pub fn hands_pose(left_hand: &Isometry3<f32>, right_hand: &Isometry3<f32>) -> Isometry3<f32> {
+        let left = left_hand.translation.vector;
+        let right = right_hand.translation.vector;
+        let midpoint = Translation3::from((left + right) / 2.);
+
+        let x = match (right - left).try_normalize(f32::EPSILON) {
+            Some(x) => x,
+            None => return Isometry3::from_parts(midpoint, left_hand.rotation),
+        };
+        let up = left_hand.rotation * Vector3::y() + right_hand.rotation * Vector3::y();
+        let y = (up - x * x.dot(&up))
+            .try_normalize(f32::EPSILON)
+            .or_else(|| {
+                let up = Vector3::y();
+                (up - x * x.dot(&up)).try_normalize(f32::EPSILON)
+            })
+            .unwrap_or_else(|| x.cross(&Vector3::z()).normalize());
+        let z = x.cross(&y);
+        let rotation = Rotation3::from_matrix_unchecked(Matrix3::from_columns(&[x, y, z]));
+
+        Isometry3::from_parts(midpoint, UnitQuaternion::from_rotation_matrix(&rotation))
+    }
+
+    /// Where the entity should be for the current hand poses
+    pub fn pose(&self, left_hand: &Isometry3<f32>, right_hand: &Isometry3<f32>) -> Isometry3<f32> {
+        Self::hands_pose(left_hand, right_hand) * self.offset
+    }
+
+    /// How much bigger the entity should be, given how far the hands have moved apart since they grabbed it,
+    /// clamped between `min` and `max`.
+    pub fn scale(
+        &self,
+        left_hand: &Isometry3<f32>,
+        right_hand: &Isometry3<f32>,
+        min: f32,
+        max: f32,
+    ) -> f32 {
+        if self.initial_distance <= f32::EPSILON {
+            return 1.;
+        }
+        (distance(left_hand, right_hand) / self.initial_distance).clamp(min, max)
+    }
+
+    /// Like `pose`, but with the entity's distance from the hands scaled by `scale`, so that it grows and shrinks
+    /// around the point between them.
+    pub fn scaled_pose(
+        &self,
+        left_hand: &Isometry3<f32>,
+        right_hand: &Isometry3<f32>,
+        scale: f32,
+    ) -> Isometry3<f32> {
+        let offset = Isometry3::from_parts(
+            Translation3::from(self.offset.translation.vector * scale),
+            self.offset.rotation,
+        );
+        Self::hands_pose(left_hand, right_hand) * offset
+    }
+}
+
+fn distance(a: &Isometry3<f32>, b: &Isometry3<f32>) -> f32 {
+    (a.translation.vector - b.translation.vector).norm()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::vector;
+
+    #[test]
+    pub fn test_two_handed_pose() {
+        // Hold a stick out in front with both hands
+        let left_hand = Isometry3::translation(-0.2, 1., -0.5);
+        let right_hand = Isometry3::translation(0.2, 1., -0.5);
+        let stick = Isometry3::translation(0., 1., -0.6);
+        let grab = TwoHandedGrab::new(&left_hand, &right_hand, &stick, vector![1., 1., 1.]);
+        assert_relative_eq!(grab.pose(&left_hand, &right_hand), stick, epsilon = 0.0001);
+
+        // Pull the right hand back and push the left hand forward, turning the stick 90 degrees to the right
+        let left_hand = Isometry3::translation(0., 1., -0.7);
+        let right_hand = Isometry3::translation(0., 1., -0.3);
+        let pose = grab.pose(&left_hand, &right_hand);
+        assert_relative_eq!(
+            pose.translation.vector,
+            vector![0.1, 1., -0.5],
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(
+            pose.rotation.angle(),
+            std::f32::consts::FRAC_PI_2,
+            epsilon = 0.0001
+        );
+
+        // Pulling the hands further apart should scale the stick, but only as far as allowed
+        let left_hand = Isometry3::translation(-0.4, 1., -0.5);
+        let right_hand = Isometry3::translation(0.4, 1., -0.5);
+        assert_relative_eq!(grab.scale(&left_hand, &right_hand, 0.1, 10.), 2.);
+        assert_relative_eq!(grab.scale(&left_hand, &right_hand, 0.1, 1.5), 1.5);
+        assert_relative_eq!(
+            grab.scaled_pose(&left_hand, &right_hand, 2.)
+                .translation
+                .vector,
+            vector![0., 1., -0.7],
+            epsilon = 0.0001
+        );
+    }
+}
@@ -0,0 +1,19 @@
+/// A component that lets an entity be resized by pulling it apart or squeezing it together with both hands.
+/// Used by `hands_system`, which scales the entity's `Transform` and the colliders of its rigid body. Entities with a
+/// collider that isn't a ball, cuboid, capsule, cylinder or cone can't be resized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TwoHandedScaling {
+    /// The smallest the entity can be made, relative to its size when it was grabbed
+    pub min_scale: f32,
+    /// The largest the entity can be made, relative to its size when it was grabbed
+    pub max_scale: f32,
+}
+
+impl Default for TwoHandedScaling {
+    fn default() -> Self {
+        Self {
+            min_scale: 0.1,
+            max_scale: 10.,
+        }
+    }
+}
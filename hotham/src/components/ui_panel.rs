@@ -19,7 +19,6 @@ use super::{Collider, Transform, TransformMatrix, Visible};
 
 /// A component added to an entity to display a 2D "panel" in space
 /// Used by `panels_system`
-#[derive(Clone)]
 pub struct UIPanel {
     /// The text to be displayed
     pub text: String,
@@ -0,0 +1,258 @@
+use std::{
+    ops::Range,
+    sync::{Arc, Mutex},
+};
+
+use ash::vk;
+
+use crate::{
+    allocator::Allocation, buffer::Buffer, image::Image, resources::VulkanContext,
+    texture::Texture, SWAPCHAIN_LENGTH,
+};
+
+/// A Vulkan object that is no longer needed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Garbage {
+    Buffer(vk::Buffer, Allocation),
+    Image(vk::Image, vk::ImageView, Allocation),
+    Sampler(vk::Sampler),
+    DescriptorSet(vk::DescriptorSet),
+    /// Space in one of the `MeshRegistry`'s blocks
+    Geometry {
+        block: usize,
+        vertices: Range<u64>,
+        indices: Range<u64>,
+    },
+}
+
+/// Holds on to `Garbage` until every frame that could be using it has finished on the GPU.
+/// `RenderContext::begin_frame` collects it once the frame's fence has been waited on.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DeletionQueue {
+    queue: Arc<Mutex<Queue>>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    frame: u64,
+    pending: Vec<(u64, Garbage)>,
+}
+
+impl DeletionQueue {
+    pub fn push(&self, garbage: Garbage) {
+        let mut queue = self.queue.lock().unwrap();
+        let frame = queue.frame;
+        queue.pending.push((frame, garbage));
+    }
+
+    /// Move on to the next frame, returning any garbage that was thrown out `SWAPCHAIN_LENGTH` frames ago
+    pub fn begin_frame(&self) -> Vec<Garbage> {
+        let mut queue = self.queue.lock().unwrap();
+        queue.frame += 1;
+
+        let frame = queue.frame;
+        let (ready, pending): (Vec<_>, Vec<_>) = queue
+            .pending
+            .drain(..)
+            .partition(|(f, _)| f + SWAPCHAIN_LENGTH as u64 <= frame);
+        queue.pending = pending;
+
+        ready.into_iter().map(|(_, g)| g).collect()
+    }
+}
+
+/// GPU resources owned by a component, like a `Mesh`'s uniform buffer or a `Primitive`'s textures. Clones share the
+/// resources, which are destroyed when the last clone is dropped (eg. when the last entity using them is despawned)
+/// and the GPU has finished with them.
+#[derive(Debug, Clone, Default)]
+pub struct GpuResources {
+    owned: Option<Arc<Owned>>,
+}
+
+#[derive(Debug)]
+struct Owned {
+    deletion_queue: DeletionQueue,
+    garbage: Vec<Garbage>,
+}
+
+impl GpuResources {
+    pub(crate) fn new(vulkan_context: &VulkanContext, garbage: Vec<Garbage>) -> Self {
+        Self {
+            owned: Some(Arc::new(Owned {
+                deletion_queue: vulkan_context.deletion_queue.clone(),
+                garbage,
+            })),
+        }
+    }
+}
+
+impl Drop for Owned {
+    fn drop(&mut self) {
+        for garbage in self.garbage.drain(..) {
+            self.deletion_queue.push(garbage);
+        }
+    }
+}
+
+/// Resources are equal if they are shared
+impl PartialEq for GpuResources {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.owned, &other.owned) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Something that can be destroyed once the GPU has finished with it, like a `Buffer`, `Image` or `Texture`
+pub trait IntoGarbage {
+    /// Give up the object, so that it can be destroyed
+    fn into_garbage(self) -> Vec<Garbage>;
+}
+
+impl<T> IntoGarbage for Buffer<T> {
+    fn into_garbage(self) -> Vec<Garbage> {
+        vec![self.garbage()]
+    }
+}
+
+impl IntoGarbage for Image {
+    fn into_garbage(self) -> Vec<Garbage> {
+        vec![self.garbage()]
+    }
+}
+
+impl IntoGarbage for Texture {
+    fn into_garbage(self) -> Vec<Garbage> {
+        self.garbage().to_vec()
+    }
+}
+
+/// A `Buffer`, `Image` or `Texture` that is shared between components, like the uniform buffer that copies of a `Mesh`
+/// share. Clones share it, and it's destroyed when the last clone is dropped and the GPU has finished with it.
+#[derive(Debug)]
+pub struct Shared<T: IntoGarbage> {
+    inner: Arc<SharedInner<T>>,
+}
+
+#[derive(Debug)]
+struct SharedInner<T: IntoGarbage> {
+    value: Option<T>,
+    deletion_queue: Option<DeletionQueue>,
+}
+
+impl<T: IntoGarbage> Shared<T> {
+    pub(crate) fn new(vulkan_context: &VulkanContext, value: T) -> Self {
+        Self {
+            inner: Arc::new(SharedInner {
+                value: Some(value),
+                deletion_queue: Some(vulkan_context.deletion_queue.clone()),
+            }),
+        }
+    }
+
+    /// Share something that is never destroyed, like the placeholder buffers used in tests
+    #[cfg(test)]
+    pub(crate) fn untracked(value: T) -> Self {
+        Self {
+            inner: Arc::new(SharedInner {
+                value: Some(value),
+                deletion_queue: None,
+            }),
+        }
+    }
+}
+
+impl<T: IntoGarbage> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: IntoGarbage> std::ops::Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // Only taken when the last clone is dropped
+        self.inner.value.as_ref().unwrap()
+    }
+}
+
+/// Shared objects are equal if they are the same object
+impl<T: IntoGarbage> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl<T: IntoGarbage> Drop for SharedInner<T> {
+    fn drop(&mut self) {
+        if let (Some(value), Some(deletion_queue)) = (self.value.take(), &self.deletion_queue) {
+            for garbage in value.into_garbage() {
+                deletion_queue.push(garbage);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_buffer;
+
+    #[test]
+    pub fn test_garbage_is_collected_after_frames_in_flight() {
+        let deletion_queue = DeletionQueue::default();
+        let resources = GpuResources {
+            owned: Some(Arc::new(Owned {
+                deletion_queue: deletion_queue.clone(),
+                garbage: vec![Garbage::Sampler(vk::Sampler::null())],
+            })),
+        };
+
+        // Nothing happens while the resources are still being used
+        let clone = resources.clone();
+        drop(resources);
+        assert!(deletion_queue.begin_frame().is_empty());
+
+        drop(clone);
+        for _ in 0..SWAPCHAIN_LENGTH - 1 {
+            assert!(deletion_queue.begin_frame().is_empty());
+        }
+        assert_eq!(
+            deletion_queue.begin_frame(),
+            vec![Garbage::Sampler(vk::Sampler::null())]
+        );
+        assert!(deletion_queue.begin_frame().is_empty());
+    }
+
+    #[test]
+    pub fn test_shared_is_destroyed_once_by_the_last_clone() {
+        let deletion_queue = DeletionQueue::default();
+        let buffer: Shared<Buffer<u32>> = Shared {
+            inner: Arc::new(SharedInner {
+                value: Some(test_buffer()),
+                deletion_queue: Some(deletion_queue.clone()),
+            }),
+        };
+
+        let clone = buffer.clone();
+        assert_eq!(clone, buffer);
+        drop(buffer);
+        for _ in 0..SWAPCHAIN_LENGTH {
+            assert!(deletion_queue.begin_frame().is_empty());
+        }
+
+        drop(clone);
+        for _ in 0..SWAPCHAIN_LENGTH - 1 {
+            assert!(deletion_queue.begin_frame().is_empty());
+        }
+        assert_eq!(
+            deletion_queue.begin_frame(),
+            vec![Garbage::Buffer(vk::Buffer::null(), Default::default())]
+        );
+    }
+}
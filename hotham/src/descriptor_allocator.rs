@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use ash::{prelude::VkResult, vk, Device};
+
+/// How many descriptor sets each pool can hold
+pub const SETS_PER_POOL: u32 = 256;
+
+/// Allocates descriptor sets from a growing list of pools. A new pool is created whenever the existing ones are
+/// full, and sets can be freed individually.
+#[derive(Debug, Default)]
+pub(crate) struct DescriptorAllocator {
+    pools: Vec<vk::DescriptorPool>,
+    owners: HashMap<vk::DescriptorSet, vk::DescriptorPool>,
+}
+
+impl DescriptorAllocator {
+    pub fn allocate(
+        &mut self,
+        device: &Device,
+        set_layouts: &[vk::DescriptorSetLayout],
+    ) -> VkResult<Vec<vk::DescriptorSet>> {
+        // Newer pools are more likely to have room
+        for &pool in self.pools.iter().rev() {
+            match allocate_from_pool(device, pool, set_layouts) {
+                Ok(sets) => return Ok(self.track(pool, sets)),
+                Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY)
+                | Err(vk::Result::ERROR_FRAGMENTED_POOL) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        println!(
+            "[HOTHAM_VULKAN] Creating descriptor pool {}..",
+            self.pools.len()
+        );
+        let pool = create_descriptor_pool(device)?;
+        self.pools.push(pool);
+        let sets = allocate_from_pool(device, pool, set_layouts)?;
+        Ok(self.track(pool, sets))
+    }
+
+    pub fn free(&mut self, device: &Device, descriptor_set: vk::DescriptorSet) -> VkResult<()> {
+        match self.owners.remove(&descriptor_set) {
+            Some(pool) => unsafe { device.free_descriptor_sets(pool, &[descriptor_set]) },
+            None => Ok(()),
+        }
+    }
+
+    fn track(
+        &mut self,
+        pool: vk::DescriptorPool,
+        sets: Vec<vk::DescriptorSet>,
+    ) -> Vec<vk::DescriptorSet> {
+        for set in &sets {
+            self.owners.insert(*set, pool);
+        }
+        sets
+    }
+}
+
+fn allocate_from_pool(
+    device: &Device,
+    pool: vk::DescriptorPool,
+    set_layouts: &[vk::DescriptorSetLayout],
+) -> VkResult<Vec<vk::DescriptorSet>> {
+    unsafe {
+        device.allocate_descriptor_sets(
+            &vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(pool)
+                .set_layouts(set_layouts),
+        )
+    }
+}
+
+/// Room for `SETS_PER_POOL` sets. Most sets are materials, which have five textures each.
+fn create_descriptor_pool(device: &Device) -> VkResult<vk::DescriptorPool> {
+    unsafe {
+        device.create_descriptor_pool(
+            &vk::DescriptorPoolCreateInfo::builder()
+                .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+                .pool_sizes(&[
+                    vk::DescriptorPoolSize {
+                        ty: vk::DescriptorType::UNIFORM_BUFFER,
+                        descriptor_count: SETS_PER_POOL,
+                    },
+                    vk::DescriptorPoolSize {
+                        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                        descriptor_count: SETS_PER_POOL * 5,
+                    },
+                    vk::DescriptorPoolSize {
+                        ty: vk::DescriptorType::STORAGE_BUFFER,
+                        descriptor_count: SETS_PER_POOL * 2,
+                    },
+                ])
+                .max_sets(SETS_PER_POOL),
+            None,
+        )
+    }
+}
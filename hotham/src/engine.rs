@@ -1,9 +1,10 @@
 use crate::{
     resources::{
-        AudioContext, GuiContext, HapticContext, PhysicsContext, RenderContext, VulkanContext,
-        XrContext, XrContextBuilder,
+        xr_context::{ActionMap, HeadlessXr},
+        AudioContext, GuiContext, HapticContext, LocomotionContext, PhysicsContext, RenderContext,
+        VulkanContext, XrContext, XrContextBuilder,
     },
-    HothamError, HothamResult, VIEW_TYPE,
+    HothamError, HothamResult, RenderSettings,
 };
 use openxr as xr;
 
@@ -31,6 +32,9 @@ pub struct EngineBuilder<'a> {
     application_name: Option<&'a str>,
     application_version: Option<u32>,
     openxr_extensions: Option<xr::ExtensionSet>,
+    headless: Option<HeadlessXr>,
+    action_map: Option<ActionMap>,
+    render_settings: Option<RenderSettings>,
 }
 
 impl<'a> EngineBuilder<'a> {
@@ -57,6 +61,27 @@ impl<'a> EngineBuilder<'a> {
         self
     }
 
+    /// Run without a headset or display, driving the session from `headless` instead of OpenXR.
+    /// Useful for running the whole frame loop in tests.
+    pub fn headless(&mut self, headless: Option<HeadlessXr>) -> &mut Self {
+        self.headless = headless;
+        self
+    }
+
+    /// Set the input actions the application uses and how they are bound to each controller.
+    /// Defaults to `ActionMap::default()`, which is what Hotham's built in systems use.
+    pub fn action_map(&mut self, action_map: Option<ActionMap>) -> &mut Self {
+        self.action_map = action_map;
+        self
+    }
+
+    /// Set the MSAA level, render scale, depth format and foveation used by the renderer.
+    /// Defaults to `RenderSettings::default()`.
+    pub fn render_settings(&mut self, render_settings: Option<RenderSettings>) -> &mut Self {
+        self.render_settings = render_settings;
+        self
+    }
+
     /// Build the `Engine`
     pub fn build(self) -> Engine {
         #[allow(unused_mut)] // Only Android mutates this.
@@ -67,9 +92,10 @@ impl<'a> EngineBuilder<'a> {
         #[cfg(target_os = "android")]
         process_android_events(&mut resumed, &should_quit);
 
-        // On desktop, register a Ctrl-C handler.
+        // On desktop, register a Ctrl-C handler. Headless engines are driven by their script, and there may be
+        // several of them in one process, so leave Ctrl-C alone.
         #[cfg(not(target_os = "android"))]
-        {
+        if self.headless.is_none() {
             let should_quit = should_quit.clone();
             ctrlc::set_handler(move || should_quit.store(true, Ordering::Relaxed)).unwrap();
         }
@@ -79,6 +105,9 @@ impl<'a> EngineBuilder<'a> {
             .application_name(self.application_name)
             .application_version(self.application_version)
             .required_extensions(self.openxr_extensions)
+            .headless(self.headless)
+            .action_map(self.action_map)
+            .render_settings(self.render_settings)
             .build()
             .expect("!!FATAL ERROR - Unable to initialize OpenXR!!");
         let render_context = RenderContext::new(&vulkan_context, &xr_context)
@@ -97,6 +126,7 @@ impl<'a> EngineBuilder<'a> {
             audio_context: Default::default(),
             gui_context,
             haptic_context: Default::default(),
+            locomotion_context: Default::default(),
         };
 
         engine.update().unwrap();
@@ -127,6 +157,8 @@ pub struct Engine {
     pub gui_context: GuiContext,
     /// Haptics context
     pub haptic_context: HapticContext,
+    /// Locomotion context
+    pub locomotion_context: LocomotionContext,
 }
 
 impl Engine {
@@ -155,7 +187,7 @@ impl Engine {
                 sleep(Duration::from_millis(100)); // Sleep to avoid thrashing the CPU
             }
             (SessionState::IDLE, SessionState::READY) => {
-                self.xr_context.session.begin(VIEW_TYPE)?;
+                self.xr_context.begin_session()?;
             }
             (_, SessionState::EXITING) => {
                 // Show's over
@@ -232,3 +264,158 @@ pub fn poll_android_events(resumed: bool) -> Option<ndk_glue::Event> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::{hand::Handedness, Hand},
+        gltf_loader,
+        resources::{
+            input_context::InputValue,
+            xr_context::{ActionMap, ActionType, HeadlessXr, THUMBSTICK},
+        },
+        schedule_functions::{
+            apply_haptic_feedback, begin_frame, begin_pbr_renderpass, end_frame,
+            end_pbr_renderpass, physics_step,
+        },
+        systems::{
+            hands::add_hand, hands_system, lighting_system, locomotion_system, rendering_system,
+            shadows_system, update_parent_transform_matrix_system,
+            update_rigid_body_transforms_system, update_transform_matrix_system, Queries,
+        },
+    };
+    use hecs::World;
+    use nalgebra::Vector2;
+
+    #[test]
+    pub fn test_headless_engine() {
+        let headless = HeadlessXr::new().script(|frame_number, frame| {
+            frame.right_hand.grip_value = if frame_number > 1 { 1.0 } else { 0.0 };
+            frame
+                .right_hand
+                .inputs
+                .insert("jump".to_string(), InputValue::Boolean(frame_number == 3));
+            let thumbstick = if frame_number == 2 { 1. } else { 0. };
+            frame.right_hand.inputs.insert(
+                THUMBSTICK.to_string(),
+                InputValue::Vector2(Vector2::new(thumbstick, 0.)),
+            );
+        });
+        let mut builder = EngineBuilder::new();
+        builder
+            .headless(Some(headless))
+            .action_map(Some(ActionMap::default().action(
+                "jump",
+                "Jump",
+                ActionType::Boolean,
+            )));
+        let mut engine = builder.build();
+
+        let mut world = World::default();
+        let glb_buffers: Vec<&[u8]> = vec![include_bytes!("../../test_assets/right_hand.glb")];
+        let models = gltf_loader::load_models_from_glb(
+            &glb_buffers,
+            &engine.vulkan_context,
+            &engine.render_context.descriptor_set_layouts,
+        )
+        .unwrap();
+        add_hand(
+            &models,
+            Handedness::Right,
+            &mut world,
+            &engine.vulkan_context,
+            &engine.render_context,
+            &mut engine.physics_context,
+        );
+        let mut queries = Default::default();
+
+        // The session should become focused without any intervention.
+        let mut current_state = engine.xr_context.session_state;
+        while current_state != SessionState::FOCUSED {
+            current_state = engine.update().unwrap().1;
+        }
+
+        for _ in 0..3 {
+            engine.update().unwrap();
+            engine
+                .haptic_context
+                .request_haptic_feedback(0.5, Handedness::Right);
+            tick(&mut engine, &mut world, &mut queries);
+        }
+
+        let headless = engine.xr_context.headless().unwrap();
+        assert_eq!(headless.frame_number(), 3);
+        assert_eq!(headless.haptics.len(), 3);
+        assert_eq!(headless.haptics[0].handedness, Handedness::Right);
+        assert_eq!(engine.xr_context.frame_index, 2);
+        let (_, hand) = world.query_mut::<&Hand>().into_iter().next().unwrap();
+        assert_eq!(hand.grip_value, 1.0);
+        assert!(engine
+            .xr_context
+            .input
+            .just_pressed("jump", Handedness::Right));
+        assert!(!engine.xr_context.input.boolean("jump", Handedness::Left));
+
+        // Pushing the right thumbstick to the side should have snap turned the user once.
+        let stage_rotation = engine.xr_context.stage_pose().rotation;
+        assert!((stage_rotation.angle() - std::f32::consts::FRAC_PI_6).abs() < 0.0001);
+
+        // Quitting from the "headset" should shut the engine down.
+        engine.xr_context.headless_mut().unwrap().request_exit();
+        loop {
+            match engine.update() {
+                Ok(_) => continue,
+                Err(HothamError::ShuttingDown) => break,
+                Err(e) => panic!("Unexpected error: {:?}", e),
+            }
+        }
+    }
+
+    fn tick(engine: &mut Engine, world: &mut World, queries: &mut Queries) {
+        let xr_context = &mut engine.xr_context;
+        let vulkan_context = &engine.vulkan_context;
+        let render_context = &mut engine.render_context;
+        let physics_context = &mut engine.physics_context;
+
+        begin_frame(xr_context, vulkan_context, render_context);
+        locomotion_system(xr_context, physics_context, &mut engine.locomotion_context);
+        hands_system(&mut queries.hands_query, world, xr_context, physics_context);
+        physics_step(physics_context);
+        update_rigid_body_transforms_system(
+            &mut queries.update_rigid_body_transforms_query,
+            world,
+            physics_context,
+        );
+        update_transform_matrix_system(&mut queries.update_transform_matrix_query, world);
+        update_parent_transform_matrix_system(
+            &mut queries.parent_query,
+            &mut queries.roots_query,
+            world,
+        );
+        apply_haptic_feedback(xr_context, &mut engine.haptic_context);
+        lighting_system(
+            &mut queries.lighting_query,
+            world,
+            vulkan_context,
+            render_context,
+        );
+        shadows_system(
+            &mut queries.shadows_query,
+            world,
+            vulkan_context,
+            xr_context.frame_index,
+            render_context,
+        );
+        begin_pbr_renderpass(xr_context, vulkan_context, render_context);
+        rendering_system(
+            &mut queries.rendering_query,
+            world,
+            vulkan_context,
+            xr_context.frame_index,
+            render_context,
+        );
+        end_pbr_renderpass(xr_context, vulkan_context, render_context);
+        end_frame(xr_context, vulkan_context, render_context);
+    }
+}
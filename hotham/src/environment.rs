@@ -0,0 +1,629 @@
+use std::{f32::consts::PI, io::Cursor};
+
+use anyhow::{anyhow, Result};
+use ash::vk;
+use image::codecs::hdr::HdrDecoder;
+use nalgebra::{vector, Vector3};
+
+use crate::{
+    resources::VulkanContext,
+    texture::{load_ktx2, Texture},
+};
+
+/// Width and height of each face of a generated irradiance cubemap
+const IRRADIANCE_SIZE: usize = 32;
+/// Width and height of the largest mip level of each face of a generated prefiltered cubemap
+const PREFILTERED_SIZE: usize = 128;
+/// How many rays are traced through the environment for each texel of a prefiltered cubemap
+const PREFILTERED_SAMPLE_COUNT: u32 = 64;
+/// Largest width and height of each face of a generated skybox cubemap
+const SKYBOX_SIZE: usize = 512;
+/// Format of generated cubemaps. Filterable on every Vulkan implementation, and can hold HDR values.
+const CUBEMAP_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// An image based lighting environment: the light arriving at the scene from every direction.
+/// Swap it with `RenderContext::set_environment`, so each level can have its own lighting.
+///
+/// `pbr.frag` uses an irradiance cubemap for diffuse lighting, and a prefiltered cubemap for specular
+/// reflections, where each mip level is blurred for a rougher surface than the last. These can be made offline and
+/// loaded with `from_ktx2`, or generated when loading with `from_equirectangular_hdr` and `from_ktx2_cubemap`.
+/// The skybox shows the environment behind everything in the scene.
+///
+/// Like the prefiltered cubemap, the skybox is stored upside down.
+#[derive(Debug)]
+pub struct Environment {
+    pub(crate) irradiance: Texture,
+    pub(crate) prefiltered: Texture,
+    /// `None` if the skybox is the top mip level of `prefiltered`
+    skybox: Option<Texture>,
+}
+
+impl Environment {
+    /// Load an environment from KTX2 irradiance and prefiltered cubemaps that were made offline. The top mip level of
+    /// the prefiltered cubemap is used as the skybox.
+    pub fn from_ktx2(
+        vulkan_context: &VulkanContext,
+        irradiance: &[u8],
+        prefiltered: &[u8],
+    ) -> Result<Self> {
+        Ok(Self {
+            irradiance: Texture::from_ktx2("Irradiance", irradiance, vulkan_context)?,
+            prefiltered: Texture::from_ktx2("Prefiltered", prefiltered, vulkan_context)?,
+            skybox: None,
+        })
+    }
+
+    /// Generate an environment from an equirectangular (latitude/longitude) Radiance `.hdr` image. The middle of the
+    /// image faces -Z.
+    pub fn from_equirectangular_hdr(vulkan_context: &VulkanContext, hdr: &[u8]) -> Result<Self> {
+        let mut source = load_hdr(hdr)?;
+        let skybox_size = (source.width / 4).clamp(PREFILTERED_SIZE, SKYBOX_SIZE);
+        let skybox = Cubemap::from_fn(skybox_size, |d| source.sample(&flip_y(d)));
+
+        // Avoid aliasing when sampling large images into small cubemaps
+        while source.width > PREFILTERED_SIZE * 4 {
+            source = source.downsample();
+        }
+        let environment = Cubemap::from_fn(PREFILTERED_SIZE, |d| source.sample(d));
+        Self::generate(vulkan_context, &environment, &skybox)
+    }
+
+    /// Generate an environment from a KTX2 cubemap of the surroundings. It must not be supercompressed, and must be
+    /// `R8G8B8A8_UNORM`, `R8G8B8A8_SRGB`, `R16G16B16A16_SFLOAT` or `R32G32B32A32_SFLOAT`.
+    pub fn from_ktx2_cubemap(vulkan_context: &VulkanContext, ktx2: &[u8]) -> Result<Self> {
+        let mut source = load_ktx2_cubemap(ktx2)?;
+        let skybox = Cubemap::from_fn(source.size.min(SKYBOX_SIZE), |d| source.sample(&flip_y(d)));
+
+        while source.size >= PREFILTERED_SIZE * 2 {
+            source = source.downsample();
+        }
+        let environment = Cubemap::from_fn(PREFILTERED_SIZE, |d| source.sample(d));
+        Self::generate(vulkan_context, &environment, &skybox)
+    }
+
+    /// How many mip levels the prefiltered cubemap has
+    pub fn prefiltered_mip_levels(&self) -> u32 {
+        self.prefiltered.image.mip_levels
+    }
+
+    /// The texture the skybox is drawn with
+    pub(crate) fn skybox(&self) -> &Texture {
+        self.skybox.as_ref().unwrap_or(&self.prefiltered)
+    }
+
+    /// Destroy the environment's textures once the GPU has finished with them
+    pub fn destroy(self, vulkan_context: &VulkanContext) {
+        let textures = std::iter::once(self.irradiance)
+            .chain(std::iter::once(self.prefiltered))
+            .chain(self.skybox);
+        for g in textures.flat_map(Texture::garbage) {
+            vulkan_context.destroy_later(g);
+        }
+    }
+
+    fn generate(
+        vulkan_context: &VulkanContext,
+        environment: &Cubemap,
+        skybox: &Cubemap,
+    ) -> Result<Self> {
+        println!("[HOTHAM_ENVIRONMENT] Generating irradiance and prefiltered cubemaps..");
+        let irradiance = [irradiance(environment, IRRADIANCE_SIZE)];
+        let prefiltered = prefilter(environment);
+        println!("[HOTHAM_ENVIRONMENT] ..done!");
+
+        Ok(Self {
+            irradiance: upload_cubemap("Irradiance", vulkan_context, &irradiance)?,
+            prefiltered: upload_cubemap("Prefiltered", vulkan_context, &prefiltered)?,
+            skybox: Some(upload_cubemap(
+                "Skybox",
+                vulkan_context,
+                std::slice::from_ref(skybox),
+            )?),
+        })
+    }
+}
+
+/// A cubemap in linear RGB. Faces are in Vulkan order: +X, -X, +Y, -Y, +Z, -Z
+#[derive(Debug, Clone)]
+struct Cubemap {
+    size: usize,
+    faces: Vec<Vec<Vector3<f32>>>,
+}
+
+impl Cubemap {
+    /// Fill each texel with the radiance `f` returns for the texel's direction
+    fn from_fn(size: usize, f: impl Fn(&Vector3<f32>) -> Vector3<f32>) -> Self {
+        let faces = (0..6)
+            .map(|face| {
+                (0..size * size)
+                    .map(|i| f(&texel_direction(face, i % size, i / size, size)))
+                    .collect()
+            })
+            .collect();
+        Self { size, faces }
+    }
+
+    fn sample(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let (face, u, v) = face_coordinates(direction);
+        let x = ((u * self.size as f32) as usize).min(self.size - 1);
+        let y = ((v * self.size as f32) as usize).min(self.size - 1);
+        self.faces[face][y * self.size + x]
+    }
+
+    /// Halve the size of each face, averaging each 2x2 block of texels
+    fn downsample(&self) -> Self {
+        let size = self.size / 2;
+        let faces = self
+            .faces
+            .iter()
+            .map(|face| {
+                (0..size * size)
+                    .map(|i| {
+                        let (x, y) = (i % size * 2, i / size * 2);
+                        let row = y * self.size + x;
+                        let below = row + self.size;
+                        (face[row] + face[row + 1] + face[below] + face[below + 1]) / 4.
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { size, faces }
+    }
+}
+
+/// An equirectangular (latitude/longitude) image in linear RGB
+#[derive(Debug, Clone)]
+struct Equirectangular {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3<f32>>,
+}
+
+impl Equirectangular {
+    /// Bilinearly sample the image in `direction`
+    fn sample(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let direction = direction.normalize();
+        let u = 0.5 + direction.x.atan2(-direction.z) / (2. * PI);
+        let v = direction.y.clamp(-1., 1.).acos() / PI;
+
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).clamp(0., (self.height - 1) as f32);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let pixel = |x: f32, y: f32| {
+            // Wrap around horizontally, clamp vertically
+            let x = (x as isize).rem_euclid(self.width as isize) as usize;
+            let y = (y as usize).min(self.height - 1);
+            self.pixels[y * self.width + x]
+        };
+
+        let top = pixel(x0, y0) * (1. - fx) + pixel(x0 + 1., y0) * fx;
+        let bottom = pixel(x0, y0 + 1.) * (1. - fx) + pixel(x0 + 1., y0 + 1.) * fx;
+        top * (1. - fy) + bottom * fy
+    }
+
+    /// Halve the width and height of the image, averaging each 2x2 block of pixels
+    fn downsample(&self) -> Self {
+        let (width, height) = (self.width / 2, self.height / 2);
+        let pixels = (0..width * height)
+            .map(|i| {
+                let (x, y) = (i % width * 2, i / width * 2);
+                let row = y * self.width + x;
+                let below = row + self.width;
+                (self.pixels[row]
+                    + self.pixels[row + 1]
+                    + self.pixels[below]
+                    + self.pixels[below + 1])
+                    / 4.
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Direction through the center of texel (`x`, `y`) of `face`, following the Vulkan spec's cube map face selection
+fn texel_direction(face: usize, x: usize, y: usize, size: usize) -> Vector3<f32> {
+    let s = 2. * (x as f32 + 0.5) / size as f32 - 1.;
+    let t = 2. * (y as f32 + 0.5) / size as f32 - 1.;
+    let direction = match face {
+        0 => vector![1., -t, -s],
+        1 => vector![-1., -t, s],
+        2 => vector![s, 1., t],
+        3 => vector![s, -1., -t],
+        4 => vector![s, -t, 1.],
+        _ => vector![-s, -t, -1.],
+    };
+    direction.normalize()
+}
+
+/// Turn `direction` upside down, to match the way `pbr.frag` and `skybox.frag` sample environment cubemaps
+fn flip_y(direction: &Vector3<f32>) -> Vector3<f32> {
+    vector![direction.x, -direction.y, direction.z]
+}
+
+/// The face `direction` points at, and where on that face, from 0 to 1
+fn face_coordinates(direction: &Vector3<f32>) -> (usize, f32, f32) {
+    let d = direction;
+    let a = d.abs();
+    let (face, sc, tc, ma) = if a.x >= a.y && a.x >= a.z {
+        if d.x > 0. {
+            (0, -d.z, -d.y, a.x)
+        } else {
+            (1, d.z, -d.y, a.x)
+        }
+    } else if a.y >= a.z {
+        if d.y > 0. {
+            (2, d.x, d.z, a.y)
+        } else {
+            (3, d.x, -d.z, a.y)
+        }
+    } else if d.z > 0. {
+        (4, d.x, -d.y, a.z)
+    } else {
+        (5, -d.x, -d.y, a.z)
+    };
+    (face, 0.5 * (sc / ma + 1.), 0.5 * (tc / ma + 1.))
+}
+
+/// Convolve `environment` with a cosine lobe, giving the diffuse light arriving at a surface facing each direction
+fn irradiance(environment: &Cubemap, size: usize) -> Cubemap {
+    // Integrate over a small copy of the environment, weighting each texel by the solid angle it covers
+    let mut source = environment.clone();
+    while source.size > 16 {
+        source = source.downsample();
+    }
+    let texel_area = (2. / source.size as f32).powi(2);
+    let mut texels = Vec::with_capacity(6 * source.size * source.size);
+    for (face, radiance) in source.faces.iter().enumerate() {
+        for (i, radiance) in radiance.iter().enumerate() {
+            let (x, y) = (i % source.size, i / source.size);
+            let s = 2. * (x as f32 + 0.5) / source.size as f32 - 1.;
+            let t = 2. * (y as f32 + 0.5) / source.size as f32 - 1.;
+            let solid_angle = texel_area / (1. + s * s + t * t).powf(1.5);
+            texels.push((
+                texel_direction(face, x, y, source.size),
+                radiance * solid_angle,
+            ));
+        }
+    }
+
+    Cubemap::from_fn(size, |normal| {
+        texels
+            .iter()
+            .map(|(direction, radiance)| radiance * normal.dot(direction).max(0.))
+            .sum::<Vector3<f32>>()
+            / PI
+    })
+}
+
+/// Build the prefiltered mip chain, from mirror-like reflections at the top level to fully rough at the bottom
+fn prefilter(environment: &Cubemap) -> Vec<Cubemap> {
+    let mut mip_chain = vec![environment.clone()];
+    while mip_chain.last().unwrap().size > 1 {
+        mip_chain.push(mip_chain.last().unwrap().downsample());
+    }
+
+    let mip_count = mip_chain.len();
+    mip_chain
+        .iter()
+        .enumerate()
+        .map(|(level, mip)| {
+            let roughness = level as f32 / (mip_count - 1) as f32;
+            // Sample a blurrier copy of the environment to cut down on noise
+            let source = &mip_chain[(level + 1).min(mip_count - 1)];
+            Cubemap::from_fn(mip.size, |direction| {
+                // pbr.frag flips the reflection vector upside down before sampling, so flip it back
+                let reflection = flip_y(direction);
+                if level == 0 {
+                    environment.sample(&reflection)
+                } else {
+                    prefilter_ggx(source, &reflection, roughness)
+                }
+            })
+        })
+        .collect()
+}
+
+/// Importance sample the GGX distribution around `normal`, assuming the viewer is looking straight down it.
+/// See "Real Shading in Unreal Engine 4" by Brian Karis.
+fn prefilter_ggx(source: &Cubemap, normal: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let up = if normal.z.abs() < 0.999 {
+        Vector3::z()
+    } else {
+        Vector3::x()
+    };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(&tangent);
+    let alpha = roughness * roughness;
+
+    let mut radiance = Vector3::zeros();
+    let mut total_weight = 0.;
+    for i in 0..PREFILTERED_SAMPLE_COUNT {
+        // Hammersley sequence
+        let xi = (
+            i as f32 / PREFILTERED_SAMPLE_COUNT as f32,
+            i.reverse_bits() as f32 * 2.328_306_4e-10,
+        );
+        let phi = 2. * PI * xi.0;
+        let cos_theta = ((1. - xi.1) / (1. + (alpha * alpha - 1.) * xi.1)).sqrt();
+        let sin_theta = (1. - cos_theta * cos_theta).sqrt();
+        let half = tangent * (sin_theta * phi.cos())
+            + bitangent * (sin_theta * phi.sin())
+            + normal * cos_theta;
+
+        let light = half * 2. * normal.dot(&half) - normal;
+        let n_dot_l = normal.dot(&light);
+        if n_dot_l > 0. {
+            radiance += source.sample(&light) * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+
+    radiance / total_weight
+}
+
+fn load_hdr(hdr: &[u8]) -> Result<Equirectangular> {
+    let decoder = HdrDecoder::new(Cursor::new(hdr))?;
+    let metadata = decoder.metadata();
+    let pixels = decoder
+        .read_image_hdr()?
+        .iter()
+        .map(|p| vector![p[0], p[1], p[2]])
+        .collect();
+
+    Ok(Equirectangular {
+        width: metadata.width as _,
+        height: metadata.height as _,
+        pixels,
+    })
+}
+
+fn load_ktx2_cubemap(ktx2: &[u8]) -> Result<Cubemap> {
+    let mut texture = load_ktx2(ktx2)?;
+
+    let (is_cubemap, size) = unsafe {
+        let ktx_texture = &(*texture.handle());
+        (ktx_texture.isCubemap, ktx_texture.baseWidth as usize)
+    };
+    if !is_cubemap {
+        return Err(anyhow!("KTX2 texture is not a cubemap"));
+    }
+    let format = texture
+        .ktx2()
+        .map(|ktx2| vk::Format::from_raw(ktx2.vk_format() as _))
+        .ok_or_else(|| anyhow!("Texture is not KTX2"))?;
+
+    let data = texture.data();
+    let faces = (0..6)
+        .map(|face| {
+            let offset = texture.get_image_offset(0, 0, face)?;
+            decode_texels(&data[offset..], format, size * size)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(Cubemap { size, faces })
+}
+
+/// Read `count` texels of `format` from the start of `data`
+fn decode_texels(data: &[u8], format: vk::Format, count: usize) -> Result<Vec<Vector3<f32>>> {
+    let srgb_to_linear = |c: u8| {
+        let c = c as f32 / 255.;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    let texels: Vec<Vector3<f32>> = match format {
+        vk::Format::R8G8B8A8_UNORM => data
+            .chunks_exact(4)
+            .take(count)
+            .map(|t| vector![t[0], t[1], t[2]].map(|c| c as f32 / 255.))
+            .collect(),
+        vk::Format::R8G8B8A8_SRGB => data
+            .chunks_exact(4)
+            .take(count)
+            .map(|t| vector![t[0], t[1], t[2]].map(srgb_to_linear))
+            .collect(),
+        vk::Format::R16G16B16A16_SFLOAT => data
+            .chunks_exact(8)
+            .take(count)
+            .map(|t| {
+                let channel = |i: usize| f16_to_f32(u16::from_le_bytes([t[i * 2], t[i * 2 + 1]]));
+                vector![channel(0), channel(1), channel(2)]
+            })
+            .collect(),
+        vk::Format::R32G32B32A32_SFLOAT => data
+            .chunks_exact(16)
+            .take(count)
+            .map(|t| {
+                let channel = |i: usize| {
+                    f32::from_le_bytes([t[i * 4], t[i * 4 + 1], t[i * 4 + 2], t[i * 4 + 3]])
+                };
+                vector![channel(0), channel(1), channel(2)]
+            })
+            .collect(),
+        _ => return Err(anyhow!("Unsupported environment format: {:?}", format)),
+    };
+
+    if texels.len() != count {
+        return Err(anyhow!("Cubemap face is truncated"));
+    }
+    Ok(texels)
+}
+
+/// Upload a cubemap's mip chain to the GPU
+fn upload_cubemap(
+    name: &str,
+    vulkan_context: &VulkanContext,
+    mip_chain: &[Cubemap],
+) -> Result<Texture> {
+    let size = mip_chain[0].size as u32;
+    let mip_count = mip_chain.len() as u32;
+
+    // Every mip level of each face in turn, the same as KTX
+    let mut buf = Vec::new();
+    let mut offsets = Vec::new();
+    for face in 0..6 {
+        for mip in mip_chain {
+            offsets.push(buf.len() as vk::DeviceSize);
+            for texel in &mip.faces[face] {
+                for channel in [texel.x, texel.y, texel.z, 1.] {
+                    buf.extend_from_slice(&f32_to_f16(channel).to_le_bytes());
+                }
+            }
+        }
+    }
+
+    let image = vulkan_context.create_image(
+        CUBEMAP_FORMAT,
+        &vk::Extent2D {
+            width: size,
+            height: size,
+        },
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        6,
+        mip_count,
+    )?;
+    let (image, sampler) =
+        vulkan_context.create_texture_image(name, &buf, mip_count, offsets, image)?;
+    let descriptor = vk::DescriptorImageInfo::builder()
+        .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+        .image_view(image.view)
+        .sampler(sampler)
+        .build();
+
+    Ok(Texture {
+        image,
+        sampler,
+        descriptor,
+    })
+}
+
+/// Convert to a half precision float, truncating the mantissa
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+
+    if value.is_nan() {
+        sign | 0x7e00
+    } else if exponent >= 0x1f {
+        // Too big, or infinite
+        sign | 0x7c00
+    } else if exponent <= 0 {
+        if exponent < -10 {
+            // Too small
+            sign
+        } else {
+            // Subnormal
+            sign | ((mantissa | 0x80_0000) >> (14 - exponent)) as u16
+        }
+    } else {
+        sign | ((exponent as u16) << 10) | (mantissa >> 13) as u16
+    }
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 == 0 { 1. } else { -1. };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2_f32.powi(-24),
+        0x1f if mantissa == 0. => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1. + mantissa / 1024.) * 2_f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    pub fn test_half_floats() {
+        for value in [0., 1., -2.5, 0.333, 1000., 65504., 0.0001] {
+            assert_relative_eq!(f16_to_f32(f32_to_f16(value)), value, max_relative = 0.001);
+        }
+        assert_eq!(f32_to_f16(1.), 0x3c00);
+        assert_eq!(f16_to_f32(f32_to_f16(100000.)), f32::INFINITY);
+        assert_eq!(f16_to_f32(f32_to_f16(1e-10)), 0.);
+    }
+
+    #[test]
+    pub fn test_face_coordinates() {
+        let size = 4;
+        for face in 0..6 {
+            for (x, y) in [(0, 0), (3, 0), (1, 2), (3, 3)] {
+                let (sampled_face, u, v) = face_coordinates(&texel_direction(face, x, y, size));
+                assert_eq!(sampled_face, face);
+                assert_relative_eq!(u, (x as f32 + 0.5) / size as f32, epsilon = 0.0001);
+                assert_relative_eq!(v, (y as f32 + 0.5) / size as f32, epsilon = 0.0001);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_equirectangular() {
+        // Bright sky above, with a red pixel straight ahead
+        let (width, height) = (8, 4);
+        let mut pixels = (0..width * height)
+            .map(|i| {
+                if i / width < height / 2 {
+                    vector![1., 1., 1.]
+                } else {
+                    vector![0., 0., 0.]
+                }
+            })
+            .collect::<Vec<_>>();
+        pixels[width + width / 2] = vector![1., 0., 0.];
+        pixels[width + width / 2 - 1] = vector![1., 0., 0.];
+        let image = Equirectangular {
+            width,
+            height,
+            pixels,
+        };
+
+        assert_eq!(image.sample(&Vector3::y()), vector![1., 1., 1.]);
+        assert_eq!(image.sample(&-Vector3::y()), vector![0., 0., 0.]);
+        assert_relative_eq!(
+            image.sample(&vector![0., (0.375 * PI).cos(), -(0.375 * PI).sin()]),
+            vector![1., 0., 0.],
+            epsilon = 0.0001
+        );
+        assert_relative_eq!(
+            image.downsample().sample(&Vector3::y()),
+            vector![1., 1., 1.]
+        );
+    }
+
+    #[test]
+    pub fn test_uniform_environment() {
+        // Light that is the same in every direction should look the same on every surface
+        let radiance = vector![1., 2., 3.];
+        let environment = Cubemap::from_fn(16, |_| radiance);
+
+        let irradiance = irradiance(&environment, 8);
+        for texel in irradiance.faces.iter().flatten() {
+            assert_relative_eq!(*texel, radiance, max_relative = 0.02);
+        }
+
+        let prefiltered = prefilter(&environment);
+        assert_eq!(prefiltered.len(), 5);
+        assert_eq!(prefiltered.last().unwrap().size, 1);
+        for texel in prefiltered
+            .iter()
+            .flat_map(|mip| mip.faces.iter().flatten())
+        {
+            assert_relative_eq!(*texel, radiance, max_relative = 0.0001);
+        }
+    }
+}
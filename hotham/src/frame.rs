@@ -18,8 +18,7 @@ impl Frame {
         render_pass: vk::RenderPass,
         swapchain_resolution: vk::Extent2D,
         swapchain_image_view: vk::ImageView,
-        depth_image_view: vk::ImageView,
-        color_image_view: vk::ImageView,
+        attachments: &[vk::ImageView],
     ) -> Result<Self> {
         let device = &vulkan_context.device;
         let command_pool = vulkan_context.command_pool;
@@ -42,11 +41,9 @@ impl Frame {
         .pop()
         .ok_or(HothamError::EmptyListError)?;
 
-        let attachments = [color_image_view, depth_image_view, swapchain_image_view];
-
         let frame_buffer_create_info = vk::FramebufferCreateInfo::builder()
             .render_pass(render_pass)
-            .attachments(&attachments)
+            .attachments(attachments)
             .width(swapchain_resolution.width)
             .height(swapchain_resolution.height)
             .layers(1);
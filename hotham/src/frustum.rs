@@ -0,0 +1,68 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+/// The volume a camera can see, as six planes facing inwards
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Get the frustum of a view projection matrix, with Vulkan's 0 to 1 depth range. See "Fast Extraction of
+    /// Viewing Frustum Planes from the World-View-Projection Matrix" by Gil Gribb and Klaus Hartmann.
+    pub fn from_view_projection(view_projection: &Matrix4<f32>) -> Self {
+        let row = |i: usize| view_projection.row(i).transpose();
+        let planes = [
+            row(3) + row(0), // Left
+            row(3) - row(0), // Right
+            row(3) + row(1), // Top
+            row(3) - row(1), // Bottom
+            row(2),          // Near
+            row(3) - row(2), // Far
+        ]
+        .map(|plane| plane / plane.xyz().norm());
+
+        Self { planes }
+    }
+
+    /// Whether any part of a sphere is inside the frustum. Spheres near the frustum's corners may be let through.
+    pub fn intersects_sphere(&self, center: &Vector3<f32>, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.xyz().dot(center) + plane.w >= -radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{vector, Isometry3, Perspective3};
+
+    #[test]
+    pub fn test_intersects_sphere() {
+        // A camera at the origin looking down -Z, with depth mapped from 0 to 1
+        let near = 0.1;
+        let far = 100.;
+        let mut projection =
+            Perspective3::new(1., std::f32::consts::FRAC_PI_2, near, far).to_homogeneous();
+        projection[(2, 2)] = -far / (far - near);
+        projection[(2, 3)] = -(far * near) / (far - near);
+        let frustum = Frustum::from_view_projection(&projection);
+
+        assert!(frustum.intersects_sphere(&vector![0., 0., -5.], 0.5));
+        assert!(!frustum.intersects_sphere(&vector![0., 0., 5.], 0.5));
+        assert!(!frustum.intersects_sphere(&vector![0., 0., -200.], 0.5));
+        assert!(!frustum.intersects_sphere(&vector![10., 0., -5.], 0.5));
+        assert!(!frustum.intersects_sphere(&vector![0., -10., -5.], 0.5));
+
+        // Partly inside
+        assert!(frustum.intersects_sphere(&vector![5.5, 0., -5.], 1.));
+
+        // Turn the camera around, so it looks down +Z
+        let view = Isometry3::rotation(Vector3::y() * std::f32::consts::PI)
+            .inverse()
+            .to_homogeneous();
+        let frustum = Frustum::from_view_projection(&(projection * view));
+        assert!(frustum.intersects_sphere(&vector![0., 0., 5.], 0.5));
+        assert!(!frustum.intersects_sphere(&vector![0., 0., -5.], 0.5));
+    }
+}
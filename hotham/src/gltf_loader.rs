@@ -1,41 +1,68 @@
 use crate::{
-    buffer::Buffer,
     components::{
-        animation_controller::AnimationController, AnimationTarget, Info, Joint, Mesh, Parent,
-        Root, Skin, Transform, TransformMatrix, Visible,
+        animation_controller::AnimationController, material::LoadedMaterials, AnimationClip,
+        AnimationPlayer, AnimationTarget, Info, Interpolation, Joint, Keyframes, Light, Mesh,
+        MorphWeights, Parent, Root, Skin, Transform, TransformKeyframes, TransformMatrix, Visible,
     },
     resources::{render_context::DescriptorSetLayouts, VulkanContext},
+    HothamError, HothamResult,
 };
-use anyhow::Result;
-use ash::vk;
 use gltf::animation::util::ReadOutputs;
 use hecs::{Entity, World};
-use itertools::{izip, Itertools};
-use nalgebra::{vector, Matrix4, Quaternion, UnitQuaternion};
-use std::collections::HashMap;
+use itertools::Itertools;
+use nalgebra::{Matrix4, Quaternion, Vector3};
+use std::{collections::HashMap, fmt::Display};
+
+mod import;
+mod uri_resolver;
+
+#[cfg(target_os = "android")]
+pub use uri_resolver::AndroidAssetResolver;
+pub use uri_resolver::{FileResolver, UriResolver};
 
 /// Convenience type for models
 pub type Models = HashMap<String, World>;
 
-/// Load glTF models from a GLB file
+/// Load glTF models from GLB files. Everything the models need must be embedded in the files; use
+/// `load_models_from_gltf` for files that refer to others.
 pub fn load_models_from_glb(
     glb_buffers: &[&[u8]],
     vulkan_context: &VulkanContext,
     descriptor_set_layouts: &DescriptorSetLayouts,
-) -> Result<Models> {
+) -> HothamResult<Models> {
+    load_models_from_gltf(
+        glb_buffers,
+        &HashMap::new(),
+        vulkan_context,
+        descriptor_set_layouts,
+    )
+}
+
+/// Load glTF models from `.gltf` or `.glb` files. Buffers and images that the files refer to by URI, like `.bin` and
+/// `.png` files, are read with `resolver`. `data:` URIs are decoded without it.
+pub fn load_models_from_gltf(
+    gltf_buffers: &[&[u8]],
+    resolver: &dyn UriResolver,
+    vulkan_context: &VulkanContext,
+    descriptor_set_layouts: &DescriptorSetLayouts,
+) -> HothamResult<Models> {
     let mut models = HashMap::new();
 
-    for glb_buf in glb_buffers {
-        let (document, buffers, images) = gltf::import_slice(glb_buf).unwrap();
+    for (index, gltf_buf) in gltf_buffers.iter().enumerate() {
+        let (document, buffers, images) =
+            import::import(gltf_buf, resolver).map_err(|e| HothamError::Asset {
+                model: format!("glTF file {}", index),
+                node: None,
+                reason: format!("{:#}", e),
+            })?;
         load_models_from_gltf_data(
             &document,
-            &buffers[0],
+            &buffers,
             &images,
             vulkan_context,
             descriptor_set_layouts,
             &mut models,
-        )
-        .unwrap();
+        )?;
     }
 
     Ok(models)
@@ -44,65 +71,99 @@ pub fn load_models_from_glb(
 /// Load glTF models from a glTF document
 pub fn load_models_from_gltf_data(
     document: &gltf::Document,
-    buffer: &[u8],
+    buffers: &[gltf::buffer::Data],
     images: &[gltf::image::Data],
     vulkan_context: &VulkanContext,
     descriptor_set_layouts: &DescriptorSetLayouts,
     models: &mut Models,
-) -> Result<()> {
-    let root_scene = document.scenes().next().unwrap(); // safe as there is always one scene
+) -> HothamResult<()> {
+    let root_scene = document.scenes().next().ok_or_else(|| HothamError::Asset {
+        model: "glTF document".to_string(),
+        node: None,
+        reason: "The document has no scenes".to_string(),
+    })?;
     let mut node_entity_map = HashMap::new();
     let animations = document.animations().collect_vec();
+    let mut materials = HashMap::new();
 
     for node_data in root_scene.nodes() {
+        let model = node_name(&node_data);
+        if node_data.name().is_none() {
+            println!(
+                "[HOTHAM_GLTF] WARNING: Root node {} has no name, calling it \"{}\"",
+                node_data.index(),
+                model
+            );
+        }
+
         let mut world = World::default();
         load_node(
+            &model,
             &node_data,
-            buffer,
+            buffers,
             vulkan_context,
             descriptor_set_layouts,
             &mut world,
             &mut node_entity_map,
             true,
             images,
+            &mut materials,
         )?;
         add_parents(&node_data, &mut world, &mut node_entity_map);
         add_skins_and_joints(
+            &model,
             &node_data,
-            buffer,
+            buffers,
             &mut world,
-            vulkan_context,
+            &mut node_entity_map,
+        )?;
+        add_animations(
+            &animations,
+            buffers,
+            &mut world,
+            node_entity_map[&node_data.index()],
             &mut node_entity_map,
         );
-        add_animations(&animations, buffer, &mut world, &mut node_entity_map);
 
-        models.insert(
-            node_data.name().expect("Node has no name!").to_string(),
-            world,
-        );
+        models.insert(model, world);
     }
 
     Ok(())
 }
 
+/// The node's name, or a made up one if it doesn't have one
+fn node_name(node_data: &gltf::Node) -> String {
+    node_data
+        .name()
+        .map(|s| s.to_string())
+        .unwrap_or(format!("Node {}", node_data.index()))
+}
+
+fn asset_error(model: &str, node_data: &gltf::Node, reason: impl Display) -> HothamError {
+    HothamError::Asset {
+        model: model.to_string(),
+        node: Some(node_name(node_data)),
+        reason: format!("{:#}", reason),
+    }
+}
+
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
 fn load_node(
+    model: &str,
     node_data: &gltf::Node,
-    gltf_buffer: &[u8],
+    buffers: &[gltf::buffer::Data],
     vulkan_context: &VulkanContext,
     descriptor_set_layouts: &DescriptorSetLayouts,
     world: &mut World,
     node_entity_map: &mut HashMap<usize, Entity>,
     is_root: bool,
     images: &[gltf::image::Data],
-) -> Result<()> {
+    materials: &mut LoadedMaterials,
+) -> HothamResult<()> {
     let transform = Transform::load(node_data.transform());
     let transform_matrix = TransformMatrix(node_data.transform().matrix().into());
     let info = Info {
-        name: node_data
-            .name()
-            .map(|s| s.to_string())
-            .unwrap_or(format!("Node {}", node_data.index())),
+        name: node_name(node_data),
         node_id: node_data.index(),
     };
     let this_entity = world.spawn((transform, transform_matrix, info));
@@ -111,29 +172,42 @@ fn load_node(
     if let Some(mesh) = node_data.mesh() {
         let mesh = Mesh::load(
             &mesh,
-            gltf_buffer,
+            buffers,
             vulkan_context,
             descriptor_set_layouts,
             images,
-        )?;
+            materials,
+        )
+        .map_err(|e| asset_error(model, node_data, e))?;
 
+        if mesh.has_morph_targets() {
+            let morph_weights =
+                MorphWeights::new(morph_target_count(node_data), node_mesh_weights(node_data));
+            world.insert_one(this_entity, morph_weights).unwrap();
+        }
         world.insert(this_entity, (mesh, Visible {})).unwrap();
     }
 
+    if let Some(light) = node_data.light() {
+        world.insert_one(this_entity, Light::load(&light)).unwrap();
+    }
+
     if is_root {
         world.insert_one(this_entity, Root {}).unwrap();
     }
 
     for child in node_data.children() {
         load_node(
+            model,
             &child,
-            gltf_buffer,
+            buffers,
             vulkan_context,
             descriptor_set_layouts,
             world,
             node_entity_map,
             false,
             images,
+            materials,
         )?;
     }
 
@@ -156,146 +230,262 @@ fn add_parents(
 }
 
 fn add_skins_and_joints(
+    model: &str,
     node_data: &gltf::Node,
-    buffer: &[u8],
+    buffers: &[gltf::buffer::Data],
     world: &mut World,
-    vulkan_context: &VulkanContext,
     node_entity_map: &mut HashMap<usize, Entity>,
-) {
+) -> HothamResult<()> {
     // Do we need to add a Skin?
     // TODO: Extract this to components::Skin
     if let Some(node_skin_data) = node_data.skin() {
-        println!("[HOTHAM_GLTF] Adding a skin to {}", node_data.index());
-        let this_entity = *node_entity_map.get(&node_data.index()).unwrap();
-        let mut joint_matrices = Vec::new();
-        let reader = node_skin_data.reader(|_| Some(buffer));
-        let matrices = reader.read_inverse_bind_matrices().unwrap();
-        for m in matrices {
-            let m = Matrix4::from(m);
-            joint_matrices.push(m);
-        }
-        let mut joint_ids = Vec::new();
-
-        for (joint_node, inverse_bind_matrix) in node_skin_data.joints().zip(joint_matrices.iter())
-        {
-            let joint = Joint {
-                skeleton_root: this_entity,
-                inverse_bind_matrix: *inverse_bind_matrix,
+        let this_entity = node_entity_map[&node_data.index()];
+        if world.get::<Mesh>(this_entity).is_err() {
+            println!(
+                "[HOTHAM_GLTF] WARNING: Node {} of {} has a skin but no mesh. Ignoring the skin",
+                node_data.index(),
+                model
+            );
+        } else {
+            println!("[HOTHAM_GLTF] Adding a skin to {}", node_data.index());
+            let joint_count = node_skin_data.joints().count();
+
+            // Inverse bind matrices are optional, and default to the identity matrix
+            let reader = node_skin_data.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
+            let joint_matrices = match reader.read_inverse_bind_matrices() {
+                Some(matrices) => matrices.map(Matrix4::from).collect_vec(),
+                None => vec![Matrix4::identity(); joint_count],
             };
-            joint_ids.push(joint_node.index());
-            let joint_entity = node_entity_map.get(&joint_node.index()).unwrap();
-            world.insert_one(*joint_entity, joint).unwrap();
-        }
+            if joint_matrices.len() != joint_count {
+                return Err(asset_error(
+                    model,
+                    node_data,
+                    format!(
+                        "The skin has {} joints but {} inverse bind matrices",
+                        joint_count,
+                        joint_matrices.len()
+                    ),
+                ));
+            }
 
-        // Add a Skin to the entity.
-        world.insert_one(this_entity, Skin { joint_ids }).unwrap();
+            let mut joints = Vec::new();
+            for joint_node in node_skin_data.joints() {
+                let joint_entity = node_entity_map
+                    .get(&joint_node.index())
+                    .copied()
+                    .filter(|e| world.contains(*e))
+                    .ok_or_else(|| {
+                        asset_error(
+                            model,
+                            node_data,
+                            format!("Joint {} is not part of the model", joint_node.index()),
+                        )
+                    })?;
+                joints.push(joint_entity);
+
+                // Joints can be shared by several skins, which each have their own inverse bind matrices
+                if world.get::<Joint>(joint_entity).is_err() {
+                    let joint = Joint {
+                        skeleton_root: this_entity,
+                    };
+                    world.insert_one(joint_entity, joint).unwrap();
+                }
+            }
 
-        // Tell the vertex shader how many joints we have
-        let mut mesh = world.get_mut::<Mesh>(this_entity).unwrap();
-        mesh.ubo_data.joint_count = joint_matrices.len() as f32;
+            // Add a Skin to the entity.
+            let skin = Skin {
+                joints,
+                inverse_bind_matrices: joint_matrices,
+            };
+            world.insert_one(this_entity, skin).unwrap();
+
+            // Tell the vertex shader how many joints we have
+            let mut mesh = world.get_mut::<Mesh>(this_entity).unwrap();
+            mesh.ubo_data.joint_count = joint_count as f32;
+        }
     }
 
     for child in node_data.children() {
-        add_skins_and_joints(&child, buffer, world, vulkan_context, node_entity_map);
+        add_skins_and_joints(model, &child, buffers, world, node_entity_map)?;
     }
+
+    Ok(())
 }
 
 fn add_animations(
     animations: &[gltf::Animation], // Clippy ptr_arg
-    buffer: &[u8],
+    buffers: &[gltf::buffer::Data],
     world: &mut World,
+    controller_entity: Entity,
     node_entity_map: &mut HashMap<usize, Entity>,
 ) {
-    let (controller_entity, _) = world.query::<&Root>().iter().next().unwrap();
+    let mut clips = Vec::new();
+    let mut targets: HashMap<Entity, Vec<TransformKeyframes>> = HashMap::new();
 
     for animation in animations.iter() {
-        'chunks: for chunk in &animation.channels().chunks(3) {
-            let mut translations = Vec::new();
-            let mut rotations = Vec::new();
-            let mut scales = Vec::new();
-            let mut target = 0;
-            '_channels: for channel in chunk {
-                target = channel.target().node().index();
-                if !node_entity_map.contains_key(&target) {
-                    continue 'chunks;
-                }
+        let mut clip_keyframes: HashMap<Entity, TransformKeyframes> = HashMap::new();
+        for channel in animation.channels() {
+            // Animations can move nodes in any model, so only keep the channels for this one
+            let target = channel.target().node().index();
+            let target_entity = match node_entity_map.get(&target) {
+                Some(entity) if world.contains(*entity) => *entity,
+                _ => continue,
+            };
 
-                let reader = channel.reader(|_| Some(buffer));
-                match reader.read_outputs() {
-                    Some(ReadOutputs::Translations(translation_data)) => {
-                        for t in translation_data {
-                            translations.push(vector![t[0], t[1], t[2]]);
-                        }
-                    }
-                    Some(ReadOutputs::Rotations(rotation_data)) => {
-                        for r in rotation_data.into_f32() {
-                            rotations.push(UnitQuaternion::new_normalize(Quaternion::new(
-                                r[3], r[0], r[1], r[2],
-                            )));
+            let reader = channel.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
+            let times = reader.read_inputs().map(|t| t.collect_vec());
+            let interpolation = channel.sampler().interpolation().into();
+            let keyframes = clip_keyframes.entry(target_entity).or_default();
+            let loaded = match (times, reader.read_outputs()) {
+                (Some(times), Some(ReadOutputs::Translations(translation_data))) => {
+                    keyframes.translations = load_keyframes(
+                        times,
+                        translation_data.map(Vector3::from).collect(),
+                        interpolation,
+                    );
+                    keyframes.translations.is_some()
+                }
+                (Some(times), Some(ReadOutputs::Rotations(rotation_data))) => {
+                    keyframes.rotations = load_keyframes(
+                        times,
+                        rotation_data
+                            .into_f32()
                             // gltf gives us a quaternion in [x, y, z, w] but we need [w, x, y, z]
-                        }
-                    }
-                    Some(ReadOutputs::Scales(scale_data)) => {
-                        for s in scale_data {
-                            scales.push(vector![s[0], s[1], s[2]]);
-                        }
-                    }
-                    _ => {}
+                            .map(|r| Quaternion::new(r[3], r[0], r[1], r[2]))
+                            .collect(),
+                        interpolation,
+                    );
+                    keyframes.rotations.is_some()
                 }
-            }
-
-            let target_entity = *node_entity_map.get(&target).unwrap();
-            if !world.contains(target_entity) {
-                println!("[HOTHAM_GLTF] - Error importing animation {:?}. No target, probably due to malformed file. Ignoring", animation.name());
-                return;
-            }
-
-            assert!(
-                translations.len() == rotations.len() && rotations.len() == scales.len(),
-                "Animation {} - {:?} has malformed data for node {}. translations.len() - {}, rotations.len() - {}, scales.len() - {}",
-                animation.index(),
-                animation.name(),
-                target,
-                translations.len(),
-                rotations.len(),
-                scales.len(),
-            );
-
-            let animation = izip!(translations, rotations, scales)
-                .map(|(t, r, s)| Transform {
-                    translation: t,
-                    rotation: r,
-                    scale: s,
-                })
-                .collect_vec();
-
-            // If an animation target exists already, push this animation onto it. Otherwise, create a new one.
-            match world.query_one_mut::<&mut AnimationTarget>(target_entity) {
-                Ok(animation_target) => {
-                    animation_target.animations.push(animation);
+                (Some(times), Some(ReadOutputs::Scales(scale_data))) => {
+                    keyframes.scales = load_keyframes(
+                        times,
+                        scale_data.map(Vector3::from).collect(),
+                        interpolation,
+                    );
+                    keyframes.scales.is_some()
                 }
-                _ => {
-                    world
-                        .insert_one(
-                            target_entity,
-                            AnimationTarget {
-                                controller: controller_entity,
-                                animations: vec![animation],
-                            },
+                (Some(times), Some(ReadOutputs::MorphTargetWeights(weight_data))) => {
+                    let target_count = morph_target_count(&channel.target().node());
+                    let weights = weight_data.into_f32().collect_vec();
+                    keyframes.weights = if target_count == 0 {
+                        None
+                    } else {
+                        load_keyframes(
+                            times,
+                            weights
+                                .chunks_exact(target_count)
+                                .map(|w| MorphWeights::new(target_count, w).weights)
+                                .collect(),
+                            interpolation,
                         )
-                        .unwrap();
+                    };
+                    keyframes.weights.is_some()
                 }
-            }
+                _ => false,
+            };
 
-            // Add an animation controller to our parent, if needed.
-            let entity_ref = world.entity(controller_entity).unwrap();
-            if !entity_ref.has::<AnimationController>() {
-                world
-                    .insert_one(controller_entity, AnimationController::default())
-                    .unwrap();
+            if !loaded {
+                println!(
+                    "[HOTHAM_GLTF] WARNING: Animation {} - {:?} has malformed data for node {}. Ignoring",
+                    animation.index(),
+                    animation.name(),
+                    target,
+                );
             }
         }
+
+        if clip_keyframes.is_empty() {
+            continue;
+        }
+
+        // Clips are numbered by their order in this model, which skips animations for other models
+        let clip = clips.len();
+        clips.push(AnimationClip {
+            name: animation
+                .name()
+                .map(|n| n.to_string())
+                .unwrap_or(format!("Animation {}", animation.index())),
+            duration: clip_keyframes
+                .values()
+                .map(TransformKeyframes::duration)
+                .fold(0., f32::max),
+        });
+        for (target_entity, keyframes) in clip_keyframes {
+            let animations = targets.entry(target_entity).or_default();
+            animations.resize(clip, Default::default());
+            animations.push(keyframes);
+        }
     }
+
+    if clips.is_empty() {
+        return;
+    }
+
+    for (target_entity, mut animations) in targets {
+        animations.resize(clips.len(), Default::default());
+        let rest_transform = *world.get::<Transform>(target_entity).unwrap();
+        world
+            .insert_one(
+                target_entity,
+                AnimationTarget {
+                    controller: controller_entity,
+                    animations,
+                    rest_transform,
+                },
+            )
+            .unwrap();
+    }
+
+    // Add an animation controller and player to our parent
+    let animation_controller = AnimationController {
+        clips,
+        ..Default::default()
+    };
+    world
+        .insert(
+            controller_entity,
+            (animation_controller, AnimationPlayer::default()),
+        )
+        .unwrap();
+}
+
+/// Nodes can override their mesh's default morph target weights
+fn node_mesh_weights<'a>(node_data: &gltf::Node<'a>) -> &'a [f32] {
+    node_data
+        .weights()
+        .or_else(|| node_data.mesh().and_then(|m| m.weights()))
+        .unwrap_or(&[])
+}
+
+/// The number of morph targets in a node's mesh. Every primitive of a mesh has the same number.
+fn morph_target_count(node_data: &gltf::Node) -> usize {
+    node_data
+        .mesh()
+        .and_then(|m| m.primitives().next())
+        .map(|p| p.morph_targets().len())
+        .unwrap_or_default()
+}
+
+/// Keyframes, if there's a value for each keyframe time
+fn load_keyframes<T>(
+    times: Vec<f32>,
+    values: Vec<T>,
+    interpolation: Interpolation,
+) -> Option<Keyframes<T>> {
+    let values_per_keyframe = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    if times.is_empty() || values.len() != times.len() * values_per_keyframe {
+        return None;
+    }
+
+    Some(Keyframes {
+        times,
+        values,
+        interpolation,
+    })
 }
 
 /// Convenience function to add a glTF model to the world referenced by its node name
@@ -306,8 +496,12 @@ pub fn add_model_to_world(
     parent: Option<Entity>,
     vulkan_context: &VulkanContext,
     descriptor_set_layouts: &DescriptorSetLayouts,
-) -> Option<Entity> {
-    let source_world = models.get(name)?;
+) -> HothamResult<Entity> {
+    let source_world = models.get(name).ok_or_else(|| HothamError::Asset {
+        model: name.to_string(),
+        node: None,
+        reason: "No model with this name has been loaded".to_string(),
+    })?;
     let source_entities = source_world.iter();
     let mut entity_map = HashMap::new();
 
@@ -320,8 +514,54 @@ pub fn add_model_to_world(
         entity_map.insert(source_entity, destination_entity);
     }
 
+    let result = clone_entities(
+        name,
+        source_world,
+        destination_world,
+        &entity_map,
+        parent,
+        vulkan_context,
+        descriptor_set_layouts,
+    );
+
+    // Don't leave half a model behind
+    if result.is_err() {
+        for destination_entity in entity_map.values() {
+            let _ = destination_world.despawn(*destination_entity);
+        }
+    }
+
+    result
+}
+
+/// Clone every entity in `source_world` into the entities `entity_map` reserved for them in `destination_world`.
+/// Returns the model's new root entity.
+fn clone_entities(
+    name: &str,
+    source_world: &World,
+    destination_world: &mut World,
+    entity_map: &HashMap<Entity, Entity>,
+    parent: Option<Entity>,
+    vulkan_context: &VulkanContext,
+    descriptor_set_layouts: &DescriptorSetLayouts,
+) -> HothamResult<Entity> {
+    // Find an entity from the source world in the destination world
+    let map_entity = |source_entity: &Entity, target: Entity, what: &str| {
+        entity_map
+            .get(&target)
+            .copied()
+            .ok_or_else(|| HothamError::Asset {
+                model: name.to_string(),
+                node: source_world
+                    .get::<Info>(*source_entity)
+                    .ok()
+                    .map(|info| info.name.clone()),
+                reason: format!("Its {} is not part of the model", what),
+            })
+    };
+
     // Go through each entity in the source world and clone its components into the new world.
-    for (source_entity, destination_entity) in &entity_map {
+    for (source_entity, destination_entity) in entity_map {
         if let Ok(transform) = source_world.get_mut::<Transform>(*source_entity) {
             destination_world
                 .insert_one(*destination_entity, *transform)
@@ -336,48 +576,50 @@ pub fn add_model_to_world(
 
         // Create a new mesh for this entity in the destination world.
         if let Ok(mesh) = source_world.get_mut::<Mesh>(*source_entity) {
-            let info = source_world.get_mut::<Info>(*source_entity).unwrap();
-
-            // Create new description sets
-            let descriptor_sets = vulkan_context
-                .create_mesh_descriptor_sets(descriptor_set_layouts.mesh_layout, &info.name)
-                .unwrap();
-
-            // Create a new buffer
-            let ubo_buffer = Buffer::new(
-                vulkan_context,
-                &[mesh.ubo_data],
-                vk::BufferUsageFlags::UNIFORM_BUFFER,
-            )
-            .unwrap();
-            vulkan_context.update_buffer_descriptor_set(
-                &ubo_buffer,
-                mesh.descriptor_sets[0],
-                0,
-                vk::DescriptorType::UNIFORM_BUFFER,
-            );
-
-            let new_mesh = Mesh {
-                descriptor_sets: [descriptor_sets[0]],
-                ubo_buffer,
-                ubo_data: mesh.ubo_data,
-                primitives: mesh.primitives.clone(),
+            let new_mesh = if mesh.is_deformed() {
+                let mesh_name = source_world
+                    .get::<Info>(*source_entity)
+                    .map(|info| info.name.clone())
+                    .unwrap_or_else(|_| name.to_string());
+
+                // Deformed meshes need their own joints and morph weights, so give them their own UBO
+                mesh.clone_with_own_ubo(vulkan_context, descriptor_set_layouts, &mesh_name)?
+            } else {
+                // Everything else can share the model's mesh, so its copies can be drawn with one instanced draw call
+                mesh.clone()
             };
             destination_world
                 .insert_one(*destination_entity, new_mesh)
                 .unwrap();
         }
 
+        if let Ok(morph_weights) = source_world.get_mut::<MorphWeights>(*source_entity) {
+            destination_world
+                .insert_one(*destination_entity, morph_weights.clone())
+                .unwrap();
+        }
+
+        // Point the skin at the joints in the destination world.
         if let Ok(skin) = source_world.get_mut::<Skin>(*source_entity) {
+            let joints = skin
+                .joints
+                .iter()
+                .map(|joint| map_entity(source_entity, *joint, "joint"))
+                .collect::<HothamResult<Vec<_>>>()?;
+            let new_skin = Skin {
+                joints,
+                inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+            };
             destination_world
-                .insert_one(*destination_entity, skin.clone())
+                .insert_one(*destination_entity, new_skin)
                 .unwrap();
         }
 
         // If the source entity had a joint, clone it and set the skeleton root to the corresponding entity in the destination world.
         if let Ok(joint) = source_world.get_mut::<Joint>(*source_entity) {
             let mut new_joint = *joint;
-            new_joint.skeleton_root = *entity_map.get(&joint.skeleton_root).unwrap();
+            new_joint.skeleton_root =
+                map_entity(source_entity, joint.skeleton_root, "skeleton root")?;
             destination_world
                 .insert_one(*destination_entity, new_joint)
                 .unwrap();
@@ -385,9 +627,9 @@ pub fn add_model_to_world(
 
         // If the source entity had a parent, set it to the corresponding entity in the destination world.
         if let Ok(parent) = source_world.get_mut::<Parent>(*source_entity) {
-            let new_parent = entity_map.get(&parent.0).unwrap();
+            let new_parent = map_entity(source_entity, parent.0, "parent")?;
             destination_world
-                .insert_one(*destination_entity, Parent(*new_parent))
+                .insert_one(*destination_entity, Parent(new_parent))
                 .unwrap();
         }
 
@@ -418,15 +660,30 @@ pub fn add_model_to_world(
                 .unwrap();
         }
 
+        if let Ok(animation_player) = source_world.get_mut::<AnimationPlayer>(*source_entity) {
+            destination_world
+                .insert_one(*destination_entity, *animation_player)
+                .unwrap();
+        }
+
         if let Ok(animation_target) = source_world.get_mut::<AnimationTarget>(*source_entity) {
             let mut new_animation_target = animation_target.clone();
-            new_animation_target.controller =
-                *entity_map.get(&animation_target.controller).unwrap();
+            new_animation_target.controller = map_entity(
+                source_entity,
+                animation_target.controller,
+                "animation controller",
+            )?;
             destination_world
                 .insert_one(*destination_entity, new_animation_target)
                 .unwrap();
         }
 
+        if let Ok(light) = source_world.get_mut::<Light>(*source_entity) {
+            destination_world
+                .insert_one(*destination_entity, *light)
+                .unwrap();
+        }
+
         if let Ok(visible) = source_world.get_mut::<Visible>(*source_entity) {
             destination_world
                 .insert_one(*destination_entity, *visible)
@@ -435,39 +692,21 @@ pub fn add_model_to_world(
     }
 
     // Find the root entity of the source world.
-    let (root_entity, _) = source_world.query::<&Root>().iter().next().unwrap();
+    let (root_entity, _) =
+        source_world
+            .query::<&Root>()
+            .iter()
+            .next()
+            .ok_or_else(|| HothamError::Asset {
+                model: name.to_string(),
+                node: None,
+                reason: "The model has no root node".to_string(),
+            })?;
 
     // Get the new root entity.
-    let new_root_entity = entity_map.get(&root_entity).cloned().unwrap();
-
-    // We'll also need to fix up any meshes
-    for (_, (info, mesh)) in destination_world.query_mut::<(&Info, &mut Mesh)>() {
-        // Create new description sets
-        let new_descriptor_sets = vulkan_context
-            .create_mesh_descriptor_sets(descriptor_set_layouts.mesh_layout, &info.name)
-            .unwrap();
-        mesh.descriptor_sets = [new_descriptor_sets[0]];
-
-        // Create a new buffer
-        let new_ubo_buffer = Buffer::new(
-            vulkan_context,
-            &[mesh.ubo_data],
-            vk::BufferUsageFlags::UNIFORM_BUFFER,
-        )
-        .unwrap();
-        vulkan_context.update_buffer_descriptor_set(
-            &new_ubo_buffer,
-            mesh.descriptor_sets[0],
-            0,
-            vk::DescriptorType::UNIFORM_BUFFER,
-        );
-        mesh.ubo_buffer = new_ubo_buffer;
-    }
-
-    Some(new_root_entity)
+    map_entity(&root_entity, root_entity, "root")
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -476,6 +715,7 @@ mod tests {
         resources::{render_context::create_descriptor_set_layouts, VulkanContext},
     };
     use approx::assert_relative_eq;
+    use nalgebra::{vector, UnitQuaternion};
 
     #[test]
     pub fn test_load_models() {
@@ -526,7 +766,7 @@ mod tests {
                 &vulkan_context,
                 &set_layouts,
             );
-            assert!(model.is_some(), "Model {} could not be added", name);
+            assert!(model.is_ok(), "Model {} could not be added", name);
 
             let model = model.unwrap();
             let (info, transform, mesh, ..) = world
@@ -552,10 +792,45 @@ mod tests {
             let original_mesh = original_mesh.iter().next().unwrap().1 .0;
             let initial_buffer = original_mesh.ubo_buffer.handle;
             let new_buffer = mesh.ubo_buffer.handle;
-            assert_ne!(initial_buffer, new_buffer);
+            assert_eq!(initial_buffer, new_buffer);
         }
     }
 
+    #[test]
+    pub fn test_load_invalid_models() {
+        let vulkan_context = VulkanContext::testing().unwrap();
+        let set_layouts = create_descriptor_set_layouts(&vulkan_context).unwrap();
+
+        // Garbage
+        let data: Vec<&[u8]> = vec![b"definitely not a glb file"];
+        let error = load_models_from_glb(&data, &vulkan_context, &set_layouts)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            HothamError::Asset { ref model, node: None, .. } if model == "glTF file 0"
+        ));
+
+        // A model that was never loaded
+        let data: Vec<&[u8]> = vec![include_bytes!("../../test_assets/asteroid.glb")];
+        let models = load_models_from_glb(&data, &vulkan_context, &set_layouts).unwrap();
+        let mut world = World::default();
+        let error = add_model_to_world(
+            "Spaceship",
+            &models,
+            &mut world,
+            None,
+            &vulkan_context,
+            &set_layouts,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            HothamError::Asset { ref model, node: None, .. } if model == "Spaceship"
+        ));
+        assert_eq!(world.len(), 0);
+    }
+
     #[test]
     pub fn test_hand() {
         let vulkan_context = VulkanContext::testing().unwrap();
@@ -0,0 +1,179 @@
+use anyhow::{anyhow, Context, Result};
+use gltf::{buffer, Document, Gltf};
+
+use super::uri_resolver::{percent_decode, UriResolver};
+
+/// Read a `.gltf` or `.glb` file, along with every buffer and image it refers to. Buffers and images are returned in
+/// the same order as the document's, so they can be looked up by index.
+pub(crate) fn import(
+    bytes: &[u8],
+    resolver: &dyn UriResolver,
+) -> Result<(Document, Vec<buffer::Data>, Vec<gltf::image::Data>)> {
+    let Gltf { document, mut blob } = Gltf::from_slice(bytes)?;
+
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let mut data = match buffer.source() {
+            buffer::Source::Uri(uri) => read_uri(uri, resolver)?,
+            buffer::Source::Bin => blob.take().ok_or_else(|| {
+                anyhow!(
+                    "Buffer {} is in the GLB binary chunk, but there isn't one",
+                    buffer.index()
+                )
+            })?,
+        };
+        if data.len() < buffer.length() {
+            return Err(anyhow!(
+                "Buffer {} should be {} bytes long, but it is {} bytes long",
+                buffer.index(),
+                buffer.length(),
+                data.len()
+            ));
+        }
+
+        // Accessors are allowed to assume buffers are 4 byte aligned
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        buffers.push(buffer::Data(data));
+    }
+
+    let images = document
+        .images()
+        .map(|image| {
+            let encoded = match image.source() {
+                gltf::image::Source::Uri { uri, .. } => read_uri(uri, resolver)?,
+                gltf::image::Source::View { view, .. } => buffers
+                    .get(view.buffer().index())
+                    .and_then(|b| b.get(view.offset()..view.offset() + view.length()))
+                    .ok_or_else(|| anyhow!("Buffer view {} is out of range", view.index()))?
+                    .to_vec(),
+            };
+            decode_image(&encoded)
+                .with_context(|| format!("Unable to decode image {}", image.index()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((document, buffers, images))
+}
+
+/// `data:` URIs hold base64 encoded data. Anything else is up to `resolver`.
+fn read_uri(uri: &str, resolver: &dyn UriResolver) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .ok_or_else(|| anyhow!("Only base64 data URIs are supported"))?;
+        return Ok(base64::decode(encoded)?);
+    }
+
+    let uri = percent_decode(uri)?;
+    resolver
+        .resolve(&uri)
+        .with_context(|| format!("Unable to resolve URI {}", uri))
+}
+
+fn decode_image(encoded: &[u8]) -> Result<gltf::image::Data> {
+    let image = image::load_from_memory(encoded)?.to_rgba8();
+    Ok(gltf::image::Data {
+        width: image.width(),
+        height: image.height(),
+        format: gltf::image::Format::R8G8B8A8,
+        pixels: image.into_raw(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn positions() -> Vec<u8> {
+        [0f32, 0., 0., 1., 0., 0., 0., 1., 0.]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect()
+    }
+
+    fn png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::from_pixel(2, 2, image::Rgb([255, 0, 0])))
+            .write_to(&mut png, image::ImageOutputFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn gltf_json(buffer_uris: &[&str], image_uri: &str) -> String {
+        let buffers = buffer_uris
+            .iter()
+            .map(|uri| format!(r#"{{ "uri": "{}", "byteLength": 36 }}"#, uri))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "buffers": [{}],
+                "bufferViews": [{{ "buffer": 1, "byteLength": 36 }}],
+                "accessors": [{{
+                    "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                    "min": [0, 0, 0], "max": [1, 1, 0]
+                }}],
+                "images": [{{ "uri": "{}" }}],
+                "meshes": [{{ "primitives": [{{ "attributes": {{ "POSITION": 0 }} }}] }}],
+                "nodes": [{{ "name": "Triangle", "mesh": 0 }}],
+                "scenes": [{{ "nodes": [0] }}]
+            }}"#,
+            buffers, image_uri
+        )
+    }
+
+    #[test]
+    pub fn test_import_external_uris() {
+        let mut files = HashMap::new();
+        files.insert("empty.bin".to_string(), vec![0; 36]);
+        files.insert("my triangle.bin".to_string(), positions());
+        files.insert("textures/red.png".to_string(), png());
+
+        let gltf = gltf_json(&["empty.bin", "my%20triangle.bin"], "textures/red.png");
+        let (document, buffers, images) = import(gltf.as_bytes(), &files).unwrap();
+
+        // Every buffer is read, and the accessor reads from the right one
+        assert_eq!(buffers.len(), 2);
+        let primitive = document
+            .meshes()
+            .next()
+            .unwrap()
+            .primitives()
+            .next()
+            .unwrap();
+        let reader = primitive.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
+        let read = reader.read_positions().unwrap().collect::<Vec<_>>();
+        assert_eq!(read, vec![[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]]);
+
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].height), (2, 2));
+        assert_eq!(images[0].format, gltf::image::Format::R8G8B8A8);
+        assert_eq!(&images[0].pixels[0..4], &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    pub fn test_import_data_uris() {
+        let buffer_uri = format!(
+            "data:application/octet-stream;base64,{}",
+            base64::encode(positions())
+        );
+        let image_uri = format!("data:image/png;base64,{}", base64::encode(png()));
+
+        // Nothing is resolved externally
+        let gltf = gltf_json(&[&buffer_uri, &buffer_uri], &image_uri);
+        let (_, buffers, images) = import(gltf.as_bytes(), &HashMap::new()).unwrap();
+        assert_eq!(&buffers[1][..], &positions()[..]);
+        assert_eq!(images.len(), 1);
+    }
+
+    #[test]
+    pub fn test_import_missing_uri() {
+        let gltf = gltf_json(&["empty.bin", "triangle.bin"], "red.png");
+        let error = import(gltf.as_bytes(), &HashMap::new()).unwrap_err();
+        assert!(format!("{:#}", error).contains("empty.bin"));
+    }
+}
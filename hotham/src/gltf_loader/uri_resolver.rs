@@ -0,0 +1,112 @@
+use anyhow::{anyhow, Context, Result};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Finds the files a `.gltf` file refers to, like `.bin` buffers and `.png` images.
+/// `HashMap<String, Vec<u8>>` is a resolver too, for files that have already been loaded into memory.
+pub trait UriResolver {
+    /// Read everything at `uri`. The URI is relative to the glTF file, and has already been percent-decoded.
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>>;
+}
+
+/// Reads URIs from the filesystem, relative to a directory
+#[derive(Debug, Clone)]
+pub struct FileResolver {
+    base: PathBuf,
+}
+
+impl FileResolver {
+    /// Create a resolver for a glTF file in `base`
+    pub fn new(base: impl Into<PathBuf>) -> Self {
+        Self { base: base.into() }
+    }
+}
+
+impl UriResolver for FileResolver {
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>> {
+        let path = self.base.join(uri);
+        std::fs::read(&path).with_context(|| format!("Unable to read {}", path.display()))
+    }
+}
+
+/// Reads URIs from the APK's assets, relative to a directory in the assets folder
+#[cfg(target_os = "android")]
+#[derive(Debug, Clone)]
+pub struct AndroidAssetResolver {
+    base: String,
+}
+
+#[cfg(target_os = "android")]
+impl AndroidAssetResolver {
+    /// Create a resolver for a glTF file in the `base` directory of the assets folder
+    pub fn new(base: impl Into<String>) -> Self {
+        Self { base: base.into() }
+    }
+}
+
+#[cfg(target_os = "android")]
+impl UriResolver for AndroidAssetResolver {
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>> {
+        let path = if self.base.is_empty() {
+            uri.to_string()
+        } else {
+            format!("{}/{}", self.base.trim_end_matches('/'), uri)
+        };
+        crate::util::get_asset_from_path(&path)
+    }
+}
+
+impl UriResolver for HashMap<String, Vec<u8>> {
+    fn resolve(&self, uri: &str) -> Result<Vec<u8>> {
+        self.get(uri)
+            .cloned()
+            .ok_or_else(|| anyhow!("Nothing was provided for {}", uri))
+    }
+}
+
+/// Turn `%20` and friends back into the characters they stand for
+pub(crate) fn percent_decode(uri: &str) -> Result<String> {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let byte = uri
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid percent encoding in {}", uri))?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    Ok(String::from_utf8(decoded)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_percent_decode() {
+        assert_eq!(percent_decode("Sponza.bin").unwrap(), "Sponza.bin");
+        assert_eq!(
+            percent_decode("textures/my%20image.png").unwrap(),
+            "textures/my image.png"
+        );
+        assert_eq!(percent_decode("%E2%9C%93.png").unwrap(), "✓.png");
+        assert!(percent_decode("broken%2").is_err());
+        assert!(percent_decode("broken%zz.bin").is_err());
+    }
+
+    #[test]
+    pub fn test_hash_map_resolver() {
+        let mut files = HashMap::new();
+        files.insert("model.bin".to_string(), vec![1, 2, 3, 4]);
+
+        assert_eq!(files.resolve("model.bin").unwrap(), vec![1, 2, 3, 4]);
+        assert!(files.resolve("other.bin").is_err());
+    }
+}
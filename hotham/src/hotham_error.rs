@@ -23,6 +23,19 @@ pub enum HothamError {
         /// The format that was invalid
         format: String,
     },
+    /// A model couldn't be loaded
+    #[error(
+        "Unable to load model {model}{}: {reason}",
+        .node.as_ref().map(|n| format!(", node {}", n)).unwrap_or_default()
+    )]
+    Asset {
+        /// The name of the model
+        model: String,
+        /// The name of the node that couldn't be loaded, if the problem was with a particular node
+        node: Option<String>,
+        /// What went wrong
+        reason: String,
+    },
     /// Engine shutting down
     #[error("The engine is shutting down")]
     ShuttingDown,
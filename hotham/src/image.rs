@@ -1,16 +1,19 @@
 use ash::vk;
 
+use crate::{allocator::Allocation, deletion_queue::Garbage, resources::VulkanContext};
+
 /// Thin wrapper around a locally created Vulkan image.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Image {
     pub handle: vk::Image,
     pub view: vk::ImageView,
-    pub device_memory: vk::DeviceMemory,
+    pub allocation: Allocation,
     pub extent: vk::Extent2D,
     pub usage: vk::ImageUsageFlags,
     pub format: vk::Format,
     pub view_type: vk::ImageViewType,
     pub layer_count: u32,
+    pub mip_levels: u32,
 }
 
 impl Image {
@@ -18,32 +21,33 @@ impl Image {
     pub fn new(
         handle: vk::Image,
         view: vk::ImageView,
-        device_memory: vk::DeviceMemory,
+        allocation: Allocation,
         extent: vk::Extent2D,
         usage: vk::ImageUsageFlags,
         format: vk::Format,
         view_type: vk::ImageViewType,
         layer_count: u32,
+        mip_levels: u32,
     ) -> Self {
         Self {
             handle,
             view,
-            device_memory,
+            allocation,
             extent,
             usage,
             format,
             view_type,
             layer_count,
+            mip_levels,
         }
     }
 
-    // TODO: Handle destruction
-    // pub(crate) fn destroy(&self, vulkan_context: &VulkanContext) {
-    //     let device = &vulkan_context.device;
-    //     unsafe {
-    //         device.free_memory(self.device_memory, None);
-    //         device.destroy_image_view(self.view, None);
-    //         device.destroy_image(self.handle, None);
-    //     };
-    // }
+    /// Destroy the image and its view once the GPU has finished with them
+    pub fn destroy(self, vulkan_context: &VulkanContext) {
+        vulkan_context.destroy_later(self.garbage());
+    }
+
+    pub(crate) fn garbage(self) -> Garbage {
+        Garbage::Image(self.handle, self.view, self.allocation)
+    }
 }
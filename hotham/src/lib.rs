@@ -15,28 +15,39 @@
 pub use ash::vk;
 pub use openxr as xr;
 
+pub use deletion_queue::{GpuResources, Shared};
 pub use engine::{Engine, EngineBuilder};
 pub use hecs;
 pub use hotham_error::HothamError;
 pub use nalgebra;
 pub use rapier3d;
+pub use render_settings::{Foveation, RenderSettings};
 
+mod allocator;
 mod buffer;
 mod camera;
 /// Components are data that are used to update the simulation and interact with the external world
 pub mod components;
+mod deletion_queue;
+mod descriptor_allocator;
 mod engine;
+/// Image based lighting environments
+pub mod environment;
 mod frame;
+mod frustum;
 
 /// A tool to import models from glTF files into Hotham
 pub mod gltf_loader;
 mod hotham_error;
 mod image;
+mod render_settings;
 /// Resources are wrappers around some external state that the engine will interact with
 pub mod resources;
 /// Data used in the fragment shader
 pub mod scene_data;
 pub mod schedule_functions;
+mod shadow_map;
+mod skybox;
 mod swapchain;
 /// Systems are functions called each frame to update either the external state or the current simulation
 pub mod systems;
@@ -50,7 +61,7 @@ pub type HothamResult<T> = std::result::Result<T, HothamError>;
 
 /// Format used for color textures
 pub const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
-/// Format used for depth textures
+/// Format used for depth textures, unless `RenderSettings::depth_format` says otherwise
 pub const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
 
 /// Number of views
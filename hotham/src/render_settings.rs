@@ -0,0 +1,182 @@
+use ash::vk;
+use openxr as xr;
+
+use crate::DEPTH_FORMAT;
+
+/// Settings that trade image quality against frame time. Pass them to `EngineBuilder::render_settings` to tune the
+/// renderer for a particular device.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    /// How many samples to take of each pixel for MSAA. `TYPE_1` turns MSAA off. If the device can't take this many,
+    /// the most it can take is used instead.
+    pub msaa_samples: vk::SampleCountFlags,
+    /// The size of the swapchain images, relative to the size the runtime recommends. The result is clamped to the
+    /// largest size the runtime allows.
+    pub render_scale: f32,
+    /// The format of the depth buffer
+    pub depth_format: vk::Format,
+    /// How much to lower the resolution towards the edges of each eye. Only used when the runtime supports
+    /// `XR_FB_foveation` and the device supports `VK_EXT_fragment_density_map`.
+    pub foveation: Foveation,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: vk::SampleCountFlags::TYPE_4,
+            render_scale: 1.,
+            depth_format: DEPTH_FORMAT,
+            foveation: Foveation::Off,
+        }
+    }
+}
+
+/// Levels of fixed foveated rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Foveation {
+    /// Render the whole view at full resolution
+    Off,
+    /// Lower the resolution slightly at the edges of each eye
+    Low,
+    /// Lower the resolution further, over more of each eye
+    Medium,
+    /// Lower the resolution as much as the runtime allows
+    High,
+}
+
+impl Foveation {
+    pub(crate) fn level(&self) -> xr::FoveationLevelFB {
+        match self {
+            Foveation::Off => xr::FoveationLevelFB::NONE,
+            Foveation::Low => xr::FoveationLevelFB::LOW,
+            Foveation::Medium => xr::FoveationLevelFB::MEDIUM,
+            Foveation::High => xr::FoveationLevelFB::HIGH,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Scale the runtime's recommended resolution by `render_scale`, without going over `max`
+    pub(crate) fn scale_resolution(
+        &self,
+        recommended: vk::Extent2D,
+        max: vk::Extent2D,
+    ) -> vk::Extent2D {
+        let scale = |size: u32, max: u32| {
+            ((size as f32 * self.render_scale).round() as u32)
+                .max(1)
+                .min(max)
+        };
+
+        vk::Extent2D {
+            width: scale(recommended.width, max.width),
+            height: scale(recommended.height, max.height),
+        }
+    }
+
+    /// The most samples up to `msaa_samples` that both color and depth attachments support on this device
+    pub(crate) fn supported_msaa_samples(
+        &self,
+        limits: &vk::PhysicalDeviceLimits,
+    ) -> vk::SampleCountFlags {
+        let supported =
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+        let mut samples = self.msaa_samples;
+        while samples.as_raw() > 1 && !supported.contains(samples) {
+            samples = vk::SampleCountFlags::from_raw(samples.as_raw() >> 1);
+        }
+
+        if samples != self.msaa_samples {
+            println!(
+                "[HOTHAM_RENDERER] {:?} MSAA is not supported, using {:?} instead",
+                self.msaa_samples, samples
+            );
+        }
+        samples
+    }
+
+    /// Whether MSAA is on, and the scene has to be resolved into the swapchain image
+    pub(crate) fn is_multisampled(&self) -> bool {
+        self.msaa_samples != vk::SampleCountFlags::TYPE_1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_scale_resolution() {
+        let recommended = vk::Extent2D {
+            width: 1832,
+            height: 1920,
+        };
+        let max = vk::Extent2D {
+            width: 2048,
+            height: 2048,
+        };
+
+        let settings = RenderSettings {
+            render_scale: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.scale_resolution(recommended, max),
+            vk::Extent2D {
+                width: 916,
+                height: 960
+            }
+        );
+
+        // Never bigger than the runtime allows
+        let settings = RenderSettings {
+            render_scale: 1.5,
+            ..Default::default()
+        };
+        assert_eq!(settings.scale_resolution(recommended, max), max);
+
+        // Or smaller than a pixel
+        let settings = RenderSettings {
+            render_scale: 0.,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.scale_resolution(recommended, max),
+            vk::Extent2D {
+                width: 1,
+                height: 1
+            }
+        );
+    }
+
+    #[test]
+    pub fn test_supported_msaa_samples() {
+        let limits = vk::PhysicalDeviceLimits {
+            framebuffer_color_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4
+                | vk::SampleCountFlags::TYPE_8,
+            framebuffer_depth_sample_counts: vk::SampleCountFlags::TYPE_1
+                | vk::SampleCountFlags::TYPE_2
+                | vk::SampleCountFlags::TYPE_4,
+            ..Default::default()
+        };
+
+        let settings = |msaa_samples| RenderSettings {
+            msaa_samples,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings(vk::SampleCountFlags::TYPE_4).supported_msaa_samples(&limits),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            settings(vk::SampleCountFlags::TYPE_8).supported_msaa_samples(&limits),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            settings(vk::SampleCountFlags::TYPE_1).supported_msaa_samples(&limits),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+}
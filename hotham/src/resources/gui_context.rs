@@ -45,14 +45,9 @@ impl GuiContext {
                 )
                 .expect("Failed to create descriptor set layout.")
         };
-        let font_texture_descriptor_sets = unsafe {
-            device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .descriptor_pool(vulkan_context.descriptor_pool)
-                    .set_layouts(&[descriptor_set_layout]),
-            )
-        }
-        .expect("Failed to create descriptor sets.");
+        let font_texture_descriptor_sets = vulkan_context
+            .allocate_descriptor_sets(&[descriptor_set_layout])
+            .expect("Failed to create descriptor sets.");
 
         // Create PipelineLayout
         let pipeline_layout = unsafe {
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use nalgebra::{Isometry3, Vector2};
+
+use crate::{components::hand::Handedness, resources::xr_context::ActionType};
+
+/// The value of an input action for a single hand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputValue {
+    /// The value of a boolean action
+    Boolean(bool),
+    /// The value of a float action
+    Float(f32),
+    /// The value of a vector2 action
+    Vector2(Vector2<f32>),
+    /// The value of a pose action, or `None` if it couldn't be located this frame
+    Pose(Option<Isometry3<f32>>),
+}
+
+impl InputValue {
+    /// The value of an input action that isn't being used, or `None` for haptic outputs
+    pub fn default_for(action_type: ActionType) -> Option<Self> {
+        match action_type {
+            ActionType::Boolean => Some(InputValue::Boolean(false)),
+            ActionType::Float => Some(InputValue::Float(0.)),
+            ActionType::Vector2 => Some(InputValue::Vector2(Vector2::zeros())),
+            ActionType::Pose => Some(InputValue::Pose(None)),
+            ActionType::Haptic => None,
+        }
+    }
+}
+
+/// The state of every input action declared in the `ActionMap`, updated once per frame by `begin_frame`.
+///
+/// Actions are queried by name and hand. Asking for an action that doesn't exist, or asking for it as the wrong
+/// type, returns the default value for that type.
+#[derive(Debug, Clone, Default)]
+pub struct InputContext {
+    current: HashMap<String, [InputValue; 2]>,
+    previous: HashMap<String, [InputValue; 2]>,
+}
+
+impl InputContext {
+    /// Replace the state of every action with the values for a new frame
+    pub fn update(&mut self, values: HashMap<String, [InputValue; 2]>) {
+        self.previous = std::mem::replace(&mut self.current, values);
+    }
+
+    /// Get the raw value of an action
+    pub fn value(&self, name: &str, handedness: Handedness) -> Option<InputValue> {
+        get(&self.current, name, handedness)
+    }
+
+    /// Is this boolean action currently pressed?
+    pub fn boolean(&self, name: &str, handedness: Handedness) -> bool {
+        matches!(
+            self.value(name, handedness),
+            Some(InputValue::Boolean(true))
+        )
+    }
+
+    /// Was this boolean action pressed this frame?
+    pub fn just_pressed(&self, name: &str, handedness: Handedness) -> bool {
+        self.boolean(name, handedness) && !self.was_pressed(name, handedness)
+    }
+
+    /// Was this boolean action released this frame?
+    pub fn just_released(&self, name: &str, handedness: Handedness) -> bool {
+        !self.boolean(name, handedness) && self.was_pressed(name, handedness)
+    }
+
+    /// The current value of this float action
+    pub fn float(&self, name: &str, handedness: Handedness) -> f32 {
+        match self.value(name, handedness) {
+            Some(InputValue::Float(value)) => value,
+            _ => 0.,
+        }
+    }
+
+    /// The current value of this vector2 action
+    pub fn vector2(&self, name: &str, handedness: Handedness) -> Vector2<f32> {
+        match self.value(name, handedness) {
+            Some(InputValue::Vector2(value)) => value,
+            _ => Vector2::zeros(),
+        }
+    }
+
+    /// Where this pose action is in the stage, if it could be located
+    pub fn pose(&self, name: &str, handedness: Handedness) -> Option<Isometry3<f32>> {
+        match self.value(name, handedness) {
+            Some(InputValue::Pose(pose)) => pose,
+            _ => None,
+        }
+    }
+
+    /// Has this action's value changed since the last frame?
+    pub fn changed(&self, name: &str, handedness: Handedness) -> bool {
+        self.value(name, handedness) != get(&self.previous, name, handedness)
+    }
+
+    /// Move every pose by `isometry`, eg. when the stage moves part way through a frame
+    pub(crate) fn transform_poses(&mut self, isometry: &Isometry3<f32>) {
+        for values in self.current.values_mut() {
+            for value in values.iter_mut() {
+                if let InputValue::Pose(Some(pose)) = value {
+                    *pose = isometry * *pose;
+                }
+            }
+        }
+    }
+
+    fn was_pressed(&self, name: &str, handedness: Handedness) -> bool {
+        matches!(
+            get(&self.previous, name, handedness),
+            Some(InputValue::Boolean(true))
+        )
+    }
+}
+
+fn get(
+    values: &HashMap<String, [InputValue; 2]>,
+    name: &str,
+    handedness: Handedness,
+) -> Option<InputValue> {
+    let index = match handedness {
+        Handedness::Left => 0,
+        Handedness::Right => 1,
+    };
+    values.get(name).map(|v| v[index])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(jump: bool, thumbstick: Vector2<f32>) -> HashMap<String, [InputValue; 2]> {
+        vec![
+            (
+                "jump".to_string(),
+                [InputValue::Boolean(false), InputValue::Boolean(jump)],
+            ),
+            (
+                "move".to_string(),
+                [
+                    InputValue::Vector2(thumbstick),
+                    InputValue::Vector2(thumbstick),
+                ],
+            ),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    pub fn test_input_context() {
+        let mut input_context = InputContext::default();
+        input_context.update(frame(false, Vector2::zeros()));
+        assert!(!input_context.boolean("jump", Handedness::Right));
+        assert!(!input_context.just_pressed("jump", Handedness::Right));
+
+        input_context.update(frame(true, Vector2::new(0., 1.)));
+        assert!(input_context.boolean("jump", Handedness::Right));
+        assert!(input_context.just_pressed("jump", Handedness::Right));
+        assert!(!input_context.boolean("jump", Handedness::Left));
+        assert_eq!(
+            input_context.vector2("move", Handedness::Left),
+            Vector2::new(0., 1.)
+        );
+        assert!(input_context.changed("move", Handedness::Left));
+
+        input_context.update(frame(true, Vector2::new(0., 1.)));
+        assert!(input_context.boolean("jump", Handedness::Right));
+        assert!(!input_context.just_pressed("jump", Handedness::Right));
+        assert!(!input_context.changed("move", Handedness::Left));
+
+        input_context.update(frame(false, Vector2::zeros()));
+        assert!(input_context.just_released("jump", Handedness::Right));
+
+        // Unknown actions and actions of the wrong type have default values
+        assert_eq!(input_context.float("jump", Handedness::Right), 0.);
+        assert_eq!(input_context.pose("missing", Handedness::Right), None);
+    }
+}
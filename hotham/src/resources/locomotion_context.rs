@@ -0,0 +1,47 @@
+use nalgebra::Point3;
+
+/// How the user turns with the right thumbstick
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TurnMode {
+    /// Turn by a fixed angle, in radians, each time the thumbstick is pushed to the side
+    Snap(f32),
+    /// Turn continuously at a speed in radians per second
+    Smooth(f32),
+    /// Don't turn at all
+    Disabled,
+}
+
+/// Settings and state for `locomotion_system`.
+///
+/// The left thumbstick moves the user in the direction they are looking. The right thumbstick turns them, or aims a
+/// teleport when pushed forward. Letting go of the thumbstick teleports the user to where they were pointing.
+#[derive(Debug, Clone)]
+pub struct LocomotionContext {
+    /// How fast the left thumbstick moves the user, in meters per second. Set to 0 to disable smooth movement.
+    pub move_speed: f32,
+    /// How the right thumbstick turns the user
+    pub turn_mode: TurnMode,
+    /// Whether pushing the right thumbstick forward aims a teleport
+    pub teleport_enabled: bool,
+    /// How far away a teleport can land, in meters
+    pub teleport_range: f32,
+    /// Where the user will land if they let go of the thumbstick now, if they are aiming at somewhere they can stand.
+    /// Useful for drawing a marker.
+    pub teleport_target: Option<Point3<f32>>,
+    pub(crate) aiming_teleport: bool,
+    pub(crate) snap_turn_ready: bool,
+}
+
+impl Default for LocomotionContext {
+    fn default() -> Self {
+        Self {
+            move_speed: 2.,
+            turn_mode: TurnMode::Snap(std::f32::consts::FRAC_PI_6),
+            teleport_enabled: true,
+            teleport_range: 20.,
+            teleport_target: None,
+            aiming_teleport: false,
+            snap_turn_ready: true,
+        }
+    }
+}
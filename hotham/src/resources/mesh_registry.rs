@@ -0,0 +1,296 @@
+use std::{
+    cmp::max,
+    ops::Range,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::Result;
+use ash::vk;
+
+use crate::{
+    allocator::FreeList,
+    buffer::Buffer,
+    deletion_queue::{DeletionQueue, Garbage},
+    resources::VulkanContext,
+    vertex::Vertex,
+};
+
+/// How many vertices each block of the registry can hold, unless a primitive needs more
+pub const VERTEX_BLOCK_SIZE: usize = 1 << 18;
+/// How many indices each block of the registry can hold, unless a primitive needs more
+pub const INDEX_BLOCK_SIZE: usize = 1 << 20;
+
+/// Stores the vertices and indices of every `Primitive` in a few large buffers, instead of giving each one its
+/// own. Geometry is added by `gltf_loader` and handed out as `MeshGeometry` handles, which can be cloned freely.
+///
+/// Once the last handle to some geometry is dropped (eg. the last entity using it has been despawned), its space is
+/// handed to the `DeletionQueue` as `Garbage::Geometry`, and reused once the GPU has finished drawing it.
+#[derive(Debug, Clone, Default)]
+pub struct MeshRegistry {
+    registry: Arc<Mutex<Registry>>,
+}
+
+/// Where a primitive's geometry lives in the `MeshRegistry`. Cloning the handle is cheap, and the geometry is
+/// freed when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct MeshGeometry {
+    allocation: Arc<Allocation>,
+}
+
+impl MeshRegistry {
+    /// Upload the geometry of a primitive
+    pub(crate) fn add(
+        &self,
+        vulkan_context: &VulkanContext,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Result<MeshGeometry> {
+        let deletion_queue = &vulkan_context.deletion_queue;
+        let geometry = match self.allocate(deletion_queue, vertices.len(), indices.len()) {
+            Some(geometry) => geometry,
+            None => {
+                let vertex_capacity = max(VERTEX_BLOCK_SIZE, vertices.len());
+                let index_capacity = max(INDEX_BLOCK_SIZE, indices.len());
+                println!(
+                    "[HOTHAM_MESH_REGISTRY] Creating a block for {} vertices and {} indices",
+                    vertex_capacity, index_capacity
+                );
+                let vertex_buffer = Buffer::with_capacity(
+                    vulkan_context,
+                    vertex_capacity,
+                    vk::BufferUsageFlags::VERTEX_BUFFER,
+                )?;
+                let index_buffer = Buffer::with_capacity(
+                    vulkan_context,
+                    index_capacity,
+                    vk::BufferUsageFlags::INDEX_BUFFER,
+                )?;
+                self.add_block(vertex_buffer, index_buffer, vertex_capacity, index_capacity);
+                self.allocate(deletion_queue, vertices.len(), indices.len())
+                    .expect("A new block is always big enough")
+            }
+        };
+
+        let registry = self.lock();
+        let block = &registry.blocks[geometry.allocation.block];
+        block.vertex_buffer.update_range(
+            vulkan_context,
+            geometry.allocation.vertices.start as _,
+            vertices,
+        )?;
+        block.index_buffer.update_range(
+            vulkan_context,
+            geometry.allocation.indices.start as _,
+            indices,
+        )?;
+
+        Ok(geometry)
+    }
+
+    /// Make space for geometry in one of the existing blocks, if there's room
+    pub(crate) fn allocate(
+        &self,
+        deletion_queue: &DeletionQueue,
+        vertex_count: usize,
+        index_count: usize,
+    ) -> Option<MeshGeometry> {
+        let mut registry = self.lock();
+        let (block, vertices, indices) = registry.allocate(vertex_count as _, index_count as _)?;
+        let allocation = Allocation {
+            deletion_queue: deletion_queue.clone(),
+            block,
+            vertex_buffer: registry.blocks[block].vertex_buffer.handle,
+            index_buffer: registry.blocks[block].index_buffer.handle,
+            vertices,
+            indices,
+        };
+
+        Some(MeshGeometry {
+            allocation: Arc::new(allocation),
+        })
+    }
+
+    pub(crate) fn add_block(
+        &self,
+        vertex_buffer: Buffer<Vertex>,
+        index_buffer: Buffer<u32>,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) {
+        self.lock().blocks.push(Block {
+            vertex_buffer,
+            index_buffer,
+            vertices: FreeList::new(vertex_capacity as _),
+            indices: FreeList::new(index_capacity as _),
+        });
+    }
+
+    /// Make space available again. Called by `VulkanContext` when it collects `Garbage::Geometry`.
+    pub(crate) fn release(&self, block: usize, vertices: Range<u64>, indices: Range<u64>) {
+        let mut registry = self.lock();
+        let block = &mut registry.blocks[block];
+        block.vertices.release(vertices);
+        block.indices.release(indices);
+    }
+
+    /// How many vertices and indices are being used, including any that are waiting to be reused
+    pub fn used(&self) -> (usize, usize) {
+        let registry = self.lock();
+        registry.blocks.iter().fold((0, 0), |(v, i), b| {
+            (
+                v + (b.vertices.capacity() - b.vertices.available()) as usize,
+                i + (b.indices.capacity() - b.indices.available()) as usize,
+            )
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Registry> {
+        self.registry.lock().unwrap()
+    }
+}
+
+impl MeshGeometry {
+    /// The vertex buffer this geometry is stored in
+    pub fn vertex_buffer(&self) -> vk::Buffer {
+        self.allocation.vertex_buffer
+    }
+
+    /// The index buffer this geometry is stored in
+    pub fn index_buffer(&self) -> vk::Buffer {
+        self.allocation.index_buffer
+    }
+
+    /// Added to each index to find the vertex in `vertex_buffer`
+    pub fn vertex_offset(&self) -> i32 {
+        self.allocation.vertices.start as _
+    }
+
+    /// Where this geometry's indices start in `index_buffer`
+    pub fn first_index(&self) -> u32 {
+        self.allocation.indices.start as _
+    }
+
+    /// The number of indices
+    pub fn index_count(&self) -> u32 {
+        (self.allocation.indices.end - self.allocation.indices.start) as _
+    }
+}
+
+/// Handles are equal if they point to the same geometry
+impl PartialEq for MeshGeometry {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.allocation, &other.allocation)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registry {
+    blocks: Vec<Block>,
+}
+
+impl Registry {
+    fn allocate(
+        &mut self,
+        vertex_count: u64,
+        index_count: u64,
+    ) -> Option<(usize, Range<u64>, Range<u64>)> {
+        for (i, block) in self.blocks.iter_mut().enumerate() {
+            let vertices = match block.vertices.allocate(vertex_count, 1) {
+                Some(v) => v,
+                None => continue,
+            };
+            match block.indices.allocate(index_count, 1) {
+                Some(indices) => return Some((i, vertices, indices)),
+                None => block.vertices.release(vertices),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug)]
+struct Block {
+    vertex_buffer: Buffer<Vertex>,
+    index_buffer: Buffer<u32>,
+    vertices: FreeList,
+    indices: FreeList,
+}
+
+#[derive(Debug)]
+struct Allocation {
+    deletion_queue: DeletionQueue,
+    block: usize,
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    vertices: Range<u64>,
+    indices: Range<u64>,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.deletion_queue.push(Garbage::Geometry {
+            block: self.block,
+            vertices: self.vertices.clone(),
+            indices: self.indices.clone(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{util::test_buffer, SWAPCHAIN_LENGTH};
+
+    #[test]
+    pub fn test_geometry_is_released_after_last_handle_is_dropped() {
+        let registry = MeshRegistry::default();
+        registry.add_block(test_buffer(), test_buffer(), 10, 30);
+
+        // What `VulkanContext::collect_garbage` does
+        let deletion_queue = DeletionQueue::default();
+        let begin_frame = || {
+            for garbage in deletion_queue.begin_frame() {
+                if let Garbage::Geometry {
+                    block,
+                    vertices,
+                    indices,
+                } = garbage
+                {
+                    registry.release(block, vertices, indices);
+                }
+            }
+        };
+
+        let geometry = registry.allocate(&deletion_queue, 4, 6).unwrap();
+        let other = registry.allocate(&deletion_queue, 4, 6).unwrap();
+        assert_eq!(other.vertex_offset(), 4);
+        assert_eq!(other.first_index(), 6);
+        assert_eq!(other.index_count(), 6);
+        assert_ne!(geometry, other);
+
+        // Doesn't fit in the block
+        assert!(registry.allocate(&deletion_queue, 4, 6).is_none());
+
+        let clone = geometry.clone();
+        assert_eq!(clone, geometry);
+        drop(geometry);
+        begin_frame();
+        assert_eq!(registry.used(), (8, 12));
+
+        // The space isn't reused until the GPU is done with it
+        drop(clone);
+        for _ in 0..SWAPCHAIN_LENGTH - 1 {
+            begin_frame();
+            assert_eq!(registry.used(), (8, 12));
+        }
+        begin_frame();
+        assert_eq!(registry.used(), (4, 6));
+        assert_eq!(
+            registry
+                .allocate(&deletion_queue, 4, 6)
+                .unwrap()
+                .vertex_offset(),
+            0
+        );
+    }
+}
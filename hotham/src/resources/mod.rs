@@ -2,6 +2,9 @@
 pub mod audio_context;
 pub mod gui_context;
 pub mod haptic_context;
+pub mod input_context;
+pub mod locomotion_context;
+pub mod mesh_registry;
 pub mod physics_context;
 pub mod render_context;
 pub mod vulkan_context;
@@ -10,6 +13,9 @@ pub mod xr_context;
 pub use audio_context::AudioContext;
 pub use gui_context::GuiContext;
 pub use haptic_context::HapticContext;
+pub use input_context::InputContext;
+pub use locomotion_context::LocomotionContext;
+pub use mesh_registry::{MeshGeometry, MeshRegistry};
 pub use physics_context::PhysicsContext;
 pub use render_context::RenderContext;
 pub(crate) use vulkan_context::VulkanContext;
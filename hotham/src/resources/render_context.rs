@@ -18,16 +18,20 @@ use crate::{
     buffer::Buffer,
     camera::Camera,
     components::Material,
+    environment::Environment,
     frame::Frame,
     image::Image,
+    render_settings::RenderSettings,
     resources::{VulkanContext, XrContext},
-    scene_data::{SceneData, SceneParams},
-    swapchain::Swapchain,
+    scene_data::{SceneData, SceneLights, SceneParams},
+    shadow_map::ShadowMap,
+    skybox::Skybox,
+    swapchain::{FragmentDensityMaps, Swapchain},
     texture::Texture,
     vertex::Vertex,
-    COLOR_FORMAT, DEPTH_ATTACHMENT_USAGE_FLAGS, DEPTH_FORMAT, VIEW_COUNT,
+    COLOR_FORMAT, DEPTH_ATTACHMENT_USAGE_FLAGS, VIEW_COUNT,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use ash::{
     prelude::VkResult,
     vk::{self, Handle},
@@ -42,25 +46,68 @@ pub struct DescriptorSetLayouts {
     pub mesh_layout: vk::DescriptorSetLayout,
 }
 
-#[derive(Clone)]
+/// Format of the fragment density maps the runtime makes for a foveated swapchain
+const FRAGMENT_DENSITY_MAP_FORMAT: vk::Format = vk::Format::R8G8_UNORM;
+
+/// The most mesh instances that can be drawn in one frame, by each of `rendering_system` and `shadows_system`.
+/// Any more are skipped.
+pub const MAX_INSTANCES: usize = 4096;
+
+/// The most joint matrices `skinning_system` can send to the GPU in one frame, shared between every skinned mesh.
+/// Meshes whose joints don't fit are drawn in their bind pose.
+pub const MAX_JOINTS: usize = 16384;
+
+/// The most morph target weights `morph_targets_system` can send to the GPU in one frame, shared between every mesh
+/// with morph targets. Meshes whose weights don't fit aren't morphed.
+pub const MAX_MORPH_WEIGHTS: usize = 65536;
+
+/// What `rendering_system` did in the last frame
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DrawStats {
+    /// Meshes that were at least partly in view, and were drawn
+    pub meshes_drawn: usize,
+    /// Meshes that were out of view, and were skipped
+    pub meshes_culled: usize,
+    /// Primitives that were drawn, counting each instance
+    pub primitives_drawn: usize,
+    /// Instanced draw calls used to draw the primitives
+    pub draw_calls: usize,
+}
+
 pub struct RenderContext {
     pub frames: Vec<Frame>,
     pub descriptor_set_layouts: DescriptorSetLayouts,
     pub pipeline_layout: vk::PipelineLayout,
     pub pipeline: vk::Pipeline,
+    pub blend_pipeline: vk::Pipeline,
     pub render_pass: vk::RenderPass,
     pub depth_image: Image,
-    pub color_image: Image,
+    /// The multisampled image the scene is drawn into, before being resolved into the swapchain. `None` if MSAA is off.
+    pub color_image: Option<Image>,
     pub render_area: vk::Rect2D,
+    /// The settings the renderer was created with. MSAA may have been lowered to what the device supports.
+    pub render_settings: RenderSettings,
     pub scene_data: SceneData,
     pub scene_data_buffer: Buffer<SceneData>,
+    pub scene_params: SceneParams,
     pub scene_params_buffer: Buffer<SceneParams>,
+    pub scene_lights_buffer: Buffer<SceneLights>,
+    pub instance_buffer: Buffer<Matrix4<f32>>,
+    pub shadow_instance_buffer: Buffer<Matrix4<f32>>,
+    /// Joint matrices of every skinned mesh, written by `skinning_system`
+    pub joint_buffer: Buffer<Matrix4<f32>>,
+    /// Morph target weights of every mesh with morph targets, written by `morph_targets_system`
+    pub morph_weight_buffer: Buffer<f32>,
     pub scene_data_descriptor_sets: Vec<vk::DescriptorSet>,
+    pub shadow_map: ShadowMap,
+    pub environment: Environment,
+    pub skybox: Skybox,
     pub render_start_time: Instant,
     pub cameras: Vec<Camera>,
     pub views: Vec<xr::View>,
     pub last_frame_time: Instant,
     pub frame_index: usize,
+    pub draw_stats: DrawStats,
 }
 
 impl Drop for RenderContext {
@@ -99,27 +146,36 @@ impl Drop for RenderContext {
 impl RenderContext {
     pub fn new(vulkan_context: &VulkanContext, xr_context: &XrContext) -> Result<Self> {
         println!("[HOTHAM_RENDERER] Creating renderer..");
-        let xr_swapchain = &xr_context.swapchain;
-        let swapchain_resolution = xr_context.swapchain_resolution;
-
         // Build swapchain
-        let swapchain = Swapchain::new(xr_swapchain, swapchain_resolution)?;
-        Self::new_from_swapchain(vulkan_context, &swapchain)
+        let swapchain = xr_context.create_swapchain()?;
+        Self::new_from_swapchain(vulkan_context, &swapchain, &xr_context.render_settings)
     }
 
     pub(crate) fn new_from_swapchain(
         vulkan_context: &VulkanContext,
         swapchain: &Swapchain,
+        render_settings: &RenderSettings,
     ) -> Result<Self> {
         let render_area = vk::Rect2D {
             extent: swapchain.resolution,
             offset: vk::Offset2D::default(),
         };
 
+        let render_settings = RenderSettings {
+            msaa_samples: render_settings
+                .supported_msaa_samples(&vulkan_context.physical_device_properties.limits),
+            ..*render_settings
+        };
+        check_depth_format(vulkan_context, render_settings.depth_format)?;
+
         let descriptor_set_layouts = create_descriptor_set_layouts(vulkan_context)?;
 
         // Pipeline, render pass
-        let render_pass = create_render_pass(vulkan_context)?;
+        let render_pass = create_render_pass(
+            vulkan_context,
+            &render_settings,
+            swapchain.fragment_density_maps.is_some(),
+        )?;
         let pipeline_layout = create_pipeline_layout(
             vulkan_context,
             &[
@@ -128,25 +184,58 @@ impl RenderContext {
                 descriptor_set_layouts.mesh_layout,
             ],
         )?;
-        let pipeline = create_pipeline(vulkan_context, pipeline_layout, &render_area, render_pass)?;
+        let pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            &render_area,
+            render_pass,
+            false,
+            render_settings.msaa_samples,
+        )?;
+
+        // Transparent primitives are blended over the top of the opaque ones
+        let blend_pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            &render_area,
+            render_pass,
+            true,
+            render_settings.msaa_samples,
+        )?;
+
+        // Shadow map, rendered before the PBR render pass
+        let shadow_map = ShadowMap::new(vulkan_context, pipeline_layout)?;
+
+        // Skybox, drawn at the start of the PBR render pass
+        let skybox = Skybox::new(
+            vulkan_context,
+            pipeline_layout,
+            &render_area,
+            render_pass,
+            render_settings.msaa_samples,
+        )?;
 
         // Depth image, shared between frames
-        let depth_image = vulkan_context.create_image(
-            DEPTH_FORMAT,
+        let depth_image = vulkan_context.create_multisampled_image(
+            render_settings.depth_format,
             &swapchain.resolution,
             DEPTH_ATTACHMENT_USAGE_FLAGS,
             2,
-            1,
+            render_settings.msaa_samples,
         )?;
 
         // Color image, used for MSAA.
-        let color_image = vulkan_context.create_image(
-            COLOR_FORMAT,
-            &swapchain.resolution,
-            vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            2,
-            1,
-        )?;
+        let color_image = if render_settings.is_multisampled() {
+            Some(vulkan_context.create_multisampled_image(
+                COLOR_FORMAT,
+                &swapchain.resolution,
+                vk::ImageUsageFlags::TRANSIENT_ATTACHMENT | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                2,
+                render_settings.msaa_samples,
+            )?)
+        } else {
+            None
+        };
 
         // Create all the per-frame resources we need
         let frames = create_frames(
@@ -154,7 +243,7 @@ impl RenderContext {
             &render_pass,
             swapchain,
             &depth_image,
-            &color_image,
+            color_image.as_ref(),
         )?;
 
         println!("[HOTHAM_RENDERER] Creating UBO..");
@@ -172,15 +261,43 @@ impl RenderContext {
             vk::BufferUsageFlags::UNIFORM_BUFFER,
         )?;
 
-        let diffuse_ibl = Texture::from_ktx2(
-            "Diffuse IBL",
-            include_bytes!("../../data/diffuse_ibl.ktx2"),
+        let scene_lights_buffer = Buffer::new(
             vulkan_context,
+            &[SceneLights::default()],
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
         )?;
-        let specular_ibl = Texture::from_ktx2(
-            "Specular IBL",
-            include_bytes!("../../data/specular_ibl.ktx2"),
+
+        // Transforms of each mesh instance, for the PBR and shadow render passes
+        let instances = vec![Matrix4::identity(); MAX_INSTANCES];
+        let instance_buffer = Buffer::new(
+            vulkan_context,
+            &instances,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+        let shadow_instance_buffer = Buffer::new(
+            vulkan_context,
+            &instances,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+
+        // Joint matrices for skinning, for both render passes
+        let joint_buffer = Buffer::with_capacity(
+            vulkan_context,
+            MAX_JOINTS,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+
+        // Morph target weights, for both render passes
+        let morph_weight_buffer = Buffer::with_capacity(
+            vulkan_context,
+            MAX_MORPH_WEIGHTS,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        )?;
+
+        let environment = Environment::from_ktx2(
             vulkan_context,
+            include_bytes!("../../data/diffuse_ibl.ktx2"),
+            include_bytes!("../../data/specular_ibl.ktx2"),
         )?;
         let brdf_lut = Texture::from_ktx2(
             "BRDF LUT",
@@ -192,10 +309,35 @@ impl RenderContext {
             descriptor_set_layouts.scene_data_layout,
             &scene_data_buffer,
             &scene_params_buffer,
-            &diffuse_ibl,
-            &specular_ibl,
+            &scene_lights_buffer,
+            &environment,
             &brdf_lut,
+            &shadow_map,
         )?;
+        vulkan_context.update_buffer_descriptor_set(
+            &instance_buffer,
+            scene_data_descriptor_sets[0],
+            8,
+            vk::DescriptorType::STORAGE_BUFFER,
+        );
+        vulkan_context.update_buffer_descriptor_set(
+            &shadow_instance_buffer,
+            scene_data_descriptor_sets[0],
+            9,
+            vk::DescriptorType::STORAGE_BUFFER,
+        );
+        vulkan_context.update_buffer_descriptor_set(
+            &joint_buffer,
+            scene_data_descriptor_sets[0],
+            10,
+            vk::DescriptorType::STORAGE_BUFFER,
+        );
+        vulkan_context.update_buffer_descriptor_set(
+            &morph_weight_buffer,
+            scene_data_descriptor_sets[0],
+            11,
+            vk::DescriptorType::STORAGE_BUFFER,
+        );
 
         println!("[HOTHAM_RENDERER] ..done! {:?}", scene_data_buffer);
 
@@ -205,23 +347,58 @@ impl RenderContext {
             frames,
             descriptor_set_layouts,
             pipeline,
+            blend_pipeline,
             pipeline_layout,
             render_pass,
             frame_index: 0,
             depth_image,
             color_image,
             render_area,
+            render_settings,
             scene_data,
             scene_data_buffer,
+            scene_params,
             scene_params_buffer,
+            scene_lights_buffer,
+            instance_buffer,
+            shadow_instance_buffer,
+            joint_buffer,
+            morph_weight_buffer,
             scene_data_descriptor_sets,
+            shadow_map,
+            environment,
+            skybox,
             render_start_time: Instant::now(),
             cameras: vec![Default::default(); 2],
             views: Vec::new(),
             last_frame_time: Instant::now(),
+            draw_stats: Default::default(),
         })
     }
 
+    /// Light the scene with a different image based lighting environment
+    pub fn set_environment(
+        &mut self,
+        vulkan_context: &VulkanContext,
+        environment: Environment,
+    ) -> Result<()> {
+        // The old environment's descriptors may still be in use by a frame in flight
+        unsafe { vulkan_context.device.device_wait_idle()? };
+
+        let descriptor_set = self.scene_data_descriptor_sets[0];
+        vulkan_context.update_texture_descriptor_set(&environment.irradiance, descriptor_set, 2);
+        vulkan_context.update_texture_descriptor_set(&environment.prefiltered, descriptor_set, 3);
+        vulkan_context.update_texture_descriptor_set(environment.skybox(), descriptor_set, 7);
+
+        self.scene_params.prefiltered_cube_mip_levels =
+            (environment.prefiltered_mip_levels() - 1) as f32;
+        self.scene_params_buffer
+            .update(vulkan_context, &[self.scene_params])?;
+        std::mem::replace(&mut self.environment, environment).destroy(vulkan_context);
+
+        Ok(())
+    }
+
     // TODO: Make this update the scene data rather than creating a new one
     pub(crate) fn update_scene_data(
         &mut self,
@@ -289,6 +466,7 @@ impl RenderContext {
 
         // Wait for the GPU to be ready.
         self.wait(device, frame);
+        vulkan_context.collect_garbage();
 
         // Begin recording the command buffer.
         unsafe {
@@ -302,6 +480,44 @@ impl RenderContext {
         }
     }
 
+    pub(crate) fn begin_shadow_render_pass(
+        &self,
+        vulkan_context: &VulkanContext,
+        swapchain_image_index: usize,
+    ) {
+        let device = &vulkan_context.device;
+        let command_buffer = self.frames[swapchain_image_index].command_buffer;
+
+        self.shadow_map
+            .begin_render_pass(vulkan_context, command_buffer);
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.shadow_map.pipeline,
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &self.scene_data_descriptor_sets,
+                &[],
+            );
+        }
+    }
+
+    pub(crate) fn end_shadow_render_pass(
+        &self,
+        vulkan_context: &VulkanContext,
+        swapchain_image_index: usize,
+    ) {
+        let command_buffer = self.frames[swapchain_image_index].command_buffer;
+        unsafe {
+            vulkan_context.device.cmd_end_render_pass(command_buffer);
+        }
+    }
+
     pub(crate) fn begin_pbr_render_pass(
         &self,
         vulkan_context: &VulkanContext,
@@ -326,11 +542,6 @@ impl RenderContext {
                 &render_pass_begin_info,
                 vk::SubpassContents::INLINE,
             );
-            device.cmd_bind_pipeline(
-                command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.pipeline,
-            );
             device.cmd_bind_descriptor_sets(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
@@ -340,6 +551,14 @@ impl RenderContext {
                 &[],
             );
         }
+
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+        }
     }
 
     pub(crate) fn end_pbr_render_pass(
@@ -392,6 +611,22 @@ impl RenderContext {
     }
 }
 
+/// Where `MorphPushConstant` goes, right after the `Material`. Together they fit in the 128 bytes of push constants
+/// every device has.
+pub const MORPH_PUSH_CONSTANT_OFFSET: u32 = size_of::<Material>() as _;
+
+/// Tells the vertex shader where a primitive's morph targets are in its mesh's `morph_target_buffer`
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MorphPushConstant {
+    /// The index of the primitive's first vertex in the vertex buffer
+    pub base_vertex: u32,
+    /// The index of the primitive's first delta
+    pub first_delta: u32,
+    /// How many morph targets the primitive has. 0 if it has none, even if other primitives of its mesh do.
+    pub target_count: u32,
+}
+
 pub fn create_push_constant<T: Sized>(p: &T) -> &[u8] {
     unsafe { std::slice::from_raw_parts(std::mem::transmute(p), size_of::<T>()) }
 }
@@ -473,6 +708,48 @@ pub fn create_descriptor_set_layouts(
         .descriptor_count(1)
         .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
         .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    // set = 0 binding = 5
+    let scene_lights = vk::DescriptorSetLayoutBinding::builder()
+        .binding(5)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+    // set = 0 binding = 6
+    let shadow_map = vk::DescriptorSetLayoutBinding::builder()
+        .binding(6)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    // set = 0 binding = 7
+    let skybox = vk::DescriptorSetLayoutBinding::builder()
+        .binding(7)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+        .stage_flags(vk::ShaderStageFlags::FRAGMENT);
+    // set = 0 binding = 8
+    let instances = vk::DescriptorSetLayoutBinding::builder()
+        .binding(8)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
+    // set = 0 binding = 9
+    let shadow_instances = vk::DescriptorSetLayoutBinding::builder()
+        .binding(9)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
+    // set = 0 binding = 10
+    let joints = vk::DescriptorSetLayoutBinding::builder()
+        .binding(10)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
+    // set = 0 binding = 11
+    let morph_weights = vk::DescriptorSetLayoutBinding::builder()
+        .binding(11)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
     let scene_data_layout = unsafe {
         vulkan_context.device.create_descriptor_set_layout(
             &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&[
@@ -481,6 +758,13 @@ pub fn create_descriptor_set_layouts(
                 *sampler_irradiance,
                 *prefiltered_map,
                 *sampler_brdflut,
+                *scene_lights,
+                *shadow_map,
+                *skybox,
+                *instances,
+                *shadow_instances,
+                *joints,
+                *morph_weights,
             ]),
             None,
         )
@@ -547,9 +831,15 @@ pub fn create_descriptor_set_layouts(
         .descriptor_count(1)
         .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
         .stage_flags(vk::ShaderStageFlags::VERTEX);
+    // set = 2, binding = 1
+    let morph_targets = vk::DescriptorSetLayoutBinding::builder()
+        .binding(1)
+        .descriptor_count(1)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .stage_flags(vk::ShaderStageFlags::VERTEX);
     let mesh_data_layout = unsafe {
         vulkan_context.device.create_descriptor_set_layout(
-            &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&[*mesh_data]),
+            &vk::DescriptorSetLayoutCreateInfo::builder().bindings(&[*mesh_data, *morph_targets]),
             None,
         )
     }?;
@@ -571,42 +861,98 @@ fn create_frames(
     render_pass: &vk::RenderPass,
     swapchain: &Swapchain,
     depth_image: &Image,
-    color_image: &Image,
+    color_image: Option<&Image>,
 ) -> Result<Vec<Frame>> {
     print!("[HOTHAM_INIT] Creating frames..");
-    let frames = swapchain
-        .images
-        .iter()
-        .flat_map(|i| {
-            vulkan_context.create_image_view(
-                i,
-                COLOR_FORMAT,
-                vk::ImageViewType::TYPE_2D_ARRAY,
-                2,
-                1,
-            )
-        })
-        .map(|i| {
-            Frame::new(
+    let mut frames = Vec::with_capacity(swapchain.images.len());
+    for (i, image) in swapchain.images.iter().enumerate() {
+        let swapchain_image_view = vulkan_context.create_image_view(
+            image,
+            COLOR_FORMAT,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            2,
+            1,
+        )?;
+
+        // In the same order as the render pass' attachments
+        let mut attachments = match color_image {
+            Some(color_image) => vec![color_image.view, depth_image.view, swapchain_image_view],
+            None => vec![swapchain_image_view, depth_image.view],
+        };
+        if let Some(fragment_density_maps) = &swapchain.fragment_density_maps {
+            attachments.push(create_fragment_density_map_view(
                 vulkan_context,
-                *render_pass,
-                swapchain.resolution,
+                fragment_density_maps,
                 i,
-                depth_image.view,
-                color_image.view,
-            )
-        })
-        .collect::<Result<Vec<Frame>>>()?;
+            )?);
+        }
+
+        frames.push(Frame::new(
+            vulkan_context,
+            *render_pass,
+            swapchain.resolution,
+            swapchain_image_view,
+            &attachments,
+        )?);
+    }
     println!(" ..done!");
     Ok(frames)
 }
 
-fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass> {
+fn create_fragment_density_map_view(
+    vulkan_context: &VulkanContext,
+    fragment_density_maps: &FragmentDensityMaps,
+    index: usize,
+) -> Result<vk::ImageView> {
+    let image = fragment_density_maps.images.get(index).ok_or_else(|| {
+        anyhow!(
+            "There is no fragment density map for swapchain image {}",
+            index
+        )
+    })?;
+    vulkan_context.create_image_view(
+        image,
+        FRAGMENT_DENSITY_MAP_FORMAT,
+        vk::ImageViewType::TYPE_2D_ARRAY,
+        2,
+        1,
+    )
+}
+
+/// Make sure the depth buffer can be created in `depth_format`
+fn check_depth_format(vulkan_context: &VulkanContext, depth_format: vk::Format) -> Result<()> {
+    let properties = unsafe {
+        vulkan_context
+            .instance
+            .get_physical_device_format_properties(vulkan_context.physical_device, depth_format)
+    };
+    if properties
+        .optimal_tiling_features
+        .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{:?} can't be used as a depth format on this device",
+            depth_format
+        ))
+    }
+}
+
+/// The render pass draws into a multisampled color image that is resolved into the swapchain image, or straight into
+/// the swapchain image if MSAA is off. A foveated swapchain's fragment density map is the last attachment.
+fn create_render_pass(
+    vulkan_context: &VulkanContext,
+    render_settings: &RenderSettings,
+    foveated: bool,
+) -> Result<vk::RenderPass> {
     print!("[HOTHAM_INIT] Creating render pass..");
-    // Attachment used for MSAA
+    let multisampled = render_settings.is_multisampled();
+
+    // Attachment used for MSAA, or the final attachment to be presented if MSAA is off
     let color_attachment = vk::AttachmentDescription::builder()
         .format(COLOR_FORMAT)
-        .samples(vk::SampleCountFlags::TYPE_4)
+        .samples(render_settings.msaa_samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::STORE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -626,8 +972,8 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         .final_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
     let depth_attachment = vk::AttachmentDescription::builder()
-        .format(DEPTH_FORMAT)
-        .samples(vk::SampleCountFlags::TYPE_4)
+        .format(render_settings.depth_format)
+        .samples(render_settings.msaa_samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
         .store_op(vk::AttachmentStoreOp::DONT_CARE)
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
@@ -635,6 +981,22 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         .initial_layout(vk::ImageLayout::UNDEFINED)
         .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
 
+    // Written by the runtime, and read when rasterizing
+    let fragment_density_map_attachment = vk::AttachmentDescription::builder()
+        .format(FRAGMENT_DENSITY_MAP_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT)
+        .final_layout(vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT);
+
+    let mut attachments = vec![*color_attachment, *depth_attachment];
+    if multisampled {
+        attachments.push(*color_attachment_resolve);
+    }
+
     let color_attachment_reference = vk::AttachmentReference::builder()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
@@ -654,11 +1016,13 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
 
     let color_attachment_resolve_reference = [color_attachment_resolve_reference];
 
-    let subpass = vk::SubpassDescription::builder()
+    let mut subpass = vk::SubpassDescription::builder()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
         .color_attachments(&color_attachment_reference)
-        .resolve_attachments(&color_attachment_resolve_reference)
         .depth_stencil_attachment(&depth_stencil_reference);
+    if multisampled {
+        subpass = subpass.resolve_attachments(&color_attachment_resolve_reference);
+    }
 
     let dependency = vk::SubpassDependency::builder()
         .src_subpass(vk::SUBPASS_EXTERNAL)
@@ -682,22 +1046,27 @@ fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass>
         .view_masks(&view_masks)
         .correlation_masks(&view_masks);
 
-    let attachments = [
-        *color_attachment,
-        *depth_attachment,
-        *color_attachment_resolve,
-    ];
+    let mut fragment_density_map = vk::RenderPassFragmentDensityMapCreateInfoEXT::builder()
+        .fragment_density_map_attachment(vk::AttachmentReference {
+            attachment: attachments.len() as _,
+            layout: vk::ImageLayout::FRAGMENT_DENSITY_MAP_OPTIMAL_EXT,
+        });
+    if foveated {
+        attachments.push(*fragment_density_map_attachment);
+    }
 
-    let render_pass = unsafe {
-        vulkan_context.device.create_render_pass(
-            &vk::RenderPassCreateInfo::builder()
-                .attachments(&attachments)
-                .subpasses(&[*subpass])
-                .dependencies(&[*dependency])
-                .push_next(&mut multiview),
-            None,
-        )
-    }?;
+    let subpasses = [*subpass];
+    let dependencies = [*dependency];
+    let mut create_info = vk::RenderPassCreateInfo::builder()
+        .attachments(&attachments)
+        .subpasses(&subpasses)
+        .dependencies(&dependencies)
+        .push_next(&mut multiview);
+    if foveated {
+        create_info = create_info.push_next(&mut fragment_density_map);
+    }
+
+    let render_pass = unsafe { vulkan_context.device.create_render_pass(&create_info, None) }?;
     println!("..done!");
 
     Ok(render_pass)
@@ -708,6 +1077,8 @@ fn create_pipeline(
     pipeline_layout: vk::PipelineLayout,
     render_area: &vk::Rect2D,
     render_pass: vk::RenderPass,
+    alpha_blend: bool,
+    msaa_samples: vk::SampleCountFlags,
 ) -> Result<vk::Pipeline> {
     print!("[HOTHAM_INIT] Creating pipeline..");
     // Build up the state of the pipeline
@@ -777,13 +1148,13 @@ fn create_pipeline(
         .line_width(1.0);
 
     // Multisample state
-    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
-        .rasterization_samples(vk::SampleCountFlags::TYPE_4);
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(msaa_samples);
 
-    // Depth stencil state
+    // Depth stencil state. Blended primitives are drawn back to front, so they don't need to write depth.
     let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
         .depth_test_enable(true)
-        .depth_write_enable(true)
+        .depth_write_enable(!alpha_blend)
         .depth_compare_op(vk::CompareOp::LESS)
         .depth_bounds_test_enable(false)
         .min_depth_bounds(0.0)
@@ -798,7 +1169,13 @@ fn create_pipeline(
                 | vk::ColorComponentFlags::B
                 | vk::ColorComponentFlags::A,
         )
-        .blend_enable(false)
+        .blend_enable(alpha_blend)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
         .build();
 
     let color_blend_attachments = [color_blend_attachment];
@@ -873,11 +1250,18 @@ fn create_pipeline_layout(
     vulkan_context: &VulkanContext,
     set_layouts: &[vk::DescriptorSetLayout],
 ) -> Result<vk::PipelineLayout> {
-    let push_constant_ranges = [vk::PushConstantRange {
-        stage_flags: vk::ShaderStageFlags::FRAGMENT,
-        offset: 0,
-        size: size_of::<Material>() as _,
-    }];
+    let push_constant_ranges = [
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: size_of::<Material>() as _,
+        },
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: MORPH_PUSH_CONSTANT_OFFSET,
+            size: size_of::<MorphPushConstant>() as _,
+        },
+    ];
     let create_info = &vk::PipelineLayoutCreateInfo::builder()
         .set_layouts(set_layouts)
         .push_constant_ranges(&push_constant_ranges);
@@ -888,3 +1272,37 @@ fn create_pipeline_layout(
     }
     .map_err(|e| e.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::mesh::MeshUBO;
+    use std::mem::size_of;
+
+    #[test]
+    pub fn test_push_constants_fit() {
+        // Every device has at least 128 bytes of push constants
+        assert_eq!(
+            MORPH_PUSH_CONSTANT_OFFSET, 104,
+            "Update the offset in pbr.vert and shadow.vert"
+        );
+        assert!(MORPH_PUSH_CONSTANT_OFFSET as usize + size_of::<MorphPushConstant>() <= 128);
+
+        // The scalars are packed together, as they are in the shaders
+        let ubo = MeshUBO::default();
+        let offset = |field: *const u8| field as usize - &ubo as *const _ as usize;
+        assert_eq!(offset(&ubo.joint_count as *const _ as _), 0);
+        assert_eq!(
+            offset(&ubo.morph_target_count as *const _ as _),
+            offset(&ubo.joint_count as *const _ as _) + 4
+        );
+        assert_eq!(
+            offset(&ubo.first_joint as *const _ as _),
+            offset(&ubo.morph_target_count as *const _ as _) + 4
+        );
+        assert_eq!(
+            offset(&ubo.first_morph_weight as *const _ as _),
+            offset(&ubo.first_joint as *const _ as _) + 4
+        );
+    }
+}
@@ -1,10 +1,15 @@
 use crate::{
+    allocator::{Allocation, Allocator},
     buffer::Buffer,
+    deletion_queue::{DeletionQueue, Garbage},
+    descriptor_allocator::DescriptorAllocator,
+    environment::Environment,
     hotham_error::HothamError,
     image::Image,
-    scene_data::{SceneData, SceneParams},
+    resources::MeshRegistry,
+    scene_data::{SceneData, SceneLights, SceneParams},
+    shadow_map::ShadowMap,
     texture::Texture,
-    DEPTH_ATTACHMENT_USAGE_FLAGS, DEPTH_FORMAT,
 };
 use anyhow::{anyhow, Result};
 use ash::{
@@ -15,7 +20,13 @@ use ash::{
     Device, Entry, Instance as AshInstance,
 };
 use openxr as xr;
-use std::{cmp::max, ffi::CString, fmt::Debug, ptr::copy};
+use std::{
+    cmp::max,
+    ffi::{CStr, CString},
+    fmt::Debug,
+    ptr::copy,
+    sync::{Arc, Mutex},
+};
 
 type XrVulkan = xr::Vulkan;
 
@@ -31,9 +42,15 @@ pub struct VulkanContext {
     pub command_pool: vk::CommandPool,
     pub queue_family_index: u32,
     pub graphics_queue: vk::Queue,
-    pub descriptor_pool: vk::DescriptorPool,
     pub debug_utils: DebugUtils,
     pub physical_device_properties: vk::PhysicalDeviceProperties,
+    /// Vertices and indices for every primitive
+    pub mesh_registry: MeshRegistry,
+    pub(crate) allocator: Arc<Mutex<Allocator>>,
+    pub(crate) descriptor_allocator: Arc<Mutex<DescriptorAllocator>>,
+    pub(crate) deletion_queue: DeletionQueue,
+    /// Whether `VK_EXT_fragment_density_map` is enabled, so the runtime can foveate the swapchain
+    pub(crate) fragment_density_map: bool,
 }
 
 // NOTE: OpenXR created the instance / device etc. and is therefore the owner. We'll let it do the cleanup.
@@ -42,8 +59,6 @@ impl Drop for VulkanContext {
         // TODO: Currently we have a bug where if VulkanContext is cloned, each clone will try and cleanup.
         // Let's fix this.
 
-        // self.device
-        //     .destroy_descriptor_pool(self.descriptor_pool, None);
         // self.device.destroy_command_pool(self.command_pool, None);
     }
 }
@@ -154,7 +169,6 @@ impl VulkanContext {
 
         let command_pool = create_command_pool(&device, queue_family_index)?;
 
-        let descriptor_pool = create_descriptor_pool(&device)?;
         let debug_utils = DebugUtils::new(&entry, &instance);
         let physical_device_properties =
             unsafe { instance.get_physical_device_properties(physical_device) };
@@ -169,9 +183,13 @@ impl VulkanContext {
             command_pool,
             queue_family_index,
             graphics_queue,
-            descriptor_pool,
             debug_utils,
             physical_device_properties,
+            mesh_registry: Default::default(),
+            allocator: Default::default(),
+            descriptor_allocator: Default::default(),
+            deletion_queue: Default::default(),
+            fragment_density_map: false,
         })
     }
 
@@ -199,10 +217,10 @@ impl VulkanContext {
         );
         let (device, graphics_queue, queue_family_index) =
             create_vulkan_device_legacy(xr_instance, system, &vulkan_instance, physical_device)?;
+        let fragment_density_map = supports_fragment_density_map(&vulkan_instance, physical_device);
 
         let command_pool = create_command_pool(&device, queue_family_index)?;
 
-        let descriptor_pool = create_descriptor_pool(&device)?;
         let debug_utils = DebugUtils::new(&vulkan_entry, &vulkan_instance);
         let physical_device_properties =
             unsafe { vulkan_instance.get_physical_device_properties(physical_device) };
@@ -215,14 +233,23 @@ impl VulkanContext {
             graphics_queue,
             queue_family_index,
             command_pool,
-            descriptor_pool,
             debug_utils,
             physical_device_properties,
+            mesh_registry: Default::default(),
+            allocator: Default::default(),
+            descriptor_allocator: Default::default(),
+            deletion_queue: Default::default(),
+            fragment_density_map,
         })
     }
 
     pub fn testing() -> Result<Self> {
-        let (instance, entry) = vulkan_init_test()?;
+        Self::create_headless("Hotham Testing", 1)
+    }
+
+    /// Create a context directly from the Vulkan loader, without an OpenXR runtime or a display.
+    pub fn create_headless(application_name: &str, application_version: u32) -> Result<Self> {
+        let (instance, entry) = vulkan_init_headless(application_name, application_version)?;
         let physical_device = get_test_physical_device(&instance);
         let mut extension_names = Vec::new();
         add_device_extension_names(&mut extension_names);
@@ -231,7 +258,6 @@ impl VulkanContext {
             create_vulkan_device(&extension_names, &instance, physical_device)?;
 
         let command_pool = create_command_pool(&device, queue_family_index)?;
-        let descriptor_pool = create_descriptor_pool(&device)?;
         let debug_utils = DebugUtils::new(&entry, &instance);
         let physical_device_properties =
             unsafe { instance.get_physical_device_properties(physical_device) };
@@ -244,9 +270,13 @@ impl VulkanContext {
             graphics_queue,
             queue_family_index,
             command_pool,
-            descriptor_pool,
             debug_utils,
             physical_device_properties,
+            mesh_registry: Default::default(),
+            allocator: Default::default(),
+            descriptor_allocator: Default::default(),
+            deletion_queue: Default::default(),
+            fragment_density_map: false,
         })
     }
 
@@ -286,6 +316,37 @@ impl VulkanContext {
         usage: vk::ImageUsageFlags,
         array_layers: u32,
         mip_levels: u32,
+    ) -> Result<Image> {
+        self.create_image_with_samples(
+            format,
+            extent,
+            usage,
+            array_layers,
+            mip_levels,
+            vk::SampleCountFlags::TYPE_1,
+        )
+    }
+
+    /// Create an image with `samples` samples per pixel, eg. a color or depth attachment used for MSAA
+    pub fn create_multisampled_image(
+        &self,
+        format: vk::Format,
+        extent: &vk::Extent2D,
+        usage: vk::ImageUsageFlags,
+        array_layers: u32,
+        samples: vk::SampleCountFlags,
+    ) -> Result<Image> {
+        self.create_image_with_samples(format, extent, usage, array_layers, 1, samples)
+    }
+
+    fn create_image_with_samples(
+        &self,
+        format: vk::Format,
+        extent: &vk::Extent2D,
+        usage: vk::ImageUsageFlags,
+        array_layers: u32,
+        mip_levels: u32,
+        samples: vk::SampleCountFlags,
     ) -> Result<Image> {
         let tiling = vk::ImageTiling::OPTIMAL;
         let (flags, image_view_type) = if array_layers == 1 {
@@ -301,14 +362,6 @@ impl VulkanContext {
                 vk::ImageViewType::TYPE_2D_ARRAY,
             )
         };
-        let samples = if usage.contains(vk::ImageUsageFlags::TRANSIENT_ATTACHMENT)
-            || usage.contains(DEPTH_ATTACHMENT_USAGE_FLAGS)
-        {
-            vk::SampleCountFlags::TYPE_4
-        } else {
-            vk::SampleCountFlags::TYPE_1
-        };
-
         let create_info = vk::ImageCreateInfo::builder()
             .format(format)
             .image_type(vk::ImageType::TYPE_2D)
@@ -327,9 +380,12 @@ impl VulkanContext {
             .sharing_mode(vk::SharingMode::EXCLUSIVE);
         let image = unsafe { self.device.create_image(&create_info, None) }?;
 
-        let (_, device_memory) = self.allocate_image_memory(image)?;
+        let allocation = self.allocate_image_memory(image)?;
 
-        unsafe { self.device.bind_image_memory(image, device_memory, 0) }?;
+        unsafe {
+            self.device
+                .bind_image_memory(image, allocation.memory, allocation.offset)
+        }?;
 
         let image_view =
             self.create_image_view(&image, format, image_view_type, array_layers, mip_levels)?;
@@ -337,12 +393,13 @@ impl VulkanContext {
         Ok(Image::new(
             image,
             image_view,
-            device_memory,
+            allocation,
             *extent,
             usage,
             format,
             image_view_type,
             array_layers,
+            mip_levels,
         ))
     }
 
@@ -352,7 +409,7 @@ impl VulkanContext {
         data: &[T],
         usage: vk::BufferUsageFlags,
         buffer_size: vk::DeviceSize,
-    ) -> Result<(vk::Buffer, vk::DeviceMemory, vk::DeviceSize)> {
+    ) -> Result<(vk::Buffer, Allocation)> {
         let device = &self.device;
         let buffer_create_info = vk::BufferCreateInfo::builder()
             .size(buffer_size)
@@ -360,41 +417,34 @@ impl VulkanContext {
             .usage(usage);
 
         let buffer = unsafe { device.create_buffer(&buffer_create_info, None) }?;
-        let (device_memory_size, device_memory) = self.allocate_buffer_memory(buffer)?;
+        let allocation = self.allocate_buffer_memory(buffer)?;
 
-        println!(
-            "[HOTHAM_VULKAN] Allocated {} bits of buffer memory: {:?}",
-            device_memory_size, device_memory
-        );
-        unsafe { device.bind_buffer_memory(buffer, device_memory, 0) }?;
-        self.update_buffer(data, device_memory, buffer_size, usage)?;
+        unsafe { device.bind_buffer_memory(buffer, allocation.memory, allocation.offset) }?;
+        self.update_buffer(data, &allocation, usage)?;
 
-        Ok((buffer, device_memory, device_memory_size))
+        Ok((buffer, allocation))
     }
 
     pub fn update_buffer<T: Sized + Copy>(
         &self,
         data: &[T],
-        device_memory: vk::DeviceMemory,
-        device_memory_size: vk::DeviceSize,
+        allocation: &Allocation,
         usage: vk::BufferUsageFlags,
     ) -> Result<()> {
-        unsafe {
-            let dst = self.device.map_memory(
-                device_memory,
-                0,
-                vk::WHOLE_SIZE,
-                vk::MemoryMapFlags::empty(),
-            )?;
+        // Hold on to the allocator while we write, so the block can't be freed underneath us
+        let allocator = self.allocator.lock().unwrap();
+        let dst = allocator
+            .mapped_ptr(allocation)
+            .ok_or_else(|| anyhow!("Buffer memory is not host visible"))?;
 
+        unsafe {
             if usage == vk::BufferUsageFlags::UNIFORM_BUFFER {
-                let (alignment, aligned_size) = self.get_alignment_info::<T>(device_memory_size);
-                let mut align = Align::new(dst, alignment, aligned_size);
+                let (alignment, aligned_size) = self.get_alignment_info::<T>(allocation.size);
+                let mut align = Align::new(dst as *mut _, alignment, aligned_size);
                 align.copy_from_slice(data);
             } else {
                 copy(data.as_ptr(), dst as *mut _, data.len())
             }
-            self.device.unmap_memory(device_memory);
         };
 
         Ok(())
@@ -440,42 +490,95 @@ impl VulkanContext {
         ))
     }
 
-    fn allocate_buffer_memory(
-        &self,
-        buffer: vk::Buffer,
-    ) -> Result<(vk::DeviceSize, vk::DeviceMemory)> {
+    fn allocate_buffer_memory(&self, buffer: vk::Buffer) -> Result<Allocation> {
         let memory_requirements = unsafe { self.device.get_buffer_memory_requirements(buffer) };
-        // PERF: This is slow.
         let properties =
             vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT;
-        self.allocate_memory(memory_requirements, properties)
+        self.allocate_memory(memory_requirements, properties, false)
     }
 
-    fn allocate_image_memory(
-        &self,
-        image: vk::Image,
-    ) -> Result<(vk::DeviceSize, vk::DeviceMemory)> {
+    fn allocate_image_memory(&self, image: vk::Image) -> Result<Allocation> {
         let properties = vk::MemoryPropertyFlags::DEVICE_LOCAL;
         let memory_requirements = unsafe { self.device.get_image_memory_requirements(image) };
-        self.allocate_memory(memory_requirements, properties)
+        self.allocate_memory(memory_requirements, properties, true)
     }
 
     fn allocate_memory(
         &self,
         memory_requirements: vk::MemoryRequirements,
         properties: vk::MemoryPropertyFlags,
-    ) -> Result<(vk::DeviceSize, vk::DeviceMemory)> {
-        // Get memory requirements
+        is_image: bool,
+    ) -> Result<Allocation> {
         let memory_type_index =
             self.find_memory_type(memory_requirements.memory_type_bits, properties)?;
 
-        let allocate_info = vk::MemoryAllocateInfo::builder()
-            .memory_type_index(memory_type_index)
-            .allocation_size(memory_requirements.size);
+        self.allocator.lock().unwrap().allocate(
+            &self.device,
+            memory_requirements,
+            memory_type_index,
+            is_image,
+            properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE),
+        )
+    }
+
+    /// Allocate descriptor sets, creating a new descriptor pool if the others are full
+    pub fn allocate_descriptor_sets(
+        &self,
+        set_layouts: &[vk::DescriptorSetLayout],
+    ) -> VkResult<Vec<vk::DescriptorSet>> {
+        self.descriptor_allocator
+            .lock()
+            .unwrap()
+            .allocate(&self.device, set_layouts)
+    }
 
-        let device_memory = unsafe { self.device.allocate_memory(&allocate_info, None) }?;
+    /// Destroy a Vulkan object once the GPU has finished every frame that might be using it
+    pub(crate) fn destroy_later(&self, garbage: Garbage) {
+        self.deletion_queue.push(garbage);
+    }
 
-        Ok((memory_requirements.size, device_memory))
+    /// Destroy anything that was thrown out long enough ago. Called by `RenderContext::begin_frame`, once the
+    /// frame's fence has been waited on.
+    pub(crate) fn collect_garbage(&self) {
+        for garbage in self.deletion_queue.begin_frame() {
+            self.destroy(garbage);
+        }
+    }
+
+    fn destroy(&self, garbage: Garbage) {
+        let device = &self.device;
+        unsafe {
+            match garbage {
+                Garbage::Buffer(buffer, allocation) => {
+                    device.destroy_buffer(buffer, None);
+                    self.allocator.lock().unwrap().free(device, &allocation);
+                }
+                Garbage::Image(image, view, allocation) => {
+                    device.destroy_image_view(view, None);
+                    device.destroy_image(image, None);
+                    self.allocator.lock().unwrap().free(device, &allocation);
+                }
+                Garbage::Sampler(sampler) => device.destroy_sampler(sampler, None),
+                Garbage::Geometry {
+                    block,
+                    vertices,
+                    indices,
+                } => self.mesh_registry.release(block, vertices, indices),
+                Garbage::DescriptorSet(descriptor_set) => {
+                    if let Err(e) = self
+                        .descriptor_allocator
+                        .lock()
+                        .unwrap()
+                        .free(device, descriptor_set)
+                    {
+                        eprintln!(
+                            "[HOTHAM_VULKAN] Unable to free descriptor set {:?}: {:?}",
+                            descriptor_set, e
+                        );
+                    }
+                }
+            }
+        }
     }
 
     pub fn create_texture_image(
@@ -496,7 +599,7 @@ impl VulkanContext {
         println!("[HOTHAM_VULKAN] Creating staging buffer..");
         let usage = vk::BufferUsageFlags::TRANSFER_SRC;
         let size = 8 * image_buf.len();
-        let (staging_buffer, staging_memory, _) =
+        let (staging_buffer, staging_allocation) =
             self.create_buffer_with_data(image_buf, usage, size as _)?;
         println!("[HOTHAM_VULKAN] ..done!");
 
@@ -540,11 +643,8 @@ impl VulkanContext {
         let sampler = self.create_texture_sampler(sampler_address_mode, mip_count)?;
         self.set_debug_name(vk::ObjectType::SAMPLER, sampler.as_raw(), name)?;
 
-        // Free the staging buffer
-        unsafe {
-            self.device.destroy_buffer(staging_buffer, None);
-            self.device.free_memory(staging_memory, None);
-        }
+        // Free the staging buffer. The copy has already finished, so there's no need to wait.
+        self.destroy(Garbage::Buffer(staging_buffer, staging_allocation));
 
         println!(
             "[HOTHAM_VULKAN] ..done! Texture {} created successfully.",
@@ -786,13 +886,7 @@ impl VulkanContext {
         mesh_name: &str,
     ) -> VkResult<Vec<vk::DescriptorSet>> {
         println!("[HOTHAM_VULKAN] Allocating mesh descriptor sets..");
-        let descriptor_sets = unsafe {
-            self.device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .set_layouts(&[set_layout])
-                    .descriptor_pool(self.descriptor_pool),
-            )
-        }?;
+        let descriptor_sets = self.allocate_descriptor_sets(&[set_layout])?;
         self.set_debug_name(
             vk::ObjectType::DESCRIPTOR_SET,
             descriptor_sets[0].as_raw(),
@@ -810,13 +904,7 @@ impl VulkanContext {
         textures: &[&Texture; 5],
     ) -> VkResult<Vec<vk::DescriptorSet>> {
         println!("[HOTHAM_VULKAN] Allocating textures descriptor sets..");
-        let descriptor_sets = unsafe {
-            self.device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .set_layouts(&[set_layout])
-                    .descriptor_pool(self.descriptor_pool),
-            )
-        }?;
+        let descriptor_sets = self.allocate_descriptor_sets(&[set_layout])?;
 
         self.set_debug_name(
             vk::ObjectType::DESCRIPTOR_SET,
@@ -904,23 +992,19 @@ impl VulkanContext {
         Ok(descriptor_sets)
     }
 
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     pub fn create_scene_data_descriptor_sets(
         &self,
         set_layout: vk::DescriptorSetLayout,
         scene_data: &Buffer<SceneData>,
         scene_params: &Buffer<SceneParams>,
-        irradiance: &Texture,
-        prefiltered_map: &Texture,
+        scene_lights: &Buffer<SceneLights>,
+        environment: &Environment,
         brdflut: &Texture,
+        shadow_map: &ShadowMap,
     ) -> VkResult<Vec<vk::DescriptorSet>> {
         println!("[HOTHAM_VULKAN] Allocating scene data sets..");
-        let descriptor_sets = unsafe {
-            self.device.allocate_descriptor_sets(
-                &vk::DescriptorSetAllocateInfo::builder()
-                    .set_layouts(&[set_layout])
-                    .descriptor_pool(self.descriptor_pool),
-            )
-        }?;
+        let descriptor_sets = self.allocate_descriptor_sets(&[set_layout])?;
         self.set_debug_name(
             vk::ObjectType::DESCRIPTOR_SET,
             descriptor_sets[0].as_raw(),
@@ -939,6 +1023,12 @@ impl VulkanContext {
             1,
             vk::DescriptorType::UNIFORM_BUFFER,
         );
+        self.update_buffer_descriptor_set(
+            scene_lights,
+            descriptor_sets[0],
+            5,
+            vk::DescriptorType::UNIFORM_BUFFER,
+        );
         unsafe {
             self.device.update_descriptor_sets(
                 &[
@@ -948,8 +1038,8 @@ impl VulkanContext {
                         .dst_array_element(0)
                         .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                         .image_info(&[*vk::DescriptorImageInfo::builder()
-                            .image_view(irradiance.image.view)
-                            .sampler(irradiance.sampler)
+                            .image_view(environment.irradiance.image.view)
+                            .sampler(environment.irradiance.sampler)
                             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
                     *vk::WriteDescriptorSet::builder()
                         .dst_set(descriptor_sets[0])
@@ -957,8 +1047,8 @@ impl VulkanContext {
                         .dst_array_element(0)
                         .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
                         .image_info(&[*vk::DescriptorImageInfo::builder()
-                            .image_view(prefiltered_map.image.view)
-                            .sampler(prefiltered_map.sampler)
+                            .image_view(environment.prefiltered.image.view)
+                            .sampler(environment.prefiltered.sampler)
                             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
                     *vk::WriteDescriptorSet::builder()
                         .dst_set(descriptor_sets[0])
@@ -969,6 +1059,21 @@ impl VulkanContext {
                             .image_view(brdflut.image.view)
                             .sampler(brdflut.sampler)
                             .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)]),
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_sets[0])
+                        .dst_binding(6)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(&[*vk::DescriptorImageInfo::builder()
+                            .image_view(shadow_map.image.view)
+                            .sampler(shadow_map.sampler)
+                            .image_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL)]),
+                    *vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_sets[0])
+                        .dst_binding(7)
+                        .dst_array_element(0)
+                        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                        .image_info(&[environment.skybox().descriptor]),
                 ],
                 &[],
             )
@@ -1000,6 +1105,25 @@ impl VulkanContext {
         };
     }
 
+    pub fn update_texture_descriptor_set(
+        &self,
+        texture: &Texture,
+        descriptor_set: vk::DescriptorSet,
+        binding: usize,
+    ) {
+        unsafe {
+            self.device.update_descriptor_sets(
+                &[*vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(binding as _)
+                    .dst_array_element(0)
+                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+                    .image_info(&[texture.descriptor])],
+                &[],
+            )
+        };
+    }
+
     #[cfg(not(debug_assertions))]
     pub fn set_debug_name(
         &self,
@@ -1060,37 +1184,17 @@ fn create_command_pool(
     Ok(command_pool)
 }
 
-// TODO HACK: Make these values real
-fn create_descriptor_pool(device: &Device) -> Result<vk::DescriptorPool, anyhow::Error> {
-    let descriptor_pool = unsafe {
-        device.create_descriptor_pool(
-            &vk::DescriptorPoolCreateInfo::builder()
-                .pool_sizes(&[
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::UNIFORM_BUFFER,
-                        descriptor_count: 100,
-                    },
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                        descriptor_count: 1000,
-                    },
-                    vk::DescriptorPoolSize {
-                        ty: vk::DescriptorType::STORAGE_BUFFER,
-                        descriptor_count: 1000,
-                    },
-                ])
-                .max_sets(1000),
-            None,
-        )
-    }?;
-    Ok(descriptor_pool)
-}
-
 fn get_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
-    if format == DEPTH_FORMAT {
-        vk::ImageAspectFlags::DEPTH
-    } else {
-        vk::ImageAspectFlags::COLOR
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        _ => vk::ImageAspectFlags::COLOR,
     }
 }
 
@@ -1175,14 +1279,28 @@ fn vulkan_init_legacy(
     }
 }
 
-fn vulkan_init_test() -> Result<(AshInstance, Entry)> {
-    use crate::util::{get_raw_strings, parse_raw_strings};
+fn vulkan_init_headless(
+    application_name: &str,
+    application_version: u32,
+) -> Result<(AshInstance, Entry)> {
+    use crate::util::parse_raw_strings;
+    use std::ffi::CStr;
 
     println!("[HOTHAM_VULKAN] Initializing Vulkan..");
-    let app_name = CString::new("Hotham Testing")?;
+    let app_name = CString::new(application_name)?;
     let entry = unsafe { Entry::new()? };
-    let layers = vec!["VK_LAYER_KHRONOS_validation\0"];
-    let layer_names = unsafe { get_raw_strings(layers) };
+
+    // CI machines often don't have the validation layers installed, so only ask for them if they're there.
+    let validation_layer = CString::new("VK_LAYER_KHRONOS_validation")?;
+    let has_validation_layer = entry
+        .enumerate_instance_layer_properties()?
+        .iter()
+        .any(|l| unsafe { CStr::from_ptr(l.layer_name.as_ptr()) } == validation_layer.as_c_str());
+    let layer_names = if has_validation_layer {
+        vec![validation_layer.as_ptr()]
+    } else {
+        Vec::new()
+    };
     println!("[HOTHAM_VULKAN] Trying to use layers: {:?}", unsafe {
         parse_raw_strings(&layer_names)
     });
@@ -1192,6 +1310,7 @@ fn vulkan_init_test() -> Result<(AshInstance, Entry)> {
 
     let app_info = vk::ApplicationInfo::builder()
         .application_name(&app_name)
+        .application_version(application_version)
         .api_version(vk::make_api_version(0, 1, 2, 0));
     let create_info = vk::InstanceCreateInfo::builder()
         .application_info(&app_info)
@@ -1221,9 +1340,37 @@ pub fn create_vulkan_device_legacy(
 
     add_device_extension_names(&mut extension_names);
 
+    // Lets the runtime foveate the swapchain, if the application asks for it
+    if supports_fragment_density_map(vulkan_instance, physical_device) {
+        extension_names.push(vk::ExtFragmentDensityMapFn::name().to_owned());
+    }
+
     create_vulkan_device(&extension_names, vulkan_instance, physical_device)
 }
 
+/// Whether the device can shade parts of an image at a lower resolution, with `VK_EXT_fragment_density_map`
+fn supports_fragment_density_map(
+    vulkan_instance: &AshInstance,
+    physical_device: vk::PhysicalDevice,
+) -> bool {
+    let extension_name = vk::ExtFragmentDensityMapFn::name();
+    let has_extension =
+        unsafe { vulkan_instance.enumerate_device_extension_properties(physical_device) }
+            .unwrap_or_default()
+            .iter()
+            .any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == extension_name);
+    if !has_extension {
+        return false;
+    }
+
+    let mut fragment_density_map_features =
+        vk::PhysicalDeviceFragmentDensityMapFeaturesEXT::default();
+    let mut features =
+        vk::PhysicalDeviceFeatures2::builder().push_next(&mut fragment_density_map_features);
+    unsafe { vulkan_instance.get_physical_device_features2(physical_device, &mut features) };
+    fragment_density_map_features.fragment_density_map == vk::TRUE
+}
+
 fn create_vulkan_device(
     extension_names: &[std::ffi::CString],
     vulkan_instance: &AshInstance,
@@ -1233,7 +1380,7 @@ fn create_vulkan_device(
         "[HOTHAM_VULKAN] Using device extensions: {:?}",
         extension_names
     );
-    let extension_names = extension_names
+    let extension_pointers = extension_names
         .iter()
         .map(|e| e.as_ptr())
         .collect::<Vec<_>>();
@@ -1269,11 +1416,22 @@ fn create_vulkan_device(
         ..Default::default()
     };
 
-    let device_create_info = vk::DeviceCreateInfo::builder()
+    let fragment_density_map = &mut vk::PhysicalDeviceFragmentDensityMapFeaturesEXT {
+        fragment_density_map: vk::TRUE,
+        ..Default::default()
+    };
+
+    let mut device_create_info = vk::DeviceCreateInfo::builder()
         .queue_create_infos(&queue_create_infos)
-        .enabled_extension_names(&extension_names)
+        .enabled_extension_names(&extension_pointers)
         .enabled_features(&physical_device_features)
         .push_next(multiview);
+    if extension_names
+        .iter()
+        .any(|e| e.as_c_str() == vk::ExtFragmentDensityMapFn::name())
+    {
+        device_create_info = device_create_info.push_next(fragment_density_map);
+    }
 
     let device =
         unsafe { vulkan_instance.create_device(physical_device, &device_create_info, None) }?;
@@ -1,15 +1,42 @@
-use anyhow::Result;
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
 use ash::vk::{self, Handle};
+use nalgebra::{Isometry3, Vector2};
 use openxr::{
-    self as xr, Action, ActionSet, EventDataBuffer, FrameStream, FrameWaiter, Path, Posef, Session,
-    SessionState, Space, Swapchain, Vulkan,
+    self as xr, Action, ActionInput, ActionSet, ActiveActionSet, EventDataBuffer,
+    FoveationProfileFB, FrameStream, FrameWaiter, HandTracker, HapticVibration, Path, Posef,
+    Session, SessionState, Space, SpaceLocation, SpaceLocationFlags, Swapchain, Vulkan,
 };
 use xr::{
     vulkan::SessionCreateInfo, Duration, FrameState, Haptic, ReferenceSpaceType,
-    SwapchainCreateFlags, SwapchainCreateInfo, SwapchainUsageFlags, Time, View, ViewStateFlags,
+    SwapchainCreateFlags, SwapchainCreateInfo, SwapchainUsageFlags, Time, Vector2f, View,
+    ViewStateFlags,
+};
+
+use crate::{
+    components::hand::Handedness,
+    render_settings::{Foveation, RenderSettings},
+    resources::{
+        input_context::{InputContext, InputValue},
+        VulkanContext,
+    },
+    swapchain::{FragmentDensityMaps, Swapchain as HothamSwapchain},
+    util::{is_space_valid, isometry_to_posef, posef_to_isometry},
+    BLEND_MODE, COLOR_FORMAT, VIEW_COUNT, VIEW_TYPE,
 };
 
-use crate::{resources::VulkanContext, BLEND_MODE, COLOR_FORMAT, VIEW_COUNT, VIEW_TYPE};
+pub mod action_map;
+mod foveation;
+pub mod hand_tracking;
+pub mod headless;
+pub use action_map::{
+    ActionDescription, ActionMap, ActionType, GRAB_OBJECT, HAND_PATHS, HAND_POSE, HAPTIC_FEEDBACK,
+    HTC_VIVE_PROFILE, KHR_SIMPLE_PROFILE, OCULUS_TOUCH_PROFILE, POINTER_POSE, THUMBSTICK,
+    TRIGGER_PULLED, VALVE_INDEX_PROFILE,
+};
+pub use hand_tracking::{HandJoint, HandJoints, HAND_JOINT_COUNT, HAND_JOINT_NAMES};
+pub use headless::{HeadlessFrame, HeadlessHand, HeadlessHaptic, HeadlessXr};
 
 #[derive(Default)]
 pub struct XrContextBuilder<'a> {
@@ -17,6 +44,9 @@ pub struct XrContextBuilder<'a> {
     application_name: Option<&'a str>,
     application_version: Option<u32>,
     required_extensions: Option<xr::ExtensionSet>,
+    headless: Option<HeadlessXr>,
+    action_map: Option<ActionMap>,
+    render_settings: Option<RenderSettings>,
 }
 
 impl<'a> XrContextBuilder<'a> {
@@ -44,39 +74,164 @@ impl<'a> XrContextBuilder<'a> {
         self
     }
 
+    /// Run without an OpenXR runtime, driving the session from `headless` instead.
+    pub fn headless(&mut self, headless: Option<HeadlessXr>) -> &mut Self {
+        self.headless = headless;
+        self
+    }
+
+    /// Set the actions the application uses and how they are bound. Defaults to `ActionMap::default()`.
+    pub fn action_map(&mut self, action_map: Option<ActionMap>) -> &mut Self {
+        self.action_map = action_map;
+        self
+    }
+
+    /// Set the render scale and foveation used for the swapchain. Defaults to `RenderSettings::default()`.
+    pub fn render_settings(&mut self, render_settings: Option<RenderSettings>) -> &mut Self {
+        self.render_settings = render_settings;
+        self
+    }
+
     pub fn build(&mut self) -> Result<(XrContext, VulkanContext)> {
         let application_name = self.application_name.unwrap_or("Hotham Application");
         let application_version = self.application_version.unwrap_or(1);
+        let action_map = self.action_map.take().unwrap_or_default();
+        action_map.validate()?;
+        let render_settings = self.render_settings.unwrap_or_default();
+
+        if let Some(headless) = self.headless.take() {
+            return XrContext::new_headless(
+                headless,
+                action_map,
+                render_settings,
+                application_name,
+                application_version,
+            );
+        }
+
         let (instance, system) = create_xr_instance(
             self.path,
             application_name,
             application_version,
             self.required_extensions.as_ref(),
+            render_settings.foveation != Foveation::Off,
         )?;
-        XrContext::_new(instance, system, application_name, application_version)
+        XrContext::_new(
+            instance,
+            system,
+            action_map,
+            render_settings,
+            application_name,
+            application_version,
+        )
     }
 }
 
-pub struct XrContext {
+/// Handles to the objects created by a real OpenXR runtime.
+pub struct OpenXrBackend {
     pub instance: openxr::Instance,
     pub session: Session<Vulkan>,
-    pub session_state: SessionState,
     pub swapchain: Swapchain<Vulkan>,
+    /// The STAGE space. Locations are moved into the world by `XrContext::stage_pose` after they're located.
     pub reference_space: Space,
     pub action_set: ActionSet,
-    pub pose_action: Action<Posef>,
-    pub grab_action: Action<f32>,
-    pub trigger_action: Action<f32>,
-    pub haptic_feedback_action: Action<Haptic>,
-    pub left_hand_space: Space,
-    pub left_hand_subaction_path: Path,
-    pub left_pointer_space: Space,
-    pub right_hand_space: Space,
-    pub right_hand_subaction_path: Path,
-    pub right_pointer_space: Space,
-    pub swapchain_resolution: vk::Extent2D,
+    /// Every action in the `ActionMap`, by name
+    pub actions: HashMap<String, OpenXrAction>,
+    /// Subaction paths for the left and right hands
+    pub hand_paths: [Path; 2],
+    /// Trackers for the left and right hands, if the runtime supports `XR_EXT_hand_tracking`
+    pub hand_trackers: Option<[HandTracker; 2]>,
+    /// A fragment density map for each swapchain image, if the swapchain is foveated
+    pub fragment_density_maps: Option<FragmentDensityMaps>,
+    /// How the swapchain is foveated. The runtime only uses it while it's alive.
+    pub foveation_profile: Option<FoveationProfileFB>,
     pub frame_waiter: FrameWaiter,
     pub frame_stream: FrameStream<Vulkan>,
+}
+
+impl OpenXrBackend {
+    fn action(&self, name: &str) -> Result<&OpenXrAction> {
+        self.actions
+            .get(name)
+            .ok_or_else(|| anyhow!("There is no action named {} in the action map", name))
+    }
+
+    fn hand_path(&self, handedness: Handedness) -> Path {
+        self.hand_paths[hand_index(handedness)]
+    }
+}
+
+/// An OpenXR action created from an `ActionDescription`
+pub enum OpenXrAction {
+    Boolean(Action<bool>),
+    Float(Action<f32>),
+    Vector2(Action<Vector2f>),
+    /// Pose actions have a space for each hand
+    Pose(Action<Posef>, [Space; 2]),
+    Haptic(Action<Haptic>),
+}
+
+impl OpenXrAction {
+    fn create(
+        action_set: &ActionSet,
+        session: &Session<Vulkan>,
+        description: &ActionDescription,
+        hand_paths: &[Path; 2],
+    ) -> Result<Self> {
+        let name = description.name.as_str();
+        let localized_name = description.localized_name.as_str();
+        let action = match description.action_type {
+            ActionType::Boolean => {
+                OpenXrAction::Boolean(action_set.create_action(name, localized_name, hand_paths)?)
+            }
+            ActionType::Float => {
+                OpenXrAction::Float(action_set.create_action(name, localized_name, hand_paths)?)
+            }
+            ActionType::Vector2 => {
+                OpenXrAction::Vector2(action_set.create_action(name, localized_name, hand_paths)?)
+            }
+            ActionType::Pose => {
+                let action = action_set.create_action(name, localized_name, hand_paths)?;
+                let spaces = [
+                    action.create_space(session.clone(), hand_paths[0], Posef::IDENTITY)?,
+                    action.create_space(session.clone(), hand_paths[1], Posef::IDENTITY)?,
+                ];
+                OpenXrAction::Pose(action, spaces)
+            }
+            ActionType::Haptic => {
+                OpenXrAction::Haptic(action_set.create_action(name, localized_name, hand_paths)?)
+            }
+        };
+        Ok(action)
+    }
+
+    fn binding(&self, path: Path) -> xr::Binding<'_> {
+        match self {
+            OpenXrAction::Boolean(action) => xr::Binding::new(action, path),
+            OpenXrAction::Float(action) => xr::Binding::new(action, path),
+            OpenXrAction::Vector2(action) => xr::Binding::new(action, path),
+            OpenXrAction::Pose(action, _) => xr::Binding::new(action, path),
+            OpenXrAction::Haptic(action) => xr::Binding::new(action, path),
+        }
+    }
+}
+
+enum XrBackend {
+    OpenXr(Box<OpenXrBackend>),
+    Headless(Box<HeadlessXr>),
+}
+
+pub struct XrContext {
+    backend: XrBackend,
+    action_map: ActionMap,
+    stage_pose: Isometry3<f32>,
+    hand_joints: [Option<HandJoints>; 2],
+    /// The state of every input action, updated once per frame by `begin_frame`
+    pub input: InputContext,
+    pub session_state: SessionState,
+    pub swapchain_resolution: vk::Extent2D,
+    /// The settings this context was created with, which `RenderContext::new` uses too
+    pub render_settings: RenderSettings,
     pub frame_state: FrameState,
     pub views: Vec<View>,
     pub view_state_flags: ViewStateFlags,
@@ -92,9 +247,54 @@ impl XrContext {
         XrContextBuilder::new().path(Some(path)).build()
     }
 
+    /// Create a headless context with the default `ActionMap`, for tests that don't have an OpenXR runtime
+    pub fn testing() -> Result<(XrContext, VulkanContext)> {
+        XrContextBuilder::new()
+            .application_name(Some("Hotham Testing"))
+            .headless(Some(HeadlessXr::new()))
+            .build()
+    }
+
+    /// Create a context that is driven by `headless` rather than an OpenXR runtime
+    pub fn new_headless(
+        headless: HeadlessXr,
+        action_map: ActionMap,
+        render_settings: RenderSettings,
+        application_name: &str,
+        application_version: u32,
+    ) -> Result<(XrContext, VulkanContext)> {
+        let vulkan_context = VulkanContext::create_headless(application_name, application_version)?;
+
+        // The headless session's resolution stands in for the runtime's recommended one
+        let resolution = headless.resolution_extent();
+        let mut headless =
+            headless.resolution(render_settings.scale_resolution(resolution, resolution));
+        headless.create_swapchain_images(&vulkan_context)?;
+        let swapchain_resolution = headless.resolution_extent();
+
+        let xr_context = XrContext {
+            backend: XrBackend::Headless(Box::new(headless)),
+            action_map,
+            stage_pose: Isometry3::identity(),
+            hand_joints: [None, None],
+            input: Default::default(),
+            session_state: SessionState::IDLE,
+            swapchain_resolution,
+            render_settings,
+            frame_state: empty_frame_state(),
+            views: Vec::new(),
+            view_state_flags: ViewStateFlags::EMPTY,
+            frame_index: 0,
+        };
+
+        Ok((xr_context, vulkan_context))
+    }
+
     fn _new(
         instance: xr::Instance,
         system: xr::SystemId,
+        action_map: ActionMap,
+        render_settings: RenderSettings,
         application_name: &str,
         application_version: u32,
     ) -> Result<(XrContext, VulkanContext)> {
@@ -104,139 +304,98 @@ impl XrContext {
             create_xr_session(&instance, system, &vulkan_context)?;
         let reference_space =
             session.create_reference_space(ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)?;
-        let swapchain_resolution = get_swapchain_resolution(&instance, system)?;
-        let swapchain = create_xr_swapchain(&session, &swapchain_resolution, VIEW_COUNT)?;
+        let swapchain_resolution = get_swapchain_resolution(&instance, system, &render_settings)?;
+
+        // Foveation needs support from both the runtime and the device
+        let foveated = render_settings.foveation != Foveation::Off
+            && foveation::is_enabled(&instance)
+            && vulkan_context.fragment_density_map;
+        if render_settings.foveation != Foveation::Off && !foveated {
+            println!("[HOTHAM_XR] Foveated rendering is not supported, so it has been turned off");
+        }
+        let swapchain = create_xr_swapchain(&session, &swapchain_resolution, VIEW_COUNT, foveated)?;
+        let (fragment_density_maps, foveation_profile) = if foveated {
+            (
+                Some(foveation::enumerate_density_maps(&swapchain)?),
+                Some(foveation::apply(
+                    &session,
+                    &swapchain,
+                    render_settings.foveation,
+                )?),
+            )
+        } else {
+            (None, None)
+        };
 
         // Create an action set to encapsulate our actions
         let action_set = instance.create_action_set("input", "input pose information", 0)?;
 
-        let left_hand_subaction_path = instance.string_to_path("/user/hand/left").unwrap();
-        let right_hand_subaction_path = instance.string_to_path("/user/hand/right").unwrap();
-        let left_hand_pose_path = instance
-            .string_to_path("/user/hand/left/input/grip/pose")
-            .unwrap();
-        let left_pointer_path = instance
-            .string_to_path("/user/hand/left/input/aim/pose")
-            .unwrap();
-        let right_hand_pose_path = instance
-            .string_to_path("/user/hand/right/input/grip/pose")
-            .unwrap();
-        let right_pointer_path = instance
-            .string_to_path("/user/hand/right/input/aim/pose")
-            .unwrap();
-
-        let left_hand_grip_squeeze_path = instance
-            .string_to_path("/user/hand/left/input/squeeze/value")
-            .unwrap();
-        let left_hand_grip_trigger_path = instance
-            .string_to_path("/user/hand/left/input/trigger/value")
-            .unwrap();
-        let left_hand_haptic_feedback_path = instance
-            .string_to_path("/user/hand/left/output/haptic")
-            .unwrap();
-
-        let right_hand_grip_squeeze_path = instance
-            .string_to_path("/user/hand/right/input/squeeze/value")
-            .unwrap();
-        let right_hand_grip_trigger_path = instance
-            .string_to_path("/user/hand/right/input/trigger/value")
-            .unwrap();
-        let right_hand_haptic_feedback_path = instance
-            .string_to_path("/user/hand/right/output/haptic")
-            .unwrap();
-
-        let pose_action = action_set.create_action::<xr::Posef>(
-            "hand_pose",
-            "Hand Pose",
-            &[left_hand_subaction_path, right_hand_subaction_path],
-        )?;
-
-        let aim_action = action_set.create_action::<xr::Posef>(
-            "pointer_pose",
-            "Pointer Pose",
-            &[left_hand_subaction_path, right_hand_subaction_path],
-        )?;
-
-        let trigger_action = action_set.create_action::<f32>(
-            "trigger_pulled",
-            "Pull Trigger",
-            &[left_hand_subaction_path, right_hand_subaction_path],
-        )?;
-
-        let grab_action = action_set.create_action::<f32>(
-            "grab_object",
-            "Grab Object",
-            &[left_hand_subaction_path, right_hand_subaction_path],
-        )?;
+        let hand_paths = [
+            instance.string_to_path(HAND_PATHS[0])?,
+            instance.string_to_path(HAND_PATHS[1])?,
+        ];
 
-        let haptic_feedback_action = action_set.create_action::<Haptic>(
-            "haptic_feedback",
-            "Haptic Feedback",
-            &[left_hand_subaction_path, right_hand_subaction_path],
-        )?;
+        let mut actions = HashMap::new();
+        for description in action_map.actions() {
+            let action = OpenXrAction::create(&action_set, &session, description, &hand_paths)?;
+            actions.insert(description.name.clone(), action);
+        }
 
-        // Bind our actions to input devices using the given profile
-        instance.suggest_interaction_profile_bindings(
-            instance
-                .string_to_path("/interaction_profiles/oculus/touch_controller")
-                .unwrap(),
-            &[
-                xr::Binding::new(&pose_action, left_hand_pose_path),
-                xr::Binding::new(&pose_action, right_hand_pose_path),
-                xr::Binding::new(&aim_action, left_pointer_path),
-                xr::Binding::new(&aim_action, right_pointer_path),
-                xr::Binding::new(&grab_action, left_hand_grip_squeeze_path),
-                xr::Binding::new(&grab_action, right_hand_grip_squeeze_path),
-                xr::Binding::new(&trigger_action, left_hand_grip_trigger_path),
-                xr::Binding::new(&trigger_action, right_hand_grip_trigger_path),
-                xr::Binding::new(&grab_action, right_hand_grip_squeeze_path),
-                xr::Binding::new(&haptic_feedback_action, left_hand_haptic_feedback_path),
-                xr::Binding::new(&haptic_feedback_action, right_hand_haptic_feedback_path),
-            ],
-        )?;
+        // Bind our actions to input devices for every profile in the action map
+        for profile in action_map.interaction_profiles() {
+            let mut bindings = Vec::new();
+            for description in action_map.actions() {
+                for (_, path) in description.bindings.iter().filter(|(p, _)| p == profile) {
+                    let path = instance.string_to_path(path)?;
+                    bindings.push(actions[&description.name].binding(path));
+                }
+            }
 
-        let left_hand_space =
-            pose_action.create_space(session.clone(), left_hand_subaction_path, Posef::IDENTITY)?;
-        let left_pointer_space =
-            aim_action.create_space(session.clone(), left_hand_subaction_path, Posef::IDENTITY)?;
+            instance.suggest_interaction_profile_bindings(
+                instance.string_to_path(profile)?,
+                &bindings,
+            )?;
+        }
 
-        let right_hand_space = pose_action.create_space(
-            session.clone(),
-            right_hand_subaction_path,
-            Posef::IDENTITY,
-        )?;
-        let right_pointer_space =
-            aim_action.create_space(session.clone(), left_hand_subaction_path, Posef::IDENTITY)?;
+        // Attach the action set to the session
+        session.attach_action_sets(&[&action_set])?;
 
-        let frame_state = FrameState {
-            predicted_display_time: Time::from_nanos(0),
-            predicted_display_period: Duration::from_nanos(0),
-            should_render: false,
+        // Track the user's hands if they can put their controllers down
+        let hand_trackers = if instance.exts().ext_hand_tracking.is_some()
+            && instance.supports_hand_tracking(system)?
+        {
+            Some([
+                session.create_hand_tracker(xr::Hand::LEFT)?,
+                session.create_hand_tracker(xr::Hand::RIGHT)?,
+            ])
+        } else {
+            None
         };
 
-        // Attach the action set to the session
-        session.attach_action_sets(&[&action_set])?;
-        let xr_context = XrContext {
+        let backend = OpenXrBackend {
             instance,
             session,
-            session_state: SessionState::IDLE,
             swapchain,
             reference_space,
             action_set,
-            pose_action,
-            trigger_action,
-            grab_action,
-            haptic_feedback_action,
-            left_hand_space,
-            left_pointer_space,
-            left_hand_subaction_path,
-            right_hand_space,
-            right_pointer_space,
-            right_hand_subaction_path,
-            swapchain_resolution,
+            actions,
+            hand_paths,
+            hand_trackers,
+            fragment_density_maps,
+            foveation_profile,
             frame_waiter,
             frame_stream,
-            frame_state,
+        };
+        let xr_context = XrContext {
+            backend: XrBackend::OpenXr(Box::new(backend)),
+            action_map,
+            stage_pose: Isometry3::identity(),
+            hand_joints: [None, None],
+            input: Default::default(),
+            session_state: SessionState::IDLE,
+            swapchain_resolution,
+            render_settings,
+            frame_state: empty_frame_state(),
             views: Vec::new(),
             view_state_flags: ViewStateFlags::EMPTY,
             frame_index: 0,
@@ -245,12 +404,84 @@ impl XrContext {
         Ok((xr_context, vulkan_context))
     }
 
+    /// The OpenXR objects backing this context, if it isn't headless
+    pub fn openxr(&self) -> Option<&OpenXrBackend> {
+        match &self.backend {
+            XrBackend::OpenXr(openxr) => Some(openxr),
+            XrBackend::Headless(_) => None,
+        }
+    }
+
+    /// The actions this context was created with
+    pub fn action_map(&self) -> &ActionMap {
+        &self.action_map
+    }
+
+    /// Where the user's play area is in the world. Views, hands and pointers are all located relative to this.
+    pub fn stage_pose(&self) -> Isometry3<f32> {
+        self.stage_pose
+    }
+
+    /// Move the user's play area to `stage_pose` in the world, eg. for locomotion.
+    ///
+    /// The views and input poses of the current frame are moved along with it, so this can be called at any point
+    /// between `begin_frame` and rendering.
+    pub fn set_stage_pose(&mut self, stage_pose: Isometry3<f32>) {
+        let delta = stage_pose * self.stage_pose.inverse();
+        for view in self.views.iter_mut() {
+            view.pose = transform_posef(&delta, view.pose);
+        }
+        self.input.transform_poses(&delta);
+        for joints in self.hand_joints.iter_mut().flatten() {
+            joints.transform(&delta);
+        }
+        self.stage_pose = stage_pose;
+    }
+
+    /// The headless session driving this context, if there is one
+    pub fn headless(&self) -> Option<&HeadlessXr> {
+        match &self.backend {
+            XrBackend::OpenXr(_) => None,
+            XrBackend::Headless(headless) => Some(headless),
+        }
+    }
+
+    /// Mutable access to the headless session driving this context, if there is one
+    pub fn headless_mut(&mut self) -> Option<&mut HeadlessXr> {
+        match &mut self.backend {
+            XrBackend::OpenXr(_) => None,
+            XrBackend::Headless(headless) => Some(headless),
+        }
+    }
+
+    pub(crate) fn create_swapchain(&self) -> Result<HothamSwapchain> {
+        match &self.backend {
+            XrBackend::OpenXr(openxr) => HothamSwapchain::new(
+                &openxr.swapchain,
+                self.swapchain_resolution,
+                openxr.fragment_density_maps.clone(),
+            ),
+            XrBackend::Headless(headless) => Ok(headless.swapchain()),
+        }
+    }
+
     pub(crate) fn poll_xr_event(
         &mut self,
         event_buffer: &mut EventDataBuffer,
     ) -> Result<SessionState> {
+        let openxr = match &mut self.backend {
+            XrBackend::OpenXr(openxr) => openxr,
+            XrBackend::Headless(headless) => {
+                if let Some(new_state) = headless.poll_session_state() {
+                    println!("[HOTHAM_POLL_EVENT] State is now {:?}", new_state);
+                    self.session_state = new_state;
+                }
+                return Ok(self.session_state);
+            }
+        };
+
         loop {
-            match self.instance.poll_event(event_buffer)? {
+            match openxr.instance.poll_event(event_buffer)? {
                 Some(xr::Event::SessionStateChanged(session_changed)) => {
                     let new_state = session_changed.state();
                     println!("[HOTHAM_POLL_EVENT] State is now {:?}", new_state);
@@ -268,19 +499,270 @@ impl XrContext {
         Ok(self.session_state)
     }
 
+    pub(crate) fn begin_session(&mut self) -> Result<()> {
+        match &mut self.backend {
+            XrBackend::OpenXr(openxr) => {
+                openxr.session.begin(VIEW_TYPE)?;
+            }
+            XrBackend::Headless(headless) => headless.begin_session(),
+        }
+        Ok(())
+    }
+
+    /// Sync the state of all actions with the runtime. Make sure to call this once per frame before reading any input.
+    pub fn sync_actions(&mut self) -> Result<()> {
+        if let XrBackend::OpenXr(openxr) = &self.backend {
+            let active_action_set = ActiveActionSet::new(&openxr.action_set);
+            openxr.session.sync_actions(&[active_action_set])?;
+        }
+        Ok(())
+    }
+
     pub(crate) fn begin_frame(&mut self) -> Result<()> {
-        self.frame_state = self.frame_waiter.wait()?;
-        self.frame_stream.begin()?;
+        let openxr = match &mut self.backend {
+            XrBackend::OpenXr(openxr) => openxr,
+            XrBackend::Headless(headless) => {
+                let (frame_state, frame_index) = headless.begin_frame(self.session_state);
+                self.frame_state = frame_state;
+                self.frame_index = frame_index;
+                return Ok(());
+            }
+        };
+
+        self.frame_state = openxr.frame_waiter.wait()?;
+        openxr.frame_stream.begin()?;
+
+        self.frame_index = openxr.swapchain.acquire_image()? as _;
+        openxr.swapchain.wait_image(openxr::Duration::INFINITE)?;
+
+        Ok(())
+    }
+
+    /// Locate the user's eyes for the current frame and store them in `views`
+    pub fn locate_views(&mut self) -> Result<()> {
+        let (view_state_flags, mut views) = match &self.backend {
+            XrBackend::OpenXr(openxr) => openxr.session.locate_views(
+                VIEW_TYPE,
+                self.frame_state.predicted_display_time,
+                &openxr.reference_space,
+            )?,
+            XrBackend::Headless(headless) => (HeadlessXr::view_state_flags(), headless.views()),
+        };
+        for view in views.iter_mut() {
+            view.pose = transform_posef(&self.stage_pose, view.pose);
+        }
+        self.views = views;
+        self.view_state_flags = view_state_flags;
+        Ok(())
+    }
+
+    /// The joints of the user's hand, if it is being tracked with `XR_EXT_hand_tracking`. Updated by `begin_frame`.
+    pub fn hand_joints(&self, handedness: Handedness) -> Option<&HandJoints> {
+        self.hand_joints[hand_index(handedness)].as_ref()
+    }
+
+    /// Read the current value of every input action into `input`, and locate the joints of any tracked hands.
+    /// Called by `begin_frame`.
+    pub fn update_input(&mut self) -> Result<()> {
+        self.hand_joints = [
+            self.locate_hand_joints(Handedness::Left)?,
+            self.locate_hand_joints(Handedness::Right)?,
+        ];
+
+        let mut values = HashMap::new();
+        for description in self.action_map.actions() {
+            if description.action_type == ActionType::Haptic {
+                continue;
+            }
+
+            let value = [
+                self.input_value(description, Handedness::Left)?,
+                self.input_value(description, Handedness::Right)?,
+            ];
+            values.insert(description.name.clone(), value);
+        }
+
+        self.input.update(values);
+        Ok(())
+    }
+
+    fn locate_hand_joints(&self, handedness: Handedness) -> Result<Option<HandJoints>> {
+        let mut joints = match &self.backend {
+            XrBackend::OpenXr(openxr) => {
+                let tracker = match &openxr.hand_trackers {
+                    Some(trackers) => &trackers[hand_index(handedness)],
+                    None => return Ok(None),
+                };
+                let locations = openxr
+                    .reference_space
+                    .locate_hand_joints(tracker, self.frame_state.predicted_display_time)?;
+                locations.as_ref().and_then(HandJoints::from_locations)
+            }
+            XrBackend::Headless(headless) => headless.frame.hand(handedness).joints,
+        };
+        if let Some(joints) = joints.as_mut() {
+            joints.transform(&self.stage_pose);
+        }
+        Ok(joints)
+    }
+
+    fn input_value(
+        &self,
+        description: &ActionDescription,
+        handedness: Handedness,
+    ) -> Result<InputValue> {
+        let name = description.name.as_str();
+        if description.action_type == ActionType::Pose {
+            let location = self.locate_pose(name, handedness)?;
+            let pose = is_space_valid(&location).then(|| posef_to_isometry(location.pose));
+            return Ok(InputValue::Pose(pose));
+        }
 
-        self.frame_index = self.swapchain.acquire_image()? as _;
-        self.swapchain.wait_image(openxr::Duration::INFINITE)?;
+        let openxr = match &self.backend {
+            XrBackend::OpenXr(openxr) => openxr,
+            XrBackend::Headless(headless) => {
+                return headless
+                    .frame
+                    .hand(handedness)
+                    .input(name)
+                    .or_else(|| InputValue::default_for(description.action_type))
+                    .ok_or_else(|| anyhow!("Action {} is not an input", name))
+            }
+        };
+
+        let session = &openxr.session;
+        let path = openxr.hand_path(handedness);
+        let value = match openxr.action(name)? {
+            OpenXrAction::Boolean(action) => {
+                InputValue::Boolean(ActionInput::get(action, session, path)?.current_state)
+            }
+            OpenXrAction::Float(action) => {
+                InputValue::Float(ActionInput::get(action, session, path)?.current_state)
+            }
+            OpenXrAction::Vector2(action) => {
+                let value = ActionInput::get(action, session, path)?.current_state;
+                InputValue::Vector2(Vector2::new(value.x, value.y))
+            }
+            _ => return Err(anyhow!("Action {} is not an input", name)),
+        };
+        Ok(value)
+    }
+
+    /// Locate a pose action for the given controller at the predicted display time of the current frame
+    pub fn locate_pose(&self, name: &str, handedness: Handedness) -> Result<SpaceLocation> {
+        match &self.backend {
+            XrBackend::OpenXr(openxr) => match openxr.action(name)? {
+                OpenXrAction::Pose(_, spaces) => {
+                    let mut location = spaces[hand_index(handedness)].locate(
+                        &openxr.reference_space,
+                        self.frame_state.predicted_display_time,
+                    )?;
+                    location.pose = transform_posef(&self.stage_pose, location.pose);
+                    Ok(location)
+                }
+                _ => Err(anyhow!("Action {} is not a pose", name)),
+            },
+            XrBackend::Headless(headless) => {
+                let hand = headless.frame.hand(handedness);
+                let pose = match name {
+                    HAND_POSE => Some(hand.grip_pose),
+                    POINTER_POSE => Some(hand.aim_pose),
+                    _ => match hand.inputs.get(name) {
+                        Some(InputValue::Pose(pose)) => pose.map(isometry_to_posef),
+                        _ => None,
+                    },
+                };
+                let pose = pose.map(|p| transform_posef(&self.stage_pose, p));
+                Ok(pose.map(HeadlessXr::locate).unwrap_or(SpaceLocation {
+                    location_flags: SpaceLocationFlags::EMPTY,
+                    pose: Posef::IDENTITY,
+                }))
+            }
+        }
+    }
 
+    /// Locate the grip of the given controller at the predicted display time of the current frame
+    pub fn locate_hand(&self, handedness: Handedness) -> Result<SpaceLocation> {
+        self.locate_pose(HAND_POSE, handedness)
+    }
+
+    /// Locate the aim of the given controller at the predicted display time of the current frame
+    pub fn locate_pointer(&self, handedness: Handedness) -> Result<SpaceLocation> {
+        self.locate_pose(POINTER_POSE, handedness)
+    }
+
+    /// How far the grip of the given controller is squeezed, from 0 to 1
+    pub fn grip_value(&self, handedness: Handedness) -> Result<f32> {
+        self.float_value(GRAB_OBJECT, handedness)
+    }
+
+    /// How far the trigger of the given controller is pulled, from 0 to 1
+    pub fn trigger_value(&self, handedness: Handedness) -> Result<f32> {
+        self.float_value(TRIGGER_PULLED, handedness)
+    }
+
+    fn float_value(&self, name: &str, handedness: Handedness) -> Result<f32> {
+        let description = self
+            .action_map
+            .get(name)
+            .ok_or_else(|| anyhow!("There is no action named {} in the action map", name))?;
+        match self.input_value(description, handedness)? {
+            InputValue::Float(value) => Ok(value),
+            _ => Err(anyhow!("Action {} is not a float", name)),
+        }
+    }
+
+    /// Vibrate the given controller using the default haptic action
+    pub fn apply_haptic_feedback(
+        &mut self,
+        handedness: Handedness,
+        amplitude: f32,
+        frequency: f32,
+        duration: Duration,
+    ) -> Result<()> {
+        self.apply_haptic(HAPTIC_FEEDBACK, handedness, amplitude, frequency, duration)
+    }
+
+    /// Vibrate the given controller using the haptic action called `name`
+    pub fn apply_haptic(
+        &mut self,
+        name: &str,
+        handedness: Handedness,
+        amplitude: f32,
+        frequency: f32,
+        duration: Duration,
+    ) -> Result<()> {
+        match &mut self.backend {
+            XrBackend::OpenXr(openxr) => {
+                let action = match openxr.action(name)? {
+                    OpenXrAction::Haptic(action) => action,
+                    _ => return Err(anyhow!("Action {} is not a haptic output", name)),
+                };
+                let event = HapticVibration::new()
+                    .amplitude(amplitude)
+                    .frequency(frequency)
+                    .duration(duration);
+                action.apply_feedback(&openxr.session, openxr.hand_path(handedness), &event)?;
+            }
+            XrBackend::Headless(headless) => headless.haptics.push(HeadlessHaptic {
+                action: name.to_string(),
+                handedness,
+                amplitude,
+                frequency,
+                duration,
+            }),
+        }
         Ok(())
     }
 
     pub fn end_frame(&mut self) -> std::result::Result<(), openxr::sys::Result> {
+        let openxr = match &mut self.backend {
+            XrBackend::OpenXr(openxr) => openxr,
+            XrBackend::Headless(_) => return Ok(()),
+        };
+
         // Submit the image to OpenXR
-        self.swapchain.release_image().unwrap();
+        openxr.swapchain.release_image().unwrap();
 
         let rect = xr::Rect2Di {
             offset: xr::Offset2Di { x: 0, y: 0 },
@@ -292,43 +774,74 @@ impl XrContext {
 
         let display_time = self.frame_state.predicted_display_time;
 
+        // The views are in the world, so move them back into the STAGE space they're submitted in
+        let world_to_stage = self.stage_pose.inverse();
+        let poses = [
+            transform_posef(&world_to_stage, self.views[0].pose),
+            transform_posef(&world_to_stage, self.views[1].pose),
+        ];
+
         let views = [
             xr::CompositionLayerProjectionView::new()
-                .pose(self.views[0].pose)
+                .pose(poses[0])
                 .fov(self.views[0].fov)
                 .sub_image(
                     xr::SwapchainSubImage::new()
-                        .swapchain(&self.swapchain)
+                        .swapchain(&openxr.swapchain)
                         .image_array_index(0)
                         .image_rect(rect),
                 ),
             xr::CompositionLayerProjectionView::new()
-                .pose(self.views[1].pose)
+                .pose(poses[1])
                 .fov(self.views[1].fov)
                 .sub_image(
                     xr::SwapchainSubImage::new()
-                        .swapchain(&self.swapchain)
+                        .swapchain(&openxr.swapchain)
                         .image_array_index(1)
                         .image_rect(rect),
                 ),
         ];
 
         let layer_projection = xr::CompositionLayerProjection::new()
-            .space(&self.reference_space)
+            .space(&openxr.reference_space)
             .views(&views);
 
         let layers = [&*layer_projection];
-        self.frame_stream.end(display_time, BLEND_MODE, &layers)
+        openxr.frame_stream.end(display_time, BLEND_MODE, &layers)
     }
 
     pub(crate) fn end_session(&mut self) -> anyhow::Result<()> {
         println!("[HOTHAM_XR] - Ending session..");
-        self.session.end()?;
+        match &mut self.backend {
+            XrBackend::OpenXr(openxr) => {
+                openxr.session.end()?;
+            }
+            XrBackend::Headless(headless) => headless.end_session(),
+        }
         println!("[HOTHAM_XR] - ..done!");
         Ok(())
     }
 }
 
+fn transform_posef(isometry: &Isometry3<f32>, pose: Posef) -> Posef {
+    isometry_to_posef(isometry * posef_to_isometry(pose))
+}
+
+fn hand_index(handedness: Handedness) -> usize {
+    match handedness {
+        Handedness::Left => 0,
+        Handedness::Right => 1,
+    }
+}
+
+fn empty_frame_state() -> FrameState {
+    FrameState {
+        predicted_display_time: Time::from_nanos(0),
+        predicted_display_period: Duration::from_nanos(0),
+        should_render: false,
+    }
+}
+
 #[cfg(not(target_os = "android"))]
 pub(crate) fn create_vulkan_context(
     xr_instance: &xr::Instance,
@@ -366,35 +879,47 @@ fn create_vulkan_context(
 pub(crate) fn get_swapchain_resolution(
     xr_instance: &xr::Instance,
     system: xr::SystemId,
+    render_settings: &RenderSettings,
 ) -> Result<vk::Extent2D> {
     let views = xr_instance.enumerate_view_configuration_views(system, VIEW_TYPE)?;
     println!("[HOTHAM_VULKAN] Views: {:?}", views);
-    let resolution = vk::Extent2D {
+    let recommended = vk::Extent2D {
         width: views[0].recommended_image_rect_width,
         height: views[0].recommended_image_rect_height,
     };
+    let max = vk::Extent2D {
+        width: views[0].max_image_rect_width,
+        height: views[0].max_image_rect_height,
+    };
 
-    Ok(resolution)
+    Ok(render_settings.scale_resolution(recommended, max))
 }
 
 pub(crate) fn create_xr_swapchain(
     xr_session: &Session<Vulkan>,
     resolution: &vk::Extent2D,
     array_size: u32,
+    foveated: bool,
 ) -> Result<Swapchain<Vulkan>> {
-    xr_session
-        .create_swapchain(&SwapchainCreateInfo {
-            create_flags: SwapchainCreateFlags::EMPTY,
-            usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT,
-            format: COLOR_FORMAT.as_raw() as u32,
-            sample_count: 1,
-            width: resolution.width,
-            height: resolution.height,
-            face_count: 1,
-            array_size,
-            mip_count: 1,
-        })
-        .map_err(Into::into)
+    let create_info = SwapchainCreateInfo {
+        create_flags: SwapchainCreateFlags::EMPTY,
+        usage_flags: SwapchainUsageFlags::COLOR_ATTACHMENT,
+        format: COLOR_FORMAT.as_raw() as u32,
+        sample_count: 1,
+        width: resolution.width,
+        height: resolution.height,
+        face_count: 1,
+        array_size,
+        mip_count: 1,
+    };
+
+    if foveated {
+        foveation::create_swapchain(xr_session, &create_info)
+    } else {
+        xr_session
+            .create_swapchain(&create_info)
+            .map_err(Into::into)
+    }
 }
 
 pub(crate) fn create_xr_session(
@@ -423,6 +948,7 @@ pub(crate) fn create_xr_instance(
     application_name: &str,
     application_version: u32,
     required_extensions: Option<&xr::ExtensionSet>,
+    foveation: bool,
 ) -> anyhow::Result<(xr::Instance, xr::SystemId)> {
     let xr_entry = if let Some(path) = path {
         xr::Entry::load_from(path)?
@@ -445,23 +971,29 @@ pub(crate) fn create_xr_instance(
         xr_entry.initialize_android_loader()?;
     }
 
-    println!(
-        "Available extensions: {:?}",
-        xr_entry.enumerate_extensions()?
-    );
+    let available_extensions = xr_entry.enumerate_extensions()?;
+    println!("Available extensions: {:?}", available_extensions);
+
+    // Hand tracking is optional: if the runtime doesn't have it, the user will just have to hold their controllers.
+    required_extensions.ext_hand_tracking |= available_extensions.ext_hand_tracking;
+
+    // Likewise for foveation, which is only turned on if the application asks for it
+    if foveation {
+        foveation::enable_extensions(&mut required_extensions, &available_extensions);
+    }
 
     let instance = xr_entry.create_instance(&xr_app_info, &required_extensions, &[])?;
     let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
     Ok((instance, system))
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use super::XrContext;
 
     #[test]
     pub fn test_xr_context_smoke_test() {
-        XrContext::new().unwrap();
+        let (xr_context, _) = XrContext::testing().unwrap();
+        assert!(xr_context.headless().is_some());
     }
 }
@@ -0,0 +1,280 @@
+use anyhow::{anyhow, Result};
+
+/// Oculus Touch controllers, used by the Quest and Rift
+pub const OCULUS_TOUCH_PROFILE: &str = "/interaction_profiles/oculus/touch_controller";
+/// Valve Index "knuckles" controllers
+pub const VALVE_INDEX_PROFILE: &str = "/interaction_profiles/valve/index_controller";
+/// HTC Vive wand controllers
+pub const HTC_VIVE_PROFILE: &str = "/interaction_profiles/htc/vive_controller";
+/// The generic controller that every OpenXR runtime supports
+pub const KHR_SIMPLE_PROFILE: &str = "/interaction_profiles/khr/simple_controller";
+
+/// The pose of the grip of each controller. Used by `XrContext::locate_hand`.
+pub const HAND_POSE: &str = "hand_pose";
+/// The pose of the aim of each controller. Used by `XrContext::locate_pointer`.
+pub const POINTER_POSE: &str = "pointer_pose";
+/// How far each grip is squeezed. Used by `XrContext::grip_value`.
+pub const GRAB_OBJECT: &str = "grab_object";
+/// How far each trigger is pulled. Used by `XrContext::trigger_value`.
+pub const TRIGGER_PULLED: &str = "trigger_pulled";
+/// The position of each thumbstick, or trackpad on controllers that don't have one. Used by `locomotion_system`.
+pub const THUMBSTICK: &str = "thumbstick";
+/// Vibration of each controller. Used by `XrContext::apply_haptic_feedback`.
+pub const HAPTIC_FEEDBACK: &str = "haptic_feedback";
+
+/// The subaction path of each hand. Every action can be queried separately for each hand.
+pub const HAND_PATHS: [&str; 2] = ["/user/hand/left", "/user/hand/right"];
+
+/// The kind of value an action produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionType {
+    /// A button that is either pressed or not
+    Boolean,
+    /// An analog value from 0 to 1, like a trigger
+    Float,
+    /// A two dimensional value from -1 to 1 on each axis, like a thumbstick
+    Vector2,
+    /// A position and orientation, like the grip of a controller
+    Pose,
+    /// A haptic output, like a controller's vibration motor
+    Haptic,
+}
+
+/// A single input or output action and the controller inputs it is bound to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionDescription {
+    /// Name used to query the action. Must be lowercase letters, numbers, `-`, `_` or `.`
+    pub name: String,
+    /// Human readable name shown to the user by the runtime
+    pub localized_name: String,
+    /// The kind of value this action produces
+    pub action_type: ActionType,
+    /// Pairs of interaction profile and input path, eg. `/user/hand/left/input/x/click`
+    pub bindings: Vec<(String, String)>,
+}
+
+/// A declarative description of every action an application uses and how they are bound to each kind of
+/// controller. Pass one to `XrContextBuilder::action_map` to replace the default actions.
+///
+/// ```
+/// use hotham::resources::xr_context::{ActionMap, ActionType, OCULUS_TOUCH_PROFILE, VALVE_INDEX_PROFILE};
+///
+/// let action_map = ActionMap::default()
+///     .action("jump", "Jump", ActionType::Boolean)
+///     .bind("jump", OCULUS_TOUCH_PROFILE, &["/user/hand/right/input/a/click"])
+///     .bind("jump", VALVE_INDEX_PROFILE, &["/user/hand/right/input/a/click"]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionMap {
+    actions: Vec<ActionDescription>,
+    unknown_actions: Vec<String>,
+}
+
+impl ActionMap {
+    /// Create an action map with no actions at all
+    pub fn empty() -> Self {
+        Self {
+            actions: Vec::new(),
+            unknown_actions: Vec::new(),
+        }
+    }
+
+    /// Add an action. It can then be bound to inputs with `bind`.
+    pub fn action(mut self, name: &str, localized_name: &str, action_type: ActionType) -> Self {
+        self.actions.push(ActionDescription {
+            name: name.to_string(),
+            localized_name: localized_name.to_string(),
+            action_type,
+            bindings: Vec::new(),
+        });
+        self
+    }
+
+    /// Bind the action called `name` to some inputs on the controllers described by `interaction_profile`.
+    /// Unknown actions are reported as an error when the `XrContext` is built.
+    pub fn bind(mut self, name: &str, interaction_profile: &str, paths: &[&str]) -> Self {
+        let bindings = paths
+            .iter()
+            .map(|p| (interaction_profile.to_string(), p.to_string()));
+        match self.actions.iter_mut().find(|a| a.name == name) {
+            Some(action) => action.bindings.extend(bindings),
+            None => self.unknown_actions.push(name.to_string()),
+        }
+        self
+    }
+
+    /// Bind the action called `name` to the same input on both controllers, eg. `input/trigger/value`
+    pub fn bind_both_hands(self, name: &str, interaction_profile: &str, input: &str) -> Self {
+        let paths = HAND_PATHS.map(|hand| format!("{}/{}", hand, input));
+        self.bind(name, interaction_profile, &[&paths[0], &paths[1]])
+    }
+
+    /// All of the actions in this map
+    pub fn actions(&self) -> &[ActionDescription] {
+        &self.actions
+    }
+
+    /// Get the action called `name`
+    pub fn get(&self, name: &str) -> Option<&ActionDescription> {
+        self.actions.iter().find(|a| a.name == name)
+    }
+
+    /// Every interaction profile used by at least one binding
+    pub fn interaction_profiles(&self) -> Vec<&str> {
+        let mut profiles = Vec::new();
+        for (profile, _) in self.actions.iter().flat_map(|a| &a.bindings) {
+            if !profiles.contains(&profile.as_str()) {
+                profiles.push(profile.as_str());
+            }
+        }
+        profiles
+    }
+
+    /// Check the map for mistakes that the OpenXR runtime would otherwise reject with a less helpful error
+    pub fn validate(&self) -> Result<()> {
+        if let Some(name) = self.unknown_actions.first() {
+            return Err(anyhow!(
+                "Action {} was bound before it was added to the action map",
+                name
+            ));
+        }
+
+        for (i, action) in self.actions.iter().enumerate() {
+            let valid_name = !action.name.is_empty()
+                && action.name.chars().all(|c| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '-' | '_' | '.')
+                });
+            if !valid_name {
+                return Err(anyhow!(
+                    "Invalid action name {:?}: names may only contain lowercase letters, numbers, '-', '_' and '.'",
+                    action.name
+                ));
+            }
+
+            if self.actions[..i].iter().any(|a| a.name == action.name) {
+                return Err(anyhow!("Action {} was added twice", action.name));
+            }
+
+            for (_, path) in &action.bindings {
+                if !HAND_PATHS
+                    .iter()
+                    .any(|hand| path.starts_with(&format!("{}/", hand)))
+                {
+                    return Err(anyhow!(
+                        "Unable to bind {} to {}: only inputs on /user/hand/left and /user/hand/right are supported",
+                        action.name,
+                        path
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ActionMap {
+    /// The actions used by Hotham's built in systems, bound for every common controller
+    fn default() -> Self {
+        let mut action_map = ActionMap::empty()
+            .action(HAND_POSE, "Hand Pose", ActionType::Pose)
+            .action(POINTER_POSE, "Pointer Pose", ActionType::Pose)
+            .action(GRAB_OBJECT, "Grab Object", ActionType::Float)
+            .action(TRIGGER_PULLED, "Pull Trigger", ActionType::Float)
+            .action(THUMBSTICK, "Thumbstick", ActionType::Vector2)
+            .action(HAPTIC_FEEDBACK, "Haptic Feedback", ActionType::Haptic);
+
+        for profile in [
+            OCULUS_TOUCH_PROFILE,
+            VALVE_INDEX_PROFILE,
+            HTC_VIVE_PROFILE,
+            KHR_SIMPLE_PROFILE,
+        ] {
+            let (squeeze, trigger, thumbstick) = match profile {
+                HTC_VIVE_PROFILE => (
+                    "input/squeeze/click",
+                    "input/trigger/value",
+                    Some("input/trackpad"),
+                ),
+                KHR_SIMPLE_PROFILE => ("input/select/click", "input/select/click", None),
+                _ => (
+                    "input/squeeze/value",
+                    "input/trigger/value",
+                    Some("input/thumbstick"),
+                ),
+            };
+
+            action_map = action_map
+                .bind_both_hands(HAND_POSE, profile, "input/grip/pose")
+                .bind_both_hands(POINTER_POSE, profile, "input/aim/pose")
+                .bind_both_hands(GRAB_OBJECT, profile, squeeze)
+                .bind_both_hands(TRIGGER_PULLED, profile, trigger)
+                .bind_both_hands(HAPTIC_FEEDBACK, profile, "output/haptic");
+            if let Some(thumbstick) = thumbstick {
+                action_map = action_map.bind_both_hands(THUMBSTICK, profile, thumbstick);
+            }
+        }
+
+        action_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_default_action_map() {
+        let action_map = ActionMap::default();
+        action_map.validate().unwrap();
+        assert_eq!(action_map.interaction_profiles().len(), 4);
+
+        let grab = action_map.get(GRAB_OBJECT).unwrap();
+        assert_eq!(grab.action_type, ActionType::Float);
+        assert!(grab.bindings.contains(&(
+            OCULUS_TOUCH_PROFILE.to_string(),
+            "/user/hand/right/input/squeeze/value".to_string()
+        )));
+    }
+
+    #[test]
+    pub fn test_custom_actions() {
+        let action_map = ActionMap::empty()
+            .action("move", "Move", ActionType::Vector2)
+            .bind_both_hands("move", VALVE_INDEX_PROFILE, "input/thumbstick")
+            .bind(
+                "move",
+                OCULUS_TOUCH_PROFILE,
+                &["/user/hand/left/input/thumbstick"],
+            );
+        action_map.validate().unwrap();
+
+        let action = action_map.get("move").unwrap();
+        assert_eq!(action.bindings.len(), 3);
+        assert_eq!(
+            action_map.interaction_profiles(),
+            [VALVE_INDEX_PROFILE, OCULUS_TOUCH_PROFILE]
+        );
+    }
+
+    #[test]
+    pub fn test_invalid_action_maps() {
+        let unknown_action = ActionMap::empty().bind("jump", OCULUS_TOUCH_PROFILE, &[]);
+        assert!(unknown_action.validate().is_err());
+
+        let bad_name = ActionMap::empty().action("Jump", "Jump", ActionType::Boolean);
+        assert!(bad_name.validate().is_err());
+
+        let duplicate = ActionMap::default().action(GRAB_OBJECT, "Grab", ActionType::Boolean);
+        assert!(duplicate.validate().is_err());
+
+        let bad_path = ActionMap::empty()
+            .action("menu", "Menu", ActionType::Boolean)
+            .bind(
+                "menu",
+                OCULUS_TOUCH_PROFILE,
+                &["/user/gamepad/input/menu/click"],
+            );
+        assert!(bad_path.validate().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Fixed foveated rendering with `XR_FB_foveation`. On Vulkan the runtime hands us a fragment density map for each
+//! swapchain image, which the render pass uses to shade the edges of each eye at a lower resolution.
+use std::ptr;
+
+use anyhow::{anyhow, Result};
+use ash::vk::{self, Handle};
+use openxr::{
+    self as xr, sys, FoveationDynamicFB, FoveationLevelProfile, FoveationProfileFB, Session,
+    Swapchain, SwapchainCreateInfo, Vulkan,
+};
+
+use crate::{render_settings::Foveation, swapchain::FragmentDensityMaps};
+
+/// Turn on the extensions needed for foveation, if the runtime has all of them.
+pub(crate) fn enable_extensions(required: &mut xr::ExtensionSet, available: &xr::ExtensionSet) {
+    if available.fb_foveation
+        && available.fb_foveation_configuration
+        && available.fb_foveation_vulkan
+        && available.fb_swapchain_update_state
+    {
+        required.fb_foveation = true;
+        required.fb_foveation_configuration = true;
+        required.fb_foveation_vulkan = true;
+        required.fb_swapchain_update_state = true;
+    }
+}
+
+/// Whether the instance was created with the extensions foveation needs
+pub(crate) fn is_enabled(instance: &xr::Instance) -> bool {
+    let exts = instance.exts();
+    exts.fb_foveation.is_some()
+        && exts.fb_foveation_configuration.is_some()
+        && exts.fb_foveation_vulkan.is_some()
+        && exts.fb_swapchain_update_state.is_some()
+}
+
+/// Create a swapchain that has a fragment density map alongside each of its images
+pub(crate) fn create_swapchain(
+    session: &Session<Vulkan>,
+    info: &SwapchainCreateInfo<Vulkan>,
+) -> Result<Swapchain<Vulkan>> {
+    let mut foveation_info = sys::SwapchainCreateInfoFoveationFB {
+        ty: sys::SwapchainCreateInfoFoveationFB::TYPE,
+        next: ptr::null_mut(),
+        flags: sys::SwapchainCreateFoveationFlagsFB::FRAGMENT_DENSITY_MAP,
+    };
+    let create_info = sys::SwapchainCreateInfo {
+        ty: sys::SwapchainCreateInfo::TYPE,
+        next: &mut foveation_info as *mut _ as *const _,
+        create_flags: info.create_flags,
+        usage_flags: info.usage_flags,
+        format: info.format as _,
+        sample_count: info.sample_count,
+        width: info.width,
+        height: info.height,
+        face_count: info.face_count,
+        array_size: info.array_size,
+        mip_count: info.mip_count,
+    };
+
+    let mut handle = sys::Swapchain::NULL;
+    let result = unsafe {
+        (session.instance().fp().create_swapchain)(session.as_raw(), &create_info, &mut handle)
+    };
+    check(result)?;
+
+    Ok(unsafe { Swapchain::from_raw(session.clone(), handle) })
+}
+
+/// Get the fragment density map that goes with each image of a swapchain made by `create_swapchain`
+pub(crate) fn enumerate_density_maps(swapchain: &Swapchain<Vulkan>) -> Result<FragmentDensityMaps> {
+    let enumerate = swapchain.instance().fp().enumerate_swapchain_images;
+
+    let mut count = 0;
+    check(unsafe { enumerate(swapchain.as_raw(), 0, &mut count, ptr::null_mut()) })?;
+
+    let mut density_maps = vec![
+        sys::SwapchainImageFoveationVulkanFB {
+            ty: sys::SwapchainImageFoveationVulkanFB::TYPE,
+            next: ptr::null_mut(),
+            image: 0,
+            width: 0,
+            height: 0,
+        };
+        count as usize
+    ];
+    let mut images = density_maps
+        .iter_mut()
+        .map(|density_map| sys::SwapchainImageVulkanKHR {
+            ty: sys::SwapchainImageVulkanKHR::TYPE,
+            next: density_map as *mut _ as *mut _,
+            image: 0,
+        })
+        .collect::<Vec<_>>();
+    check(unsafe {
+        enumerate(
+            swapchain.as_raw(),
+            count,
+            &mut count,
+            images.as_mut_ptr() as *mut _,
+        )
+    })?;
+
+    let resolution = density_maps
+        .first()
+        .map(|d| vk::Extent2D {
+            width: d.width,
+            height: d.height,
+        })
+        .ok_or_else(|| anyhow!("The swapchain has no images"))?;
+
+    Ok(FragmentDensityMaps {
+        images: density_maps
+            .iter()
+            .map(|d| vk::Image::from_raw(d.image))
+            .collect(),
+        resolution,
+    })
+}
+
+/// Tell the runtime how much to foveate the swapchain. The profile has to be kept alive for as long as it is in use.
+pub(crate) fn apply(
+    session: &Session<Vulkan>,
+    swapchain: &Swapchain<Vulkan>,
+    foveation: Foveation,
+) -> Result<FoveationProfileFB> {
+    let profile = session.create_foveation_profile(Some(FoveationLevelProfile {
+        level: foveation.level(),
+        vertical_offset: 0.,
+        dynamic: FoveationDynamicFB::DISABLED,
+    }))?;
+
+    let update_swapchain = session
+        .instance()
+        .exts()
+        .fb_swapchain_update_state
+        .as_ref()
+        .ok_or_else(|| anyhow!("XR_FB_swapchain_update_state is not enabled"))?
+        .update_swapchain;
+    let state = sys::SwapchainStateFoveationFB {
+        ty: sys::SwapchainStateFoveationFB::TYPE,
+        next: ptr::null_mut(),
+        flags: sys::SwapchainStateFoveationFlagsFB::EMPTY,
+        profile: profile.as_raw(),
+    };
+    check(unsafe { update_swapchain(swapchain.as_raw(), &state as *const _ as *const _) })?;
+
+    Ok(profile)
+}
+
+fn check(result: sys::Result) -> Result<(), sys::Result> {
+    if result.into_raw() < 0 {
+        Err(result)
+    } else {
+        Ok(())
+    }
+}
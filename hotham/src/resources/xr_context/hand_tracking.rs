@@ -0,0 +1,207 @@
+use nalgebra::Isometry3;
+use openxr::{HandJointLocations, SpaceLocationFlags};
+
+use crate::util::posef_to_isometry;
+
+pub use openxr::{HandJoint, HAND_JOINT_COUNT};
+
+/// The name of each joint tracked by `XR_EXT_hand_tracking`, indexed by `HandJoint`. Used by `add_hand` to find
+/// the joints in a hand model.
+pub const HAND_JOINT_NAMES: [&str; HAND_JOINT_COUNT] = [
+    "Palm",
+    "Wrist",
+    "ThumbMetacarpal",
+    "ThumbProximal",
+    "ThumbDistal",
+    "ThumbTip",
+    "IndexMetacarpal",
+    "IndexProximal",
+    "IndexIntermediate",
+    "IndexDistal",
+    "IndexTip",
+    "MiddleMetacarpal",
+    "MiddleProximal",
+    "MiddleIntermediate",
+    "MiddleDistal",
+    "MiddleTip",
+    "RingMetacarpal",
+    "RingProximal",
+    "RingIntermediate",
+    "RingDistal",
+    "RingTip",
+    "LittleMetacarpal",
+    "LittleProximal",
+    "LittleIntermediate",
+    "LittleDistal",
+    "LittleTip",
+];
+
+/// Distance between the surfaces of the thumb and index fingertips, in meters, at which a pinch is fully closed
+const PINCH_CLOSED: f32 = 0.01;
+/// Distance between the surfaces of the thumb and index fingertips, in meters, at which a pinch is fully open
+const PINCH_OPEN: f32 = 0.05;
+/// Average distance from the fingertips to the palm, in meters, at which a fist is fully closed
+const GRAB_CLOSED: f32 = 0.045;
+/// Average distance from the fingertips to the palm, in meters, at which a hand is fully open
+const GRAB_OPEN: f32 = 0.09;
+
+/// The joints of a hand tracked with `XR_EXT_hand_tracking`, located in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HandJoints {
+    /// The pose of each joint, indexed by `HandJoint`. Each joint's -Z axis points towards the fingertips and its
+    /// +Y axis points out of the back of the hand.
+    pub poses: [Isometry3<f32>; HAND_JOINT_COUNT],
+    /// The radius of each joint, in meters
+    pub radii: [f32; HAND_JOINT_COUNT],
+}
+
+impl HandJoints {
+    /// Convert the joint locations reported by OpenXR, or `None` if the runtime has lost track of the hand
+    pub fn from_locations(locations: &HandJointLocations) -> Option<Self> {
+        let valid = SpaceLocationFlags::POSITION_VALID | SpaceLocationFlags::ORIENTATION_VALID;
+        if !locations.iter().all(|l| l.location_flags.contains(valid)) {
+            return None;
+        }
+
+        Some(Self {
+            poses: locations.map(|l| posef_to_isometry(l.pose)),
+            radii: locations.map(|l| l.radius),
+        })
+    }
+
+    /// The pose of a single joint
+    pub fn pose(&self, joint: HandJoint) -> Isometry3<f32> {
+        self.poses[joint.into_raw() as usize]
+    }
+
+    /// How closely the thumb and index fingertips are pinched together, from 0 to 1
+    pub fn pinch_strength(&self) -> f32 {
+        let distance = self.distance(HandJoint::THUMB_TIP, HandJoint::INDEX_TIP)
+            - self.radius(HandJoint::THUMB_TIP)
+            - self.radius(HandJoint::INDEX_TIP);
+        strength(distance, PINCH_CLOSED, PINCH_OPEN)
+    }
+
+    /// How tightly the middle, ring and little fingers are curled into a fist, from 0 to 1
+    pub fn grab_strength(&self) -> f32 {
+        let tips = [
+            HandJoint::MIDDLE_TIP,
+            HandJoint::RING_TIP,
+            HandJoint::LITTLE_TIP,
+        ];
+        let distance = tips
+            .iter()
+            .map(|tip| self.distance(*tip, HandJoint::PALM))
+            .sum::<f32>()
+            / tips.len() as f32;
+        strength(distance, GRAB_CLOSED, GRAB_OPEN)
+    }
+
+    /// The equivalent of a controller's grip value for this hand: either a pinch or a fist will grab things
+    pub fn grip_value(&self) -> f32 {
+        self.pinch_strength().max(self.grab_strength())
+    }
+
+    /// Move every joint by `isometry`, eg. when the stage moves
+    pub(crate) fn transform(&mut self, isometry: &Isometry3<f32>) {
+        for pose in self.poses.iter_mut() {
+            *pose = isometry * *pose;
+        }
+    }
+
+    fn distance(&self, a: HandJoint, b: HandJoint) -> f32 {
+        (self.pose(a).translation.vector - self.pose(b).translation.vector).norm()
+    }
+
+    fn radius(&self, joint: HandJoint) -> f32 {
+        self.radii[joint.into_raw() as usize]
+    }
+}
+
+/// 1 at `closed` or closer, 0 at `open` or further, and linear in between
+fn strength(distance: f32, closed: f32, open: f32) -> f32 {
+    (1. - (distance - closed) / (open - closed)).clamp(0., 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::vector;
+    use openxr::{HandJointLocation, Posef, Quaternionf, Vector3f};
+
+    /// A flat, open hand at the origin with its fingers pointing forward
+    fn open_hand() -> HandJointLocations {
+        let mut locations = [HandJointLocation {
+            location_flags: SpaceLocationFlags::POSITION_VALID
+                | SpaceLocationFlags::ORIENTATION_VALID,
+            pose: Posef::IDENTITY,
+            radius: 0.008,
+        }; HAND_JOINT_COUNT];
+
+        // Lay each finger out in a line, with the thumb off to the side.
+        let fingers = [
+            (HandJoint::THUMB_METACARPAL, 4, -0.1),
+            (HandJoint::INDEX_METACARPAL, 5, -0.03),
+            (HandJoint::MIDDLE_METACARPAL, 5, -0.01),
+            (HandJoint::RING_METACARPAL, 5, 0.01),
+            (HandJoint::LITTLE_METACARPAL, 5, 0.03),
+        ];
+        for (first, count, x) in fingers {
+            for joint in 0..count {
+                let index = first.into_raw() as usize + joint;
+                locations[index].pose.position = Vector3f {
+                    x,
+                    y: 0.,
+                    z: -0.03 * joint as f32 - 0.03,
+                };
+            }
+        }
+        locations
+    }
+
+    fn set_position(locations: &mut HandJointLocations, joint: HandJoint, x: f32, y: f32, z: f32) {
+        locations[joint.into_raw() as usize].pose = Posef {
+            position: Vector3f { x, y, z },
+            orientation: Quaternionf::IDENTITY,
+        };
+    }
+
+    #[test]
+    pub fn test_gestures() {
+        let mut locations = open_hand();
+        let joints = HandJoints::from_locations(&locations).unwrap();
+        assert_eq!(joints.pinch_strength(), 0.);
+        assert_eq!(joints.grab_strength(), 0.);
+
+        // Touch the thumb and index fingertips together
+        set_position(&mut locations, HandJoint::THUMB_TIP, -0.02, 0., -0.1);
+        set_position(&mut locations, HandJoint::INDEX_TIP, -0.01, 0., -0.1);
+        let joints = HandJoints::from_locations(&locations).unwrap();
+        assert_eq!(joints.pinch_strength(), 1.);
+        assert_eq!(joints.grip_value(), 1.);
+
+        // Curl the other fingers into the palm
+        let mut locations = open_hand();
+        set_position(&mut locations, HandJoint::MIDDLE_TIP, 0., -0.03, -0.02);
+        set_position(&mut locations, HandJoint::RING_TIP, 0.01, -0.03, -0.02);
+        set_position(&mut locations, HandJoint::LITTLE_TIP, 0.02, -0.03, -0.02);
+        let joints = HandJoints::from_locations(&locations).unwrap();
+        assert_eq!(joints.pinch_strength(), 0.);
+        assert_eq!(joints.grab_strength(), 1.);
+    }
+
+    #[test]
+    pub fn test_untracked_hand() {
+        let mut locations = open_hand();
+        locations[HandJoint::INDEX_TIP.into_raw() as usize].location_flags =
+            SpaceLocationFlags::EMPTY;
+        assert!(HandJoints::from_locations(&locations).is_none());
+
+        let mut joints = HandJoints::from_locations(&open_hand()).unwrap();
+        joints.transform(&Isometry3::translation(0., 1., 0.));
+        assert_eq!(
+            joints.pose(HandJoint::PALM).translation.vector,
+            vector![0., 1., 0.]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use ash::vk;
+use openxr::{
+    self as xr, Duration, Fovf, FrameState, Posef, Quaternionf, SessionState, SpaceLocation,
+    SpaceLocationFlags, Time, Vector3f, View,
+};
+
+use crate::{
+    components::hand::Handedness,
+    image::Image,
+    resources::{input_context::InputValue, VulkanContext},
+    swapchain::Swapchain,
+    COLOR_FORMAT, SWAPCHAIN_LENGTH, VIEW_COUNT,
+};
+
+use super::{HandJoints, GRAB_OBJECT, TRIGGER_PULLED};
+
+/// Default resolution of each eye in a headless session.
+const DEFAULT_RESOLUTION: vk::Extent2D = vk::Extent2D {
+    width: 512,
+    height: 512,
+};
+
+/// Headless sessions pretend to run at 90Hz
+const FRAME_PERIOD_NANOS: i64 = 11_111_111;
+
+/// Default distance between the eyes, in meters
+const DEFAULT_IPD: f32 = 0.064;
+
+/// The simulated state of a single controller in a headless session.
+#[derive(Debug, Clone)]
+pub struct HeadlessHand {
+    /// Pose reported for the grip space of this controller
+    pub grip_pose: Posef,
+    /// Pose reported for the aim space of this controller
+    pub aim_pose: Posef,
+    /// Value reported for the squeeze action
+    pub grip_value: f32,
+    /// Value reported for the trigger action
+    pub trigger_value: f32,
+    /// Values reported for any other actions in the `ActionMap`, by name
+    pub inputs: HashMap<String, InputValue>,
+    /// Joints reported by hand tracking, relative to the stage. `None` means the hand isn't being tracked.
+    pub joints: Option<HandJoints>,
+}
+
+impl HeadlessHand {
+    fn at(position: Vector3f) -> Self {
+        let pose = Posef {
+            orientation: Quaternionf::IDENTITY,
+            position,
+        };
+        Self {
+            grip_pose: pose,
+            aim_pose: pose,
+            grip_value: 0.,
+            trigger_value: 0.,
+            inputs: HashMap::new(),
+            joints: None,
+        }
+    }
+
+    /// The value reported for the action called `name`, if one has been set
+    pub fn input(&self, name: &str) -> Option<InputValue> {
+        match name {
+            GRAB_OBJECT => Some(InputValue::Float(self.grip_value)),
+            TRIGGER_PULLED => Some(InputValue::Float(self.trigger_value)),
+            _ => self.inputs.get(name).copied(),
+        }
+    }
+}
+
+/// Everything a headless session reports to the engine for a single frame.
+#[derive(Clone)]
+pub struct HeadlessFrame {
+    /// The left and right eye views
+    pub views: [View; 2],
+    /// The left controller
+    pub left_hand: HeadlessHand,
+    /// The right controller
+    pub right_hand: HeadlessHand,
+}
+
+impl HeadlessFrame {
+    /// Get the controller for the given hand
+    pub fn hand(&self, handedness: Handedness) -> &HeadlessHand {
+        match handedness {
+            Handedness::Left => &self.left_hand,
+            Handedness::Right => &self.right_hand,
+        }
+    }
+
+    /// Get a mutable reference to the controller for the given hand
+    pub fn hand_mut(&mut self, handedness: Handedness) -> &mut HeadlessHand {
+        match handedness {
+            Handedness::Left => &mut self.left_hand,
+            Handedness::Right => &mut self.right_hand,
+        }
+    }
+}
+
+impl Default for HeadlessFrame {
+    fn default() -> Self {
+        // Same starting positions as hotham-simulator: a standing player with their hands out in front of them.
+        let fov = Fovf {
+            angle_up: 45.0_f32.to_radians(),
+            angle_down: -45.0_f32.to_radians(),
+            angle_left: -45.0_f32.to_radians(),
+            angle_right: 45.0_f32.to_radians(),
+        };
+        let eye = |x| View {
+            pose: Posef {
+                orientation: Quaternionf::IDENTITY,
+                position: Vector3f { x, y: 1.6, z: 0. },
+            },
+            fov,
+        };
+
+        Self {
+            views: [eye(-DEFAULT_IPD / 2.), eye(DEFAULT_IPD / 2.)],
+            left_hand: HeadlessHand::at(Vector3f {
+                x: -0.2,
+                y: 1.4,
+                z: -0.5,
+            }),
+            right_hand: HeadlessHand::at(Vector3f {
+                x: 0.2,
+                y: 1.4,
+                z: -0.5,
+            }),
+        }
+    }
+}
+
+/// A haptic vibration applied during a headless session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeadlessHaptic {
+    /// Name of the haptic action that was applied
+    pub action: String,
+    /// Which controller the vibration was applied to
+    pub handedness: Handedness,
+    /// Amplitude of the vibration
+    pub amplitude: f32,
+    /// Frequency of the vibration
+    pub frequency: f32,
+    /// Duration of the vibration
+    pub duration: Duration,
+}
+
+/// A script is called at the start of every headless frame with the current frame number.
+pub type HeadlessScript = Box<dyn FnMut(u64, &mut HeadlessFrame)>;
+
+/// An in-process stand in for an OpenXR runtime.
+///
+/// Session state transitions, view poses and action states are all driven by the engine and an optional
+/// script rather than a headset, and the swapchain is made up of offscreen Vulkan images. This allows the
+/// whole frame loop to run somewhere without a display, such as CI.
+pub struct HeadlessXr {
+    /// The state that will be reported to the engine this frame
+    pub frame: HeadlessFrame,
+    /// Every haptic vibration that has been applied so far
+    pub haptics: Vec<HeadlessHaptic>,
+    resolution: vk::Extent2D,
+    script: Option<HeadlessScript>,
+    pending_states: VecDeque<SessionState>,
+    frame_number: u64,
+    swapchain_images: Vec<Image>,
+}
+
+impl HeadlessXr {
+    /// Create a new headless session. Like a real runtime, it will become `READY` as soon as it is polled.
+    pub fn new() -> Self {
+        Self {
+            frame: Default::default(),
+            haptics: Vec::new(),
+            resolution: DEFAULT_RESOLUTION,
+            script: None,
+            pending_states: vec![SessionState::READY].into(),
+            frame_number: 0,
+            swapchain_images: Vec::new(),
+        }
+    }
+
+    /// Set the resolution of each eye
+    pub fn resolution(mut self, resolution: vk::Extent2D) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Set a script to update the frame state before every frame
+    pub fn script(mut self, script: impl FnMut(u64, &mut HeadlessFrame) + 'static) -> Self {
+        self.script = Some(Box::new(script));
+        self
+    }
+
+    /// Queue a session state transition. Transitions are delivered one per call to `Engine::update`.
+    pub fn queue_session_state(&mut self, state: SessionState) {
+        self.pending_states.push_back(state);
+    }
+
+    /// Ask the session to wind down, as if the user had quit from the headset
+    pub fn request_exit(&mut self) {
+        self.pending_states.extend([
+            SessionState::VISIBLE,
+            SessionState::SYNCHRONIZED,
+            SessionState::STOPPING,
+        ]);
+    }
+
+    /// The number of frames that have begun so far
+    pub fn frame_number(&self) -> u64 {
+        self.frame_number
+    }
+
+    /// The offscreen images that make up the swapchain
+    pub fn swapchain_images(&self) -> &[Image] {
+        &self.swapchain_images
+    }
+
+    pub(crate) fn resolution_extent(&self) -> vk::Extent2D {
+        self.resolution
+    }
+
+    pub(crate) fn create_swapchain_images(&mut self, vulkan_context: &VulkanContext) -> Result<()> {
+        self.swapchain_images = (0..SWAPCHAIN_LENGTH)
+            .map(|_| {
+                vulkan_context.create_image(
+                    COLOR_FORMAT,
+                    &self.resolution,
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC,
+                    VIEW_COUNT,
+                    1,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    pub(crate) fn swapchain(&self) -> Swapchain {
+        Swapchain {
+            resolution: self.resolution,
+            images: self.swapchain_images.iter().map(|i| i.handle).collect(),
+            fragment_density_maps: None,
+        }
+    }
+
+    pub(crate) fn poll_session_state(&mut self) -> Option<SessionState> {
+        self.pending_states.pop_front()
+    }
+
+    pub(crate) fn begin_session(&mut self) {
+        self.pending_states.extend([
+            SessionState::SYNCHRONIZED,
+            SessionState::VISIBLE,
+            SessionState::FOCUSED,
+        ]);
+    }
+
+    pub(crate) fn end_session(&mut self) {
+        self.pending_states
+            .extend([SessionState::IDLE, SessionState::EXITING]);
+    }
+
+    /// Advance to the next frame, returning the frame state and the swapchain image to render into
+    pub(crate) fn begin_frame(&mut self, session_state: SessionState) -> (FrameState, usize) {
+        let image_count = self.swapchain_images.len().max(1) as u64;
+        let frame_index = (self.frame_number % image_count) as usize;
+
+        self.frame_number += 1;
+        if let Some(script) = self.script.as_mut() {
+            script(self.frame_number, &mut self.frame);
+        }
+
+        let frame_state = FrameState {
+            predicted_display_time: Time::from_nanos(self.frame_number as i64 * FRAME_PERIOD_NANOS),
+            predicted_display_period: Duration::from_nanos(FRAME_PERIOD_NANOS),
+            should_render: matches!(session_state, SessionState::VISIBLE | SessionState::FOCUSED),
+        };
+        (frame_state, frame_index)
+    }
+
+    pub(crate) fn locate(pose: Posef) -> SpaceLocation {
+        SpaceLocation {
+            location_flags: SpaceLocationFlags::POSITION_VALID
+                | SpaceLocationFlags::ORIENTATION_VALID
+                | SpaceLocationFlags::POSITION_TRACKED
+                | SpaceLocationFlags::ORIENTATION_TRACKED,
+            pose,
+        }
+    }
+
+    pub(crate) fn views(&self) -> Vec<View> {
+        self.frame.views.to_vec()
+    }
+
+    pub(crate) fn view_state_flags() -> xr::ViewStateFlags {
+        xr::ViewStateFlags::POSITION_VALID
+            | xr::ViewStateFlags::ORIENTATION_VALID
+            | xr::ViewStateFlags::POSITION_TRACKED
+            | xr::ViewStateFlags::ORIENTATION_TRACKED
+    }
+}
+
+impl Default for HeadlessXr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_session_lifecycle() {
+        let mut headless = HeadlessXr::new();
+        assert_eq!(headless.poll_session_state(), Some(SessionState::READY));
+        assert_eq!(headless.poll_session_state(), None);
+
+        headless.begin_session();
+        assert_eq!(
+            headless.pending_states.iter().copied().collect::<Vec<_>>(),
+            [
+                SessionState::SYNCHRONIZED,
+                SessionState::VISIBLE,
+                SessionState::FOCUSED
+            ]
+        );
+        headless.pending_states.clear();
+
+        headless.request_exit();
+        headless.pending_states.clear();
+        headless.end_session();
+        assert_eq!(headless.poll_session_state(), Some(SessionState::IDLE));
+        assert_eq!(headless.poll_session_state(), Some(SessionState::EXITING));
+    }
+
+    #[test]
+    pub fn test_script_runs_each_frame() {
+        let mut headless = HeadlessXr::new().script(|frame_number, frame| {
+            frame.right_hand.grip_value = frame_number as f32 / 10.;
+        });
+
+        let (frame_state, _) = headless.begin_frame(SessionState::SYNCHRONIZED);
+        assert!(!frame_state.should_render);
+        assert_eq!(headless.frame.right_hand.grip_value, 0.1);
+
+        let (frame_state, _) = headless.begin_frame(SessionState::FOCUSED);
+        assert!(frame_state.should_render);
+        assert_eq!(
+            frame_state.predicted_display_time.as_nanos(),
+            2 * FRAME_PERIOD_NANOS
+        );
+        assert_eq!(headless.frame.right_hand.grip_value, 0.2);
+        assert_eq!(headless.frame_number(), 2);
+    }
+}
@@ -29,7 +29,7 @@ impl Default for SceneData {
 #[derive(Deserialize, Serialize, Clone, Debug, Copy)]
 #[repr(C)]
 pub struct SceneParams {
-    /// Direction of the global light
+    /// Direction of the light used when there are no `Light`s in the scene
     pub light_direction: Vector4<f32>,
     /// Level of exposure
     pub exposure: f32,
@@ -65,3 +65,62 @@ impl Default for SceneParams {
         }
     }
 }
+
+/// The most lights that can affect the scene at once. Any more are ignored.
+pub const MAX_LIGHTS: usize = 16;
+
+/// A `Light`, packed for the fragment shader. See `pbr.frag` for more information
+#[derive(Deserialize, Serialize, Clone, Debug, Copy, Default, PartialEq)]
+#[repr(C)]
+pub struct ShaderLight {
+    /// Position of the light, with the type of light in `w` (0 = directional, 1 = point, 2 = spot)
+    pub position: Vector4<f32>,
+    /// Direction the light shines in, with its range in `w` (0 = infinite)
+    pub direction: Vector4<f32>,
+    /// Color of the light, with its intensity in `w`
+    pub color: Vector4<f32>,
+    /// Scale and offset applied to the cosine of the angle from a spot light's direction to fade it out
+    pub cone: Vector4<f32>,
+}
+
+/// The lights in the scene. Gathered each frame by `lighting_system` and sent to the fragment shader
+#[derive(Clone, Debug, Copy)]
+#[repr(C)]
+pub struct SceneLights {
+    /// How many of `lights` are in use
+    pub count: u32,
+    /// Index of the light that casts shadows, or -1 if no light does
+    pub shadow_light: i32,
+    _padding: [u32; 2],
+    /// Transforms world space into the shadow map's clip space
+    pub shadow_matrix: Matrix4<f32>,
+    /// The lights themselves
+    pub lights: [ShaderLight; MAX_LIGHTS],
+}
+
+impl SceneLights {
+    /// Gather up to `MAX_LIGHTS` lights. The first directional light casts shadows.
+    pub fn new(lights: impl IntoIterator<Item = ShaderLight>) -> Self {
+        let mut scene_lights = Self::default();
+        for (slot, light) in scene_lights.lights.iter_mut().zip(lights) {
+            if scene_lights.shadow_light < 0 && light.position.w == 0. {
+                scene_lights.shadow_light = scene_lights.count as _;
+            }
+            *slot = light;
+            scene_lights.count += 1;
+        }
+        scene_lights
+    }
+}
+
+impl Default for SceneLights {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            shadow_light: -1,
+            _padding: [0; 2],
+            shadow_matrix: Matrix4::identity(),
+            lights: [Default::default(); MAX_LIGHTS],
+        }
+    }
+}
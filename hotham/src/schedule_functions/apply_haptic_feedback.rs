@@ -1,6 +1,9 @@
-use openxr::{Duration, HapticVibration};
+use openxr::Duration;
 
-use crate::resources::{HapticContext, XrContext};
+use crate::{
+    components::hand::Handedness,
+    resources::{HapticContext, XrContext},
+};
 static HAPTIC_FREQUENCY: f32 = 400.;
 static HAPTIC_DURATION: i64 = 1e+8 as _; // 100ms
 
@@ -18,17 +21,12 @@ static HAPTIC_DURATION: i64 = 1e+8 as _; // 100ms
 pub fn apply_haptic_feedback(xr_context: &mut XrContext, haptic_context: &mut HapticContext) {
     let haptic_duration = Duration::from_nanos(HAPTIC_DURATION);
     if haptic_context.left_hand_amplitude_this_frame != 0. {
-        let event = HapticVibration::new()
-            .amplitude(haptic_context.left_hand_amplitude_this_frame)
-            .frequency(HAPTIC_FREQUENCY)
-            .duration(haptic_duration);
-
         xr_context
-            .haptic_feedback_action
-            .apply_feedback(
-                &xr_context.session,
-                xr_context.left_hand_subaction_path,
-                &event,
+            .apply_haptic_feedback(
+                Handedness::Left,
+                haptic_context.left_hand_amplitude_this_frame,
+                HAPTIC_FREQUENCY,
+                haptic_duration,
             )
             .expect("Unable to apply haptic feedback!");
 
@@ -37,17 +35,12 @@ pub fn apply_haptic_feedback(xr_context: &mut XrContext, haptic_context: &mut Ha
     }
 
     if haptic_context.right_hand_amplitude_this_frame != 0. {
-        let event = HapticVibration::new()
-            .amplitude(haptic_context.right_hand_amplitude_this_frame)
-            .frequency(HAPTIC_FREQUENCY)
-            .duration(haptic_duration);
-
         xr_context
-            .haptic_feedback_action
-            .apply_feedback(
-                &xr_context.session,
-                xr_context.right_hand_subaction_path,
-                &event,
+            .apply_haptic_feedback(
+                Handedness::Right,
+                haptic_context.right_hand_amplitude_this_frame,
+                HAPTIC_FREQUENCY,
+                haptic_duration,
             )
             .expect("Unable to apply haptic feedback!");
 
@@ -56,7 +49,6 @@ pub fn apply_haptic_feedback(xr_context: &mut XrContext, haptic_context: &mut Ha
     }
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -64,9 +56,16 @@ mod tests {
     /// Simple smoke test.
     #[test]
     pub fn apply_haptic_feedback_test() {
-        let (mut xr_context, _) = XrContext::new().unwrap();
+        let (mut xr_context, _) = XrContext::testing().unwrap();
         let mut haptic_context = HapticContext::default();
+        haptic_context.right_hand_amplitude_this_frame = 0.5;
 
         apply_haptic_feedback(&mut xr_context, &mut haptic_context);
+
+        let haptics = &xr_context.headless().unwrap().haptics;
+        assert_eq!(haptics.len(), 1);
+        assert_eq!(haptics[0].handedness, Handedness::Right);
+        assert_eq!(haptics[0].amplitude, 0.5);
+        assert_eq!(haptic_context.right_hand_amplitude_this_frame, 0.);
     }
 }
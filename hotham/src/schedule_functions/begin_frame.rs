@@ -1,9 +1,4 @@
-use openxr::ActiveActionSet;
-
-use crate::{
-    resources::{xr_context::XrContext, RenderContext, VulkanContext},
-    VIEW_TYPE,
-};
+use crate::resources::{xr_context::XrContext, RenderContext, VulkanContext};
 
 /// Begin a frame
 /// Make sure to call this BEFORE beginning any renderpasses.
@@ -12,25 +7,12 @@ pub fn begin_frame(
     vulkan_context: &VulkanContext,
     render_context: &RenderContext,
 ) {
-    let active_action_set = ActiveActionSet::new(&xr_context.action_set);
-    xr_context
-        .session
-        .sync_actions(&[active_action_set])
-        .unwrap();
+    xr_context.sync_actions().unwrap();
 
     // Wait for a frame to become available from the runtime
     xr_context.begin_frame().unwrap();
-
-    let (view_state_flags, views) = xr_context
-        .session
-        .locate_views(
-            VIEW_TYPE,
-            xr_context.frame_state.predicted_display_time,
-            &xr_context.reference_space,
-        )
-        .unwrap();
-    xr_context.views = views;
-    xr_context.view_state_flags = view_state_flags;
+    xr_context.locate_views().unwrap();
+    xr_context.update_input().unwrap();
 
     // If the shouldRender flag is set, start rendering
     if xr_context.frame_state.should_render {
@@ -42,21 +24,24 @@ pub fn begin_frame(
     }
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
+    use openxr::SessionState;
+
     use crate::resources::{RenderContext, XrContext};
 
     use super::begin_frame;
 
     #[test]
-
     pub fn test_begin_frame() {
-        let (mut xr_context, vulkan_context) = XrContext::new().unwrap();
+        let (mut xr_context, vulkan_context) = XrContext::testing().unwrap();
         let render_context = RenderContext::new(&vulkan_context, &xr_context).unwrap();
+        xr_context.session_state = SessionState::FOCUSED;
         xr_context.frame_index = 100;
 
         begin_frame(&mut xr_context, &vulkan_context, &render_context);
         assert_eq!(xr_context.frame_index, 0);
+        assert!(xr_context.frame_state.should_render);
+        assert_eq!(xr_context.views.len(), 2);
     }
 }
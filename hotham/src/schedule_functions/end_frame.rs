@@ -18,7 +18,6 @@ pub fn end_frame(
     xr_context.end_frame().unwrap();
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
 
@@ -27,11 +26,13 @@ mod tests {
         resources::{RenderContext, XrContext},
         schedule_functions::begin_frame,
     };
+    use openxr::SessionState;
 
     #[test]
     pub fn test_end_frame() {
-        let (mut xr_context, vulkan_context) = XrContext::new().unwrap();
+        let (mut xr_context, vulkan_context) = XrContext::testing().unwrap();
         let mut render_context = RenderContext::new(&vulkan_context, &xr_context).unwrap();
+        xr_context.session_state = SessionState::FOCUSED;
         begin_frame(&mut xr_context, &vulkan_context, &render_context);
         end_frame(&mut xr_context, &vulkan_context, &mut render_context);
     }
@@ -30,6 +30,28 @@ layout (set = 0, binding = 2) uniform samplerCube samplerIrradiance;
 layout (set = 0, binding = 3) uniform samplerCube prefilteredMap;
 layout (set = 0, binding = 4) uniform sampler2D samplerBRDFLUT;
 
+// Punctual lights, see KHR_lights_punctual
+#define MAX_LIGHTS 16
+#define LIGHT_DIRECTIONAL 0
+#define LIGHT_POINT 1
+#define LIGHT_SPOT 2
+
+struct Light {
+	vec4 position;	// xyz = position, w = type
+	vec4 direction;	// xyz = direction the light travels in, w = range (0 = infinite)
+	vec4 color;		// rgb = color, a = intensity
+	vec4 cone;		// x = angle scale, y = angle offset (spot lights only)
+};
+
+layout (set = 0, binding = 5) uniform UBOLights {
+	uint count;
+	int shadowLight;		// Index of the light that casts shadows, or -1
+	mat4 shadowMatrix;		// World space to shadow map clip space
+	Light lights[MAX_LIGHTS];
+} uboLights;
+
+layout (set = 0, binding = 6) uniform sampler2DShadow shadowMap;
+
 // Material bindings
 layout (set = 1, binding = 0) uniform sampler2D colorMap;
 layout (set = 1, binding = 1) uniform sampler2D physicalDescriptorMap;
@@ -81,6 +103,7 @@ const float c_MinRoughness = 0.04;
 const float PBR_WORKFLOW_METALLIC_ROUGHNESS = 0.0;
 const float PBR_WORKFLOW_SPECULAR_GLOSINESS = 1.0f;
 const float PBR_WORKFLOW_UNLIT = 2.0f;
+const float ALPHA_MODE_BLEND = 2.0f;
 #define MANUAL_SRGB 1
 
 vec3 Uncharted2Tonemap(vec3 color)
@@ -194,6 +217,43 @@ float microfacetDistribution(PBRInfo pbrInputs)
 	return roughnessSq / (M_PI * f * f);
 }
 
+// Window the inverse square falloff of a light so that it reaches zero at its range
+float rangeAttenuation(float range, float distance)
+{
+	if (range <= 0.0) {
+		return 1.0 / max(distance * distance, 0.0001);
+	}
+	return clamp(1.0 - pow(distance / range, 4.0), 0.0, 1.0) / max(distance * distance, 0.0001);
+}
+
+// Fade a spot light out between its inner and outer cone angles
+float spotAttenuation(Light light, vec3 pointToLight)
+{
+	float cd = dot(normalize(light.direction.xyz), normalize(-pointToLight));
+	float attenuation = clamp(cd * light.cone.x + light.cone.y, 0.0, 1.0);
+	return attenuation * attenuation;
+}
+
+// How much of the shadow casting light reaches this point, using 3x3 percentage closer filtering
+float shadowFactor(vec3 worldPos)
+{
+	vec4 shadowCoord = uboLights.shadowMatrix * vec4(worldPos, 1.0);
+	shadowCoord.xyz /= shadowCoord.w;
+	if (shadowCoord.z <= 0.0 || shadowCoord.z >= 1.0) {
+		return 1.0;
+	}
+
+	vec2 uv = shadowCoord.xy * 0.5 + 0.5;
+	vec2 texelSize = 1.0 / vec2(textureSize(shadowMap, 0));
+	float lit = 0.0;
+	for (int x = -1; x <= 1; x++) {
+		for (int y = -1; y <= 1; y++) {
+			lit += texture(shadowMap, vec3(uv + vec2(x, y) * texelSize, shadowCoord.z));
+		}
+	}
+	return lit / 9.0;
+}
+
 // Gets metallic factor from specular glossiness workflow inputs 
 float convertMetallic(vec3 diffuse, vec3 specular, float maxSpecular) {
 	float perceivedDiffuse = sqrt(0.299 * diffuse.r * diffuse.r + 0.587 * diffuse.g * diffuse.g + 0.114 * diffuse.b * diffuse.b);
@@ -297,23 +357,17 @@ void main()
 
 	vec3 n = (material.normalTextureSet > -1) ? getNormal() : normalize(inNormal);
 	vec3 v = normalize(ubo.camPos[gl_ViewIndex].xyz - inWorldPos);    // Vector from surface point to camera
-	vec3 l = normalize(uboParams.lightDir.xyz);     // Vector from surface point to light
-	vec3 h = normalize(l+v);                        // Half vector between both l and v
 	vec3 reflection = -normalize(reflect(v, n));
 	reflection.y *= -1.0f;
 
-	float NdotL = clamp(dot(n, l), 0.001, 1.0);
 	float NdotV = clamp(abs(dot(n, v)), 0.001, 1.0);
-	float NdotH = clamp(dot(n, h), 0.0, 1.0);
-	float LdotH = clamp(dot(l, h), 0.0, 1.0);
-	float VdotH = clamp(dot(v, h), 0.0, 1.0);
 
 	PBRInfo pbrInputs = PBRInfo(
-		NdotL,
+		0.0,
 		NdotV,
-		NdotH,
-		LdotH,
-		VdotH,
+		0.0,
+		0.0,
+		0.0,
 		perceptualRoughness,
 		metallic,
 		specularEnvironmentR0,
@@ -323,18 +377,67 @@ void main()
 		specularColor
 	);
 
-	// Calculate the shading terms for the microfacet specular shading model
-	vec3 F = specularReflection(pbrInputs);
-	float G = geometricOcclusion(pbrInputs);
-	float D = microfacetDistribution(pbrInputs);
-
-	const vec3 u_LightColor = vec3(1.0);
+	// Without any lights, fall back to the global light in the scene parameters
+	uint lightCount = uboLights.count == 0 ? 1 : min(uboLights.count, MAX_LIGHTS);
+
+	vec3 color = vec3(0.0);
+	vec3 diffuseContrib = vec3(0.0);
+	vec3 specContrib = vec3(0.0);
+	vec3 F = vec3(0.0);
+	float G = 0.0;
+	float D = 0.0;
+
+	for (uint i = 0; i < lightCount; i++) {
+		vec3 l;							// Vector from surface point to light
+		vec3 radiance;					// Light arriving at the surface point
+		if (uboLights.count == 0) {
+			l = normalize(uboParams.lightDir.xyz);
+			radiance = vec3(1.0);
+		} else {
+			Light light = uboLights.lights[i];
+			int type = int(light.position.w);
+			radiance = light.color.rgb * light.color.a;
+			if (type == LIGHT_DIRECTIONAL) {
+				l = -normalize(light.direction.xyz);
+			} else {
+				vec3 pointToLight = light.position.xyz - inWorldPos;
+				l = normalize(pointToLight);
+				radiance *= rangeAttenuation(light.direction.w, length(pointToLight));
+				if (type == LIGHT_SPOT) {
+					radiance *= spotAttenuation(light, pointToLight);
+				}
+			}
+			if (int(i) == uboLights.shadowLight) {
+				radiance *= shadowFactor(inWorldPos);
+			}
+		}
 
-	// Calculation of analytical lighting contribution
-	vec3 diffuseContrib = (1.0 - F) * diffuse(pbrInputs);
-	vec3 specContrib = F * G * D / (4.0 * NdotL * NdotV);
-	// Obtain final intensity as reflectance (BRDF) scaled by the energy of the light (cosine law)
-	vec3 color = NdotL * u_LightColor * (diffuseContrib + specContrib);
+		vec3 h = normalize(l+v);                        // Half vector between both l and v
+		pbrInputs.NdotL = clamp(dot(n, l), 0.001, 1.0);
+		pbrInputs.NdotH = clamp(dot(n, h), 0.0, 1.0);
+		pbrInputs.LdotH = clamp(dot(l, h), 0.0, 1.0);
+		pbrInputs.VdotH = clamp(dot(v, h), 0.0, 1.0);
+
+		// Calculate the shading terms for the microfacet specular shading model
+		vec3 lightF = specularReflection(pbrInputs);
+		float lightG = geometricOcclusion(pbrInputs);
+		float lightD = microfacetDistribution(pbrInputs);
+
+		// Calculation of analytical lighting contribution
+		vec3 lightDiffuse = (1.0 - lightF) * diffuse(pbrInputs);
+		vec3 lightSpec = lightF * lightG * lightD / (4.0 * pbrInputs.NdotL * NdotV);
+		// Obtain final intensity as reflectance (BRDF) scaled by the energy of the light (cosine law)
+		color += pbrInputs.NdotL * radiance * (lightDiffuse + lightSpec);
+
+		// Keep the terms for the debug views
+		diffuseContrib += lightDiffuse;
+		specContrib += lightSpec;
+		if (i == 0) {
+			F = lightF;
+			G = lightG;
+			D = lightD;
+		}
+	}
 
 	// Calculate lighting contribution from image based lighting source (IBL)
 	color += getIBLContribution(pbrInputs, n, reflection) * uboParams.scaleIBLAmbient;
@@ -357,7 +460,9 @@ void main()
 
 	if (material.workflow == PBR_WORKFLOW_UNLIT) {
 		outColor = (texture(colorMap, material.baseColorTextureSet == 0 ? inUV0 : inUV1) * material.baseColorFactor);
-		outColor.a = 1;
+		if (material.alphaMask != ALPHA_MODE_BLEND) {
+			outColor.a = 1;
+		}
 	}
 
 	// vec3 N = normalize(inNormal);
@@ -18,14 +18,48 @@ layout (set = 0, binding = 0) uniform UBO  {
 	vec4 camPos[2];
 } ubo;
 
-#define MAX_NUM_JOINTS 128
+#define NO_JOINTS 0xFFFFFFFFu
+#define NO_MORPH_WEIGHTS 0xFFFFFFFFu
 
 layout (set = 2, binding = 0) uniform UBONode {
-	mat4 matrix;
-	mat4 jointMatrix[MAX_NUM_JOINTS];
 	float jointCount;
+	float morphTargetCount;
+	uint firstJoint;
+	uint firstMorphWeight;
 } node;
 
+// How far each morph target moves each vertex, see MorphDelta
+struct MorphDelta {
+	vec4 position;
+	vec4 normal;
+};
+
+layout (std430, set = 2, binding = 1) readonly buffer MorphTargets {
+	MorphDelta deltas[];
+} morphTargets;
+
+// Where this primitive's deltas are, see MorphPushConstant. The material comes first, see pbr.frag
+layout (push_constant) uniform Morph {
+	layout (offset = 104) uint baseVertex;
+	uint firstDelta;
+	uint targetCount;
+} morph;
+
+// Transform of each instance being drawn, see rendering_system
+layout (std430, set = 0, binding = 8) readonly buffer Instances {
+	mat4 transforms[];
+} instances;
+
+// Joint matrices of every skinned mesh, in world space, see skinning_system
+layout (std430, set = 0, binding = 10) readonly buffer Joints {
+	mat4 matrices[];
+} joints;
+
+// Morph target weights of every mesh with morph targets, see morph_targets_system
+layout (std430, set = 0, binding = 11) readonly buffer MorphWeights {
+	float weights[];
+} morphWeights;
+
 layout (location = 0) out vec3 outWorldPos;
 layout (location = 1) out vec3 outNormal;
 layout (location = 2) out vec2 outUV0;
@@ -38,20 +72,33 @@ out gl_PerVertex
 
 void main() 
 {
+	mat4 model = instances.transforms[gl_InstanceIndex];
+	vec3 position = inPos;
+	vec3 normal = inNormal;
+	if (morph.targetCount > 0u && node.firstMorphWeight != NO_MORPH_WEIGHTS) {
+		// Each vertex has a delta for each morph target, next to each other
+		uint first = morph.firstDelta + (gl_VertexIndex - morph.baseVertex) * morph.targetCount;
+		for (uint i = 0; i < morph.targetCount; i++) {
+			float weight = morphWeights.weights[node.firstMorphWeight + i];
+			position += weight * morphTargets.deltas[first + i].position.xyz;
+			normal += weight * morphTargets.deltas[first + i].normal.xyz;
+		}
+	}
+
 	vec4 locPos;
-	if (node.jointCount > 0.0) {
-		// Mesh is skinned
+	if (node.jointCount > 0.0 && node.firstJoint != NO_JOINTS) {
+		// Mesh is skinned. The joints are already in world space, so the mesh's own transform isn't used.
 		mat4 skinMat = 
-			inWeight0.x * node.jointMatrix[int(inJoint0.x)] +
-			inWeight0.y * node.jointMatrix[int(inJoint0.y)] +
-			inWeight0.z * node.jointMatrix[int(inJoint0.z)] +
-			inWeight0.w * node.jointMatrix[int(inJoint0.w)];
+			inWeight0.x * joints.matrices[node.firstJoint + uint(inJoint0.x)] +
+			inWeight0.y * joints.matrices[node.firstJoint + uint(inJoint0.y)] +
+			inWeight0.z * joints.matrices[node.firstJoint + uint(inJoint0.z)] +
+			inWeight0.w * joints.matrices[node.firstJoint + uint(inJoint0.w)];
 
-		locPos = node.matrix * skinMat * vec4(inPos, 1.0);
-		outNormal = normalize(transpose(inverse(mat3(node.matrix * skinMat))) * inNormal);
+		locPos = skinMat * vec4(position, 1.0);
+		outNormal = normalize(transpose(inverse(mat3(skinMat))) * normal);
 	} else {
-		locPos = node.matrix * vec4(inPos, 1.0);
-		outNormal = normalize(transpose(inverse(mat3(node.matrix))) * inNormal);
+		locPos = model * vec4(position, 1.0);
+		outNormal = normalize(transpose(inverse(mat3(model))) * normal);
 	}
 
 	if (length(inNormal) == 0.0) {
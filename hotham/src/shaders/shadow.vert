@@ -0,0 +1,101 @@
+// Renders meshes from the point of view of the main directional light, into the shadow map.
+#version 450
+#extension GL_ARB_separate_shader_objects : enable
+
+layout (location = 0) in vec3 inPos;
+layout (location = 4) in vec4 inJoint0;
+layout (location = 5) in vec4 inWeight0;
+
+// Punctual lights, see pbr.frag
+#define MAX_LIGHTS 16
+
+struct Light {
+	vec4 position;
+	vec4 direction;
+	vec4 color;
+	vec4 cone;
+};
+
+layout (set = 0, binding = 5) uniform UBOLights {
+	uint count;
+	int shadowLight;
+	mat4 shadowMatrix;
+	Light lights[MAX_LIGHTS];
+} uboLights;
+
+#define NO_JOINTS 0xFFFFFFFFu
+#define NO_MORPH_WEIGHTS 0xFFFFFFFFu
+
+layout (set = 2, binding = 0) uniform UBONode {
+	float jointCount;
+	float morphTargetCount;
+	uint firstJoint;
+	uint firstMorphWeight;
+} node;
+
+// How far each morph target moves each vertex, see MorphDelta
+struct MorphDelta {
+	vec4 position;
+	vec4 normal;
+};
+
+layout (std430, set = 2, binding = 1) readonly buffer MorphTargets {
+	MorphDelta deltas[];
+} morphTargets;
+
+// Where this primitive's deltas are, see MorphPushConstant. The material comes first, see pbr.frag
+layout (push_constant) uniform Morph {
+	layout (offset = 104) uint baseVertex;
+	uint firstDelta;
+	uint targetCount;
+} morph;
+
+// Transform of each instance being drawn, see shadows_system
+layout (std430, set = 0, binding = 9) readonly buffer Instances {
+	mat4 transforms[];
+} instances;
+
+// Joint matrices of every skinned mesh, see pbr.vert
+layout (std430, set = 0, binding = 10) readonly buffer Joints {
+	mat4 matrices[];
+} joints;
+
+// Morph target weights of every mesh with morph targets, see pbr.vert
+layout (std430, set = 0, binding = 11) readonly buffer MorphWeights {
+	float weights[];
+} morphWeights;
+
+out gl_PerVertex
+{
+	vec4 gl_Position;
+};
+
+void main()
+{
+	mat4 model = instances.transforms[gl_InstanceIndex];
+	vec3 position = inPos;
+	if (morph.targetCount > 0u && node.firstMorphWeight != NO_MORPH_WEIGHTS) {
+		// Each vertex has a delta for each morph target, see pbr.vert
+		uint first = morph.firstDelta + (gl_VertexIndex - morph.baseVertex) * morph.targetCount;
+		for (uint i = 0; i < morph.targetCount; i++) {
+			float weight = morphWeights.weights[node.firstMorphWeight + i];
+			position += weight * morphTargets.deltas[first + i].position.xyz;
+		}
+	}
+
+	vec4 locPos;
+	if (node.jointCount > 0.0 && node.firstJoint != NO_JOINTS) {
+		// Mesh is skinned, in world space
+		mat4 skinMat =
+			inWeight0.x * joints.matrices[node.firstJoint + uint(inJoint0.x)] +
+			inWeight0.y * joints.matrices[node.firstJoint + uint(inJoint0.y)] +
+			inWeight0.z * joints.matrices[node.firstJoint + uint(inJoint0.z)] +
+			inWeight0.w * joints.matrices[node.firstJoint + uint(inJoint0.w)];
+
+		locPos = skinMat * vec4(position, 1.0);
+	} else {
+		locPos = model * vec4(position, 1.0);
+	}
+
+	gl_Position = uboLights.shadowMatrix * vec4(locPos.xyz / locPos.w, 1.0);
+}
@@ -0,0 +1,53 @@
+// Draws the environment behind everything else in the scene.
+#version 450
+
+layout (location = 0) in vec3 inDirection;
+
+layout (set = 0, binding = 1) uniform UBOParams {
+	vec4 lightDir;
+	float exposure;
+	float gamma;
+	float prefilteredCubeMipLevels;
+	float scaleIBLAmbient;
+	float debugViewInputs;
+	float debugViewEquation;
+} uboParams;
+
+layout (set = 0, binding = 7) uniform samplerCube skybox;
+
+layout (location = 0) out vec4 outColor;
+
+// Tone mapping and color space conversion, the same as the image based lighting in pbr.frag
+vec3 Uncharted2Tonemap(vec3 color)
+{
+	float A = 0.15;
+	float B = 0.50;
+	float C = 0.10;
+	float D = 0.20;
+	float E = 0.02;
+	float F = 0.30;
+	return ((color*(A*color+C*B)+D*E)/(color*(A*color+B)+D*F))-E/F;
+}
+
+vec3 tonemap(vec3 color)
+{
+	vec3 outcol = Uncharted2Tonemap(color * uboParams.exposure);
+	outcol = outcol * (1.0f / Uncharted2Tonemap(vec3(11.2f)));
+	return pow(outcol, vec3(1.0f / uboParams.gamma));
+}
+
+vec3 SRGBtoLINEAR(vec3 srgbIn)
+{
+	vec3 bLess = step(vec3(0.04045), srgbIn);
+	return mix(srgbIn / vec3(12.92), pow((srgbIn + vec3(0.055)) / vec3(1.055), vec3(2.4)), bLess);
+}
+
+void main()
+{
+	// Environment cubemaps are stored upside down, see the reflection vector in pbr.frag
+	vec3 direction = normalize(inDirection);
+	direction.y *= -1.0;
+
+	vec3 color = textureLod(skybox, direction, 0.0).rgb;
+	outColor = vec4(SRGBtoLINEAR(tonemap(color)), 1.0);
+}
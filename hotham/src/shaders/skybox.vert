@@ -0,0 +1,28 @@
+// Draws the environment behind everything else in the scene, with one triangle that covers the whole screen.
+#version 450
+#extension GL_EXT_multiview : enable
+
+layout (set = 0, binding = 0) uniform UBO {
+	mat4 projection[2];
+	mat4 view[2];
+	vec4 camPos[2];
+} ubo;
+
+layout (location = 0) out vec3 outDirection;
+
+out gl_PerVertex
+{
+	vec4 gl_Position;
+};
+
+void main()
+{
+	vec2 position = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2) * 2.0 - 1.0;
+	gl_Position = vec4(position, 1.0, 1.0);
+
+	// Work out which way this point on the far plane is from the eye, ignoring where the eye is
+	mat4 view = ubo.view[gl_ViewIndex];
+	view[3] = vec4(0.0, 0.0, 0.0, 1.0);
+	vec4 direction = inverse(ubo.projection[gl_ViewIndex] * view) * vec4(position, 1.0, 1.0);
+	outDirection = direction.xyz / direction.w;
+}
@@ -0,0 +1,377 @@
+use std::mem::size_of;
+
+use anyhow::Result;
+use ash::vk::{self, Handle};
+use nalgebra::{Matrix4, Point3, Translation3, UnitQuaternion, Vector3};
+
+use crate::{
+    image::Image,
+    resources::{render_context::create_shader, VulkanContext},
+    vertex::Vertex,
+    DEPTH_FORMAT,
+};
+
+/// Width and height of the shadow map, in texels
+pub const SHADOW_MAP_RESOLUTION: u32 = 2048;
+
+/// Depth texture rendered from the point of view of the scene's main directional light, before the PBR render pass.
+/// `pbr.frag` samples it to work out which parts of the scene are in shadow.
+#[derive(Debug)]
+pub struct ShadowMap {
+    pub image: Image,
+    pub sampler: vk::Sampler,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub pipeline: vk::Pipeline,
+    /// Radius, in meters, of the area around the user that shadows are rendered in.
+    /// Smaller areas give sharper shadows.
+    pub radius: f32,
+}
+
+impl ShadowMap {
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        pipeline_layout: vk::PipelineLayout,
+    ) -> Result<Self> {
+        print!("[HOTHAM_INIT] Creating shadow map..");
+        let extent = vk::Extent2D {
+            width: SHADOW_MAP_RESOLUTION,
+            height: SHADOW_MAP_RESOLUTION,
+        };
+        let image = vulkan_context.create_image(
+            DEPTH_FORMAT,
+            &extent,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            1,
+            1,
+        )?;
+        vulkan_context.set_debug_name(
+            vk::ObjectType::IMAGE,
+            image.handle.as_raw(),
+            "Shadow Map",
+        )?;
+
+        let sampler = create_sampler(vulkan_context)?;
+        let render_pass = create_render_pass(vulkan_context)?;
+        let framebuffer = unsafe {
+            vulkan_context.device.create_framebuffer(
+                &vk::FramebufferCreateInfo::builder()
+                    .render_pass(render_pass)
+                    .attachments(&[image.view])
+                    .width(extent.width)
+                    .height(extent.height)
+                    .layers(1),
+                None,
+            )
+        }?;
+        let pipeline = create_pipeline(vulkan_context, pipeline_layout, &extent, render_pass)?;
+
+        let shadow_map = Self {
+            image,
+            sampler,
+            render_pass,
+            framebuffer,
+            pipeline,
+            radius: 5.,
+        };
+
+        // Clear the shadow map so it can be sampled even if `shadows_system` never runs.
+        let command_buffer = vulkan_context.begin_single_time_commands();
+        shadow_map.begin_render_pass(vulkan_context, command_buffer);
+        unsafe { vulkan_context.device.cmd_end_render_pass(command_buffer) };
+        vulkan_context.end_single_time_commands(command_buffer);
+
+        println!("..done!");
+        Ok(shadow_map)
+    }
+
+    /// Transforms world space into the shadow map's clip space, for a directional light shining along
+    /// `light_direction` onto the area around `center`
+    pub fn view_projection(
+        &self,
+        light_direction: &Vector3<f32>,
+        center: &Point3<f32>,
+    ) -> Matrix4<f32> {
+        light_view_projection(light_direction, center, self.radius, SHADOW_MAP_RESOLUTION)
+    }
+
+    pub(crate) fn begin_render_pass(
+        &self,
+        vulkan_context: &VulkanContext,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let clear_values = [vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        }];
+        let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(vk::Rect2D {
+                extent: self.image.extent,
+                offset: vk::Offset2D::default(),
+            })
+            .clear_values(&clear_values);
+
+        unsafe {
+            vulkan_context.device.cmd_begin_render_pass(
+                command_buffer,
+                &render_pass_begin_info,
+                vk::SubpassContents::INLINE,
+            );
+        }
+    }
+}
+
+/// Orthographic projection looking along `light_direction`, covering a sphere of `radius` around `center`.
+/// The center is snapped to whole texels so that shadows don't shimmer as the user moves.
+pub(crate) fn light_view_projection(
+    light_direction: &Vector3<f32>,
+    center: &Point3<f32>,
+    radius: f32,
+    resolution: u32,
+) -> Matrix4<f32> {
+    let up = if light_direction.cross(&Vector3::y()).norm() > 0.001 {
+        Vector3::y()
+    } else {
+        Vector3::z()
+    };
+    let rotation = UnitQuaternion::look_at_rh(light_direction, &up);
+
+    // Snap to whole texels, and keep casters up to one radius outside the area, between it and the light.
+    let texel_size = 2. * radius / resolution as f32;
+    let center = rotation * center;
+    let translation = Translation3::new(
+        -(center.x / texel_size).round() * texel_size,
+        -(center.y / texel_size).round() * texel_size,
+        -center.z - 2. * radius,
+    );
+    let view = (translation * rotation).to_homogeneous();
+
+    // Maps x and y from [-radius, radius] to [-1, 1], and depth from [0, 3 * radius] to [0, 1]
+    let far = 3. * radius;
+    #[rustfmt::skip]
+    let projection = Matrix4::new(
+        1. / radius, 0., 0., 0.,
+        0., 1. / radius, 0., 0.,
+        0., 0., -1. / far, 0.,
+        0., 0., 0., 1.,
+    );
+
+    projection * view
+}
+
+fn create_sampler(vulkan_context: &VulkanContext) -> Result<vk::Sampler> {
+    // Comparison sampler, so each lookup is filtered between the four nearest texels.
+    // Anything outside the shadow map is lit.
+    let create_info = vk::SamplerCreateInfo::builder()
+        .mag_filter(vk::Filter::LINEAR)
+        .min_filter(vk::Filter::LINEAR)
+        .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        .border_color(vk::BorderColor::FLOAT_OPAQUE_WHITE)
+        .unnormalized_coordinates(false)
+        .compare_enable(true)
+        .compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .mipmap_mode(vk::SamplerMipmapMode::NEAREST)
+        .min_lod(0.0)
+        .max_lod(1.0);
+
+    unsafe {
+        vulkan_context
+            .device
+            .create_sampler(&create_info, None)
+            .map_err(Into::into)
+    }
+}
+
+fn create_render_pass(vulkan_context: &VulkanContext) -> Result<vk::RenderPass> {
+    let depth_attachment = vk::AttachmentDescription::builder()
+        .format(DEPTH_FORMAT)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL);
+
+    let depth_stencil_reference = vk::AttachmentReference::builder()
+        .attachment(0)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let subpass = vk::SubpassDescription::builder()
+        .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+        .depth_stencil_attachment(&depth_stencil_reference);
+
+    // Wait for the previous frame to finish reading the shadow map before writing to it,
+    // and finish writing to it before this frame reads it.
+    let dependencies = [
+        *vk::SubpassDependency::builder()
+            .src_subpass(vk::SUBPASS_EXTERNAL)
+            .dst_subpass(0)
+            .src_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .src_access_mask(vk::AccessFlags::SHADER_READ)
+            .dst_stage_mask(vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+            .dst_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE),
+        *vk::SubpassDependency::builder()
+            .src_subpass(0)
+            .dst_subpass(vk::SUBPASS_EXTERNAL)
+            .src_stage_mask(vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+            .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+            .dst_stage_mask(vk::PipelineStageFlags::FRAGMENT_SHADER)
+            .dst_access_mask(vk::AccessFlags::SHADER_READ),
+    ];
+
+    let render_pass = unsafe {
+        vulkan_context.device.create_render_pass(
+            &vk::RenderPassCreateInfo::builder()
+                .attachments(&[*depth_attachment])
+                .subpasses(&[*subpass])
+                .dependencies(&dependencies),
+            None,
+        )
+    }?;
+
+    Ok(render_pass)
+}
+
+fn create_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    extent: &vk::Extent2D,
+    render_pass: vk::RenderPass,
+) -> Result<vk::Pipeline> {
+    // Depth only, so there's no fragment shader
+    let (vertex_shader, vertex_stage) = create_shader(
+        include_bytes!("../shaders/shadow.vert.spv"),
+        vk::ShaderStageFlags::VERTEX,
+        vulkan_context,
+    )?;
+    let stages = [vertex_stage];
+
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription::builder()
+        .binding(0)
+        .stride(size_of::<Vertex>() as _)
+        .input_rate(vk::VertexInputRate::VERTEX)
+        .build()];
+    let vertex_attribute_descriptions = Vertex::attribute_descriptions();
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions)
+        .vertex_binding_descriptions(&vertex_binding_descriptions);
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: extent.width as _,
+        height: extent.height as _,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [vk::Rect2D {
+        extent: *extent,
+        offset: vk::Offset2D::default(),
+    }];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    // Render both faces so that open meshes still cast shadows, and bias the depth to avoid shadow acne.
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .rasterizer_discard_enable(false)
+        .depth_clamp_enable(false)
+        .depth_bias_enable(true)
+        .depth_bias_constant_factor(1.25)
+        .depth_bias_clamp(0.0)
+        .depth_bias_slope_factor(1.75)
+        .line_width(1.0);
+
+    let multisample_state = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS)
+        .depth_bounds_test_enable(false)
+        .min_depth_bounds(0.0)
+        .max_depth_bounds(1.0)
+        .stencil_test_enable(false);
+
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder().logic_op_enable(false);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        vulkan_context.device.create_graphics_pipelines(
+            vk::PipelineCache::null(),
+            &[create_info],
+            None,
+        )
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        vulkan_context
+            .device
+            .destroy_shader_module(vertex_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::{point, vector};
+
+    #[test]
+    pub fn test_light_view_projection() {
+        // The sun, straight overhead
+        let light_direction = vector![0., -1., 0.];
+        let center = point![0., 0., 0.];
+        let view_projection = light_view_projection(&light_direction, &center, 5., 2048);
+        let project = |p: Point3<f32>| view_projection.transform_point(&p);
+
+        // The center of the area is in the middle of the shadow map
+        let projected = project(center);
+        assert_relative_eq!(projected.x, 0., epsilon = 0.0001);
+        assert_relative_eq!(projected.y, 0., epsilon = 0.0001);
+        assert_relative_eq!(projected.z, 2. / 3., epsilon = 0.0001);
+
+        // The edges of the area are at the edges of the shadow map
+        assert_relative_eq!(project(point![5., 0., 0.]).x.abs(), 1., epsilon = 0.0001);
+        assert_relative_eq!(project(point![0., 0., 5.]).y.abs(), 1., epsilon = 0.0001);
+
+        // Things closer to the light are closer in the shadow map
+        assert!(project(point![0., 1., 0.]).z < projected.z);
+        assert_relative_eq!(project(point![0., 10., 0.]).z, 0., epsilon = 0.0001);
+        assert_relative_eq!(project(point![0., -5., 0.]).z, 1., epsilon = 0.0001);
+
+        // Moving the area by less than a texel doesn't move the shadow map
+        let nudged = light_view_projection(&light_direction, &point![0.001, 0., 0.], 5., 2048);
+        assert_eq!(nudged, view_projection);
+    }
+}
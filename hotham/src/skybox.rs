@@ -0,0 +1,157 @@
+use anyhow::Result;
+use ash::vk;
+
+use crate::resources::{render_context::create_shader, VulkanContext};
+
+/// Draws the current `Environment` behind everything else. `rendering_system` draws it after the opaque primitives,
+/// so it only fills in the pixels they didn't cover. Turn it off to clear the background to black instead.
+#[derive(Debug, Clone)]
+pub struct Skybox {
+    pub pipeline: vk::Pipeline,
+    /// Whether the skybox is drawn
+    pub enabled: bool,
+}
+
+impl Skybox {
+    pub(crate) fn new(
+        vulkan_context: &VulkanContext,
+        pipeline_layout: vk::PipelineLayout,
+        render_area: &vk::Rect2D,
+        render_pass: vk::RenderPass,
+        msaa_samples: vk::SampleCountFlags,
+    ) -> Result<Self> {
+        print!("[HOTHAM_INIT] Creating skybox..");
+        let pipeline = create_pipeline(
+            vulkan_context,
+            pipeline_layout,
+            render_area,
+            render_pass,
+            msaa_samples,
+        )?;
+        println!("..done!");
+
+        Ok(Self {
+            pipeline,
+            enabled: true,
+        })
+    }
+
+    /// Draw the skybox. The scene data descriptor set must already be bound.
+    pub(crate) fn draw(&self, vulkan_context: &VulkanContext, command_buffer: vk::CommandBuffer) {
+        let device = &vulkan_context.device;
+        unsafe {
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline,
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        }
+    }
+}
+
+fn create_pipeline(
+    vulkan_context: &VulkanContext,
+    pipeline_layout: vk::PipelineLayout,
+    render_area: &vk::Rect2D,
+    render_pass: vk::RenderPass,
+    msaa_samples: vk::SampleCountFlags,
+) -> Result<vk::Pipeline> {
+    let (vertex_shader, vertex_stage) = create_shader(
+        include_bytes!("../shaders/skybox.vert.spv"),
+        vk::ShaderStageFlags::VERTEX,
+        vulkan_context,
+    )?;
+    let (fragment_shader, fragment_stage) = create_shader(
+        include_bytes!("../shaders/skybox.frag.spv"),
+        vk::ShaderStageFlags::FRAGMENT,
+        vulkan_context,
+    )?;
+    let stages = [vertex_stage, fragment_stage];
+
+    // The triangle is generated in the vertex shader
+    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::builder();
+
+    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewports = [vk::Viewport {
+        x: 0.0,
+        y: 0.0,
+        width: render_area.extent.width as _,
+        height: render_area.extent.height as _,
+        min_depth: 0.0,
+        max_depth: 1.0,
+    }];
+    let scissors = [*render_area];
+    let viewport_state = vk::PipelineViewportStateCreateInfo::builder()
+        .viewports(&viewports)
+        .scissors(&scissors);
+
+    let rasterization_state = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE)
+        .rasterizer_discard_enable(false)
+        .depth_clamp_enable(false)
+        .depth_bias_enable(false)
+        .line_width(1.0);
+
+    let multisample_state =
+        vk::PipelineMultisampleStateCreateInfo::builder().rasterization_samples(msaa_samples);
+
+    // Drawn on the far plane after the opaque primitives, so only the pixels they didn't cover are shaded
+    let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(false)
+        .depth_compare_op(vk::CompareOp::LESS_OR_EQUAL)
+        .depth_bounds_test_enable(false)
+        .stencil_test_enable(false);
+
+    let color_blend_attachments = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(
+            vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        )
+        .blend_enable(false)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .logic_op_enable(false)
+        .attachments(&color_blend_attachments);
+
+    let create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&stages)
+        .vertex_input_state(&vertex_input_state)
+        .input_assembly_state(&input_assembly_state)
+        .viewport_state(&viewport_state)
+        .rasterization_state(&rasterization_state)
+        .multisample_state(&multisample_state)
+        .depth_stencil_state(&depth_stencil_state)
+        .color_blend_state(&color_blend_state)
+        .layout(pipeline_layout)
+        .render_pass(render_pass)
+        .subpass(0)
+        .build();
+
+    let pipelines = unsafe {
+        vulkan_context.device.create_graphics_pipelines(
+            vk::PipelineCache::null(),
+            &[create_info],
+            None,
+        )
+    }
+    .map_err(|(_, r)| r)?;
+
+    unsafe {
+        vulkan_context
+            .device
+            .destroy_shader_module(vertex_shader, None);
+        vulkan_context
+            .device
+            .destroy_shader_module(fragment_shader, None);
+    }
+
+    Ok(pipelines[0])
+}
@@ -5,10 +5,24 @@ use openxr::{Swapchain as SwapchainHandle, Vulkan};
 pub struct Swapchain {
     pub resolution: vk::Extent2D,
     pub images: Vec<vk::Image>,
+    /// Used for foveated rendering, if it's enabled
+    pub fragment_density_maps: Option<FragmentDensityMaps>,
+}
+
+/// A fragment density map for each swapchain image, which tells the render pass how finely to shade each part of
+/// the image
+#[derive(Debug, Clone)]
+pub struct FragmentDensityMaps {
+    pub images: Vec<vk::Image>,
+    pub resolution: vk::Extent2D,
 }
 
 impl Swapchain {
-    pub(crate) fn new(handle: &SwapchainHandle<Vulkan>, resolution: vk::Extent2D) -> Result<Self> {
+    pub(crate) fn new(
+        handle: &SwapchainHandle<Vulkan>,
+        resolution: vk::Extent2D,
+        fragment_density_maps: Option<FragmentDensityMaps>,
+    ) -> Result<Self> {
         print!("[HOTHAM_INIT] Creating swapchain..");
 
         let images = handle
@@ -19,6 +33,10 @@ impl Swapchain {
 
         println!("..done!");
 
-        Ok(Self { resolution, images })
+        Ok(Self {
+            resolution,
+            images,
+            fragment_density_maps,
+        })
     }
 }
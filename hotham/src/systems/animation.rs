@@ -1,22 +1,56 @@
-use crate::components::{animation_controller::AnimationController, AnimationTarget, Transform};
+use crate::{
+    components::{
+        animation_controller::AnimationController, AnimationPlayer, AnimationTarget, MorphWeights,
+        Transform,
+    },
+    resources::XrContext,
+};
 use hecs::{PreparedQuery, World};
 
 /// Animation system
-/// Walks through each AnimationTarget and applies the appropriate animation
+/// Moves each `AnimationPlayer` forward by a frame, then walks through each AnimationTarget and applies the clips
+/// its `AnimationController` has chosen, sampled at the player's time. Targets with `MorphWeights` have their weights
+/// animated too.
 pub fn animation_system(
     query: &mut PreparedQuery<(&mut AnimationTarget, &mut Transform)>,
     world: &mut World,
+    xr_context: &XrContext,
 ) {
-    for (_, (animation_target, transform)) in query.query(world).iter() {
+    let delta_time = xr_context.frame_state.predicted_display_period.as_nanos() as f32 / 1e9;
+    advance_players(world, delta_time);
+    apply_animations(query, world);
+}
+
+fn advance_players(world: &mut World, delta_time: f32) {
+    for (_, (player, controller)) in world
+        .query_mut::<(&mut AnimationPlayer, &AnimationController)>()
+        .into_iter()
+    {
+        player.advance(delta_time, controller.duration());
+    }
+}
+
+fn apply_animations(
+    query: &mut PreparedQuery<(&mut AnimationTarget, &mut Transform)>,
+    world: &mut World,
+) {
+    for (entity, (animation_target, transform)) in query.query(world).iter() {
         let controller = world
             .get::<AnimationController>(animation_target.controller)
             .unwrap();
+        let player = world
+            .get::<AnimationPlayer>(animation_target.controller)
+            .map(|p| *p)
+            .unwrap_or_default();
         let blend_from = controller.blend_from;
         let blend_to = controller.blend_to;
         let blend_amount = controller.blend_amount;
 
-        let transform_from = animation_target.animations[blend_from][0];
-        let transform_to = animation_target.animations[blend_to][0];
+        // Clips shorter than the player's time loop or stop on their own
+        let time_from = player.clip_time(controller.clip_duration(blend_from));
+        let time_to = player.clip_time(controller.clip_duration(blend_to));
+        let transform_from = animation_target.sample(blend_from, time_from);
+        let transform_to = animation_target.sample(blend_to, time_to);
 
         transform.translation = transform_from
             .translation
@@ -25,43 +59,44 @@ pub fn animation_system(
             .rotation
             .slerp(&transform_to.rotation, blend_amount);
         transform.scale = transform_from.scale.lerp(&transform_to.scale, blend_amount);
+
+        if !animation_target.animates_weights() {
+            continue;
+        }
+        if let Ok(mut morph_weights) = world.get_mut::<MorphWeights>(entity) {
+            let rest_weights = morph_weights.rest_weights.clone();
+            let weights_from =
+                animation_target.sample_weights(blend_from, time_from, &rest_weights);
+            let weights_to = animation_target.sample_weights(blend_to, time_to, &rest_weights);
+            morph_weights.weights = weights_from.lerp(&weights_to, blend_amount);
+        }
     }
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use crate::{
+        components::Info,
         gltf_loader::{add_model_to_world, load_models_from_glb},
         resources::{render_context::create_descriptor_set_layouts, VulkanContext},
     };
+    use approx::assert_relative_eq;
+    use gltf::animation::{util::ReadOutputs, Interpolation, Property};
+    use hecs::Entity;
+    use nalgebra::{Quaternion, UnitQuaternion, Vector3};
 
     use super::*;
+
+    const LEFT_HAND: &[u8] = include_bytes!("../../../test_assets/left_hand.glb");
+
     #[test]
     pub fn animation_test() {
-        let vulkan_context = VulkanContext::testing().unwrap();
-        let set_layouts = create_descriptor_set_layouts(&vulkan_context).unwrap();
-
-        let data: Vec<&[u8]> = vec![include_bytes!("../../../test_assets/left_hand.glb")];
-        let models = load_models_from_glb(&data, &vulkan_context, &set_layouts).unwrap();
+        let (xr_context, vulkan_context) = XrContext::testing().unwrap();
+        let (mut world, left_hand) = setup(&vulkan_context);
         let mut query = PreparedQuery::<(&mut AnimationTarget, &mut Transform)>::default();
-        let mut world = World::new();
-
-        // Add the left hand
-        let left_hand = add_model_to_world(
-            "Left Hand",
-            &models,
-            &mut world,
-            None,
-            &vulkan_context,
-            &set_layouts,
-        )
-        .unwrap();
         {
             let mut left_hand_controller = world.get_mut::<AnimationController>(left_hand).unwrap();
-            left_hand_controller.blend_from = 0;
-            left_hand_controller.blend_from = 1;
-            left_hand_controller.blend_amount = 0.5;
+            left_hand_controller.blend(0, 1, 0.5);
         }
 
         // Collect all the transforms in the world so we can compare them later.
@@ -72,7 +107,7 @@ mod tests {
             .collect::<Vec<Transform>>();
 
         // Run the animation system
-        animation_system(&mut query, &mut world);
+        animation_system(&mut query, &mut world, &xr_context);
 
         // Collect all the transforms after the system has been run.
         let transforms_after = query
@@ -84,4 +119,144 @@ mod tests {
         // Make sure our transforms have been modified!
         assert_ne!(transforms_before, transforms_after);
     }
+
+    #[test]
+    pub fn test_sampled_transforms_match_gltf() {
+        let vulkan_context = VulkanContext::testing().unwrap();
+        let (mut world, left_hand) = setup(&vulkan_context);
+        let mut query = PreparedQuery::<(&mut AnimationTarget, &mut Transform)>::default();
+
+        // Looping would wrap the last keyframe of a clip around to its first
+        world.get_mut::<AnimationPlayer>(left_hand).unwrap().looping = false;
+
+        // Read the keyframes straight out of the file
+        let (document, buffers, _) = gltf::import_slice(LEFT_HAND).unwrap();
+        for animation in document.animations() {
+            let clip_name = animation
+                .name()
+                .map(|n| n.to_string())
+                .unwrap_or(format!("Animation {}", animation.index()));
+            for channel in animation.channels() {
+                let reader = channel.reader(|b| buffers.get(b.index()).map(|d| &d[..]));
+                let times = reader.read_inputs().unwrap().collect::<Vec<_>>();
+                let outputs = match reader.read_outputs() {
+                    Some(ReadOutputs::Translations(t)) => t.map(|t| t.to_vec()).collect(),
+                    Some(ReadOutputs::Rotations(r)) => r.into_f32().map(|r| r.to_vec()).collect(),
+                    Some(ReadOutputs::Scales(s)) => s.map(|s| s.to_vec()).collect::<Vec<_>>(),
+                    _ => continue,
+                };
+                let property = channel.target().property();
+                let interpolation = channel.sampler().interpolation();
+
+                // Cubic spline outputs are an in-tangent, value and out-tangent for each keyframe
+                let values = (0..times.len())
+                    .map(|i| match interpolation {
+                        Interpolation::CubicSpline => outputs[i * 3 + 1].clone(),
+                        _ => outputs[i].clone(),
+                    })
+                    .collect::<Vec<_>>();
+                let node = find_node(&mut world, channel.target().node().index());
+
+                let clip = world
+                    .get::<AnimationController>(left_hand)
+                    .unwrap()
+                    .clip_index(&clip_name)
+                    .unwrap();
+                world
+                    .get_mut::<AnimationController>(left_hand)
+                    .unwrap()
+                    .set_clip(clip);
+
+                // Every keyframe is hit exactly
+                for (time, value) in times.iter().zip(&values) {
+                    world
+                        .get_mut::<AnimationPlayer>(left_hand)
+                        .unwrap()
+                        .seek(*time);
+                    apply_animations(&mut query, &mut world);
+                    let transform = world.get::<Transform>(node).unwrap();
+                    assert_property_eq(&transform, property, &keyframe_transform(property, value));
+                }
+
+                // Linear keyframes are interpolated in between
+                if times.len() > 1 && interpolation == Interpolation::Linear {
+                    let time = (times[0] + times[1]) / 2.;
+                    world
+                        .get_mut::<AnimationPlayer>(left_hand)
+                        .unwrap()
+                        .seek(time);
+                    apply_animations(&mut query, &mut world);
+                    let transform = world.get::<Transform>(node).unwrap();
+                    let (from, to) = (
+                        keyframe_transform(property, &values[0]),
+                        keyframe_transform(property, &values[1]),
+                    );
+                    let expected = Transform {
+                        translation: from.translation.lerp(&to.translation, 0.5),
+                        rotation: from.rotation.slerp(&to.rotation, 0.5),
+                        scale: from.scale.lerp(&to.scale, 0.5),
+                    };
+                    assert_property_eq(&transform, property, &expected);
+                }
+            }
+        }
+    }
+
+    /// A transform with `property` set to a keyframe's value, as read from the glTF file
+    fn keyframe_transform(property: Property, value: &[f32]) -> Transform {
+        let mut transform = Transform::default();
+        match property {
+            Property::Translation => transform.translation = Vector3::from_column_slice(value),
+            Property::Rotation => {
+                transform.rotation = UnitQuaternion::new_normalize(Quaternion::new(
+                    value[3], value[0], value[1], value[2],
+                ))
+            }
+            Property::Scale => transform.scale = Vector3::from_column_slice(value),
+            Property::MorphTargetWeights => unreachable!(),
+        }
+        transform
+    }
+
+    /// Check the part of `transform` that `property` animates
+    fn assert_property_eq(transform: &Transform, property: Property, expected: &Transform) {
+        match property {
+            Property::Translation => {
+                assert_relative_eq!(transform.translation, expected.translation, epsilon = 1e-5)
+            }
+            Property::Rotation => {
+                assert_relative_eq!(transform.rotation, expected.rotation, epsilon = 1e-5)
+            }
+            Property::Scale => assert_relative_eq!(transform.scale, expected.scale, epsilon = 1e-5),
+            Property::MorphTargetWeights => unreachable!(),
+        }
+    }
+
+    fn setup(vulkan_context: &VulkanContext) -> (World, Entity) {
+        let set_layouts = create_descriptor_set_layouts(vulkan_context).unwrap();
+        let models = load_models_from_glb(&[LEFT_HAND], vulkan_context, &set_layouts).unwrap();
+        let mut world = World::new();
+
+        // Add the left hand
+        let left_hand = add_model_to_world(
+            "Left Hand",
+            &models,
+            &mut world,
+            None,
+            vulkan_context,
+            &set_layouts,
+        )
+        .unwrap();
+
+        (world, left_hand)
+    }
+
+    fn find_node(world: &mut World, node_id: usize) -> Entity {
+        world
+            .query_mut::<&Info>()
+            .into_iter()
+            .find(|(_, info)| info.node_id == node_id)
+            .unwrap()
+            .0
+    }
 }
@@ -72,7 +72,6 @@ fn get_location_from_poses(left_eye: openxr::Posef, right_eye: openxr::Posef) ->
     posef_to_isometry(left_eye).lerp_slerp(&posef_to_isometry(right_eye), 0.5)
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use hecs::{Entity, PreparedQuery, World};
@@ -84,17 +83,14 @@ mod tests {
     use rapier3d::prelude::RigidBodyBuilder;
     const DURATION_SECS: u32 = 8;
 
-    use crate::{
-        resources::{audio_context::MusicTrack, XrContext},
-        VIEW_TYPE,
-    };
+    use crate::resources::{audio_context::MusicTrack, XrContext};
 
     use super::*;
 
     #[test]
     pub fn test_audio_system() {
         // Create resources
-        let (mut xr_context, _) = XrContext::new().unwrap();
+        let (mut xr_context, _) = XrContext::testing().unwrap();
         let mut audio_context = AudioContext::default();
         let mut physics_context = PhysicsContext::default();
 
@@ -181,16 +177,7 @@ mod tests {
 
     fn update_xr(xr_context: &mut XrContext) {
         xr_context.begin_frame().unwrap();
-        let (view_state_flags, views) = xr_context
-            .session
-            .locate_views(
-                VIEW_TYPE,
-                xr_context.frame_state.predicted_display_time,
-                &xr_context.reference_space,
-            )
-            .unwrap();
-        xr_context.views = views;
-        xr_context.view_state_flags = view_state_flags;
+        xr_context.locate_views().unwrap();
     }
 
     fn update_audio(
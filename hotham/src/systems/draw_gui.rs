@@ -52,7 +52,6 @@ pub fn draw_gui_system(
     }
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -62,6 +61,7 @@ mod tests {
     use image::{jpeg::JpegEncoder, DynamicImage, RgbaImage};
     use nalgebra::UnitQuaternion;
     use openxr::{Fovf, Quaternionf, Vector3f};
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
     use std::process::Command;
 
     use crate::{
@@ -347,10 +347,12 @@ mod tests {
         let swapchain = Swapchain {
             images: vec![image.handle],
             resolution,
+            fragment_density_maps: None,
         };
 
         let render_context =
-            RenderContext::new_from_swapchain(&vulkan_context, &swapchain).unwrap();
+            RenderContext::new_from_swapchain(&vulkan_context, &swapchain, &Default::default())
+                .unwrap();
         let gui_context = GuiContext::new(&vulkan_context);
 
         let gltf_data: Vec<&[u8]> = vec![include_bytes!(
@@ -1,28 +1,42 @@
-use hecs::{PreparedQuery, World};
+use hecs::{Entity, PreparedQuery, World};
+use nalgebra::{vector, Isometry3};
 use rapier3d::prelude::RigidBodyType;
 
 use crate::{
-    components::{Collider, Hand, RigidBody},
+    components::{
+        hand::{Handedness, PoseHistory},
+        Collider, GrabPoints, Hand, RigidBody, Transform, TwoHandedGrab,
+    },
     resources::PhysicsContext,
 };
 
 /// Grabbing system
 /// Used to allow a player to grab objects. Used in conjunction with `hands_system`
+///
+/// Grabbed objects are held where they were grabbed, or by their closest `GrabPoints`, and are thrown with the
+/// hand's recent velocity when they are let go. Grabbing an object that is already held with the other hand adds a
+/// `TwoHandedGrab`, and letting go with either hand leaves it in the other.
 pub fn grabbing_system(
     query: &mut PreparedQuery<(&mut Hand, &Collider)>,
     world: &mut World,
     physics_context: &mut PhysicsContext,
 ) {
+    // Entities that were grabbed or let go of this frame
+    let mut changed = Vec::new();
+    // Where the hands that let go of something have been, so that it can be thrown
+    let mut released = Vec::new();
+
     for (_, (hand, collider)) in query.query(world).iter() {
         // Check to see if we are currently gripping
         if hand.grip_value >= 1.0 {
             // If we already have a grabbed entity, no need to do anything.
             if hand.grabbed_entity.is_some() {
-                return;
+                continue;
             };
 
             // Check to see if we are colliding with an entity
             if let Some(other_entity) = collider.collisions_this_frame.first() {
+                let hand_pose = *physics_context.colliders[collider.handle].position();
                 let rigid_body_handle = world.get::<RigidBody>(*other_entity).unwrap().handle;
                 let rigid_body = physics_context
                     .rigid_bodies
@@ -32,21 +46,101 @@ pub fn grabbing_system(
                 // Set its body type to kinematic position based so it can be updated with the hand
                 rigid_body.set_body_type(RigidBodyType::KinematicPositionBased);
 
+                // Hold it by its closest grab point, or wherever the hand is now
+                let object_pose = *rigid_body.position();
+                let hand_in_object = object_pose.inverse() * hand_pose;
+                hand.grab_offset = world
+                    .get::<GrabPoints>(*other_entity)
+                    .ok()
+                    .and_then(|grab_points| grab_points.closest(&hand_in_object).copied())
+                    .unwrap_or(hand_in_object)
+                    .inverse();
+
                 // Store a reference to the grabbed entity
                 hand.grabbed_entity.replace(*other_entity);
+                changed.push(*other_entity);
             }
         } else {
-            // If we are not gripping, but we have a grabbed entity, release it
+            // If we are not gripping, but we have a grabbed entity, let go of it
             if let Some(grabbed_entity) = hand.grabbed_entity.take() {
-                let rigid_body_handle = world.get::<RigidBody>(grabbed_entity).unwrap().handle;
-                let rigid_body = physics_context
-                    .rigid_bodies
-                    .get_mut(rigid_body_handle)
-                    .unwrap();
+                released.push((grabbed_entity, hand.pose_history.clone()));
+                changed.push(grabbed_entity);
+            }
+        }
+    }
+
+    changed.sort();
+    changed.dedup();
+    for entity in changed {
+        update_holders(query, world, physics_context, entity, &released);
+    }
+}
+
+/// Work out how `entity` is held now that a hand has grabbed it or let go of it
+fn update_holders(
+    query: &mut PreparedQuery<(&mut Hand, &Collider)>,
+    world: &mut World,
+    physics_context: &mut PhysicsContext,
+    entity: Entity,
+    released: &[(Entity, PoseHistory)],
+) {
+    let handle = world.get::<RigidBody>(entity).unwrap().handle;
+    let object_pose = *physics_context.rigid_bodies[handle].position();
+    let holders = query
+        .query(world)
+        .iter()
+        .filter(|(_, (hand, _))| hand.grabbed_entity == Some(entity))
+        .map(|(hand_entity, (hand, collider))| {
+            let hand_pose = *physics_context.colliders[collider.handle].position();
+            (hand_entity, hand.handedness, hand_pose)
+        })
+        .collect::<Vec<_>>();
+
+    match holders.as_slice() {
+        // Held in both hands
+        [a, b] => {
+            if world.get::<TwoHandedGrab>(entity).is_ok() {
+                return;
+            }
+            let (left_hand, right_hand) = match a.1 {
+                Handedness::Left => (a.2, b.2),
+                Handedness::Right => (b.2, a.2),
+            };
+            let scale = world
+                .get::<Transform>(entity)
+                .map(|t| t.scale)
+                .unwrap_or_else(|_| vector![1., 1., 1.]);
+            let grab = TwoHandedGrab::new(&left_hand, &right_hand, &object_pose, scale);
+            world.insert_one(entity, grab).unwrap();
+        }
+        // Held in one hand. If the other hand has just let go, hand it over without it jumping.
+        [(hand_entity, _, hand_pose)] => {
+            if world.remove_one::<TwoHandedGrab>(entity).is_ok() {
+                let grab_offset: Isometry3<f32> = hand_pose.inverse() * object_pose;
+                world.get_mut::<Hand>(*hand_entity).unwrap().grab_offset = grab_offset;
+            }
+        }
+        // Let go of completely, so throw it
+        _ => {
+            let _ = world.remove_one::<TwoHandedGrab>(entity);
+            let rigid_body = physics_context.rigid_bodies.get_mut(handle).unwrap();
 
-                // Set its body type back to dynamic
-                rigid_body.set_body_type(RigidBodyType::Dynamic);
+            // Set its body type back to dynamic
+            rigid_body.set_body_type(RigidBodyType::Dynamic);
+
+            // Throw it with the hand's velocity. Spinning the hand also moves anything held away from it.
+            let pose_history = match released.iter().find(|(e, _)| *e == entity) {
+                Some((_, pose_history)) => pose_history,
+                None => return,
+            };
+            let angular_velocity = pose_history.angular_velocity();
+            let mut linear_velocity = pose_history.linear_velocity();
+            if let Some(hand_pose) = pose_history.latest() {
+                let lever = object_pose.translation.vector - hand_pose.translation.vector;
+                linear_velocity += angular_velocity.cross(&lever);
             }
+            rigid_body.set_linvel(linear_velocity, true);
+            rigid_body.set_angvel(angular_velocity, true);
         }
     }
 }
@@ -54,10 +148,11 @@ pub fn grabbing_system(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use approx::assert_relative_eq;
     use rapier3d::prelude::{ColliderBuilder, RigidBodyBuilder};
 
     use crate::{
-        components::{hand::Handedness, Info, Transform},
+        components::{Info, Transform},
         resources::PhysicsContext,
         systems::update_rigid_body_transforms_system,
     };
@@ -81,11 +176,8 @@ mod tests {
         world.insert(grabbed_entity, components).unwrap();
 
         // Fully gripped hand
-        let hand = Hand {
-            handedness: Handedness::Left,
-            grip_value: 1.0,
-            grabbed_entity: None,
-        };
+        let mut hand = Hand::left();
+        hand.grip_value = 1.0;
 
         // Collider
         let hand_collider = ColliderBuilder::cuboid(1.0, 1.0, 1.0).build();
@@ -123,6 +215,170 @@ mod tests {
         assert!(hand.grabbed_entity.is_none());
     }
 
+    #[test]
+    fn test_grab_offset() {
+        let mut world = World::default();
+        let mut physics_context = PhysicsContext::default();
+        let grabbed_entity = add_grabbable(&mut world, &mut physics_context, 1.);
+
+        // Grab the object from a metre away, then grab it again by its grab point
+        let hand_entity = add_hand(
+            &mut world,
+            &mut physics_context,
+            Handedness::Left,
+            0.,
+            grabbed_entity,
+        );
+        grabbing_system(&mut Default::default(), &mut world, &mut physics_context);
+        let hand = world.get::<Hand>(hand_entity).unwrap();
+        assert_eq!(hand.grabbed_entity, Some(grabbed_entity));
+        assert_relative_eq!(hand.grab_offset, Isometry3::translation(0., 0., -1.));
+        drop(hand);
+
+        let grab_points = GrabPoints {
+            poses: vec![
+                Isometry3::translation(0., 0.2, 0.),
+                Isometry3::translation(0., 0.2, 5.),
+            ],
+        };
+        world.insert_one(grabbed_entity, grab_points).unwrap();
+        world.get_mut::<Hand>(hand_entity).unwrap().grabbed_entity = None;
+        grabbing_system(&mut Default::default(), &mut world, &mut physics_context);
+        let hand = world.get::<Hand>(hand_entity).unwrap();
+        assert_relative_eq!(hand.grab_offset, Isometry3::translation(0., -0.2, 0.));
+    }
+
+    #[test]
+    fn test_throw() {
+        let mut world = World::default();
+        let mut physics_context = PhysicsContext::default();
+        let grabbed_entity = add_grabbable(&mut world, &mut physics_context, 0.);
+        let hand_entity = add_hand(
+            &mut world,
+            &mut physics_context,
+            Handedness::Left,
+            0.,
+            grabbed_entity,
+        );
+        grabbing_system(&mut Default::default(), &mut world, &mut physics_context);
+
+        // Swing the hand to the right at 2m/s, then let go
+        let mut hand = world.get_mut::<Hand>(hand_entity).unwrap();
+        for frame in 0..5 {
+            let time = frame as f64 * 0.01;
+            hand.pose_history
+                .record(time, Isometry3::translation(time as f32 * 2., 0., 0.));
+        }
+        hand.grip_value = 0.;
+        drop(hand);
+        grabbing_system(&mut Default::default(), &mut world, &mut physics_context);
+
+        let handle = world.get::<RigidBody>(grabbed_entity).unwrap().handle;
+        let rigid_body = &physics_context.rigid_bodies[handle];
+        assert_eq!(rigid_body.body_type(), RigidBodyType::Dynamic);
+        assert_relative_eq!(*rigid_body.linvel(), vector![2., 0., 0.], epsilon = 0.0001);
+    }
+
+    fn add_grabbable(world: &mut World, physics_context: &mut PhysicsContext, z: f32) -> Entity {
+        let collider = ColliderBuilder::cuboid(0.1, 0.1, 0.1).build();
+        let rigid_body = RigidBodyBuilder::new_dynamic()
+            .translation(vector![0., 0., -z])
+            .build();
+        let entity = world.spawn(());
+        let components = physics_context.get_rigid_body_and_collider(entity, rigid_body, collider);
+        world.insert(entity, components).unwrap();
+        entity
+    }
+
+    #[test]
+    fn test_two_handed_grab() {
+        let mut world = World::default();
+        let mut physics_context = PhysicsContext::default();
+        let grabbed_entity = add_grabbable(&mut world, &mut physics_context, 1.);
+        let left = add_hand(
+            &mut world,
+            &mut physics_context,
+            Handedness::Left,
+            -0.5,
+            grabbed_entity,
+        );
+        let right = add_hand(
+            &mut world,
+            &mut physics_context,
+            Handedness::Right,
+            0.5,
+            grabbed_entity,
+        );
+        let handle = world.get::<RigidBody>(grabbed_entity).unwrap().handle;
+        let mut query = Default::default();
+
+        // Grab with the left hand, then with the right
+        world.get_mut::<Hand>(right).unwrap().grip_value = 0.;
+        grabbing_system(&mut query, &mut world, &mut physics_context);
+        assert!(world.get::<TwoHandedGrab>(grabbed_entity).is_err());
+
+        world.get_mut::<Hand>(right).unwrap().grip_value = 1.;
+        grabbing_system(&mut query, &mut world, &mut physics_context);
+        let grab = *world.get::<TwoHandedGrab>(grabbed_entity).unwrap();
+        assert_relative_eq!(grab.offset, Isometry3::translation(0., 0., -1.));
+        assert_relative_eq!(grab.initial_distance, 1.);
+        assert_eq!(
+            world.get::<Hand>(left).unwrap().grabbed_entity,
+            Some(grabbed_entity)
+        );
+        assert_eq!(
+            world.get::<Hand>(right).unwrap().grabbed_entity,
+            Some(grabbed_entity)
+        );
+
+        // Let go with the left hand. The right hand should keep holding it where it is.
+        world.get_mut::<Hand>(left).unwrap().grip_value = 0.;
+        grabbing_system(&mut query, &mut world, &mut physics_context);
+        assert!(world.get::<TwoHandedGrab>(grabbed_entity).is_err());
+        assert!(world.get::<Hand>(left).unwrap().grabbed_entity.is_none());
+        let right_hand = world.get::<Hand>(right).unwrap();
+        assert_eq!(right_hand.grabbed_entity, Some(grabbed_entity));
+        assert_relative_eq!(
+            right_hand.grab_offset,
+            Isometry3::translation(-0.5, 0., -1.)
+        );
+        drop(right_hand);
+        assert_eq!(
+            physics_context.rigid_bodies[handle].body_type(),
+            RigidBodyType::KinematicPositionBased
+        );
+
+        // Let go with the right hand too, and it should be dropped
+        world.get_mut::<Hand>(right).unwrap().grip_value = 0.;
+        grabbing_system(&mut query, &mut world, &mut physics_context);
+        assert!(world.get::<Hand>(right).unwrap().grabbed_entity.is_none());
+        assert_eq!(
+            physics_context.rigid_bodies[handle].body_type(),
+            RigidBodyType::Dynamic
+        );
+    }
+
+    fn add_hand(
+        world: &mut World,
+        physics_context: &mut PhysicsContext,
+        handedness: Handedness,
+        x: f32,
+        touching: Entity,
+    ) -> Entity {
+        let mut hand = Hand::new(handedness);
+        hand.grip_value = 1.0;
+        let handle = physics_context.colliders.insert(
+            ColliderBuilder::ball(0.1)
+                .translation(vector![x, 0., 0.])
+                .build(),
+        );
+        let collider = Collider {
+            handle,
+            collisions_this_frame: vec![touching],
+        };
+        world.spawn((hand, collider))
+    }
+
     fn schedule(
         query: &mut PreparedQuery<(&mut Hand, &Collider)>,
         world: &mut World,
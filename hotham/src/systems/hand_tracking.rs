@@ -0,0 +1,159 @@
+use hecs::{Entity, PreparedQuery, World};
+use nalgebra::Isometry3;
+
+use crate::{
+    components::{Hand, HandSkeleton, Parent, Transform},
+    resources::{xr_context::HandJoints, XrContext},
+};
+
+/// Hand tracking system
+/// Poses the joints of each `Hand` with a `HandSkeleton` to match the user's real hand, when it is being tracked.
+/// Run this after `animation_system` and `update_rigid_body_transforms_system`, and before
+/// `update_transform_matrix_system`.
+pub fn hand_tracking_system(
+    query: &mut PreparedQuery<(&Hand, &HandSkeleton, &Transform)>,
+    world: &mut World,
+    xr_context: &XrContext,
+) {
+    let tracked_hands = query
+        .query(world)
+        .iter()
+        .filter_map(|(entity, (hand, skeleton, transform))| {
+            let joints = xr_context.hand_joints(hand.handedness)?;
+            Some((entity, skeleton.clone(), transform.position(), *joints))
+        })
+        .collect::<Vec<_>>();
+
+    for (entity, skeleton, hand_pose, joints) in tracked_hands {
+        for (joint_entity, transform) in
+            pose_skeleton(world, entity, &hand_pose, &skeleton, &joints)
+        {
+            *world.get_mut::<Transform>(joint_entity).unwrap() = transform;
+        }
+    }
+}
+
+/// Work out the local transform of each joint in `skeleton` so that it ends up at the matching tracked joint.
+///
+/// The first tracked joint in each chain, usually the wrist, is moved into place. The rest are only rotated so
+/// that the model keeps its own proportions.
+fn pose_skeleton(
+    world: &World,
+    root: Entity,
+    root_pose: &Isometry3<f32>,
+    skeleton: &HandSkeleton,
+    joints: &HandJoints,
+) -> Vec<(Entity, Transform)> {
+    let tracked_pose = |entity: Entity| {
+        skeleton
+            .joints
+            .iter()
+            .position(|j| *j == Some(entity))
+            .map(|index| joints.poses[index] * skeleton.joint_rotation)
+    };
+
+    // Where an entity in the hierarchy is in the world, once every tracked joint has been moved.
+    let world_pose = |mut entity: Entity| {
+        let mut pose = Isometry3::identity();
+        loop {
+            if entity == root {
+                return root_pose * pose;
+            }
+            if let Some(tracked) = tracked_pose(entity) {
+                return tracked * pose;
+            }
+            let local = world.get::<Transform>(entity).unwrap().position();
+            pose = local * pose;
+            match world.get::<Parent>(entity) {
+                Ok(parent) => entity = parent.0,
+                Err(_) => return pose,
+            }
+        }
+    };
+
+    let mut transforms = Vec::new();
+    for entity in skeleton.joints.iter().flatten() {
+        let (mut transform, parent) = match (
+            world.get::<Transform>(*entity),
+            world.get::<Parent>(*entity),
+        ) {
+            (Ok(transform), Ok(parent)) => (*transform, parent.0),
+            _ => continue,
+        };
+        let target = tracked_pose(*entity).unwrap();
+        let local = world_pose(parent).inverse() * target;
+
+        transform.rotation = local.rotation;
+        if tracked_pose(parent).is_none() {
+            transform.translation = local.translation.vector;
+        }
+        transforms.push((*entity, transform));
+    }
+
+    transforms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::xr_context::{HandJoint, HAND_JOINT_COUNT};
+    use approx::assert_relative_eq;
+    use nalgebra::{vector, UnitQuaternion, Vector3};
+
+    #[test]
+    pub fn test_pose_skeleton() {
+        let mut world = World::new();
+        let hand = world.spawn((Transform::default(),));
+        let armature = world.spawn((
+            Transform {
+                translation: vector![0., 0., 0.1],
+                ..Default::default()
+            },
+            Parent(hand),
+        ));
+        let wrist = world.spawn((Transform::default(), Parent(armature)));
+        let index = world.spawn((
+            Transform {
+                translation: vector![0., 0., -0.05],
+                ..Default::default()
+            },
+            Parent(wrist),
+        ));
+
+        let mut skeleton = HandSkeleton {
+            joints: [None; HAND_JOINT_COUNT],
+            joint_rotation: UnitQuaternion::identity(),
+        };
+        skeleton.joints[HandJoint::WRIST.into_raw() as usize] = Some(wrist);
+        skeleton.joints[HandJoint::INDEX_PROXIMAL.into_raw() as usize] = Some(index);
+
+        // The hand is at (1, 1, 1), the tracked wrist is 10cm in front of it and the index finger is bent down
+        // 90 degrees and further away than the model's.
+        let hand_pose = Isometry3::translation(1., 1., 1.);
+        let mut joints = HandJoints {
+            poses: [Isometry3::identity(); HAND_JOINT_COUNT],
+            radii: [0.01; HAND_JOINT_COUNT],
+        };
+        let bend = Vector3::x() * -std::f32::consts::FRAC_PI_2;
+        joints.poses[HandJoint::WRIST.into_raw() as usize] = Isometry3::translation(1., 1., 0.9);
+        joints.poses[HandJoint::INDEX_PROXIMAL.into_raw() as usize] =
+            Isometry3::new(vector![1., 1., 0.8], bend);
+
+        let transforms = pose_skeleton(&world, hand, &hand_pose, &skeleton, &joints);
+        assert_eq!(transforms.len(), 2);
+        let (_, wrist_transform) = transforms.iter().find(|(e, _)| *e == wrist).unwrap();
+        let (_, index_transform) = transforms.iter().find(|(e, _)| *e == index).unwrap();
+
+        // The wrist should be moved to the tracked wrist, relative to the armature.
+        assert_relative_eq!(wrist_transform.translation, vector![0., 0., -0.2]);
+        assert_relative_eq!(wrist_transform.rotation, UnitQuaternion::identity());
+
+        // The index finger should be rotated, but keep the model's length.
+        assert_relative_eq!(index_transform.translation, vector![0., 0., -0.05]);
+        assert_relative_eq!(
+            index_transform.rotation,
+            UnitQuaternion::new(bend),
+            epsilon = 0.0001
+        );
+    }
+}
@@ -1,11 +1,25 @@
 use crate::{
-    components::{hand::Handedness, AnimationController, Hand, RigidBody},
+    components::{
+        hand::Handedness, AnimationController, Hand, HandSkeleton, PhysicalHand, RigidBody,
+        Transform, TwoHandedGrab, TwoHandedScaling,
+    },
     gltf_loader::add_model_to_world,
-    resources::{PhysicsContext, RenderContext, VulkanContext, XrContext},
+    resources::{
+        physics_context::DEFAULT_COLLISION_GROUP,
+        xr_context::{HandJoint, HAND_JOINT_NAMES},
+        PhysicsContext, RenderContext, VulkanContext, XrContext,
+    },
     util::{is_space_valid, posef_to_isometry},
 };
-use hecs::{PreparedQuery, World};
-use rapier3d::prelude::{ActiveCollisionTypes, ActiveEvents, ColliderBuilder, RigidBodyBuilder};
+use hecs::{Entity, PreparedQuery, World};
+use nalgebra::{Isometry3, Translation3};
+use rapier3d::prelude::{
+    ActiveCollisionTypes, ActiveEvents, ColliderBuilder, InteractionGroups, RigidBodyBuilder,
+    RigidBodyType, Shape, SharedShape,
+};
+
+/// How heavy a physical hand is, in kilograms
+const PHYSICAL_HAND_MASS: f32 = 1.0;
 
 /// Hands system
 /// Used to allow users to interact with objects using their controllers as representations of their hands
@@ -15,29 +29,23 @@ pub fn hands_system(
     xr_context: &XrContext,
     physics_context: &mut PhysicsContext,
 ) {
-    for (_, (hand, animation_controller, rigid_body_component)) in query.query(world).iter() {
-        // Get our the space and path of the hand.
-        let time = xr_context.frame_state.predicted_display_time;
-        let (space, path) = match hand.handedness {
-            Handedness::Left => (
-                &xr_context.left_hand_space,
-                xr_context.left_hand_subaction_path,
-            ),
-            Handedness::Right => (
-                &xr_context.right_hand_space,
-                xr_context.right_hand_subaction_path,
-            ),
-        };
+    // Entities held in both hands, and where each hand is
+    let mut two_handed = Vec::new();
 
-        // Locate the hand in the space.
-        let space = space.locate(&xr_context.reference_space, time).unwrap();
+    for (entity, (hand, animation_controller, rigid_body_component)) in query.query(world).iter() {
+        let hand_joints = xr_context.hand_joints(hand.handedness);
 
-        // Check it's valid before using it
-        if !is_space_valid(&space) {
-            return;
-        }
+        // Locate the hand in the space.
+        let space = xr_context.locate_hand(hand.handedness).unwrap();
 
-        let pose = space.pose;
+        // Check it's valid before using it, falling back to the palm if the user isn't holding a controller
+        let position = if is_space_valid(&space) {
+            posef_to_isometry(space.pose)
+        } else if let Some(hand_joints) = hand_joints {
+            hand_joints.pose(HandJoint::PALM)
+        } else {
+            continue;
+        };
 
         // apply transform
         let rigid_body = physics_context
@@ -45,20 +53,56 @@ pub fn hands_system(
             .get_mut(rigid_body_component.handle)
             .unwrap();
 
-        let position = posef_to_isometry(pose);
-        rigid_body.set_next_kinematic_position(position);
+        let hand_pose = match world.get::<PhysicalHand>(entity) {
+            Ok(physical_hand) => {
+                // Chase the user's hand over the next physics step, letting the physics engine stop us if we hit
+                // something
+                let (linear, angular) = physical_hand.velocity_towards(
+                    rigid_body.position(),
+                    &position,
+                    physics_context.integration_parameters.dt,
+                );
+                rigid_body.set_linvel(linear, true);
+                rigid_body.set_angvel(angular, true);
 
+                // Don't push against whatever we're holding
+                let solver_groups = if hand.grabbed_entity.is_some() {
+                    InteractionGroups::none()
+                } else {
+                    InteractionGroups::all()
+                };
+                physics_context.colliders[physical_hand.solid_collider]
+                    .set_solver_groups(solver_groups);
+
+                // The hand may have been stopped short of the user's hand, so anything held follows the hand itself
+                *rigid_body.position()
+            }
+            Err(_) => {
+                rigid_body.set_next_kinematic_position(position);
+                position
+            }
+        };
+
+        // Remember where the hand was, so that grabbed objects can be thrown
+        let time = xr_context.frame_state.predicted_display_time.as_nanos() as f64 / 1e9;
+        hand.pose_history.record(time, hand_pose);
+
+        // Move whatever we're holding along with the hand, keeping it where it was grabbed
         if let Some(grabbed_entity) = hand.grabbed_entity {
-            let handle = world.get::<RigidBody>(grabbed_entity).unwrap().handle;
-            let rigid_body = physics_context.rigid_bodies.get_mut(handle).unwrap();
-            rigid_body.set_next_kinematic_position(position);
+            if world.get::<TwoHandedGrab>(grabbed_entity).is_ok() {
+                two_handed.push((grabbed_entity, hand.handedness, hand_pose));
+            } else {
+                let handle = world.get::<RigidBody>(grabbed_entity).unwrap().handle;
+                let rigid_body = physics_context.rigid_bodies.get_mut(handle).unwrap();
+                rigid_body.set_next_kinematic_position(hand_pose * hand.grab_offset);
+            }
         }
 
-        // get grip value
-        let grip_value =
-            openxr::ActionInput::get(&xr_context.grab_action, &xr_context.session, path)
-                .unwrap()
-                .current_state;
+        // get grip value, either from the controller or from the user pinching or making a fist
+        let grip_value = match hand_joints {
+            Some(hand_joints) => hand_joints.grip_value(),
+            None => xr_context.grip_value(hand.handedness).unwrap(),
+        };
 
         // Apply to Hand
         hand.grip_value = grip_value;
@@ -66,6 +110,109 @@ pub fn hands_system(
         // Apply to AnimationController
         animation_controller.blend_amount = grip_value;
     }
+
+    for (entity, handedness, left_hand) in &two_handed {
+        if *handedness != Handedness::Left {
+            continue;
+        }
+        let right_hand = two_handed
+            .iter()
+            .find(|(e, h, _)| e == entity && *h == Handedness::Right)
+            .map(|(_, _, pose)| pose);
+        if let Some(right_hand) = right_hand {
+            move_two_handed(world, physics_context, *entity, left_hand, right_hand);
+        }
+    }
+}
+
+/// Move an entity that is held in both hands, resizing it and its colliders if it has `TwoHandedScaling`
+fn move_two_handed(
+    world: &mut World,
+    physics_context: &mut PhysicsContext,
+    entity: Entity,
+    left_hand: &Isometry3<f32>,
+    right_hand: &Isometry3<f32>,
+) {
+    let grab = *world.get::<TwoHandedGrab>(entity).unwrap();
+    let handle = world.get::<RigidBody>(entity).unwrap().handle;
+    let scaling = world.get::<TwoHandedScaling>(entity).ok().map(|s| *s);
+    let mut pose = grab.pose(left_hand, right_hand);
+    if let (Some(scaling), Ok(mut transform)) = (scaling, world.get_mut::<Transform>(entity)) {
+        let scale = grab.scale(left_hand, right_hand, scaling.min_scale, scaling.max_scale);
+        let new_scale = grab.initial_scale * scale;
+
+        // The scale is changed by the same amount on every axis, so the colliders can keep up
+        if transform.scale.x != 0.
+            && scale_colliders(physics_context, handle, new_scale.x / transform.scale.x)
+        {
+            transform.scale = new_scale;
+            pose = grab.scaled_pose(left_hand, right_hand, scale);
+        }
+    }
+
+    let rigid_body = physics_context.rigid_bodies.get_mut(handle).unwrap();
+    rigid_body.set_next_kinematic_position(pose);
+}
+
+/// Make every collider of a rigid body `factor` times bigger, so that it collides at the size it's drawn. Returns
+/// `false`, without changing anything, if one of them has a shape that can't be resized.
+fn scale_colliders(
+    physics_context: &mut PhysicsContext,
+    rigid_body: rapier3d::prelude::RigidBodyHandle,
+    factor: f32,
+) -> bool {
+    let handles = physics_context.rigid_bodies[rigid_body]
+        .colliders()
+        .to_vec();
+    let shapes = handles
+        .iter()
+        .map(|h| scaled_shape(physics_context.colliders[*h].shape(), factor))
+        .collect::<Option<Vec<_>>>();
+    let shapes = match shapes {
+        Some(shapes) => shapes,
+        None => return false,
+    };
+
+    for (handle, shape) in handles.into_iter().zip(shapes) {
+        let collider = &mut physics_context.colliders[handle];
+        collider.set_shape(shape);
+        if let Some(position) = collider.position_wrt_parent().copied() {
+            collider.set_position_wrt_parent(Isometry3::from_parts(
+                Translation3::from(position.translation.vector * factor),
+                position.rotation,
+            ));
+        }
+    }
+    true
+}
+
+/// `shape` made `factor` times bigger, if it's a ball, cuboid, capsule, cylinder or cone
+fn scaled_shape(shape: &dyn Shape, factor: f32) -> Option<SharedShape> {
+    if let Some(ball) = shape.as_ball() {
+        Some(SharedShape::ball(ball.radius * factor))
+    } else if let Some(cuboid) = shape.as_cuboid() {
+        let half_extents = cuboid.half_extents * factor;
+        Some(SharedShape::cuboid(
+            half_extents.x,
+            half_extents.y,
+            half_extents.z,
+        ))
+    } else if let Some(capsule) = shape.as_capsule() {
+        Some(SharedShape::capsule(
+            capsule.segment.a * factor,
+            capsule.segment.b * factor,
+            capsule.radius * factor,
+        ))
+    } else if let Some(cylinder) = shape.as_cylinder() {
+        Some(SharedShape::cylinder(
+            cylinder.half_height * factor,
+            cylinder.radius * factor,
+        ))
+    } else {
+        shape
+            .as_cone()
+            .map(|cone| SharedShape::cone(cone.half_height * factor, cone.radius * factor))
+    }
 }
 
 /// Convenience function to add a Hand and corresponding Mesh to the world
@@ -76,7 +223,7 @@ pub fn add_hand(
     vulkan_context: &VulkanContext,
     render_context: &RenderContext,
     physics_context: &mut PhysicsContext,
-) {
+) -> Entity {
     let model_name = match handedness {
         Handedness::Left => "Left Hand",
         Handedness::Right => "Right Hand",
@@ -92,16 +239,11 @@ pub fn add_hand(
     .unwrap();
     {
         // Add a hand component
-        world
-            .insert_one(
-                hand,
-                Hand {
-                    grip_value: 0.,
-                    handedness,
-                    grabbed_entity: None,
-                },
-            )
-            .unwrap();
+        world.insert_one(hand, Hand::new(handedness)).unwrap();
+
+        // Map the model's joints to the joints of a tracked hand
+        let skeleton = HandSkeleton::from_joint_names(world, hand, &HAND_JOINT_NAMES);
+        world.insert_one(hand, skeleton).unwrap();
 
         // Modify the animation controller
         let mut animation_controller = world.get_mut::<AnimationController>(hand).unwrap();
@@ -119,9 +261,65 @@ pub fn add_hand(
         let components = physics_context.get_rigid_body_and_collider(hand, rigid_body, collider);
         world.insert(hand, components).unwrap();
     }
+
+    hand
+}
+
+/// Like `add_hand`, but the hand is solid: it pushes objects around and is stopped by walls. See `PhysicalHand`
+pub fn add_physical_hand(
+    models: &std::collections::HashMap<String, World>,
+    handedness: Handedness,
+    world: &mut World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
+    physics_context: &mut PhysicsContext,
+) -> Entity {
+    let hand = add_hand(
+        models,
+        handedness,
+        world,
+        vulkan_context,
+        render_context,
+        physics_context,
+    );
+    make_hand_physical(world, hand, physics_context);
+    hand
+}
+
+/// Turn a hand's kinematic body into a dynamic one with a solid collider inside its grabbing sensor
+pub(crate) fn make_hand_physical(
+    world: &mut World,
+    hand: Entity,
+    physics_context: &mut PhysicsContext,
+) {
+    let handle = world.get::<RigidBody>(hand).unwrap().handle;
+    let rigid_body = &mut physics_context.rigid_bodies[handle];
+    rigid_body.set_body_type(RigidBodyType::Dynamic);
+    rigid_body.set_gravity_scale(0., true);
+    rigid_body.enable_ccd(true);
+
+    // Slightly smaller than the sensor, so that anything touching the hand can be grabbed
+    let builder = ColliderBuilder::capsule_y(0.04, 0.015);
+    let volume = 1. / builder.shape.mass_properties(1.).inv_mass;
+    let mut solid_collider = builder
+        .density(PHYSICAL_HAND_MASS / volume)
+        .collision_groups(InteractionGroups::new(
+            DEFAULT_COLLISION_GROUP,
+            DEFAULT_COLLISION_GROUP,
+        ))
+        .build();
+    solid_collider.user_data = hand.to_bits().get() as _;
+    let solid_collider = physics_context.colliders.insert_with_parent(
+        solid_collider,
+        handle,
+        &mut physics_context.rigid_bodies,
+    );
+
+    world
+        .insert_one(hand, PhysicalHand::new(solid_collider))
+        .unwrap();
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -169,10 +367,106 @@ mod tests {
         assert_relative_eq!(transform.translation, vector![-0.2, 1.4, -0.5]);
     }
 
+    #[test]
+    pub fn test_physical_hand_moves_grabbed_objects() {
+        let (mut world, mut xr_context, mut physics_context) = setup();
+
+        // Put a wall between the hand and the user's hand
+        let wall = ColliderBuilder::cuboid(5., 5., 0.05)
+            .translation(vector![0., 0., -0.25])
+            .build();
+        physics_context.colliders.insert(wall);
+
+        let grabbed_object_rigid_body = RigidBodyBuilder::new_kinematic_position_based().build();
+        let handle = physics_context
+            .rigid_bodies
+            .insert(grabbed_object_rigid_body);
+        let grabbed_entity = world.spawn((RigidBody { handle }, Transform::default()));
+        let hand = add_hand_to_world(&mut physics_context, &mut world, Some(grabbed_entity));
+        make_hand_physical(&mut world, hand, &mut physics_context);
+
+        for _ in 0..30 {
+            schedule(&mut world, &mut xr_context, &mut physics_context);
+        }
+
+        // The object stays in the hand, which has been stopped by the wall
+        let hand_handle = world.get::<RigidBody>(hand).unwrap().handle;
+        let hand_translation = *physics_context.rigid_bodies[hand_handle].translation();
+        assert!(hand_translation.z > -0.25);
+        let transform = world.get::<Transform>(grabbed_entity).unwrap();
+        assert_relative_eq!(transform.translation, hand_translation, epsilon = 0.001);
+    }
+
+    #[test]
+    pub fn test_two_handed_scaling_resizes_colliders() {
+        let mut world = World::new();
+        let mut physics_context = PhysicsContext::default();
+        let left_hand = Isometry3::translation(-0.1, 1., 0.);
+        let right_hand = Isometry3::translation(0.1, 1., 0.);
+
+        let rigid_body = RigidBodyBuilder::new_kinematic_position_based().build();
+        let ball = ColliderBuilder::ball(0.1)
+            .translation(vector![0., 0.2, 0.])
+            .build();
+        let entity = world.spawn((
+            Transform::default(),
+            TwoHandedGrab::new(
+                &left_hand,
+                &right_hand,
+                &Isometry3::translation(0., 1., 0.),
+                vector![1., 1., 1.],
+            ),
+            TwoHandedScaling::default(),
+        ));
+        let components = physics_context.get_rigid_body_and_collider(entity, rigid_body, ball);
+        let collider = components.1.handle;
+        world.insert(entity, components).unwrap();
+
+        // Pull the hands half as far apart again. Doing it twice doesn't make the collider keep growing.
+        let apart = Isometry3::translation(0.2, 1., 0.);
+        move_two_handed(&mut world, &mut physics_context, entity, &left_hand, &apart);
+        move_two_handed(&mut world, &mut physics_context, entity, &left_hand, &apart);
+        let transform = world.get::<Transform>(entity).unwrap();
+        assert_relative_eq!(transform.scale, vector![1.5, 1.5, 1.5]);
+        drop(transform);
+        let ball = &physics_context.colliders[collider];
+        assert_relative_eq!(ball.shape().as_ball().unwrap().radius, 0.15);
+        assert_relative_eq!(
+            ball.position_wrt_parent().unwrap().translation.vector,
+            vector![0., 0.3, 0.]
+        );
+
+        move_two_handed(
+            &mut world,
+            &mut physics_context,
+            entity,
+            &left_hand,
+            &right_hand,
+        );
+        let ball = &physics_context.colliders[collider];
+        assert_relative_eq!(ball.shape().as_ball().unwrap().radius, 0.1);
+
+        // Shapes that can't be resized stop the entity from being resized
+        let trimesh = SharedShape::trimesh(
+            vec![
+                [0., 0., 0.].into(),
+                [1., 0., 0.].into(),
+                [0., 1., 0.].into(),
+            ],
+            vec![[0, 1, 2]],
+        );
+        physics_context.colliders[collider].set_shape(trimesh);
+        move_two_handed(&mut world, &mut physics_context, entity, &left_hand, &apart);
+        assert_relative_eq!(
+            world.get::<Transform>(entity).unwrap().scale,
+            vector![1., 1., 1.]
+        );
+    }
+
     // HELPER FUNCTIONS
     fn setup() -> (World, XrContext, PhysicsContext) {
         let world = World::new();
-        let (xr_context, _) = XrContext::new().unwrap();
+        let (xr_context, _) = XrContext::testing().unwrap();
         let physics_context = PhysicsContext::default();
         (world, xr_context, physics_context)
     }
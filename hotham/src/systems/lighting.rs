@@ -0,0 +1,79 @@
+use hecs::{PreparedQuery, World};
+use nalgebra::Point3;
+
+use crate::{
+    components::{Light, TransformMatrix},
+    resources::{RenderContext, VulkanContext},
+    scene_data::SceneLights,
+};
+
+/// Lighting system
+/// Gathers every `Light` in the world and sends them to the fragment shader. Run this after
+/// `update_parent_transform_matrix_system` and before `shadows_system` and `rendering_system`. If there are no lights,
+/// the scene is lit by `SceneParams::light_direction` instead.
+///
+/// The first directional light casts shadows onto the area around the user.
+pub fn lighting_system(
+    query: &mut PreparedQuery<(&Light, &TransformMatrix)>,
+    world: &mut World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
+) {
+    let mut scene_lights = gather_lights(query, world);
+    if scene_lights.shadow_light >= 0 {
+        let light = &scene_lights.lights[scene_lights.shadow_light as usize];
+        let center = Point3::from(render_context.cameras[0].position().xyz());
+        scene_lights.shadow_matrix = render_context
+            .shadow_map
+            .view_projection(&light.direction.xyz(), &center);
+    }
+    render_context
+        .scene_lights_buffer
+        .update(vulkan_context, &[scene_lights])
+        .unwrap();
+}
+
+fn gather_lights(
+    query: &mut PreparedQuery<(&Light, &TransformMatrix)>,
+    world: &mut World,
+) -> SceneLights {
+    SceneLights::new(
+        query
+            .query_mut(world)
+            .map(|(_, (light, transform_matrix))| light.shader_light(&transform_matrix.0)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene_data::MAX_LIGHTS;
+    use nalgebra::{vector, Matrix4};
+
+    #[test]
+    pub fn test_gather_lights() {
+        let mut world = World::new();
+        let mut query = Default::default();
+        let scene_lights = gather_lights(&mut query, &mut world);
+        assert_eq!(scene_lights.count, 0);
+        assert_eq!(scene_lights.shadow_light, -1);
+
+        let transform = TransformMatrix(Matrix4::new_translation(&vector![1., 2., 3.]));
+        world.spawn((Light::point(5., None), transform));
+        let scene_lights = gather_lights(&mut query, &mut world);
+        assert_eq!(scene_lights.count, 1);
+        assert_eq!(scene_lights.lights[0].position, vector![1., 2., 3., 1.]);
+        assert_eq!(scene_lights.lights[0].color.w, 5.);
+        assert_eq!(scene_lights.shadow_light, -1);
+
+        // Lights past the limit should be ignored
+        for _ in 0..MAX_LIGHTS {
+            world.spawn((Light::directional(1.), transform));
+        }
+        let scene_lights = gather_lights(&mut query, &mut world);
+        assert_eq!(scene_lights.count as usize, MAX_LIGHTS);
+
+        // The first directional light casts shadows
+        assert_eq!(scene_lights.shadow_light, 1);
+    }
+}
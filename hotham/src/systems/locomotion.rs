@@ -0,0 +1,242 @@
+use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector2, Vector3};
+use openxr::View;
+use rapier3d::prelude::{ColliderHandle, InteractionGroups, Ray};
+
+use crate::{
+    components::hand::Handedness,
+    resources::{
+        locomotion_context::TurnMode,
+        physics_context::DEFAULT_COLLISION_GROUP,
+        xr_context::{POINTER_POSE, THUMBSTICK},
+        LocomotionContext, PhysicsContext, XrContext,
+    },
+    util::posef_to_isometry,
+};
+
+/// How far a thumbstick has to be pushed before it does anything
+const DEADZONE: f32 = 0.1;
+/// How far the right thumbstick has to be pushed to snap turn or start aiming a teleport
+const ENGAGE_THRESHOLD: f32 = 0.7;
+/// How far the right thumbstick has to come back before it can snap turn or teleport again
+const RELEASE_THRESHOLD: f32 = 0.3;
+/// Surfaces steeper than this can't be teleported on to. This is the cosine of the largest allowed slope.
+const MAX_TELEPORT_SLOPE: f32 = 0.7;
+
+/// Locomotion system
+/// Moves the user around the world with the thumbsticks by moving the stage. Call this after `begin_frame` and
+/// before any systems that locate the hands, so that everything follows the user this frame.
+pub fn locomotion_system(
+    xr_context: &mut XrContext,
+    physics_context: &PhysicsContext,
+    locomotion_context: &mut LocomotionContext,
+) {
+    let head = match head_pose(&xr_context.views) {
+        Some(head) => head,
+        None => return,
+    };
+    let head_position = Point3::from(head.translation.vector);
+    let delta_time = xr_context.frame_state.predicted_display_period.as_nanos() as f32 / 1e9;
+    let move_input = xr_context.input.vector2(THUMBSTICK, Handedness::Left);
+    let turn_input = xr_context.input.vector2(THUMBSTICK, Handedness::Right);
+    let mut stage = xr_context.stage_pose();
+
+    // Teleport
+    if locomotion_context.teleport_enabled && turn_input.y > ENGAGE_THRESHOLD {
+        locomotion_context.aiming_teleport = true;
+        locomotion_context.teleport_target = xr_context
+            .input
+            .pose(POINTER_POSE, Handedness::Right)
+            .and_then(|pointer| {
+                find_teleport_target(&pointer, physics_context, locomotion_context.teleport_range)
+            });
+    } else if locomotion_context.aiming_teleport && turn_input.y < RELEASE_THRESHOLD {
+        locomotion_context.aiming_teleport = false;
+        if let Some(target) = locomotion_context.teleport_target.take() {
+            stage = teleport_stage(&stage, &head_position, &target);
+        }
+    }
+
+    // Turn
+    if !locomotion_context.aiming_teleport {
+        match locomotion_context.turn_mode {
+            TurnMode::Snap(angle) => {
+                if turn_input.x.abs() < RELEASE_THRESHOLD {
+                    locomotion_context.snap_turn_ready = true;
+                } else if locomotion_context.snap_turn_ready
+                    && turn_input.x.abs() > ENGAGE_THRESHOLD
+                {
+                    locomotion_context.snap_turn_ready = false;
+                    stage = turn_stage(&stage, &head_position, -angle * turn_input.x.signum());
+                }
+            }
+            TurnMode::Smooth(speed) => {
+                if turn_input.x.abs() > DEADZONE {
+                    let angle = -turn_input.x * speed * delta_time;
+                    stage = turn_stage(&stage, &head_position, angle);
+                }
+            }
+            TurnMode::Disabled => {}
+        }
+    }
+
+    // Move
+    if move_input.norm() > DEADZONE {
+        let distance = locomotion_context.move_speed * delta_time;
+        stage = move_stage(&stage, &head, &move_input, distance);
+    }
+
+    if stage != xr_context.stage_pose() {
+        xr_context.set_stage_pose(stage);
+    }
+}
+
+/// Where the user's head is in the world: between their eyes, looking the same way as the left eye.
+fn head_pose(views: &[View]) -> Option<Isometry3<f32>> {
+    if views.len() < 2 {
+        return None;
+    }
+    let left_eye = posef_to_isometry(views[0].pose);
+    let right_eye = posef_to_isometry(views[1].pose);
+    let position = (left_eye.translation.vector + right_eye.translation.vector) / 2.;
+
+    Some(Isometry3::from_parts(
+        Translation3::from(position),
+        left_eye.rotation,
+    ))
+}
+
+/// Move the stage along the floor, relative to the direction the head is facing. The thumbstick's Y axis moves
+/// forward and back and its X axis moves side to side.
+fn move_stage(
+    stage: &Isometry3<f32>,
+    head: &Isometry3<f32>,
+    input: &Vector2<f32>,
+    distance: f32,
+) -> Isometry3<f32> {
+    let flatten = |v: Vector3<f32>| Vector3::new(v.x, 0., v.z).try_normalize(f32::EPSILON);
+    let forward = flatten(head.rotation * -Vector3::z()).unwrap_or_else(|| -Vector3::z());
+    let right = flatten(head.rotation * Vector3::x()).unwrap_or_else(Vector3::x);
+    let direction = (right * input.x + forward * input.y) * distance;
+
+    Translation3::from(direction) * stage
+}
+
+/// Turn the stage around the user's head by `angle` radians, so that they stay in the same place as they turn.
+fn turn_stage(stage: &Isometry3<f32>, head_position: &Point3<f32>, angle: f32) -> Isometry3<f32> {
+    let pivot = Translation3::from(head_position.coords);
+    let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle);
+
+    pivot * rotation * pivot.inverse() * stage
+}
+
+/// Move the stage so that the user is standing on `target`.
+fn teleport_stage(
+    stage: &Isometry3<f32>,
+    head_position: &Point3<f32>,
+    target: &Point3<f32>,
+) -> Isometry3<f32> {
+    let floor_height = stage.translation.vector.y;
+    let offset = Vector3::new(
+        target.x - head_position.x,
+        target.y - floor_height,
+        target.z - head_position.z,
+    );
+
+    Translation3::from(offset) * stage
+}
+
+/// Find somewhere flat enough to stand that the pointer is aiming at, ignoring sensors like the hands.
+fn find_teleport_target(
+    pointer: &Isometry3<f32>,
+    physics_context: &PhysicsContext,
+    range: f32,
+) -> Option<Point3<f32>> {
+    let ray = Ray::new(
+        Point3::from(pointer.translation.vector),
+        pointer.rotation * -Vector3::z(),
+    );
+    let groups = InteractionGroups::new(DEFAULT_COLLISION_GROUP, DEFAULT_COLLISION_GROUP);
+    let colliders = &physics_context.colliders;
+    let filter = |handle: ColliderHandle| !colliders[handle].is_sensor();
+
+    let (_, intersection) = physics_context.query_pipeline.cast_ray_and_get_normal(
+        colliders,
+        &ray,
+        range,
+        true,
+        groups,
+        Some(&filter),
+    )?;
+
+    (intersection.normal.y > MAX_TELEPORT_SLOPE).then(|| ray.point_at(intersection.toi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::vector;
+    use rapier3d::prelude::ColliderBuilder;
+
+    #[test]
+    pub fn test_move_stage() {
+        // Looking to the left, pushing the thumbstick forward should move the user to the left.
+        let head = Isometry3::new(
+            vector![0., 1.6, 0.],
+            Vector3::y() * std::f32::consts::FRAC_PI_2,
+        );
+        let stage = move_stage(&Isometry3::identity(), &head, &Vector2::new(0., 1.), 0.5);
+        assert_relative_eq!(stage.translation.vector, vector![-0.5, 0., 0.]);
+
+        // Looking straight down shouldn't stop the user from moving.
+        let head = Isometry3::new(
+            vector![0., 1.6, 0.],
+            Vector3::x() * -std::f32::consts::FRAC_PI_2,
+        );
+        let stage = move_stage(&Isometry3::identity(), &head, &Vector2::new(1., 0.), 1.);
+        assert_relative_eq!(stage.translation.vector, vector![1., 0., 0.]);
+    }
+
+    #[test]
+    pub fn test_turn_stage() {
+        // Turning should leave the head where it is.
+        let stage = Isometry3::translation(1., 0., 0.);
+        let head_in_stage = Point3::new(0., 1.6, -1.);
+        let head_position = stage * head_in_stage;
+        let stage = turn_stage(&stage, &head_position, std::f32::consts::FRAC_PI_2);
+        assert_relative_eq!(stage * head_in_stage, head_position, epsilon = 0.0001);
+        assert_relative_eq!(
+            stage.rotation.angle(),
+            std::f32::consts::FRAC_PI_2,
+            epsilon = 0.0001
+        );
+    }
+
+    #[test]
+    pub fn test_teleport() {
+        let mut physics_context = PhysicsContext::default();
+        let floor = ColliderBuilder::cuboid(10., 0.1, 10.)
+            .translation(vector![0., 1.9, -10.])
+            .build();
+        physics_context.colliders.insert(floor);
+        physics_context.update();
+
+        // Point down at the raised floor in front of us.
+        let pointer = Isometry3::new(
+            vector![0., 3., 0.],
+            Vector3::x() * -std::f32::consts::FRAC_PI_4,
+        );
+        let target = find_teleport_target(&pointer, &physics_context, 20.).unwrap();
+        assert_relative_eq!(target, Point3::new(0., 2., -1.), epsilon = 0.0001);
+
+        // Pointing at the sky shouldn't find anywhere to stand.
+        let pointer = Isometry3::new(vector![0., 3., 0.], Vector3::x());
+        assert!(find_teleport_target(&pointer, &physics_context, 20.).is_none());
+
+        // The user's head should end up directly above the target, with their feet on it.
+        let head_in_stage = Point3::new(0.5, 1.6, 0.5);
+        let stage = teleport_stage(&Isometry3::identity(), &head_in_stage, &target);
+        let head_position = stage * head_in_stage;
+        assert_relative_eq!(head_position, Point3::new(0., 3.6, -1.), epsilon = 0.0001);
+    }
+}
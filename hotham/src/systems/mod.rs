@@ -4,9 +4,14 @@ pub mod audio;
 pub mod collision;
 pub mod draw_gui;
 pub mod grabbing;
+pub mod hand_tracking;
 pub mod hands;
+pub mod lighting;
+pub mod locomotion;
+pub mod morph_targets;
 pub mod pointers;
 pub mod rendering;
+pub mod shadows;
 pub mod skinning;
 pub mod update_parent_transform_matrix;
 pub mod update_rigid_body_transforms;
@@ -17,17 +22,23 @@ pub use audio::audio_system;
 pub use collision::collision_system;
 pub use draw_gui::draw_gui_system;
 pub use grabbing::grabbing_system;
+pub use hand_tracking::hand_tracking_system;
 pub use hands::hands_system;
+pub use lighting::lighting_system;
+pub use locomotion::locomotion_system;
+pub use morph_targets::morph_targets_system;
 pub use pointers::pointers_system;
 pub use rendering::rendering_system;
+pub use shadows::shadows_system;
 pub use skinning::skinning_system;
 pub use update_parent_transform_matrix::update_parent_transform_matrix_system;
 pub use update_rigid_body_transforms::update_rigid_body_transforms_system;
 pub use update_transform_matrix::update_transform_matrix_system;
 
 use crate::components::{
-    AnimationController, AnimationTarget, Collider, Hand, Info, Joint, Mesh, Panel, Parent,
-    Pointer, RigidBody, Skin, SoundEmitter, Transform, TransformMatrix, UIPanel, Visible,
+    AnimationController, AnimationTarget, CastsShadow, Collider, Hand, HandSkeleton, Light, Mesh,
+    MorphWeights, Panel, Parent, Pointer, RigidBody, Skin, SoundEmitter, Transform,
+    TransformMatrix, UIPanel, Visible,
 };
 use hecs::{PreparedQuery, With, Without};
 
@@ -39,11 +50,16 @@ pub struct Queries<'a> {
     pub collision_query: PreparedQuery<&'a mut Collider>,
     pub draw_gui_query: PreparedQuery<(&'a mut Panel, &'a mut UIPanel)>,
     pub grabbing_query: PreparedQuery<(&'a mut Hand, &'a Collider)>,
+    pub hand_tracking_query: PreparedQuery<(&'a Hand, &'a HandSkeleton, &'a Transform)>,
     pub hands_query: PreparedQuery<(&'a mut Hand, &'a mut AnimationController, &'a mut RigidBody)>,
-    pub joints_query: PreparedQuery<(&'a TransformMatrix, &'a Joint, &'a Info)>,
+    pub lighting_query: PreparedQuery<(&'a Light, &'a TransformMatrix)>,
     pub meshes_query: PreparedQuery<(&'a mut Mesh, &'a Skin)>,
+    pub morph_targets_query: PreparedQuery<(&'a mut Mesh, &'a MorphWeights)>,
     pub parent_query: PreparedQuery<&'a Parent>,
     pub rendering_query: PreparedQuery<With<Visible, (&'a mut Mesh, &'a TransformMatrix)>>,
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::type_complexity))]
+    pub shadows_query:
+        PreparedQuery<With<Visible, With<CastsShadow, (&'a mut Mesh, &'a TransformMatrix)>>>,
     pub roots_query: PreparedQuery<Without<Parent, &'a TransformMatrix>>,
     pub update_rigid_body_transforms_query: PreparedQuery<(&'a RigidBody, &'a mut Transform)>,
     pub update_transform_matrix_query: PreparedQuery<(&'a Transform, &'a mut TransformMatrix)>,
@@ -0,0 +1,114 @@
+use hecs::{PreparedQuery, World};
+
+use crate::{
+    components::{mesh::NO_MORPH_WEIGHTS, Mesh, MorphWeights, Skin},
+    resources::{render_context::MAX_MORPH_WEIGHTS, RenderContext, VulkanContext},
+};
+
+/// Morph targets system
+/// Sends the weights of each `MorphWeights` to the vertex shader in `RenderContext::morph_weight_buffer`. The
+/// `MeshUBO::first_morph_weight` of the `Mesh` on the same entity says where its weights start, and is sent to the
+/// GPU along with the rest of the `MeshUBO`. Run this after `animation_system` and before `skinning_system`,
+/// `shadows_system` and `rendering_system`.
+pub fn morph_targets_system(
+    query: &mut PreparedQuery<(&mut Mesh, &MorphWeights)>,
+    world: &mut World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
+) {
+    let weights = gather_morph_weights(query, world);
+    render_context
+        .morph_weight_buffer
+        .update(vulkan_context, &weights)
+        .unwrap();
+
+    // `skinning_system` sends the UBOs of skinned meshes once it has added their joints
+    for (entity, (mesh, _)) in query.query(world).iter() {
+        if world.get::<Skin>(entity).is_err() {
+            mesh.ubo_buffer
+                .update(vulkan_context, &[mesh.ubo_data])
+                .unwrap();
+        }
+    }
+}
+
+/// Collect the weights of every mesh with morph targets, and point each mesh at where its weights start
+fn gather_morph_weights(
+    query: &mut PreparedQuery<(&mut Mesh, &MorphWeights)>,
+    world: &mut World,
+) -> Vec<f32> {
+    let mut weights = Vec::new();
+    for (_, (mesh, morph_weights)) in query.query_mut(world) {
+        // The shaders read a weight for every morph target
+        let target_count = mesh.ubo_data.morph_target_count as usize;
+        if weights.len() + target_count > MAX_MORPH_WEIGHTS {
+            mesh.ubo_data.first_morph_weight = NO_MORPH_WEIGHTS;
+            continue;
+        }
+
+        mesh.ubo_data.first_morph_weight = weights.len() as u32;
+        weights.extend(
+            morph_weights
+                .weights
+                .iter()
+                .copied()
+                .chain(std::iter::repeat(0.))
+                .take(target_count),
+        );
+    }
+
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        components::{mesh::MeshUBO, MorphTargetWeights},
+        util::test_mesh,
+    };
+
+    fn spawn_morphed_mesh(world: &mut World, target_count: usize) -> hecs::Entity {
+        let ubo_data = MeshUBO {
+            morph_target_count: target_count as _,
+            ..Default::default()
+        };
+        let mesh = test_mesh(ubo_data, Vec::new());
+        let mut morph_weights = MorphWeights::new(target_count, &[]);
+        morph_weights.weights = MorphTargetWeights::from_fn(target_count, |i, _| i as f32);
+        world.spawn((mesh, morph_weights))
+    }
+
+    #[test]
+    pub fn test_gather_morph_weights() {
+        let mut world = World::new();
+        let first = spawn_morphed_mesh(&mut world, 3);
+        let second = spawn_morphed_mesh(&mut world, 40);
+
+        // Missing weights are zero
+        world.get_mut::<MorphWeights>(first).unwrap().weights = MorphTargetWeights::repeat(2, 1.);
+
+        // Meshes whose weights don't fit aren't morphed
+        let too_many = spawn_morphed_mesh(&mut world, MAX_MORPH_WEIGHTS);
+
+        let mut query = Default::default();
+        let weights = gather_morph_weights(&mut query, &mut world);
+        assert_eq!(weights.len(), 43);
+
+        let first_morph_weight = |entity| {
+            world
+                .get::<Mesh>(entity)
+                .unwrap()
+                .ubo_data
+                .first_morph_weight as usize
+        };
+        let first = first_morph_weight(first);
+        assert_eq!(&weights[first..first + 3], &[1., 1., 0.]);
+        let second = first_morph_weight(second);
+        assert_eq!(
+            weights[second..second + 40],
+            (0..40).map(|i| i as f32).collect::<Vec<_>>()
+        );
+        assert_eq!(first_morph_weight(too_many), NO_MORPH_WEIGHTS as usize);
+    }
+}
@@ -22,7 +22,7 @@ const ROTATION_OFFSET: Quaternion<f32> =
     Quaternion::new(-0.558_149_8, 0.827_491_2, 0.034_137_9, -0.050_611_5);
 
 use crate::{
-    components::{hand::Handedness, panel::PanelInput, Info, Panel, Pointer, Transform, Visible},
+    components::{panel::PanelInput, Info, Panel, Pointer, Transform, Visible},
     resources::{PhysicsContext, XrContext},
     util::{is_space_valid, posef_to_isometry},
 };
@@ -36,21 +36,8 @@ pub fn pointers_system(
     physics_context: &mut PhysicsContext,
 ) {
     for (_, (pointer, transform)) in query.query(world).iter() {
-        // Get our the space and path of the pointer.
-        let time = xr_context.frame_state.predicted_display_time;
-        let (space, path) = match pointer.handedness {
-            Handedness::Left => (
-                &xr_context.left_hand_space,
-                xr_context.left_hand_subaction_path,
-            ),
-            Handedness::Right => (
-                &xr_context.right_hand_space,
-                xr_context.right_hand_subaction_path,
-            ),
-        };
-
         // Locate the pointer in the space.
-        let space = space.locate(&xr_context.reference_space, time).unwrap();
+        let space = xr_context.locate_hand(pointer.handedness).unwrap();
         if !is_space_valid(&space) {
             return;
         }
@@ -65,10 +52,7 @@ pub fn pointers_system(
         transform.rotation = position.rotation;
 
         // get trigger value
-        let trigger_value =
-            openxr::ActionInput::get(&xr_context.trigger_action, &xr_context.session, path)
-                .unwrap()
-                .current_state;
+        let trigger_value = xr_context.trigger_value(pointer.handedness).unwrap();
         pointer.trigger_value = trigger_value;
 
         let ray_direction = transform.rotation.transform_vector(&vector![0., 1.0, 0.]);
@@ -173,18 +157,18 @@ mod tests {
     use approx::assert_relative_eq;
     use ash::vk;
 
-    #[cfg(target_os = "windows")]
     #[test]
     pub fn test_pointers_system() {
         use crate::{
-            components::{Collider, Panel, Transform},
+            components::{hand::Handedness, Collider, Panel, Transform},
+            deletion_queue::Shared,
             resources::physics_context::{DEFAULT_COLLISION_GROUP, PANEL_COLLISION_GROUP},
             texture::Texture,
         };
         use nalgebra::vector;
         use rapier3d::prelude::ColliderBuilder;
 
-        let (mut xr_context, mut vulkan_context) = XrContext::new().unwrap();
+        let (mut xr_context, mut vulkan_context) = XrContext::testing().unwrap();
         let mut physics_context = PhysicsContext::default();
         let mut world = World::default();
 
@@ -194,7 +178,7 @@ mod tests {
                 height: 300,
             },
             world_size: [1.0, 1.0].into(),
-            texture: Texture::empty(&mut vulkan_context).unwrap(),
+            texture: Shared::untracked(Texture::empty(&mut vulkan_context).unwrap()),
             input: None,
         };
         let panel_entity = world.spawn((panel,));
@@ -257,7 +241,6 @@ mod tests {
         assert_eq!(input.trigger_value, 0.);
     }
 
-    #[cfg(target_os = "windows")]
     fn schedule(
         physics_context: &mut PhysicsContext,
         world: &mut hecs::World,
@@ -1,56 +1,290 @@
 use crate::{
-    components::{Mesh, TransformMatrix, Visible},
+    components::{Mesh, Primitive, TransformMatrix, Visible},
+    frustum::Frustum,
     resources::VulkanContext,
-    resources::{render_context::create_push_constant, RenderContext},
+    resources::{
+        render_context::{
+            create_push_constant, DrawStats, MorphPushConstant, MAX_INSTANCES,
+            MORPH_PUSH_CONSTANT_OFFSET,
+        },
+        RenderContext,
+    },
+    scene_data::SceneData,
 };
-use ash::vk;
+use ash::vk::{self, Handle};
 use hecs::{PreparedQuery, With, World};
+use nalgebra::Matrix4;
+use std::cmp::Ordering;
 
 /// Rendering system
-/// Walks through each Mesh that is Visible and renders it.
+/// Walks through each Mesh that is Visible and renders it, skipping any that are out of view.
+/// Opaque primitives are drawn first, sorted to keep state changes down, then the skybox fills in the background, then
+/// transparent primitives are blended over them from back to front. Instances of the same primitive, like the meshes `add_model_to_world` creates from
+/// the same model, are drawn together with one instanced draw call. See `RenderContext::draw_stats` for what was drawn.
 pub fn rendering_system(
     query: &mut PreparedQuery<With<Visible, (&mut Mesh, &TransformMatrix)>>,
     world: &mut World,
     vulkan_context: &VulkanContext,
     swapchain_image_index: usize,
-    render_context: &RenderContext,
+    render_context: &mut RenderContext,
 ) {
-    for (_, (mesh, transform_matrix)) in query.query_mut(world) {
-        let device = &vulkan_context.device;
-        let command_buffer = render_context.frames[swapchain_image_index].command_buffer;
+    let device = &vulkan_context.device;
+    let command_buffer = render_context.frames[swapchain_image_index].command_buffer;
+    let draw_list = build_draw_list(query, world, &render_context.scene_data);
+
+    // Send the transforms to the GPU before recording any draws
+    render_context
+        .instance_buffer
+        .update(vulkan_context, &draw_list.instances)
+        .unwrap();
 
+    record_batches(
+        &draw_list.opaque,
+        vulkan_context,
+        command_buffer,
+        render_context,
+    );
+
+    if render_context.skybox.enabled {
+        render_context.skybox.draw(vulkan_context, command_buffer);
+    }
+
+    if !draw_list.transparent.is_empty() {
         unsafe {
-            mesh.ubo_data.transform = transform_matrix.0;
-            mesh.ubo_buffer
-                .update(vulkan_context, &[mesh.ubo_data])
-                .unwrap();
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                render_context.blend_pipeline,
+            );
+        }
+        record_batches(
+            &draw_list.transparent,
+            vulkan_context,
+            command_buffer,
+            render_context,
+        );
+    }
 
-            // Bind mesh descriptor sets
-            device.cmd_bind_descriptor_sets(
+    // Leave the opaque pipeline bound for anything drawn after this
+    if render_context.skybox.enabled || !draw_list.transparent.is_empty() {
+        unsafe {
+            device.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                render_context.pipeline_layout,
-                2,
-                &mesh.descriptor_sets,
-                &[],
+                render_context.pipeline,
             );
+        }
+    }
+
+    render_context.draw_stats = draw_list.stats;
+}
+
+/// A primitive that's going to be drawn
+pub(crate) struct Draw<'a> {
+    pub mesh: &'a Mesh,
+    pub primitive: &'a Primitive,
+    pub transform: Matrix4<f32>,
+    /// Squared distance from the cameras
+    pub distance: f32,
+}
+
+/// Instances of a primitive that are drawn with one draw call. Their transforms are next to each other in the
+/// instance buffer, starting at `first_instance`.
+#[derive(Debug)]
+pub(crate) struct Batch<'a> {
+    pub mesh: &'a Mesh,
+    pub primitive: &'a Primitive,
+    pub first_instance: u32,
+    pub instance_count: u32,
+}
+
+/// Everything that's going to be drawn this frame, in order
+struct DrawList<'a> {
+    opaque: Vec<Batch<'a>>,
+    transparent: Vec<Batch<'a>>,
+    instances: Vec<Matrix4<f32>>,
+    stats: DrawStats,
+}
+
+/// Cull meshes that are outside of both eyes' frustums, then sort and batch the primitives of the rest
+fn build_draw_list<'a>(
+    query: &'a mut PreparedQuery<With<Visible, (&mut Mesh, &TransformMatrix)>>,
+    world: &'a mut World,
+    scene_data: &SceneData,
+) -> DrawList<'a> {
+    let frustums = [0, 1].map(|eye| {
+        Frustum::from_view_projection(&(scene_data.projection[eye] * scene_data.view[eye]))
+    });
+    let camera_position =
+        (scene_data.camera_position[0].xyz() + scene_data.camera_position[1].xyz()) / 2.;
+
+    let mut stats = DrawStats::default();
+    let mut opaque = Vec::new();
+    let mut transparent = Vec::new();
+
+    for (_, (mesh, transform_matrix)) in query.query_mut(world) {
+        let mesh: &Mesh = mesh;
+        let bounding_sphere = mesh
+            .bounding_sphere
+            .map(|s| s.transformed(&transform_matrix.0));
+
+        // Deformed meshes can move away from their bounds, so they're always drawn
+        let in_view = match bounding_sphere {
+            Some(s) if !mesh.is_deformed() => frustums
+                .iter()
+                .any(|f| f.intersects_sphere(&s.center, s.radius)),
+            _ => true,
+        };
+        if !in_view {
+            stats.meshes_culled += 1;
+            continue;
+        }
+
+        stats.meshes_drawn += 1;
+
+        let center = bounding_sphere
+            .map(|s| s.center)
+            .unwrap_or_else(|| transform_matrix.0.column(3).xyz());
+        let distance = (center - camera_position).norm_squared();
+        for primitive in &mesh.primitives {
+            let draw = Draw {
+                mesh,
+                primitive,
+                transform: transform_matrix.0,
+                distance,
+            };
+            if primitive.material.is_transparent() {
+                transparent.push(draw);
+            } else {
+                opaque.push(draw);
+            }
+        }
+    }
+
+    sort_by_state(&mut opaque);
+
+    // Furthest away first, so nearer primitives are blended over the top of them
+    transparent.sort_by(|a, b| {
+        b.distance
+            .partial_cmp(&a.distance)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut instances = Vec::new();
+    let opaque = batch_draws(&opaque, &mut instances);
+    let transparent = batch_draws(&transparent, &mut instances);
+    stats.primitives_drawn = instances.len();
+    stats.draw_calls = opaque.len() + transparent.len();
+
+    DrawList {
+        opaque,
+        transparent,
+        instances,
+        stats,
+    }
+}
+
+/// Put instances of the same primitive next to each other, grouped by their textures
+pub(crate) fn sort_by_state(draws: &mut [Draw]) {
+    draws.sort_by_key(|d| {
+        // Deformed meshes each have their own joints and morph weights, so keep them apart
+        let mesh = if d.mesh.is_deformed() {
+            d.mesh.descriptor_sets[0].as_raw()
+        } else {
+            0
+        };
+        (
+            d.primitive.texture_descriptor_set.as_raw(),
+            d.primitive.geometry.vertex_buffer().as_raw(),
+            d.primitive.geometry.first_index(),
+            mesh,
+        )
+    });
+}
+
+/// Merge neighbouring draws of the same primitive into instanced batches, adding their transforms to `instances`.
+/// Draws past `MAX_INSTANCES` are dropped.
+pub(crate) fn batch_draws<'a>(
+    draws: &[Draw<'a>],
+    instances: &mut Vec<Matrix4<f32>>,
+) -> Vec<Batch<'a>> {
+    let mut batches: Vec<Batch> = Vec::new();
+    for draw in draws {
+        if instances.len() >= MAX_INSTANCES {
+            break;
+        }
+        instances.push(draw.transform);
+
+        match batches.last_mut() {
+            Some(batch) if can_instance(batch, draw) => batch.instance_count += 1,
+            _ => batches.push(Batch {
+                mesh: draw.mesh,
+                primitive: draw.primitive,
+                first_instance: (instances.len() - 1) as _,
+                instance_count: 1,
+            }),
+        }
+    }
+    batches
+}
+
+fn can_instance(batch: &Batch, draw: &Draw) -> bool {
+    let (a, b) = (batch.primitive, draw.primitive);
+    !batch.mesh.is_deformed()
+        && !draw.mesh.is_deformed()
+        && a.geometry == b.geometry
+        && a.texture_descriptor_set == b.texture_descriptor_set
+        && a.material == b.material
+}
+
+/// Record draw calls, only binding descriptor sets and pushing materials when they change
+fn record_batches(
+    batches: &[Batch],
+    vulkan_context: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    render_context: &RenderContext,
+) {
+    let device = &vulkan_context.device;
+    let mut bound_mesh = None;
+    let mut bound_geometry = None;
+    let mut bound_textures = None;
+
+    for (i, batch) in batches.iter().enumerate() {
+        let primitive = batch.primitive;
+        unsafe {
+            // Bind mesh descriptor sets
+            if bound_mesh != Some(batch.mesh.descriptor_sets[0]) {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    render_context.pipeline_layout,
+                    2,
+                    &batch.mesh.descriptor_sets,
+                    &[],
+                );
+                bound_mesh = Some(batch.mesh.descriptor_sets[0]);
+            }
 
-            for primitive in &mesh.primitives {
-                // Bind vertex and index buffers
+            // Bind vertex and index buffers
+            let geometry = &primitive.geometry;
+            if bound_geometry != Some(geometry.vertex_buffer()) {
                 device.cmd_bind_vertex_buffers(
                     command_buffer,
                     0,
-                    &[primitive.vertex_buffer.handle],
+                    &[geometry.vertex_buffer()],
                     &[0],
                 );
                 device.cmd_bind_index_buffer(
                     command_buffer,
-                    primitive.index_buffer.handle,
+                    geometry.index_buffer(),
                     0,
                     vk::IndexType::UINT32,
                 );
+                bound_geometry = Some(geometry.vertex_buffer());
+            }
 
-                // Bind texture descriptor sets
+            // Bind texture descriptor sets
+            if bound_textures != Some(primitive.texture_descriptor_set) {
                 device.cmd_bind_descriptor_sets(
                     command_buffer,
                     vk::PipelineBindPoint::GRAPHICS,
@@ -59,8 +293,11 @@ pub fn rendering_system(
                     &[primitive.texture_descriptor_set],
                     &[],
                 );
+                bound_textures = Some(primitive.texture_descriptor_set);
+            }
 
-                // Push constants
+            // Push constants
+            if i == 0 || batches[i - 1].primitive.material != primitive.material {
                 let material_push_constant = create_push_constant(&primitive.material);
                 device.cmd_push_constants(
                     command_buffer,
@@ -69,13 +306,194 @@ pub fn rendering_system(
                     0,
                     material_push_constant,
                 );
-                device.cmd_draw_indexed(command_buffer, primitive.indices_count, 1, 0, 0, 1);
             }
+            push_morph_targets(vulkan_context, command_buffer, render_context, primitive);
+            device.cmd_draw_indexed(
+                command_buffer,
+                geometry.index_count(),
+                batch.instance_count,
+                geometry.first_index(),
+                geometry.vertex_offset(),
+                batch.first_instance,
+            );
         }
     }
 }
 
-#[cfg(target_os = "windows")]
+/// Tell the vertex shader where the primitive's morph targets are. Primitives without any are pushed too, so that
+/// they aren't drawn with the morph targets of the primitive before them.
+pub(crate) fn push_morph_targets(
+    vulkan_context: &VulkanContext,
+    command_buffer: vk::CommandBuffer,
+    render_context: &RenderContext,
+    primitive: &Primitive,
+) {
+    let morph = morph_push_constant(primitive);
+    unsafe {
+        vulkan_context.device.cmd_push_constants(
+            command_buffer,
+            render_context.pipeline_layout,
+            vk::ShaderStageFlags::VERTEX,
+            MORPH_PUSH_CONSTANT_OFFSET,
+            create_push_constant(&morph),
+        );
+    }
+}
+
+fn morph_push_constant(primitive: &Primitive) -> MorphPushConstant {
+    match primitive.morph_targets {
+        Some(morph_targets) => MorphPushConstant {
+            base_vertex: primitive.geometry.vertex_offset() as _,
+            first_delta: morph_targets.first_delta,
+            target_count: morph_targets.target_count,
+        },
+        None => MorphPushConstant::default(),
+    }
+}
+
+#[cfg(test)]
+mod draw_list_tests {
+    use super::*;
+    use crate::{
+        components::{mesh::MeshUBO, primitive::MorphTargets, BoundingSphere, Material},
+        util::{test_geometry, test_mesh},
+    };
+    use nalgebra::{vector, Matrix4, Vector3};
+
+    #[test]
+    pub fn test_build_draw_list() {
+        let mut world = World::new();
+        let spawn = |world: &mut World, id: u64, position: Vector3<f32>, material: Material| {
+            let primitive = Primitive {
+                geometry: test_geometry(),
+                material,
+                texture_descriptor_set: vk::DescriptorSet::from_raw(id),
+                resources: Default::default(),
+                morph_targets: None,
+            };
+            let mesh = Mesh {
+                descriptor_sets: [vk::DescriptorSet::from_raw(id)],
+                bounding_sphere: Some(BoundingSphere {
+                    center: Vector3::zeros(),
+                    radius: 0.1,
+                }),
+                ..test_mesh(MeshUBO::default(), vec![primitive])
+            };
+            let transform = TransformMatrix(Matrix4::new_translation(&position));
+            world.spawn((Visible {}, mesh, transform))
+        };
+        let transparent = Material {
+            alpha_mask: 2.,
+            ..Default::default()
+        };
+
+        // The default scene data can see from -1 to 1 on X and Y, and 0 to 1 on Z
+        spawn(&mut world, 2, vector![0.5, 0., 0.5], Default::default());
+        spawn(&mut world, 1, vector![-0.5, 0., 0.5], Default::default());
+        spawn(&mut world, 3, vector![10., 0., 0.5], Default::default());
+        spawn(&mut world, 4, vector![0., 0., 0.2], transparent.clone());
+        spawn(&mut world, 5, vector![0., 0., 0.8], transparent);
+
+        // Skinned meshes are never culled
+        let skinned = spawn(&mut world, 6, vector![0., 10., 0.5], Default::default());
+        world.get_mut::<Mesh>(skinned).unwrap().ubo_data.joint_count = 2.;
+
+        let mut query = Default::default();
+        let draw_list = build_draw_list(&mut query, &mut world, &SceneData::default());
+        assert_eq!(
+            draw_list.stats,
+            DrawStats {
+                meshes_drawn: 5,
+                meshes_culled: 1,
+                primitives_drawn: 5,
+                draw_calls: 5,
+            }
+        );
+
+        let ids = |batches: &[Batch]| {
+            batches
+                .iter()
+                .map(|b| b.primitive.texture_descriptor_set.as_raw())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(&draw_list.opaque), vec![1, 2, 6]);
+        assert_eq!(ids(&draw_list.transparent), vec![5, 4]);
+        assert_eq!(
+            draw_list.instances[0],
+            Matrix4::new_translation(&vector![-0.5, 0., 0.5])
+        );
+    }
+
+    #[test]
+    pub fn test_instancing() {
+        let mut world = World::new();
+        let primitive = Primitive {
+            geometry: test_geometry(),
+            material: Default::default(),
+            texture_descriptor_set: vk::DescriptorSet::from_raw(1),
+            resources: Default::default(),
+            morph_targets: None,
+        };
+        let spawn = |world: &mut World, x: f32, joint_count: f32| {
+            let ubo_data = MeshUBO {
+                joint_count,
+                ..Default::default()
+            };
+            let mesh = Mesh {
+                descriptor_sets: [vk::DescriptorSet::from_raw(1)],
+                ..test_mesh(ubo_data, vec![primitive.clone()])
+            };
+            let transform = TransformMatrix(Matrix4::new_translation(&vector![x, 0., 0.5]));
+            world.spawn((Visible {}, mesh, transform))
+        };
+
+        // Three copies of the same model, and a skinned one that has to be drawn on its own
+        spawn(&mut world, -0.5, 0.);
+        spawn(&mut world, 0., 0.);
+        spawn(&mut world, 0.5, 0.);
+        spawn(&mut world, 0.2, 2.);
+
+        let mut query = Default::default();
+        let draw_list = build_draw_list(&mut query, &mut world, &SceneData::default());
+        assert_eq!(draw_list.stats.primitives_drawn, 4);
+        assert_eq!(draw_list.stats.draw_calls, 2);
+
+        let instanced = draw_list
+            .opaque
+            .iter()
+            .find(|b| b.instance_count == 3)
+            .unwrap();
+        let first = instanced.first_instance as usize;
+        let mut xs = draw_list.instances[first..first + 3]
+            .iter()
+            .map(|m| m[(0, 3)])
+            .collect::<Vec<_>>();
+        xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(xs, vec![-0.5, 0., 0.5]);
+    }
+
+    #[test]
+    pub fn test_morph_push_constant() {
+        let mut primitive = Primitive {
+            geometry: test_geometry(),
+            material: Default::default(),
+            texture_descriptor_set: vk::DescriptorSet::null(),
+            resources: Default::default(),
+            morph_targets: Some(MorphTargets {
+                first_delta: 6,
+                target_count: 2,
+            }),
+        };
+        let morph = morph_push_constant(&primitive);
+        assert_eq!(morph.first_delta, 6);
+        assert_eq!(morph.target_count, 2);
+
+        // Primitives whose morph targets were dropped mustn't use the last primitive's
+        primitive.morph_targets = None;
+        assert_eq!(morph_push_constant(&primitive).target_count, 0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::hash_map::DefaultHasher, hash::Hasher};
@@ -121,16 +539,22 @@ mod tests {
         let swapchain = Swapchain {
             images: vec![image.handle],
             resolution,
+            fragment_density_maps: None,
         };
 
         let mut render_context =
-            RenderContext::new_from_swapchain(&vulkan_context, &swapchain).unwrap();
+            RenderContext::new_from_swapchain(&vulkan_context, &swapchain, &Default::default())
+                .unwrap();
 
         // Get a model from GLTF
-        // let gltf_data: Vec<(&[u8], &[u8])> = vec![(
-        //     include_bytes!("../../../test_assets/Sponza.gltf"),
-        //     include_bytes!("../../../test_assets/Sponza.bin"),
-        // )];
+        // let gltf_data: Vec<&[u8]> = vec![include_bytes!("../../../test_assets/Sponza.gltf")];
+        // let mut models = gltf_loader::load_models_from_gltf(
+        //     &gltf_data,
+        //     &gltf_loader::FileResolver::new("../test_assets"),
+        //     &vulkan_context,
+        //     &render_context.descriptor_set_layouts,
+        // )
+        // .unwrap();
         let gltf_data: Vec<&[u8]> = vec![include_bytes!("../../../test_assets/damaged_helmet.glb")];
         let mut models = gltf_loader::load_models_from_glb(
             &gltf_data,
@@ -154,7 +578,7 @@ mod tests {
                 &mut render_context,
                 &mut world,
                 resolution,
-                image.clone(),
+                &image,
                 name,
                 *debug_view_equation,
             );
@@ -166,7 +590,7 @@ mod tests {
         render_context: &mut RenderContext,
         world: &mut World,
         resolution: vk::Extent2D,
-        image: crate::image::Image,
+        image: &crate::image::Image,
         name: &str,
         debug_view_equation: f32,
     ) {
@@ -181,7 +605,7 @@ mod tests {
     fn save_image_to_disk(
         resolution: vk::Extent2D,
         vulkan_context: &VulkanContext,
-        image: crate::image::Image,
+        image: &crate::image::Image,
         name: &str,
     ) {
         let size = (resolution.height * resolution.width * 4) as usize;
@@ -200,7 +624,7 @@ mod tests {
             1,
         );
         vulkan_context.copy_image_to_buffer(
-            &image,
+            image,
             vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
             buffer.handle,
         );
@@ -219,9 +643,10 @@ mod tests {
         let known_good_path = format!("../test_assets/render_{}_known_good.jpg", name);
         let known_good_hash = hash_file(&known_good_path);
 
-        //  TODO: Fix this on non-windows platforms.
-        #[cfg(target_os = "windows")]
-        assert_eq!(output_hash, known_good_hash, "Bad render: {}", name);
+        //  TODO: Fix this on non-windows platforms, whose drivers render slightly differently.
+        if cfg!(target_os = "windows") {
+            assert_eq!(output_hash, known_good_hash, "Bad render: {}", name);
+        }
     }
 
     fn schedule(
@@ -273,6 +698,8 @@ mod tests {
                 }],
             )
             .unwrap();
+        // The known good renders were made before there was a skybox
+        render_context.skybox.enabled = false;
         render_context.begin_frame(&vulkan_context, 0);
         render_context.begin_pbr_render_pass(&vulkan_context, 0);
         update_transform_matrix_system(&mut Default::default(), world);
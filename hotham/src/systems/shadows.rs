@@ -0,0 +1,97 @@
+use crate::{
+    components::{CastsShadow, Mesh, TransformMatrix, Visible},
+    resources::{RenderContext, VulkanContext},
+    systems::rendering::{batch_draws, push_morph_targets, sort_by_state, Draw},
+};
+use ash::vk;
+use hecs::{PreparedQuery, With, World};
+
+/// Shadows system
+/// Renders each Mesh that is Visible and has `CastsShadow` into the shadow map. Run this after `lighting_system` and
+/// BEFORE calling `begin_pbr_renderpass`. Like `rendering_system`, instances of the same primitive are drawn together.
+#[cfg_attr(feature = "cargo-clippy", allow(clippy::type_complexity))]
+pub fn shadows_system(
+    query: &mut PreparedQuery<With<Visible, With<CastsShadow, (&mut Mesh, &TransformMatrix)>>>,
+    world: &mut World,
+    vulkan_context: &VulkanContext,
+    swapchain_image_index: usize,
+    render_context: &RenderContext,
+) {
+    let device = &vulkan_context.device;
+    let command_buffer = render_context.frames[swapchain_image_index].command_buffer;
+
+    // Anything could be casting a shadow into view, so nothing is culled
+    let mut draws = Vec::new();
+    for (_, (mesh, transform_matrix)) in query.query_mut(world) {
+        let mesh: &Mesh = mesh;
+        for primitive in &mesh.primitives {
+            draws.push(Draw {
+                mesh,
+                primitive,
+                transform: transform_matrix.0,
+                distance: 0.,
+            });
+        }
+    }
+    sort_by_state(&mut draws);
+
+    let mut instances = Vec::new();
+    let batches = batch_draws(&draws, &mut instances);
+    render_context
+        .shadow_instance_buffer
+        .update(vulkan_context, &instances)
+        .unwrap();
+
+    render_context.begin_shadow_render_pass(vulkan_context, swapchain_image_index);
+    let mut bound_mesh = None;
+    let mut bound_geometry = None;
+    for batch in &batches {
+        unsafe {
+            // Bind mesh descriptor sets
+            if bound_mesh != Some(batch.mesh.descriptor_sets[0]) {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    render_context.pipeline_layout,
+                    2,
+                    &batch.mesh.descriptor_sets,
+                    &[],
+                );
+                bound_mesh = Some(batch.mesh.descriptor_sets[0]);
+            }
+
+            // Bind vertex and index buffers
+            let geometry = &batch.primitive.geometry;
+            if bound_geometry != Some(geometry.vertex_buffer()) {
+                device.cmd_bind_vertex_buffers(
+                    command_buffer,
+                    0,
+                    &[geometry.vertex_buffer()],
+                    &[0],
+                );
+                device.cmd_bind_index_buffer(
+                    command_buffer,
+                    geometry.index_buffer(),
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                bound_geometry = Some(geometry.vertex_buffer());
+            }
+            push_morph_targets(
+                vulkan_context,
+                command_buffer,
+                render_context,
+                batch.primitive,
+            );
+            device.cmd_draw_indexed(
+                command_buffer,
+                geometry.index_count(),
+                batch.instance_count,
+                geometry.first_index(),
+                geometry.vertex_offset(),
+                batch.first_instance,
+            );
+        }
+    }
+    render_context.end_shadow_render_pass(vulkan_context, swapchain_image_index);
+}
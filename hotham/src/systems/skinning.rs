@@ -1,166 +1,176 @@
 use hecs::{Entity, PreparedQuery, World};
 use nalgebra::Matrix4;
-use std::collections::HashMap;
 
-use crate::components::{Info, Joint, Mesh, Skin, TransformMatrix};
+use crate::{
+    components::{mesh::NO_JOINTS, Mesh, Skin, TransformMatrix},
+    resources::{render_context::MAX_JOINTS, RenderContext, VulkanContext},
+};
 
 /// Skinning system
-/// Walks through each joint in the system and builds up the `joint_matrices` that will be sent to the vertex shader
+/// Works out the joint matrices of each skinned `Mesh` from its `Skin`, and sends them to the vertex shader in
+/// `RenderContext::joint_buffer`. Each mesh's `MeshUBO::first_joint` says where its matrices start, and meshes with
+/// equal skins share them. It then sends the `MeshUBO` of each skinned mesh to the GPU. Run this after
+/// `morph_targets_system` and `update_parent_transform_matrix_system`, and before `shadows_system` and
+/// `rendering_system`.
 pub fn skinning_system(
-    joints_query: &mut PreparedQuery<(&TransformMatrix, &Joint, &Info)>,
-    meshes_query: &mut PreparedQuery<(&mut Mesh, &Skin)>,
+    query: &mut PreparedQuery<(&mut Mesh, &Skin)>,
     world: &mut World,
+    vulkan_context: &VulkanContext,
+    render_context: &RenderContext,
 ) {
-    let mut joint_matrices: HashMap<Entity, HashMap<usize, Matrix4<f32>>> = HashMap::new();
-    for (_, (transform_matrix, joint, info)) in joints_query.query(world).iter() {
-        let inverse_transform = world
-            .get_mut::<TransformMatrix>(joint.skeleton_root)
-            .unwrap()
-            .0
-            .try_inverse()
+    let joint_matrices = gather_joint_matrices(query, world);
+    render_context
+        .joint_buffer
+        .update(vulkan_context, &joint_matrices)
+        .unwrap();
+
+    // Including any morph target weights, which have already been gathered
+    for (_, (mesh, _)) in query.query_mut(world) {
+        mesh.ubo_buffer
+            .update(vulkan_context, &[mesh.ubo_data])
             .unwrap();
-        let joint_transform = transform_matrix.0;
-        let inverse_bind_matrix = joint.inverse_bind_matrix;
-        let id = info.node_id;
-
-        let joint_matrix = inverse_transform * joint_transform * inverse_bind_matrix;
-        let matrices = joint_matrices.entry(joint.skeleton_root).or_default();
-        matrices.insert(id, joint_matrix);
     }
+}
+
+/// Build the joint matrices of every skinned mesh in world space, and point each mesh at where its matrices start
+fn gather_joint_matrices(
+    query: &mut PreparedQuery<(&mut Mesh, &Skin)>,
+    world: &World,
+) -> Vec<Matrix4<f32>> {
+    let mut joint_matrices = Vec::new();
+    let mut skins: Vec<(&Skin, u32)> = Vec::new();
+    for (entity, (mesh, skin)) in query.query(world).iter() {
+        // Meshes with the same skin get the same matrices
+        if let Some((_, first_joint)) = skins.iter().find(|(s, _)| *s == skin) {
+            mesh.ubo_data.first_joint = *first_joint;
+            continue;
+        }
+
+        if joint_matrices.len() + skin.joints.len() > MAX_JOINTS {
+            mesh.ubo_data.first_joint = NO_JOINTS;
+            continue;
+        }
 
-    for (entity, (mesh, skin)) in meshes_query.query_mut(world) {
-        let mut matrices_map = joint_matrices
-            .remove(&entity)
-            .unwrap_or_else(|| panic!("Unable to get joint_matrix for entity: {:?}", entity));
-        let joint_matrices = &mut mesh.ubo_data.joint_matrices;
-        for (i, joint_id) in skin.joint_ids.iter().enumerate() {
-            let joint_matrix = matrices_map.remove(joint_id).unwrap();
-            joint_matrices[i] = joint_matrix;
+        let first_joint = joint_matrices.len() as u32;
+        for (joint, inverse_bind_matrix) in skin.joints.iter().zip(&skin.inverse_bind_matrices) {
+            // Joints that have been despawned stay in the bind pose
+            let joint_matrix = match world.get::<TransformMatrix>(*joint) {
+                Ok(joint_transform) => joint_transform.0 * inverse_bind_matrix,
+                Err(_) => mesh_transform(world, entity),
+            };
+            joint_matrices.push(joint_matrix);
         }
+        mesh.ubo_data.first_joint = first_joint;
+        skins.push((skin, first_joint));
     }
+
+    joint_matrices
+}
+
+fn mesh_transform(world: &World, entity: Entity) -> Matrix4<f32> {
+    world
+        .get::<TransformMatrix>(entity)
+        .map(|t| t.0)
+        .unwrap_or_else(|_| Matrix4::identity())
 }
 
-#[cfg(target_os = "windows")]
 #[cfg(test)]
 mod tests {
+    use std::io::Write;
 
-    use std::{io::Write, marker::PhantomData};
-
+    use super::*;
     use crate::{
-        buffer::Buffer,
-        components::{mesh::MeshUBO, Joint, Parent, Skin},
-        resources::VulkanContext,
-        systems::skinning_system,
-        util::{get_from_device_memory, get_world_with_hands},
+        components::{mesh::MeshUBO, Info, Joint, Parent},
+        util::{get_world_with_hands, test_mesh},
     };
-
-    use super::*;
     use approx::relative_eq;
-    use ash::vk;
     use hecs::Satisfies;
     use nalgebra::vector;
 
-    #[test]
-    pub fn test_skinning_system() {
-        let mut world = World::new();
-
-        // Create the transform for the skin entity
-        let translation = vector![1.0, 2.0, 3.0];
-        let root_transform_matrix = Matrix4::new_translation(&translation);
-
-        // Create a skin
-        let inverse = root_transform_matrix.try_inverse().unwrap();
-        let mut ubo_data = MeshUBO::default();
-        ubo_data.joint_matrices[0] = inverse.clone();
-        ubo_data.joint_matrices[1] = inverse.clone();
-        ubo_data.joint_count = 2.;
-        let ubo_buffer = Buffer {
-            handle: vk::Buffer::null(),
-            device_memory: vk::DeviceMemory::null(),
-            size: 0,
-            device_memory_size: 0,
-            usage: vk::BufferUsageFlags::UNIFORM_BUFFER,
-            _phantom: PhantomData,
-        };
-
-        // Store the inverse matrices for our test
-        let joint_matrices = vec![inverse.clone(), inverse];
-
-        // Create a Mesh
-        let mesh = Mesh {
-            descriptor_sets: [vk::DescriptorSet::null()],
-            primitives: Vec::new(),
-            ubo_data,
-            ubo_buffer,
+    fn spawn_skinned_mesh(world: &mut World, skin: Skin) -> Entity {
+        let ubo_data = MeshUBO {
+            joint_count: skin.joints.len() as _,
+            ..Default::default()
         };
+        let mesh = test_mesh(ubo_data, Vec::new());
+        world.spawn((mesh, skin, TransformMatrix(Matrix4::identity())))
+    }
 
-        // Now create the skin entity
-        let skinned_entity = world.spawn((
-            mesh,
-            TransformMatrix(root_transform_matrix),
-            Skin {
-                joint_ids: vec![0, 1],
+    #[test]
+    pub fn test_gather_joint_matrices() {
+        let mut world = World::new();
+        let root = world.spawn((TransformMatrix(Matrix4::new_translation(&vector![
+            1., 2., 3.
+        ])),));
+        let joint = world.spawn((
+            Joint {
+                skeleton_root: root,
             },
+            TransformMatrix(Matrix4::new_translation(&vector![1., 0., 0.])),
+            Parent(root),
+            Info::default(),
         ));
 
-        // Create a child joint
-        let child_joint = Joint {
-            skeleton_root: skinned_entity,
-            inverse_bind_matrix: Matrix4::identity(),
+        // Two meshes using one skeleton, one of them through a copy of the other's skin
+        let skin = Skin {
+            joints: vec![root, joint],
+            inverse_bind_matrices: vec![
+                Matrix4::identity(),
+                Matrix4::new_translation(&vector![-1., 0., 0.]),
+            ],
         };
+        let first = spawn_skinned_mesh(&mut world, skin.clone());
+        let second = spawn_skinned_mesh(&mut world, skin.clone());
 
-        let child_translation = vector![1.0, 0.0, 0.0];
-        let matrix = Matrix4::new_translation(&child_translation);
-        let child = world.spawn((
-            child_joint,
-            TransformMatrix(matrix),
-            Parent(skinned_entity),
-            Info {
-                name: "0".to_string(),
-                node_id: 0,
-            },
-        ));
-
-        // Create a grandchild joint
-        let grandchild_joint = Joint {
-            skeleton_root: skinned_entity,
-            inverse_bind_matrix: Matrix4::identity(),
-        };
+        // A different skin of the same joints
+        let mut other_skin = skin;
+        other_skin.inverse_bind_matrices[0] = Matrix4::new_scaling(2.);
+        let third = spawn_skinned_mesh(&mut world, other_skin);
 
-        let grandchild_translation = vector![1.0, 0.0, 0.0];
-        let matrix = Matrix4::new_translation(&grandchild_translation);
-        let _grandchild = world.spawn((
-            grandchild_joint,
-            TransformMatrix(matrix),
-            Parent(child),
-            Info {
-                name: "1".to_string(),
-                node_id: 1,
+        // A static mesh doesn't have any joint matrices
+        let static_mesh = spawn_skinned_mesh(
+            &mut world,
+            Skin {
+                joints: Vec::new(),
+                inverse_bind_matrices: Vec::new(),
             },
-        ));
-
-        skinning_system(&mut Default::default(), &mut Default::default(), &mut world);
-
-        let mesh = world.get_mut::<Mesh>(skinned_entity).unwrap();
-        let matrices_from_buffer = mesh.ubo_data.joint_matrices;
-
-        for (from_buf, joint_matrices) in matrices_from_buffer.iter().zip(joint_matrices.iter()) {
-            assert_ne!(*from_buf, Matrix4::identity());
-            assert_ne!(from_buf, joint_matrices);
-        }
+        );
+        world.remove_one::<Skin>(static_mesh).unwrap();
+
+        let mut query = Default::default();
+        let joint_matrices = gather_joint_matrices(&mut query, &world);
+        assert_eq!(joint_matrices.len(), 4);
+
+        let first_joint = |entity| world.get::<Mesh>(entity).unwrap().ubo_data.first_joint;
+        assert_eq!(first_joint(first), first_joint(second));
+        assert_ne!(first_joint(first), first_joint(third));
+        assert_eq!(first_joint(static_mesh), NO_JOINTS);
+
+        let first = first_joint(first) as usize;
+        assert_eq!(
+            joint_matrices[first],
+            Matrix4::new_translation(&vector![1., 2., 3.])
+        );
+        assert_eq!(joint_matrices[first + 1], Matrix4::identity());
+
+        // Skins that don't fit are left in their bind pose
+        let big_skin = Skin {
+            joints: vec![joint; MAX_JOINTS],
+            inverse_bind_matrices: vec![Matrix4::identity(); MAX_JOINTS],
+        };
+        let big_mesh = spawn_skinned_mesh(&mut world, big_skin);
+        let joint_matrices = gather_joint_matrices(&mut query, &world);
+        assert!(joint_matrices.len() <= MAX_JOINTS);
+        assert_eq!(
+            world.get::<Mesh>(big_mesh).unwrap().ubo_data.first_joint,
+            NO_JOINTS
+        );
     }
 
     #[test]
     pub fn test_hand_skinning() {
-        let vulkan_context = VulkanContext::testing().unwrap();
-        let mut world = get_world_with_hands();
-
-        skinning_system(&mut Default::default(), &mut Default::default(), &mut world);
-        for (_, mesh) in world.query_mut::<&Mesh>() {
-            mesh.ubo_buffer
-                .update(&vulkan_context, &[mesh.ubo_data])
-                .unwrap();
-        }
+        let world = get_world_with_hands();
+        let joint_matrices = gather_joint_matrices(&mut Default::default(), &world);
 
         let mut called = 0;
         for skinned_entity in world
@@ -183,8 +193,17 @@ mod tests {
                 ))
                 .unwrap()
             };
-            let ubo = unsafe { get_from_device_memory(&vulkan_context, &mesh.ubo_buffer) };
-            let matrices_from_buffer = ubo[0].joint_matrices.to_vec();
+
+            // The expected matrices are relative to the mesh
+            let inverse_transform = mesh_transform(&world, skinned_entity)
+                .try_inverse()
+                .unwrap();
+            let first_joint = mesh.ubo_data.first_joint as usize;
+            let matrices_from_buffer = joint_matrices[first_joint..]
+                .iter()
+                .take(correct_matrices.len())
+                .map(|m| inverse_transform * m)
+                .collect::<Vec<_>>();
             for i in 0..correct_matrices.len() {
                 let expected = correct_matrices[i];
                 let actual = matrices_from_buffer[i];
@@ -1,15 +1,13 @@
-use crate::{image::Image, resources::VulkanContext};
+use crate::{deletion_queue::Garbage, image::Image, resources::VulkanContext};
 use anyhow::{anyhow, Result};
 use ash::vk;
-use gltf::image::Format;
-use image::io::Reader as ImageReader;
 use libktx_rs::{sources::StreamSource, RustKtxStream, TextureCreateFlags, TextureSource};
 use std::{
     io::Cursor,
     sync::{Arc, Mutex},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Texture {
     pub image: Image,
     pub sampler: vk::Sampler,
@@ -61,54 +59,33 @@ impl Texture {
             texture.name().unwrap_or(""),
             mesh_name
         );
-        match texture.source().source() {
-            gltf::image::Source::Uri { uri, .. } => {
-                let (buf, width, height) = parse_image(uri)
-                    .unwrap_or_else(|_| panic!("Unable to load image! URI: {}", uri));
-                Some(
-                    Texture::new(
-                        texture_name,
-                        vulkan_context,
-                        &buf,
-                        width,
-                        height,
-                        TEXTURE_FORMAT,
-                    )
-                    .unwrap(),
-                )
-            }
-            // TODO: Fix this
-            gltf::image::Source::View { .. } => {
-                let index = texture.source().index();
-                let image = &images[index];
-                let texture = if image.format != Format::R8G8B8A8 {
-                    let pixels = add_alpha_channel(image);
-                    Texture::new(
-                        texture_name,
-                        vulkan_context,
-                        &pixels,
-                        image.width,
-                        image.height,
-                        TEXTURE_FORMAT,
-                    )
-                } else {
-                    Texture::new(
-                        texture_name,
-                        vulkan_context,
-                        &image.pixels,
-                        image.width,
-                        image.height,
-                        TEXTURE_FORMAT,
-                    )
-                };
-
-                texture
-                    .map_err(|e| eprintln!("Failed to load texture {} - {:?}", index, e))
-                    .ok()
-            }
+
+        // Images are decoded to RGBA8 by the loader, whether they're embedded in the file or found with a URI
+        let index = texture.source().index();
+        let image = images.get(index)?;
+        Texture::new(
+            texture_name,
+            vulkan_context,
+            &image.pixels,
+            image.width,
+            image.height,
+            TEXTURE_FORMAT,
+        )
+        .map_err(|e| eprintln!("Failed to load texture {} - {:?}", index, e))
+        .ok()
+    }
+
+    /// Destroy the texture once the GPU has finished with it
+    pub fn destroy(self, vulkan_context: &VulkanContext) {
+        for garbage in self.garbage() {
+            vulkan_context.destroy_later(garbage);
         }
     }
 
+    pub(crate) fn garbage(self) -> [Garbage; 2] {
+        [self.image.garbage(), Garbage::Sampler(self.sampler)]
+    }
+
     pub fn empty(vulkan_context: &VulkanContext) -> Result<Self> {
         Self::new(
             "Empty Texture",
@@ -121,8 +98,6 @@ impl Texture {
     }
 
     pub fn from_ktx2(name: &str, buf: &[u8], vulkan_context: &VulkanContext) -> Result<Self> {
-        let buf = Cursor::new(buf.to_vec());
-        let buf = Box::new(buf);
         let (buf, image_t, mip_levels, offsets) = parse_ktx(buf, vulkan_context)?;
         println!(
             "Creating texture image with format {:?}, array layers {} and mip_levels {}",
@@ -146,40 +121,22 @@ impl Texture {
     }
 }
 
-#[cfg(not(target_os = "android"))]
-fn parse_image(path: &str) -> Result<(Vec<u8>, u32, u32)> {
-    let path = format!(r#"..\test_assets\\{}"#, path);
-    let img = ImageReader::open(path)?.decode()?;
-    let img = img.to_rgba8();
-    let width = img.width();
-    let height = img.height();
-    Ok((img.into_raw(), width, height))
-}
-
-#[cfg(target_os = "android")]
-fn parse_image(path: &str) -> Result<(Vec<u8>, u32, u32)> {
-    use crate::util::get_asset_from_path;
-    let bytes = get_asset_from_path(path)?;
-    let format = image::guess_format(&bytes).unwrap();
-    let asset = Cursor::new(bytes);
-    let mut img = ImageReader::new(asset);
-    img.set_format(format);
-    let img = img.decode()?;
-    let img = img.to_rgba8();
-    let width = img.width();
-    let height = img.height();
-    return Ok((img.into_raw(), width, height));
+/// Read a KTX2 file, along with its image data
+#[allow(clippy::arc_with_non_send_sync)] // `StreamSource` wants the stream in an `Arc<Mutex>`, though it never shares it
+pub(crate) fn load_ktx2(buf: &[u8]) -> Result<libktx_rs::Texture<'static>> {
+    let stream = RustKtxStream::new(Box::new(Cursor::new(buf.to_vec())))
+        .map_err(|e| anyhow!("Couldn't create stream: {}", e))?;
+    let source = Arc::new(Mutex::new(stream));
+    StreamSource::new(source, TextureCreateFlags::LOAD_IMAGE_DATA)
+        .create_texture()
+        .map_err(|e| anyhow!("Couldn't load KTX2 texture: {}", e))
 }
 
 pub fn parse_ktx(
-    buf: Box<Cursor<Vec<u8>>>,
+    buf: &[u8],
     vulkan_context: &VulkanContext,
 ) -> Result<(Vec<u8>, Image, u32, Vec<vk::DeviceSize>)> {
-    let stream = RustKtxStream::new(buf).map_err(|e| anyhow!("Couldn't create stream: {}", e))?;
-    let source = Arc::new(Mutex::new(stream));
-    let mut texture = StreamSource::new(source, TextureCreateFlags::LOAD_IMAGE_DATA)
-        .create_texture()
-        .unwrap();
+    let mut texture = load_ktx2(buf)?;
 
     let image_buf = texture.data().to_vec();
     let mut offsets = Vec::new();
@@ -220,24 +177,6 @@ pub fn parse_ktx(
     Ok((image_buf, image, mip_count, offsets))
 }
 
-fn add_alpha_channel(image: &gltf::image::Data) -> Vec<u8> {
-    let final_size = (image.height * image.width) * 4;
-    let mut final_image = vec![0; final_size as _];
-    let original_image = &image.pixels;
-    let mut original_index = 0;
-    let mut final_index = 0;
-    while original_index < original_image.len() {
-        final_image[final_index..(3 + final_index)]
-            .clone_from_slice(&original_image[original_index..(3 + original_index)]);
-        final_image[final_index + 3] = 1;
-
-        original_index += 3;
-        final_index += 4;
-    }
-
-    final_image
-}
-
 const EMPTY_KTX: [u8; 104] = [
     0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A, 0x01, 0x02, 0x03, 0x04,
     0x01, 0x14, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x08, 0x19, 0x00, 0x00, 0x58, 0x80, 0x00, 0x00,
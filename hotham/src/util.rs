@@ -98,9 +98,27 @@ pub fn posef_to_isometry(pose: Posef) -> Isometry3<f32> {
     }
 }
 
+/// Convert a `nalgebra::Isometry3` into a `Posef` for OpenXR
+pub fn isometry_to_posef(isometry: Isometry3<f32>) -> Posef {
+    let position: mint::Vector3<f32> = isometry.translation.vector.into();
+    let orientation: mint::Quaternion<f32> = isometry.rotation.into_inner().into();
+
+    Posef {
+        position: position.into(),
+        orientation: orientation.into(),
+    }
+}
+
 #[cfg(test)]
 use crate::buffer::Buffer;
 #[cfg(test)]
+use crate::resources::{MeshGeometry, MeshRegistry};
+#[cfg(test)]
+use crate::{
+    components::{mesh::MeshUBO, Mesh, Primitive},
+    deletion_queue::Shared,
+};
+#[cfg(test)]
 use ash::vk;
 #[cfg(test)]
 use std::marker::PhantomData;
@@ -111,29 +129,48 @@ pub unsafe fn get_from_device_memory<'a, T: Sized>(
     buffer: &'a Buffer<T>,
 ) -> &'a [T] {
     let memory = vulkan_context
-        .device
-        .map_memory(
-            buffer.device_memory,
-            0,
-            ash::vk::WHOLE_SIZE,
-            ash::vk::MemoryMapFlags::empty(),
-        )
+        .allocator
+        .lock()
+        .unwrap()
+        .mapped_ptr(&buffer.allocation)
         .unwrap();
-    std::slice::from_raw_parts(std::mem::transmute(memory), buffer.size as _)
+    std::slice::from_raw_parts(memory as *const T, buffer.size as _)
 }
 
 #[cfg(test)]
 pub fn test_buffer<T>() -> Buffer<T> {
     Buffer {
         handle: vk::Buffer::null(),
-        device_memory: vk::DeviceMemory::null(),
+        allocation: Default::default(),
         _phantom: PhantomData,
         size: 0,
-        device_memory_size: 0,
         usage: vk::BufferUsageFlags::empty(),
     }
 }
 
+/// Geometry with three vertices and indices, in a registry of its own that has no Vulkan buffers. Its space is never
+/// released.
+#[cfg(test)]
+pub fn test_geometry() -> MeshGeometry {
+    let registry = MeshRegistry::default();
+    registry.add_block(test_buffer(), test_buffer(), 3, 3);
+    registry.allocate(&Default::default(), 3, 3).unwrap()
+}
+
+/// A mesh with placeholder Vulkan objects, for testing systems that only look at its `MeshUBO` and primitives
+#[cfg(test)]
+pub fn test_mesh(ubo_data: MeshUBO, primitives: Vec<Primitive>) -> Mesh {
+    Mesh {
+        descriptor_sets: [vk::DescriptorSet::null()],
+        ubo_buffer: Shared::untracked(test_buffer()),
+        ubo_data,
+        primitives,
+        bounding_sphere: None,
+        resources: Default::default(),
+        morph_target_buffer: None,
+    }
+}
+
 /// Check to see if the current XrSpace is valid
 pub fn is_space_valid(space: &SpaceLocation) -> bool {
     space